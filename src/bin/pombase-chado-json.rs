@@ -65,6 +65,8 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 "The gene history file in this format: https://github.com/pombase/genome_changelog/blob/master/results/all_coordinate_changes_file_comments_no_type_change.tsv", "FILE");
     opts.optopt("d", "output-directory",
                 "Destination directory for the output", "DIR");
+    opts.optflag("", "binary-api-maps",
+                 "Also write the API maps in the binary format that pombase-server loads more quickly");
 
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
@@ -126,6 +128,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
            None
         };
     let output_dir = matches.opt_str("d").unwrap();
+    let write_binary_api_maps = matches.opt_present("binary-api-maps");
 
     let pg_config = tokio_postgres::Config::from_str(&connection_string)?;
 
@@ -165,7 +168,8 @@ async fn main() -> Result<(), Box<dyn Error>> {
                                            &config);
     let web_data = web_data_build.get_web_data();
 
-    match web_data.write(&config, &go_eco_mapping, &doc_config, &output_dir) {
+    match web_data.write(&config, &go_eco_mapping, &doc_config, &output_dir,
+                         write_binary_api_maps) {
         Ok(_) => (),
         Err(e) => {
             panic!("error while writing: {}", e);
@@ -1,7 +1,7 @@
 extern crate getopts;
 
 use axum::{
-    body::Body, extract::{Path, Request, State}, http::{header, HeaderMap, StatusCode}, response::{Html, IntoResponse, Response}, routing::{get, post}, Json, Router, ServiceExt
+    async_trait, body::Body, extract::{FromRequestParts, MatchedPath, Path, Query as QueryParams, RawPathParams, Request, State}, http::{header, request::Parts, HeaderMap, StatusCode}, middleware::{self, Next}, response::{Html, IntoResponse, Response}, routing::{get, post}, Json, Router, ServiceExt
 };
 
 use tokio::fs::read;
@@ -9,7 +9,7 @@ use tower::layer::Layer;
 
 use tower_http::normalize_path::NormalizePathLayer;
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 
 use pombase::data_types::{GoCamDetails, ProteinViewType};
@@ -20,17 +20,35 @@ extern crate serde_derive;
 
 extern crate pombase;
 
-use std::{process, sync::Arc};
+use std::{collections::{HashMap, HashSet}, process};
+use std::sync::{Arc, RwLock, atomic::{AtomicBool, Ordering}};
 use std::env;
+use std::time::{Duration, Instant};
 
 use getopts::Options;
 
+use chrono::Local;
 
-use pombase::api::query::Query;
+
+use pombase::api::query::{Query, QueryOutputOptions};
+use pombase::api::query_validation::QueryValidationError;
+use pombase::api::query_cache::QueryCacheStats;
+use pombase::api::query_text::parse_query_text;
+use pombase::api::enrichment::{EnrichmentOptions, EnrichmentResult, gene_set_enrichment};
+use pombase::api::motif_search::{MotifSearchResult, DEFAULT_MAX_MATCHES_PER_GENE, motif_search};
+use pombase::api::gene_complete::{GeneCompletionMatch, MAX_GENE_COMPLETIONS};
+use pombase::api::id_mapping::{IdMappingResult, IdMappingStatus};
+use pombase::api::openapi::openapi_spec;
+use pombase::api::metrics::{ReleaseCacheStats, ServerMetrics};
+use pombase::api::server_log::init_logging;
+
+use flexstr::ToSharedStr;
+
+use futures_util::StreamExt;
 
 use pombase::api::search::{Search, DocSearchMatch, SolrSearchScope};
 use pombase::api::query_exec::QueryExec;
-use pombase::api_data::{api_maps_from_file, APIData};
+use pombase::api_data::{read_api_maps, APIData, MapsDatabaseCacheStats};
 use pombase::api::site_db::SiteDB;
 use pombase::api::stats_plot::StatsPlots;
 
@@ -42,6 +60,34 @@ use pombase::web::config::Config;
 const PKG_NAME: &str = env!("CARGO_PKG_NAME");
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+// the alias for the default release in "/api/v1/dataset/latest/..."
+const LATEST_RELEASE: &str = "latest";
+
+// Handlers and middleware add these to the response extensions so that
+// track_request() can log and count them
+
+// the route that matched in the Router of the current data
+#[derive(Clone)]
+struct RoutePath(String);
+
+// an advanced search query run by the request
+#[derive(Clone)]
+struct QueryInfo {
+    id: String,
+    release_name: String,
+    duration: Duration,
+}
+
+// the request failed because Django or the search backend returned an error
+#[derive(Clone)]
+struct UpstreamError(&'static str);
+
+fn upstream_error_response(upstream: &'static str, response: impl IntoResponse) -> Response {
+    let mut response = response.into_response();
+    response.extensions_mut().insert(UpstreamError(upstream));
+    response
+}
+
 struct StaticFileState {
     web_root_dir: String,
 }
@@ -70,22 +116,112 @@ async fn get_static_file(path: &str) -> Response {
     }
 }
 
-struct AllState {
+// The data files of one PomBase release
+struct Release {
+    name: String,
     query_exec: QueryExec,
-    search: Search,
     stats_plots: StatsPlots,
     static_file_state: StaticFileState,
-    config: Config
+}
+
+// The search index and the site config are shared by all releases.  The
+// search index is built from the latest release so the search routes are
+// only available for that release, see LatestRelease.
+struct AllState {
+    releases: HashMap<String, Arc<Release>>,
+    // the release to use for "/api/v1/dataset/latest/..." and for pages
+    // outside the dataset API
+    latest_release: String,
+    search: Search,
+    config: Config,
+    // when the data files were read
+    loaded_at: String,
+}
+
+impl AllState {
+    fn release(&self, release_name: &str) -> Option<&Arc<Release>> {
+        if release_name == LATEST_RELEASE {
+            self.releases.get(&self.latest_release)
+        } else {
+            self.releases.get(release_name)
+        }
+    }
+}
+
+// the ":release" part of the path, or "latest" for routes without one
+async fn release_name_param(parts: &mut Parts, all_state: &Arc<AllState>)
+    -> Result<String, (StatusCode, String)>
+{
+    let params = RawPathParams::from_request_parts(parts, all_state).await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+
+    let release_name = params.iter()
+        .find(|(key, _)| *key == "release")
+        .map(|(_, value)| value)
+        .unwrap_or(LATEST_RELEASE);
+
+    Ok(release_name.to_owned())
+}
+
+// Extracts the Release named by the ":release" part of the path, or the
+// latest release for routes without one
+struct DatasetRelease(Arc<Release>);
+
+#[async_trait]
+impl FromRequestParts<Arc<AllState>> for DatasetRelease {
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, all_state: &Arc<AllState>)
+        -> Result<Self, Self::Rejection>
+    {
+        let release_name = release_name_param(parts, all_state).await?;
+
+        match all_state.release(&release_name) {
+            Some(release) => Ok(DatasetRelease(release.clone())),
+            None => Err((StatusCode::NOT_FOUND, format!("no such release: {}", release_name))),
+        }
+    }
+}
+
+// Rejects requests for releases other than the latest, for the routes
+// that use the search index
+struct LatestRelease;
+
+#[async_trait]
+impl FromRequestParts<Arc<AllState>> for LatestRelease {
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, all_state: &Arc<AllState>)
+        -> Result<Self, Self::Rejection>
+    {
+        let release_name = release_name_param(parts, all_state).await?;
+
+        if release_name == LATEST_RELEASE || release_name == all_state.latest_release {
+            Ok(LatestRelease)
+        } else if all_state.releases.contains_key(&release_name) {
+            Err((StatusCode::NOT_FOUND,
+                 format!("search is only available for the latest release, not: {}",
+                         release_name)))
+        } else {
+            Err((StatusCode::NOT_FOUND, format!("no such release: {}", release_name)))
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct MiscPath {
+    path: String,
 }
 
 // If the path is a directory, return path+"/index.html".  Otherwise
 // try the path, then try path + ".json", then default to loading the
 // Angular app from /index.html
-async fn get_misc(Path(mut path): Path<String>,
+async fn get_misc(Path(MiscPath { mut path }): Path<MiscPath>,
+                  DatasetRelease(release): DatasetRelease,
                   State(all_state): State<Arc<AllState>>)
             -> Response
 {
-    let static_file_state = &all_state.static_file_state;
+    let static_file_state = &release.static_file_state;
     let config = &all_state.config;
     let web_root_dir = &static_file_state.web_root_dir;
     let is_jbrowse_path = path.starts_with("jbrowse/");
@@ -131,59 +267,59 @@ fn option_json_to_result<T>(id: &str, opt: Option<Json<T>>) -> Result<(StatusCod
     }
 }
 
-async fn get_gene(Path(id): Path<String>, State(all_state): State<Arc<AllState>>) -> impl IntoResponse {
-    let res = all_state.query_exec.get_api_data().get_full_gene_details(&id).map(Json);
+async fn get_gene(Path((_, id)): Path<(String, String)>, DatasetRelease(release): DatasetRelease) -> impl IntoResponse {
+    let res = release.query_exec.get_api_data().get_full_gene_details(&id).map(Json);
     option_json_to_result(&id, res)
 }
 
-async fn get_genotype(Path(id): Path<String>, State(all_state): State<Arc<AllState>>) -> impl IntoResponse {
-    let res = all_state.query_exec.get_api_data().get_genotype_details(&id).map(Json);
+async fn get_genotype(Path((_, id)): Path<(String, String)>, DatasetRelease(release): DatasetRelease) -> impl IntoResponse {
+    let res = release.query_exec.get_api_data().get_genotype_details(&id).map(Json);
     option_json_to_result(&id, res)
 }
 
-async fn get_allele(Path(id): Path<String>, State(all_state): State<Arc<AllState>>) -> impl IntoResponse {
-    let res = all_state.query_exec.get_api_data().get_allele_details(&id).map(Json);
+async fn get_allele(Path((_, id)): Path<(String, String)>, DatasetRelease(release): DatasetRelease) -> impl IntoResponse {
+    let res = release.query_exec.get_api_data().get_allele_details(&id).map(Json);
     option_json_to_result(&id, res)
 }
 
-async fn get_term(Path(id): Path<String>, State(all_state): State<Arc<AllState>>) -> impl IntoResponse {
-    let res = all_state.query_exec.get_api_data().get_term_details(&id).map(Json);
+async fn get_term(Path((_, id)): Path<(String, String)>, DatasetRelease(release): DatasetRelease) -> impl IntoResponse {
+    let res = release.query_exec.get_api_data().get_term_details(&id).map(Json);
     option_json_to_result(&id, res)
 }
 
-async fn get_protein_features(Path((full_or_widget, gene_uniquename)): Path<(String, String)>,
-                              State(all_state): State<Arc<AllState>>)
+async fn get_protein_features(Path((_, full_or_widget, gene_uniquename)): Path<(String, String, String)>,
+                              DatasetRelease(release): DatasetRelease)
        -> impl IntoResponse
 {
     let full_or_widget =
         match ProteinViewType::try_from(full_or_widget.as_ref()) {
             Ok(val) => val,
             Err(err) => {
-                eprintln!("get_protein_features(): {}", err);
+                tracing::error!("get_protein_features(): {}", err);
                 return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Internal error: {}", err)));
             }
         };
 
-    let res = all_state.query_exec.get_api_data().get_protein_features_of_gene(full_or_widget, &gene_uniquename)
+    let res = release.query_exec.get_api_data().get_protein_features_of_gene(full_or_widget, &gene_uniquename)
               .map(|s| s.to_owned())
               .map(Json);
     option_json_to_result(&gene_uniquename, res)
 }
 
-async fn get_gocam_data(Path((_full_or_widget, gene_uniquename)): Path<(String, String)>,
-                        State(all_state): State<Arc<AllState>>)
+async fn get_gocam_data(Path((_, _full_or_widget, gene_uniquename)): Path<(String, String, String)>,
+                        DatasetRelease(release): DatasetRelease)
         -> impl IntoResponse
 {
-   let res = all_state.query_exec.get_api_data().get_gocam_data_of_gene(&gene_uniquename)
+   let res = release.query_exec.get_api_data().get_gocam_data_of_gene(&gene_uniquename)
               .map(|s| s.to_owned())
               .map(Json);
     option_json_to_result(&gene_uniquename, res)
 }
 
-async fn get_all_gocam_data(State(all_state): State<Arc<AllState>>)
+async fn get_all_gocam_data(DatasetRelease(release): DatasetRelease)
         -> impl IntoResponse
 {
-    let res = all_state.query_exec.get_api_data().get_all_gocam_data();
+    let res = release.query_exec.get_api_data().get_all_gocam_data();
 
     let res: Result<(StatusCode, Json<Vec<GoCamDetails>>), (StatusCode, String)> =
       Ok((StatusCode::OK, Json(res)));
@@ -191,177 +327,116 @@ async fn get_all_gocam_data(State(all_state): State<Arc<AllState>>)
     res
 }
 
-async fn get_all_gocam_data_by_id(Path(gocam_id): Path<String>,
-                                  State(all_state): State<Arc<AllState>>)
+async fn get_all_gocam_data_by_id(Path((_, gocam_id)): Path<(String, String)>,
+                                  DatasetRelease(release): DatasetRelease)
         -> impl IntoResponse
 {
-    let res = all_state.query_exec.get_api_data().get_gocam_details_by_id(&gocam_id).map(Json);
+    let res = release.query_exec.get_api_data().get_gocam_details_by_id(&gocam_id).map(Json);
 
     option_json_to_result(&gocam_id, res)
 }
 
-async fn get_term_summary_by_id(Path(id): Path<String>, State(all_state): State<Arc<AllState>>)
-   -> impl IntoResponse
+async fn get_term_summary_by_id(Path((_, id)): Path<(String, String)>, _: LatestRelease,
+                                State(all_state): State<Arc<AllState>>)
+   -> Response
 {
     let res = all_state.search.term_summary_by_id(&id).await;
 
-    let lookup_response =
-        match res {
-            Ok(summary) => {
-                TermLookupResponse {
-                    status: "Ok".to_owned(),
-                    summary,
-                }
-            },
-            Err(err) => {
-                println!("{:?}", err);
-                TermLookupResponse {
-                    status: "Error".to_owned(),
-                    summary: None,
-                }
-            },
-        };
-
-    Json(lookup_response)
+    match res {
+        Ok(summary) => {
+            Json(TermLookupResponse {
+                status: "Ok".to_owned(),
+                summary,
+            }).into_response()
+        },
+        Err(err) => {
+            tracing::error!("term summary lookup failed: {:?}", err);
+            upstream_error_response("search", Json(TermLookupResponse {
+                status: "Error".to_owned(),
+                summary: None,
+            }))
+        },
+    }
 }
 
-async fn get_reference(Path(id): Path<String>, State(all_state): State<Arc<AllState>>) -> impl IntoResponse {
-    let res = all_state.query_exec.get_api_data().get_reference_details(&id).map(Json);
+async fn get_reference(Path((_, id)): Path<(String, String)>, DatasetRelease(release): DatasetRelease) -> impl IntoResponse {
+    let res = release.query_exec.get_api_data().get_reference_details(&id).map(Json);
     option_json_to_result(&id, res)
 }
 
-async fn seq_feature_page_features(State(all_state): State<Arc<AllState>>) -> impl IntoResponse {
-    Json(all_state.query_exec.get_api_data().seq_feature_page_features())
+async fn seq_feature_page_features(DatasetRelease(release): DatasetRelease) -> impl IntoResponse {
+    Json(release.query_exec.get_api_data().seq_feature_page_features())
 }
 
-async fn get_index(State(all_state): State<Arc<AllState>>) -> Response {
-    let web_root_dir = &all_state.static_file_state.web_root_dir;
+async fn get_index(DatasetRelease(release): DatasetRelease) -> Response {
+    let web_root_dir = &release.static_file_state.web_root_dir;
     get_static_file(&format!("{}/index.html", web_root_dir)).await
 }
 
-async fn structure_view(Path((structure_type, id)): Path<(String, String)>,
-                        State(all_state): State<Arc<AllState>>)
-                        -> (StatusCode, Html<String>)
+// Fetch a page from Django, passing on the parameters
+async fn proxy_to_django(all_state: &AllState, page: &str, params: &[(&str, String)])
+   -> Response
 {
-    let search_url = all_state.config.server.django_url.to_owned() + "/structure_view/";
-    let params = [("structure_type", structure_type), ("id", id)];
+    let url = format!("{}/{}/", all_state.config.server.django_url, page);
     let client = reqwest::Client::new();
-    let result = client.get(search_url).query(&params).send().await;
 
-    match result {
-        Ok(res) => {
-            match res.text().await {
-                Ok(text) => (StatusCode::OK, Html(text)),
-                Err(err) => {
-                    let err_mess = format!("Error proxying to Django: {:?}", err);
-                    eprintln!("{}", err_mess);
-                    (StatusCode::INTERNAL_SERVER_ERROR, Html(err_mess))
-                }
+    let result =
+        match client.get(url).query(params).send().await {
+            Ok(res) => res.text().await,
+            Err(err) => Err(err),
+        };
 
-            }
-        },
+    match result {
+        Ok(text) => (StatusCode::OK, Html(text)).into_response(),
         Err(err) => {
-            eprintln!("Error proxying to Django: {:?}", err);
-            (StatusCode::INTERNAL_SERVER_ERROR, Html(err.to_string()))
+            let err_mess = format!("Error proxying to Django: {:?}", err);
+            tracing::error!("{}", err_mess);
+            upstream_error_response("django", (StatusCode::INTERNAL_SERVER_ERROR, Html(err_mess)))
         }
     }
+}
 
+async fn structure_view(Path((structure_type, id)): Path<(String, String)>,
+                        State(all_state): State<Arc<AllState>>)
+                        -> Response
+{
+    let params = [("structure_type", structure_type), ("id", id)];
+    proxy_to_django(&all_state, "structure_view", &params).await
 }
 
 async fn rna_2d_structure(Path((gene_uniquename, urs_id)): Path<(String, String)>,
                         State(all_state): State<Arc<AllState>>)
-                        -> (StatusCode, Html<String>)
+                        -> Response
 {
-    let search_url = all_state.config.server.django_url.to_owned() + "/rna_2d_structure/";
     let params = [("gene_uniquename", gene_uniquename), ("urs_id", urs_id)];
-    let client = reqwest::Client::new();
-    let result = client.get(search_url).query(&params).send().await;
-
-    match result {
-        Ok(res) => {
-            match res.text().await {
-                Ok(text) => (StatusCode::OK, Html(text)),
-                Err(err) => {
-                    let err_mess = format!("Error proxying to Django: {:?}", err);
-                    eprintln!("{}", err_mess);
-                    (StatusCode::INTERNAL_SERVER_ERROR, Html(err_mess))
-                }
-
-            }
-        },
-        Err(err) => {
-            eprintln!("Error proxying to Django: {:?}", err);
-            (StatusCode::INTERNAL_SERVER_ERROR, Html(err.to_string()))
-        }
-    }
-
+    proxy_to_django(&all_state, "rna_2d_structure", &params).await
 }
 
 async fn protein_feature_view(Path((full_or_widget, gene_uniquename)): Path<(String, String)>,
                               State(all_state): State<Arc<AllState>>)
-   -> (StatusCode, Html<String>)
+   -> Response
 {
-    let search_url = all_state.config.server.django_url.to_owned() + "/protein_feature_view/";
     let params = [("full_or_widget", full_or_widget),
                   ("gene_uniquename", gene_uniquename)];
-    let client = reqwest::Client::new();
-    let result = client.get(search_url).query(&params).send().await;
-
-    match result {
-        Ok(res) => {
-            match res.text().await {
-                Ok(text) => (StatusCode::OK, Html(text)),
-                Err(err) => {
-                    let err_mess = format!("Error proxying to Django: {:?}", err);
-                    eprintln!("{}", err_mess);
-                    (StatusCode::INTERNAL_SERVER_ERROR, Html(err_mess))
-                }
-            }
-        },
-        Err(err) => {
-            let err_mess = format!("Error proxying to Django: {:?}", err);
-            eprintln!("{}", err_mess);
-            (StatusCode::INTERNAL_SERVER_ERROR, Html(err_mess))
-        }
-    }
+    proxy_to_django(&all_state, "protein_feature_view", &params).await
 }
 
 async fn gocam_viz(Path((full_or_widget, gocam_id)): Path<(String, String)>,
                    State(all_state): State<Arc<AllState>>)
-   -> (StatusCode, Html<String>)
+   -> Response
 {
-    let search_url = all_state.config.server.django_url.to_owned() + "/gocam_viz/";
-
     let params = [("full_or_widget", full_or_widget),
                   ("gocam_id", gocam_id)];
-    let client = reqwest::Client::new();
-    let result = client.get(search_url).query(&params).send().await;
-
-    match result {
-        Ok(res) => {
-            match res.text().await {
-                Ok(text) => (StatusCode::OK, Html(text)),
-                Err(err) => {
-                    let err_mess = format!("Error proxying to Django: {:?}", err);
-                    eprintln!("{}", err_mess);
-                    (StatusCode::INTERNAL_SERVER_ERROR, Html(err_mess))
-                }
-            }
-        },
-        Err(err) => {
-            let err_mess = format!("Error proxying to Django: {:?}", err);
-            eprintln!("{}", err_mess);
-            (StatusCode::INTERNAL_SERVER_ERROR, Html(err_mess))
-        }
-    }
+    proxy_to_django(&all_state, "gocam_viz", &params).await
 }
 
 /*
 Return a simple HTML version a gene page for search engines
 */
 async fn get_simple_gene(Path(id): Path<String>,
+                         DatasetRelease(release): DatasetRelease,
                          State(all_state): State<Arc<AllState>>) -> (StatusCode, Html<String>) {
-    if let Some(gene) = all_state.query_exec.get_api_data().get_full_gene_details(&id) {
+    if let Some(gene) = release.query_exec.get_api_data().get_full_gene_details(&id) {
         (StatusCode::OK, Html(render_simple_gene_page(&all_state.config, &gene)))
     } else {
         (StatusCode::NOT_FOUND, Html(format!("no page for: {}", id)))
@@ -372,8 +447,9 @@ async fn get_simple_gene(Path(id): Path<String>,
 Return a simple HTML version a genotype page for search engines
 */
 async fn get_simple_genotype(Path(id): Path<String>,
+                             DatasetRelease(release): DatasetRelease,
                              State(all_state): State<Arc<AllState>>) -> (StatusCode, Html<String>) {
-    if let Some(genotype) = all_state.query_exec.get_api_data().get_genotype_details(&id) {
+    if let Some(genotype) = release.query_exec.get_api_data().get_genotype_details(&id) {
         (StatusCode::OK, Html(render_simple_genotype_page(&all_state.config, &genotype)))
     } else {
         (StatusCode::NOT_FOUND, Html(format!("no page for: {}", id)))
@@ -384,8 +460,9 @@ async fn get_simple_genotype(Path(id): Path<String>,
 Return a simple HTML version a reference page for search engines
 */
 async fn get_simple_reference(Path(id): Path<String>,
+                              DatasetRelease(release): DatasetRelease,
                               State(all_state): State<Arc<AllState>>) -> (StatusCode, Html<String>) {
-    if let Some(reference) = all_state.query_exec.get_api_data().get_reference_details(&id) {
+    if let Some(reference) = release.query_exec.get_api_data().get_reference_details(&id) {
         (StatusCode::OK, Html(render_simple_reference_page(&all_state.config, &reference)))
     } else {
         (StatusCode::NOT_FOUND, Html(format!("no page for: {}", id)))
@@ -395,25 +472,367 @@ async fn get_simple_reference(Path(id): Path<String>,
 Return a simple HTML version a term page for search engines
 */
 async fn get_simple_term(Path(id): Path<String>,
+                         DatasetRelease(release): DatasetRelease,
                          State(all_state): State<Arc<AllState>>) -> (StatusCode, Html<String>) {
-    if let Some(term) = all_state.query_exec.get_api_data().get_term_details(&id) {
+    if let Some(term) = release.query_exec.get_api_data().get_term_details(&id) {
         (StatusCode::OK, Html(render_simple_term_page(&all_state.config, &term)))
     } else {
         (StatusCode::NOT_FOUND, Html(format!("no page for: {}", id)))
     }
 }
 
-async fn query_post(State(all_state): State<Arc<AllState>>, Json(q): Json<Query>)
+// run a query and return the result, with a QueryInfo for the access log
+// and metrics
+async fn exec_query(release: &Release, query: &Query) -> Response {
+    let start = Instant::now();
+    let result = release.query_exec.exec(query).await;
+
+    let query_info = QueryInfo {
+        id: result.id.to_string(),
+        release_name: release.name.clone(),
+        duration: start.elapsed(),
+    };
+
+    let mut response = Json(result).into_response();
+    response.extensions_mut().insert(query_info);
+    response
+}
+
+async fn query_post(DatasetRelease(release): DatasetRelease, Json(q): Json<Query>)
               -> impl IntoResponse
 {
-    Json(all_state.query_exec.exec(&q).await)
+    exec_query(&release, &q).await
 }
 
-async fn query_get(State(all_state): State<Arc<AllState>>, Path(q): Path<String>)
+async fn query_get(DatasetRelease(release): DatasetRelease, Path((_, q)): Path<(String, String)>)
               -> impl IntoResponse
 {
     match serde_json::from_str::<Query>(&q) {
-        Ok(q) => Ok(Json(all_state.query_exec.exec(&q).await)),
+        Ok(q) => Ok(exec_query(&release, &q).await),
+        Err(err) => Err((StatusCode::BAD_REQUEST, err.to_string()))
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct QueryValidationResponse {
+    status: String,
+    errors: Vec<QueryValidationError>,
+}
+
+// check a query without running it, returning a list of problems
+async fn query_validate(DatasetRelease(release): DatasetRelease, Json(q): Json<Query>)
+              -> impl IntoResponse
+{
+    let errors = q.validate(release.query_exec.get_api_data());
+
+    let status =
+        if errors.is_empty() {
+            "ok"
+        } else {
+            "invalid"
+        };
+
+    Json(QueryValidationResponse {
+        status: status.to_owned(),
+        errors,
+    })
+}
+
+#[derive(Serialize, Debug)]
+struct QueryCacheStatsResponse {
+    status: String,
+    stats: QueryCacheStats,
+}
+
+async fn query_cache_stats(DatasetRelease(release): DatasetRelease)
+              -> impl IntoResponse
+{
+    Json(QueryCacheStatsResponse {
+        status: "ok".to_owned(),
+        stats: release.query_exec.cache_stats(),
+    })
+}
+
+#[derive(Serialize, Debug)]
+struct MapsCacheStatsResponse {
+    status: String,
+    stats: MapsDatabaseCacheStats,
+}
+
+async fn maps_cache_stats(DatasetRelease(release): DatasetRelease)
+              -> impl IntoResponse
+{
+    Json(MapsCacheStatsResponse {
+        status: "ok".to_owned(),
+        stats: release.query_exec.get_api_data().maps_database_cache_stats(),
+    })
+}
+
+#[derive(Deserialize, Debug)]
+struct EnrichmentRequest {
+    // gene uniquenames or names
+    genes: Vec<String>,
+    #[serde(default)]
+    options: EnrichmentOptions,
+}
+
+#[derive(Serialize, Debug)]
+struct EnrichmentResponse {
+    status: String,
+    // IDs from the request that don't match a gene
+    unknown_ids: Vec<String>,
+    enrichment: Option<EnrichmentResult>,
+}
+
+#[derive(Deserialize, Debug)]
+struct IdMappingRequest {
+    // identifiers, names or synonyms, one per gene
+    ids: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct IdMappingResponse {
+    status: String,
+    matched_count: usize,
+    ambiguous_count: usize,
+    unmatched_count: usize,
+    results: Vec<IdMappingResult>,
+}
+
+// map a pasted list of gene identifiers to genes, reporting identifiers
+// that match no gene or more than one gene
+async fn id_mapping_post(DatasetRelease(release): DatasetRelease,
+                         Json(request): Json<IdMappingRequest>)
+              -> Json<IdMappingResponse>
+{
+    let api_data = release.query_exec.get_api_data();
+
+    let results = api_data.map_gene_ids(&request.ids);
+
+    let count = |status| results.iter().filter(|result| result.status == status).count();
+
+    Json(IdMappingResponse {
+        status: "ok".to_owned(),
+        matched_count: count(IdMappingStatus::Matched),
+        ambiguous_count: count(IdMappingStatus::Ambiguous),
+        unmatched_count: count(IdMappingStatus::Unmatched),
+        results,
+    })
+}
+
+// the maximum number of IDs in a request to a batch endpoint
+const MAX_BATCH_IDS: usize = 10000;
+
+// the maximum number of IDs when the response is one JSON object, which is
+// built in memory.  Larger batches need "?format=ndjson".
+const MAX_JSON_BATCH_IDS: usize = 500;
+
+#[derive(Deserialize, Debug)]
+struct BatchRequest {
+    ids: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct BatchParams {
+    // "json" (the default) or "ndjson"
+    format: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct BatchResponse<T: Serialize> {
+    status: String,
+    // the details of each ID that was found
+    found: HashMap<String, T>,
+    missing: Vec<String>,
+}
+
+// one line of a batch response in NDJSON format
+#[derive(Serialize, Debug)]
+struct BatchLine<T: Serialize> {
+    id: String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    data: Option<T>,
+}
+
+// the number of IDs read together when writing a batch response as NDJSON
+const NDJSON_BATCH_CHUNK_SIZE: usize = 100;
+
+// Return the details of a list of IDs, either as a BatchResponse or, with
+// "?format=ndjson", as one BatchLine per ID.  get_details() returns the
+// details of the IDs it finds, reading them from the maps database in one
+// pass.  It's run with spawn_blocking() as building the details of a large
+// batch is slow.  The NDJSON lines are written as each chunk of IDs is read.
+async fn batch_response<T, F>(release: Arc<Release>, params: BatchParams,
+                              request: BatchRequest, get_details: F)
+                              -> Response
+    where T: Serialize + Send + 'static,
+          F: Fn(&APIData, &[String]) -> HashMap<String, T> + Send + Sync + 'static
+{
+    if request.ids.len() > MAX_BATCH_IDS {
+        return (StatusCode::BAD_REQUEST,
+                format!("too many IDs, the maximum is {}", MAX_BATCH_IDS)).into_response();
+    }
+
+    match params.format.as_deref() {
+        None | Some("json") => {
+            if request.ids.len() > MAX_JSON_BATCH_IDS {
+                return (StatusCode::BAD_REQUEST,
+                        format!("too many IDs, the maximum is {} or {} with format=ndjson",
+                                MAX_JSON_BATCH_IDS, MAX_BATCH_IDS)).into_response();
+            }
+
+            let res = tokio::task::spawn_blocking(move || {
+                let found = get_details(release.query_exec.get_api_data(), &request.ids);
+
+                let mut missing = vec![];
+                let mut seen_missing = HashSet::new();
+
+                for id in request.ids {
+                    if !found.contains_key(&id) && seen_missing.insert(id.clone()) {
+                        missing.push(id);
+                    }
+                }
+
+                BatchResponse {
+                    status: "ok".to_owned(),
+                    found,
+                    missing,
+                }
+            }).await;
+
+            match res {
+                Ok(batch_response) => Json(batch_response).into_response(),
+                Err(err) => {
+                    tracing::error!("failed to read batch: {}", err);
+                    (StatusCode::INTERNAL_SERVER_ERROR, "failed to read batch").into_response()
+                }
+            }
+        },
+        Some("ndjson") => {
+            let chunks = request.ids.chunks(NDJSON_BATCH_CHUNK_SIZE)
+                .map(|chunk| chunk.to_vec())
+                .collect::<Vec<_>>();
+
+            let get_details = Arc::new(get_details);
+
+            let lines = futures_util::stream::iter(chunks)
+                .then(move |ids| {
+                    let release = release.clone();
+                    let get_details = get_details.clone();
+
+                    tokio::task::spawn_blocking(move || {
+                        let found = get_details(release.query_exec.get_api_data(), &ids);
+                        let mut lines = String::new();
+                        for id in ids {
+                            let data = found.get(&id);
+                            lines.push_str(&serde_json::to_string(&BatchLine { id, data }).unwrap());
+                            lines.push('\n');
+                        }
+                        lines
+                    })
+                });
+
+            (StatusCode::OK,
+             [(header::CONTENT_TYPE, "application/x-ndjson")],
+             Body::from_stream(lines)).into_response()
+        },
+        Some(format) => {
+            (StatusCode::BAD_REQUEST, format!("unknown format: {}", format)).into_response()
+        },
+    }
+}
+
+async fn batch_genes(DatasetRelease(release): DatasetRelease,
+                     QueryParams(params): QueryParams<BatchParams>,
+                     Json(request): Json<BatchRequest>)
+              -> Response
+{
+    batch_response(release, params, request,
+                   |api_data, ids| api_data.get_full_gene_details_batch(ids)).await
+}
+
+async fn batch_terms(DatasetRelease(release): DatasetRelease,
+                     QueryParams(params): QueryParams<BatchParams>,
+                     Json(request): Json<BatchRequest>)
+              -> Response
+{
+    batch_response(release, params, request,
+                   |api_data, ids| api_data.get_term_details_batch(ids)).await
+}
+
+async fn batch_alleles(DatasetRelease(release): DatasetRelease,
+                       QueryParams(params): QueryParams<BatchParams>,
+                       Json(request): Json<BatchRequest>)
+              -> Response
+{
+    batch_response(release, params, request,
+                   |api_data, ids| api_data.get_allele_details_batch(ids)).await
+}
+
+async fn batch_genotypes(DatasetRelease(release): DatasetRelease,
+                         QueryParams(params): QueryParams<BatchParams>,
+                         Json(request): Json<BatchRequest>)
+              -> Response
+{
+    batch_response(release, params, request,
+                   |api_data, ids| api_data.get_genotype_details_batch(ids)).await
+}
+
+async fn batch_references(DatasetRelease(release): DatasetRelease,
+                          QueryParams(params): QueryParams<BatchParams>,
+                          Json(request): Json<BatchRequest>)
+              -> Response
+{
+    batch_response(release, params, request,
+                   |api_data, ids| api_data.get_reference_details_batch(ids)).await
+}
+
+// find the terms that are over-represented in a list of genes
+async fn enrichment_post(DatasetRelease(release): DatasetRelease,
+                         Json(request): Json<EnrichmentRequest>)
+              -> impl IntoResponse
+{
+    let api_data = release.query_exec.get_api_data();
+
+    let mut genes = vec![];
+    let mut unknown_ids = vec![];
+
+    for id in request.genes {
+        match api_data.gene_uniquename_of_id(&id.to_shared_str()) {
+            Some(gene_uniquename) => genes.push(gene_uniquename),
+            None => unknown_ids.push(id),
+        }
+    }
+
+    match gene_set_enrichment(api_data, &genes, &request.options) {
+        Ok(enrichment) => {
+            Json(EnrichmentResponse {
+                status: "ok".to_owned(),
+                unknown_ids,
+                enrichment: Some(enrichment),
+            })
+        },
+        Err(err) => {
+            Json(EnrichmentResponse {
+                status: err.to_string(),
+                unknown_ids,
+                enrichment: None,
+            })
+        }
+    }
+}
+
+// run a query written in the text syntax, eg.
+//   term(GO:0005634) AND NOT subset(SPAC*) AND protein_length(100..500)
+async fn text_query_get(DatasetRelease(release): DatasetRelease, Path((_, q)): Path<(String, String)>)
+              -> impl IntoResponse
+{
+    match parse_query_text(&q) {
+        Ok(mut constraints) => {
+            constraints.fill_names(release.query_exec.get_api_data());
+            let query = Query::new(constraints, QueryOutputOptions::default());
+            Ok(exec_query(&release, &query).await)
+        },
         Err(err) => Err((StatusCode::BAD_REQUEST, err.to_string()))
     }
 }
@@ -436,6 +855,17 @@ struct AlleleCompletionResponse {
     matches: Vec<SolrAlleleSummary>,
 }
 
+#[derive(Serialize, Debug)]
+struct GeneCompletionResponse {
+    status: String,
+    matches: Vec<GeneCompletionMatch>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GeneCompletionParams {
+    taxonid: Option<u32>,
+}
+
 #[derive(Serialize, Debug)]
 struct SolrSearchResponse  {
     status: String,
@@ -444,9 +874,9 @@ struct SolrSearchResponse  {
     doc_matches: Vec<DocSearchMatch>,
 }
 
-async fn term_complete(Path((cv_name, q)): Path<(String, String)>,
+async fn term_complete(Path((_, cv_name, q)): Path<(String, String, String)>, _: LatestRelease,
                        State(all_state): State<Arc<AllState>>)
-              -> Json<TermCompletionResponse>
+              -> Response
 {
     let res = all_state.search.term_complete(&cv_name, &q).await;
 
@@ -459,19 +889,20 @@ async fn term_complete(Path((cv_name, q)): Path<(String, String)>,
                 }
             },
             Err(err) => {
-                println!("{:?}", err);
-                TermCompletionResponse {
+                tracing::error!("term completion failed: {:?}", err);
+                return upstream_error_response("search", Json(TermCompletionResponse {
                     status: "Error".to_owned(),
                     matches: vec![],
-                }
+                }));
             },
         };
 
-    Json(completion_response)
+    Json(completion_response).into_response()
 }
 
-async fn ref_complete(Path(q): Path<String>, State(all_state): State<Arc<AllState>>)
-                -> Json<RefCompletionResponse>
+async fn ref_complete(Path((_, q)): Path<(String, String)>, _: LatestRelease,
+                      State(all_state): State<Arc<AllState>>)
+                -> Response
 {
     let res = all_state.search.ref_complete(&q).await;
 
@@ -484,19 +915,20 @@ async fn ref_complete(Path(q): Path<String>, State(all_state): State<Arc<AllStat
                 }
             },
             Err(err) => {
-                println!("{:?}", err);
-                RefCompletionResponse {
+                tracing::error!("reference completion failed: {:?}", err);
+                return upstream_error_response("search", Json(RefCompletionResponse {
                     status: "Error".to_owned(),
                     matches: vec![],
-                }
+                }));
             },
         };
 
-    Json(completion_response)
+    Json(completion_response).into_response()
 }
 
-async fn allele_complete(Path(q): Path<String>, State(all_state): State<Arc<AllState>>)
-                -> Json<AlleleCompletionResponse>
+async fn allele_complete(Path((_, q)): Path<(String, String)>, _: LatestRelease,
+                         State(all_state): State<Arc<AllState>>)
+                -> Response
 {
     let res = all_state.search.allele_complete(&q).await;
 
@@ -509,20 +941,46 @@ async fn allele_complete(Path(q): Path<String>, State(all_state): State<Arc<AllS
                 }
             },
             Err(err) => {
-                println!("{:?}", err);
-                AlleleCompletionResponse {
+                tracing::error!("allele completion failed: {:?}", err);
+                return upstream_error_response("search", Json(AlleleCompletionResponse {
                     status: "Error".to_owned(),
                     matches: vec![],
-                }
+                }));
             },
         };
 
-    Json(completion_response)
+    Json(completion_response).into_response()
+}
+
+// complete gene IDs, names, synonyms, UniProt accessions and secondary IDs,
+// optionally restricted to one of the configured organisms with
+// "?taxonid=..."
+async fn gene_complete(Path((_, q)): Path<(String, String)>, QueryParams(params): QueryParams<GeneCompletionParams>,
+                       DatasetRelease(release): DatasetRelease, State(all_state): State<Arc<AllState>>)
+                -> Json<GeneCompletionResponse>
+{
+    if let Some(taxonid) = params.taxonid {
+        if !all_state.config.organisms.iter().any(|organism| organism.taxonid == taxonid) {
+            tracing::warn!("gene completion error, unknown taxon ID: {}", taxonid);
+            return Json(GeneCompletionResponse {
+                status: "Error".to_owned(),
+                matches: vec![],
+            });
+        }
+    }
+
+    let api_data = release.query_exec.get_api_data();
+
+    Json(GeneCompletionResponse {
+        status: "Ok".to_owned(),
+        matches: api_data.gene_complete(&q, params.taxonid, MAX_GENE_COMPLETIONS),
+    })
 }
 
 // search for terms, refs or docs that match the query
-async fn solr_search(Path((scope, q)): Path<(String, String)>, State(all_state): State<Arc<AllState>>)
-    -> Json<SolrSearchResponse>
+async fn solr_search(Path((_, scope, q)): Path<(String, String, String)>, _: LatestRelease,
+                     State(all_state): State<Arc<AllState>>)
+    -> Response
 {
     if let Some(parsed_scope) = SolrSearchScope::new_from_str(&scope) {
         let search_result = all_state.search.solr_search(&parsed_scope, &q).await;
@@ -534,15 +992,16 @@ async fn solr_search(Path((scope, q)): Path<(String, String)>, State(all_state):
                     term_matches: search_all_result.term_matches,
                     ref_matches: search_all_result.ref_matches,
                     doc_matches: search_all_result.doc_matches,
-                })
+                }).into_response()
             },
             Err(err) => {
-                Json(SolrSearchResponse {
+                tracing::error!("search failed: {:?}", err);
+                upstream_error_response("search", Json(SolrSearchResponse {
                     status: err.to_string(),
                     term_matches: vec![],
                     ref_matches: vec![],
                     doc_matches: vec![],
-                })
+                }))
             },
         }
     } else {
@@ -551,35 +1010,33 @@ async fn solr_search(Path((scope, q)): Path<(String, String)>, State(all_state):
             term_matches: vec![],
             ref_matches: vec![],
             doc_matches: vec![],
-        })
+        }).into_response()
     }
 }
 
-async fn motif_search(Path((scope, q, max_gene_details)): Path<(String, String, String)>, State(all_state): State<Arc<AllState>>)
-                -> Result<(HeaderMap, String), StatusCode>
+// search protein sequences for a PROSITE style or regular expression pattern
+async fn motif_search_handler(Path((_, scope, q, max_gene_details)): Path<(String, String, String, String)>, DatasetRelease(release): DatasetRelease)
+                -> Result<Json<MotifSearchResult>, StatusCode>
 {
-    let res = all_state.search.motif_search(&scope, &q, &max_gene_details).await;
+    let Ok(max_gene_details) = max_gene_details.parse::<usize>()
+    else {
+        tracing::warn!("Motif search error: can't parse max_gene_details: {}", max_gene_details);
+        return Err(StatusCode::BAD_REQUEST);
+    };
 
-    match res {
-        Ok(search_result) => {
-            let mut headers = HeaderMap::new();
-            headers.insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
-            Ok((headers, search_result))
+    let api_data = release.query_exec.get_api_data();
 
-        },
-        Err(err) => {
-            println!("Motif search error: {:?}", err);
-            Err(StatusCode::NOT_FOUND)
-        },
-    }
+    Ok(Json(motif_search(api_data, &scope, &q, max_gene_details,
+                         DEFAULT_MAX_MATCHES_PER_GENE)))
 }
 
 
-async fn gene_ex_violin_plot(Path((plot_size, genes)): Path<(String, String)>,
-                             State(all_state): State<Arc<AllState>>)
+async fn gene_ex_violin_plot(Path((_, plot_size, genes)): Path<(String, String, String)>,
+                             DatasetRelease(release): DatasetRelease)
              -> impl IntoResponse
 {
-    let res = all_state.stats_plots.gene_ex_violin_plot(&plot_size, &genes).await;
+    let api_data = release.query_exec.get_api_data();
+    let res = release.stats_plots.gene_ex_violin_plot(api_data, &plot_size, &genes);
 
     match res {
         Ok(svg_plot) => {
@@ -587,18 +1044,22 @@ async fn gene_ex_violin_plot(Path((plot_size, genes)): Path<(String, String)>,
             [(header::CONTENT_TYPE, "image/svg+xml")],
             Body::from(svg_plot.bytes)).into_response()
         },
+        // the errors are from the arguments, eg. an unknown plot size or gene
         Err(err) => {
-            (StatusCode::INTERNAL_SERVER_ERROR,
+            tracing::error!("Gene expression plot error: {:?}", err);
+            (StatusCode::BAD_REQUEST,
              [(header::CONTENT_TYPE, "text/plain")],
-             println!("Gene expression plot error: {:?}", err)).into_response()
+             err.to_string()).into_response()
         },
     }
 }
 
-async fn get_stats(Path(graph_type): Path<String>,
-                   State(all_state): State<Arc<AllState>>)
+async fn get_stats(Path((_, graph_type)): Path<(String, String)>,
+                   DatasetRelease(release): DatasetRelease)
           -> impl IntoResponse
 {
+    let api_data = release.query_exec.get_api_data();
+
     let res = match graph_type.as_ref() {
         "curated_by_year" |
         "curatable_by_year" |
@@ -608,7 +1069,7 @@ async fn get_stats(Path(graph_type): Path<String>,
         "htp_annotations_per_pub_per_year_range" |
         "community_response_rates" |
         "cumulative_annotation_type_counts_by_year"
-            => all_state.stats_plots.get_svg_graph(graph_type.as_ref()).await,
+            => release.stats_plots.get_svg_graph(api_data, graph_type.as_ref()),
         _ => return StatusCode::NOT_FOUND.into_response(),
     };
 
@@ -618,13 +1079,40 @@ async fn get_stats(Path(graph_type): Path<String>,
              [(header::CONTENT_TYPE, "image/svg+xml")],
               Body::from(svg_plot.bytes)).into_response()
         },
+        // eg. "unknown graph type"
         Err(err) => {
-            println!("Error getting stats graph: {:?}", err);
-            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            tracing::error!("Error getting stats graph: {:?}", err);
+            (StatusCode::BAD_REQUEST,
+             [(header::CONTENT_TYPE, "text/plain")],
+             err.to_string()).into_response()
         },
     }
 }
 
+#[derive(Serialize, Debug)]
+struct ReleasesResponse {
+    status: String,
+    latest: String,
+    releases: Vec<String>,
+}
+
+// the names of the releases that can be used in "/api/v1/dataset/<release>/"
+async fn get_releases(State(all_state): State<Arc<AllState>>) -> Json<ReleasesResponse> {
+    let mut releases = all_state.releases.keys().cloned().collect::<Vec<_>>();
+    releases.sort();
+
+    Json(ReleasesResponse {
+        status: "ok".to_owned(),
+        latest: all_state.latest_release.clone(),
+        releases,
+    })
+}
+
+// the OpenAPI description of the routes under /api
+async fn get_openapi() -> Json<Value> {
+    Json(openapi_spec(VERSION))
+}
+
 async fn ping() -> String {
     String::from("OK") + " " + PKG_NAME + " " + VERSION
 }
@@ -636,6 +1124,458 @@ async fn not_found() -> Json<Value> {
     }).into()
 }
 
+struct ReleaseFiles {
+    name: String,
+    search_maps_filename: String,
+    api_maps_database_path: String,
+    web_root_dir: String,
+}
+
+// parse a --release argument: NAME:MAPS_JSON_FILE:API_MAPS_DATABASE:WEB_ROOT_DIR
+fn parse_release_arg(arg: &str) -> Result<ReleaseFiles, String> {
+    let parts = arg.split(':').collect::<Vec<_>>();
+
+    let [name, search_maps_filename, api_maps_database_path, web_root_dir] = parts[..]
+    else {
+        return Err(format!("--release needs NAME:MAPS_JSON_FILE:API_MAPS_DATABASE:WEB_ROOT_DIR, not: {}",
+                           arg));
+    };
+
+    if name.is_empty() || name == LATEST_RELEASE || name.contains('/') {
+        return Err(format!("invalid release name: \"{}\"", name));
+    }
+
+    Ok(ReleaseFiles {
+        name: name.to_owned(),
+        search_maps_filename: search_maps_filename.to_owned(),
+        api_maps_database_path: api_maps_database_path.to_owned(),
+        web_root_dir: web_root_dir.to_owned(),
+    })
+}
+
+fn read_release(config: &Config, release_files: &ReleaseFiles, site_db: Option<SiteDB>)
+    -> Result<Release, anyhow::Error>
+{
+    tracing::info!("Reading data files of release {} ...", release_files.name);
+
+    let api_maps = read_api_maps(&release_files.search_maps_filename)?;
+    let api_maps_database_conn = Connection::open(&release_files.api_maps_database_path)
+        .map_err(|err| anyhow::anyhow!("failed to open {}: {}",
+                                       release_files.api_maps_database_path, err))?;
+    let api_data = APIData::new(config, api_maps_database_conn, api_maps);
+
+    Ok(Release {
+        name: release_files.name.clone(),
+        query_exec: QueryExec::new(api_data, site_db),
+        stats_plots: StatsPlots::new(config),
+        static_file_state: StaticFileState {
+            web_root_dir: release_files.web_root_dir.clone(),
+        },
+    })
+}
+
+// The command line settings, kept so that the data files can be read
+// again by a reload
+struct ServerArgs {
+    config_file_name: String,
+    release_files_list: Vec<ReleaseFiles>,
+    latest_release: String,
+    site_db: Option<SiteDB>,
+}
+
+// Read the data files of every release.  This is slow so it's run with
+// spawn_blocking() when reloading.
+fn read_all_state(server_args: &ServerArgs, config: Config) -> Result<AllState, anyhow::Error> {
+    let mut releases = HashMap::new();
+
+    for release_files in &server_args.release_files_list {
+        // saved queries are shared by all releases
+        let release = read_release(&config, release_files, server_args.site_db.clone())?;
+        releases.insert(release_files.name.clone(), Arc::new(release));
+    }
+
+    let search = Search::new(&config)?;
+
+    Ok(AllState {
+        releases,
+        latest_release: server_args.latest_release.clone(),
+        search,
+        config,
+        loaded_at: Local::now().to_rfc3339(),
+    })
+}
+
+// Declares a function that adds the routes to a Router, and a list of the
+// methods and paths so they can be checked against the OpenAPI description
+macro_rules! routes {
+    ($routes_name:ident, $add_routes_name:ident, $state:ty,
+     $(($method:ident, $path:literal, $handler:ident)),* $(,)?) => {
+        #[cfg_attr(not(test), allow(dead_code))]
+        const $routes_name: &[(&str, &str)] = &[$((stringify!($method), $path)),*];
+
+        fn $add_routes_name(router: Router<$state>) -> Router<$state> {
+            router$(.route($path, $method($handler)))*
+        }
+    };
+}
+
+routes!(DATA_ROUTES, add_data_routes, Arc<AllState>,
+    (get, "/*path", get_misc),
+    (get, "/", get_index),
+    (get, "/structure_view/:structure_type/:id", structure_view),
+    (get, "/rna_2d_structure/:gene_uniquename/:urs_id", rna_2d_structure),
+    (get, "/protein_feature_view/:full_or_widget/:gene_uniquename", protein_feature_view),
+    (get, "/gocam_viz/:full_or_widget/:gocam_id", gocam_viz),
+    (get, "/simple/gene/:id", get_simple_gene),
+    (get, "/simple/genotype/:id", get_simple_genotype),
+    (get, "/simple/reference/:id", get_simple_reference),
+    (get, "/simple/term/:id", get_simple_term),
+    (get, "/api/v1/dataset/:release/complete/allele/*q", allele_complete),
+    (get, "/api/v1/dataset/:release/complete/ref/:q", ref_complete),
+    (get, "/api/v1/dataset/:release/complete/gene/:q", gene_complete),
+    (get, "/api/v1/dataset/:release/complete/term/:cv_name/:q", term_complete),
+    (get, "/api/v1/dataset/:release/data/allele/:id", get_allele),
+    (get, "/api/v1/dataset/:release/data/gene/:id", get_gene),
+    (get, "/api/v1/dataset/:release/data/genotype/:id", get_genotype),
+    (get, "/api/v1/dataset/:release/data/reference/:id", get_reference),
+    (get, "/api/v1/dataset/:release/data/seq_feature_page_features", seq_feature_page_features),
+    (get, "/api/v1/dataset/:release/data/term/:id", get_term),
+    (post, "/api/v1/dataset/:release/batch/gene", batch_genes),
+    (post, "/api/v1/dataset/:release/batch/term", batch_terms),
+    (post, "/api/v1/dataset/:release/batch/allele", batch_alleles),
+    (post, "/api/v1/dataset/:release/batch/genotype", batch_genotypes),
+    (post, "/api/v1/dataset/:release/batch/reference", batch_references),
+    (get, "/api/v1/dataset/:release/data/gocam/:full_or_widget/:gene_uniquename", get_gocam_data),
+    (get, "/api/v1/dataset/:release/data/gocam/all", get_all_gocam_data),
+    (get, "/api/v1/dataset/:release/data/gocam/by_id/:gocam_id", get_all_gocam_data_by_id),
+    (get, "/api/v1/dataset/:release/gene_ex_violin_plot/:plot_size/:genes", gene_ex_violin_plot),
+    (get, "/api/v1/dataset/:release/stats/:type", get_stats),
+    (get, "/api/v1/dataset/:release/motif_search/:scope/:q/:max_gene_details", motif_search_handler),
+    (get, "/api/v1/dataset/:release/protein_features/:full_or_widget/:gene_uniquename", get_protein_features),
+    (get, "/api/v1/dataset/:release/query/:q", query_get),
+    (post, "/api/v1/dataset/:release/query", query_post),
+    (post, "/api/v1/dataset/:release/query/validate", query_validate),
+    (get, "/api/v1/dataset/:release/query_cache/stats", query_cache_stats),
+    (get, "/api/v1/dataset/:release/maps_cache/stats", maps_cache_stats),
+    (post, "/api/v1/dataset/:release/enrichment", enrichment_post),
+    (post, "/api/v1/dataset/:release/id_mapping", id_mapping_post),
+    (get, "/api/v1/dataset/:release/text_query/:q", text_query_get),
+    (get, "/api/v1/dataset/:release/search/:scope/:q", solr_search),
+    (get, "/api/v1/dataset/:release/summary/term/:id", get_term_summary_by_id),
+    (get, "/api/v1/releases", get_releases),
+    (get, "/api/v1/openapi.json", get_openapi),
+    (get, "/releases/:release", get_index),
+    (get, "/releases/:release/*path", get_misc),
+    (get, "/ping", ping),
+);
+
+routes!(SERVER_ROUTES, add_server_routes, Arc<ServerState>,
+    (post, "/admin/reload", admin_reload),
+    (post, "/admin/dataset/:release/query_cache/clear", admin_query_cache_clear),
+    (get, "/api/v1/status", server_status),
+    (get, "/metrics", get_metrics),
+);
+
+// the outer Router can't see which route of the current data Router
+// matched so pass it back in the response
+async fn add_route_path(matched_path: Option<MatchedPath>, request: Request, next: Next)
+    -> Response
+{
+    let mut response = next.run(request).await;
+
+    if let Some(matched_path) = matched_path {
+        response.extensions_mut().insert(RoutePath(matched_path.as_str().to_owned()));
+    }
+
+    response
+}
+
+fn make_router(all_state: Arc<AllState>) -> Router {
+    add_data_routes(Router::new())
+        .fallback(not_found)
+        .layer(middleware::from_fn(add_route_path))
+        .with_state(all_state)
+}
+
+struct CurrentApp {
+    router: Router,
+    all_state: Arc<AllState>,
+}
+
+// Requests are passed to a clone of the current Router.  A reload reads
+// the data into a new AllState in the background and then replaces the
+// Router, so requests that have already started finish using the old
+// data.
+struct ServerState {
+    current: RwLock<CurrentApp>,
+    server_args: ServerArgs,
+    // from POMBASE_SERVER_ADMIN_TOKEN - if not set, /admin/reload is disabled
+    admin_token: Option<String>,
+    reload_in_progress: AtomicBool,
+    last_reload_error: RwLock<Option<String>>,
+    // request, query and upstream error counts since the server started
+    metrics: ServerMetrics,
+}
+
+impl ServerState {
+    fn current_router(&self) -> Router {
+        self.current.read().unwrap().router.clone()
+    }
+
+    fn current_all_state(&self) -> Arc<AllState> {
+        self.current.read().unwrap().all_state.clone()
+    }
+
+    fn set_all_state(&self, all_state: AllState) {
+        let all_state = Arc::new(all_state);
+        let router = make_router(all_state.clone());
+
+        *self.current.write().unwrap() = CurrentApp {
+            router,
+            all_state,
+        };
+    }
+
+    fn is_admin_request(&self, headers: &HeaderMap) -> bool {
+        let Some(ref admin_token) = self.admin_token
+        else {
+            return false;
+        };
+
+        let Some(request_token) = headers.get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+        else {
+            return false;
+        };
+
+        // compare every byte so the time taken doesn't depend on how much
+        // of the token matches
+        request_token.len() == admin_token.len() &&
+            request_token.bytes().zip(admin_token.bytes())
+                .fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+    }
+}
+
+async fn reload_all_state(server_state: Arc<ServerState>) {
+    tracing::info!("Reloading data files ...");
+
+    let reload_state = server_state.clone();
+    let res = tokio::task::spawn_blocking(move || {
+        let server_args = &reload_state.server_args;
+        read_all_state(server_args, Config::read(&server_args.config_file_name))
+    }).await;
+
+    let error =
+        match res {
+            Ok(Ok(all_state)) => {
+                server_state.set_all_state(all_state);
+                tracing::info!("Reload finished");
+                None
+            },
+            Ok(Err(err)) => Some(err.to_string()),
+            // Config::read() panics on errors
+            Err(err) => Some(format!("reading data files failed: {}", err)),
+        };
+
+    if let Some(ref error) = error {
+        tracing::error!("Reload failed, still using the old data: {}", error);
+    }
+
+    *server_state.last_reload_error.write().unwrap() = error;
+    server_state.reload_in_progress.store(false, Ordering::SeqCst);
+}
+
+#[derive(Serialize, Debug)]
+struct ReloadResponse {
+    status: String,
+}
+
+// start reading the data files again, authenticated with an
+// "Authorization: Bearer <POMBASE_SERVER_ADMIN_TOKEN>" header
+async fn admin_reload(State(server_state): State<Arc<ServerState>>, headers: HeaderMap)
+    -> (StatusCode, Json<ReloadResponse>)
+{
+    if !server_state.is_admin_request(&headers) {
+        return (StatusCode::FORBIDDEN,
+                Json(ReloadResponse {
+                    status: "Error: not authorised".to_owned(),
+                }));
+    }
+
+    if server_state.reload_in_progress.swap(true, Ordering::SeqCst) {
+        return (StatusCode::CONFLICT,
+                Json(ReloadResponse {
+                    status: "Error: a reload is already in progress".to_owned(),
+                }));
+    }
+
+    tokio::spawn(reload_all_state(server_state));
+
+    (StatusCode::ACCEPTED,
+     Json(ReloadResponse {
+         status: "reloading".to_owned(),
+     }))
+}
+
+// empty the query cache of a release, authenticated like /admin/reload
+async fn admin_query_cache_clear(Path(release_name): Path<String>,
+                                 State(server_state): State<Arc<ServerState>>,
+                                 headers: HeaderMap)
+    -> Response
+{
+    if !server_state.is_admin_request(&headers) {
+        return (StatusCode::FORBIDDEN,
+                Json(ReloadResponse {
+                    status: "Error: not authorised".to_owned(),
+                })).into_response();
+    }
+
+    let all_state = server_state.current_all_state();
+
+    let Some(release) = all_state.release(&release_name)
+    else {
+        return (StatusCode::NOT_FOUND, format!("no such release: {}", release_name)).into_response();
+    };
+
+    release.query_exec.clear_cache();
+
+    Json(QueryCacheStatsResponse {
+        status: "ok".to_owned(),
+        stats: release.query_exec.cache_stats(),
+    }).into_response()
+}
+
+#[derive(Serialize, Debug)]
+struct ReleaseStatus {
+    name: String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    db_creation_datetime: Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    date_version: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct ServerStatusResponse {
+    status: String,
+    version: String,
+    // when the current data was read
+    loaded_at: String,
+    latest_release: String,
+    releases: Vec<ReleaseStatus>,
+    reload_in_progress: bool,
+    #[serde(skip_serializing_if="Option::is_none")]
+    last_reload_error: Option<String>,
+}
+
+async fn server_status(State(server_state): State<Arc<ServerState>>)
+    -> Json<ServerStatusResponse>
+{
+    let all_state = server_state.current_all_state();
+
+    let mut releases = all_state.releases.iter()
+        .map(|(name, release)| {
+            let metadata = release.query_exec.get_api_data().get_maps().metadata.as_ref();
+            ReleaseStatus {
+                name: name.clone(),
+                db_creation_datetime: metadata.map(|metadata| metadata.db_creation_datetime.to_string()),
+                date_version: metadata.map(|metadata| metadata.date_version.to_string()),
+            }
+        })
+        .collect::<Vec<_>>();
+    releases.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Json(ServerStatusResponse {
+        status: "ok".to_owned(),
+        version: VERSION.to_owned(),
+        loaded_at: all_state.loaded_at.clone(),
+        latest_release: all_state.latest_release.clone(),
+        releases,
+        reload_in_progress: server_state.reload_in_progress.load(Ordering::SeqCst),
+        last_reload_error: server_state.last_reload_error.read().unwrap().clone(),
+    })
+}
+
+// Write the access log and update the metrics.  The latency of a
+// streamed response, eg. a batch request in NDJSON format, is the time
+// until the response headers are ready.
+async fn track_request(State(server_state): State<Arc<ServerState>>,
+                       matched_path: Option<MatchedPath>, request: Request, next: Next)
+    -> Response
+{
+    let start = Instant::now();
+    let method = request.method().clone();
+    let path = request.uri().path().to_owned();
+
+    let mut response = next.run(request).await;
+
+    let latency = start.elapsed();
+    let status = response.status().as_u16();
+
+    let route = response.extensions_mut().remove::<RoutePath>()
+        .map(|RoutePath(route)| route)
+        .or_else(|| matched_path.map(|matched_path| matched_path.as_str().to_owned()))
+        .unwrap_or_else(|| "unmatched".to_owned());
+
+    let query_info = response.extensions().get::<QueryInfo>();
+    let upstream_error = response.extensions().get::<UpstreamError>();
+
+    let all_state = server_state.current_all_state();
+    let monitoring_config = &all_state.config.server.monitoring;
+
+    if monitoring_config.metrics_enabled {
+        let metrics = &server_state.metrics;
+
+        metrics.record_request(method.as_str(), &route, status, latency);
+
+        if let Some(query_info) = query_info {
+            metrics.record_query(&query_info.release_name, query_info.duration);
+        }
+
+        if let Some(UpstreamError(upstream)) = upstream_error {
+            metrics.record_upstream_error(upstream);
+        }
+    }
+
+    if monitoring_config.access_log {
+        tracing::info!(target: "access",
+                       method = method.as_str(),
+                       path,
+                       route,
+                       status,
+                       latency_ms = latency.as_secs_f64() * 1000.0,
+                       query_id = query_info.map(|query_info| query_info.id.as_str()),
+                       upstream_error = upstream_error.map(|UpstreamError(upstream)| *upstream));
+    }
+
+    response
+}
+
+// request counts and latencies, query times, cache statistics and
+// upstream errors in the Prometheus text format
+async fn get_metrics(State(server_state): State<Arc<ServerState>>) -> Response {
+    let all_state = server_state.current_all_state();
+
+    if !all_state.config.server.monitoring.metrics_enabled {
+        return (StatusCode::NOT_FOUND, "metrics are disabled").into_response();
+    }
+
+    let mut release_cache_stats = all_state.releases.values()
+        .map(|release| {
+            ReleaseCacheStats {
+                release_name: release.name.clone(),
+                maps_cache: release.query_exec.get_api_data().maps_database_cache_stats(),
+                query_cache: release.query_exec.cache_stats(),
+            }
+        })
+        .collect::<Vec<_>>();
+    release_cache_stats.sort_by(|a, b| a.release_name.cmp(&b.release_name));
+
+    (StatusCode::OK,
+     [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+     server_state.metrics.render(&release_cache_stats)).into_response()
+}
+
 fn print_usage(program: &str, opts: Options) {
     let brief = format!("Usage: {} [options]", program);
     print!("{}", opts.usage(&brief));
@@ -651,9 +1591,16 @@ async fn main() {
     opts.optflag("h", "help", "print this help message");
     opts.optopt("c", "config-file", "Configuration file name", "CONFIG");
     opts.optopt("b", "bind-address-and-port", "The address:port to bind to", "BIND_ADDRESS_AND_PORT");
-    opts.optopt("m", "search-maps", "Search data", "MAPS_JSON_FILE");
+    opts.optopt("m", "search-maps", "Search data, in JSON or binary format", "MAPS_FILE");
     opts.optopt("d", "api-maps-database", "SQLite3 database of API maps", "API_MAPS_DATABASE");
     opts.optopt("w", "web-root-dir", "Root web data directory", "WEB_ROOT_DIR");
+    opts.optopt("n", "release-name",
+                "Name of the release read with -m, -d and -w (default: \"latest\")", "NAME");
+    opts.optmulti("r", "release", "An extra release to serve at /api/v1/dataset/NAME/",
+                  "NAME:MAPS_JSON_FILE:API_MAPS_DATABASE:WEB_ROOT_DIR");
+    opts.optopt("l", "latest-release",
+                "The release to use for /api/v1/dataset/latest/ (default: the -m/-d/-w release, or the first --release)",
+                "NAME");
     opts.optopt("s", "site-db", "Connection string for the site local databae", "SITE_DB");
 
     let matches = match opts.parse(&args[1..]) {
@@ -672,22 +1619,70 @@ async fn main() {
         print_usage(&program, opts);
         process::exit(1);
     }
-    if !matches.opt_present("search-maps") {
-        println!("no --search-maps|-m option");
-        print_usage(&program, opts);
-        process::exit(1);
+
+    let mut release_files_list = vec![];
+
+    let main_release_opts = ["search-maps", "api-maps-database", "web-root-dir"];
+
+    if main_release_opts.iter().any(|opt| matches.opt_present(opt)) {
+        if !matches.opt_present("search-maps") {
+            println!("no --search-maps|-m option");
+            print_usage(&program, opts);
+            process::exit(1);
+        }
+        if !matches.opt_present("api-maps-database") {
+            println!("no --api-maps-database|-d option");
+            print_usage(&program, opts);
+            process::exit(1);
+        }
+        if !matches.opt_present("web-root-dir") {
+            println!("no --web-root-dir|-w option");
+            print_usage(&program, opts);
+            process::exit(1);
+        }
+
+        release_files_list.push(ReleaseFiles {
+            name: matches.opt_str("n").unwrap_or_else(|| LATEST_RELEASE.to_owned()),
+            search_maps_filename: matches.opt_str("m").unwrap(),
+            api_maps_database_path: matches.opt_str("d").unwrap(),
+            web_root_dir: matches.opt_str("w").unwrap(),
+        });
     }
-    if !matches.opt_present("api-maps-database") {
-        println!("no --api-maps-database|-d option");
+
+    for release_arg in matches.opt_strs("release") {
+        match parse_release_arg(&release_arg) {
+            Ok(release_files) => release_files_list.push(release_files),
+            Err(err) => {
+                println!("{}", err);
+                print_usage(&program, opts);
+                process::exit(1);
+            }
+        }
+    }
+
+    if release_files_list.is_empty() {
+        println!("no release: needs -m, -d and -w or at least one --release|-r option");
         print_usage(&program, opts);
         process::exit(1);
     }
-    if !matches.opt_present("web-root-dir") {
-        println!("no --web-root-dir|-w option");
-        print_usage(&program, opts);
+
+    let latest_release = matches.opt_str("latest-release")
+        .unwrap_or_else(|| release_files_list[0].name.clone());
+
+    if !release_files_list.iter().any(|release_files| release_files.name == latest_release) {
+        println!("--latest-release|-l: no release named {}", latest_release);
         process::exit(1);
     }
 
+    let mut release_names = HashSet::new();
+
+    for release_files in &release_files_list {
+        if !release_names.insert(&release_files.name) {
+            println!("release {} given twice", release_files.name);
+            process::exit(1);
+        }
+    }
+
     let bind_address_and_port = matches.opt_str("bind-address-and-port");
     let listener =
         if let Some(bind_address_and_port) = bind_address_and_port {
@@ -697,10 +1692,13 @@ async fn main() {
         };
 
     let site_db_conn_string = matches.opt_str("s");
-    let api_maps_database_path = matches.opt_str("d").unwrap();
 
-    let search_maps_filename = matches.opt_str("m").unwrap();
-    println!("Reading data files ...");
+    let config_file_name = matches.opt_str("c").unwrap();
+    let config = Config::read(&config_file_name);
+
+    init_logging(&config.server.monitoring);
+
+    tracing::info!("Reading data files ...");
 
     let site_db =
         if let Some(conn_str) = site_db_conn_string {
@@ -712,72 +1710,87 @@ async fn main() {
             None
         };
 
-    let config_file_name = matches.opt_str("c").unwrap();
-    let config = Config::read(&config_file_name);
-    let api_maps = api_maps_from_file(&search_maps_filename);
-    let api_maps_database_conn = Connection::open(api_maps_database_path).unwrap();
-    let api_data = APIData::new(&config, api_maps_database_conn, api_maps);
+    let server_args = ServerArgs {
+        config_file_name,
+        release_files_list,
+        latest_release,
+        site_db,
+    };
 
-    let query_exec = QueryExec::new(api_data, site_db);
-    let search = Search::new(&config);
-    let stats_plots = StatsPlots::new(&config);
+    let metrics = ServerMetrics::new(&config.server.monitoring.latency_buckets);
 
-    let web_root_dir = matches.opt_str("w").unwrap();
-    let static_file_state = StaticFileState {
-        web_root_dir: web_root_dir.clone(),
-    };
+    let all_state =
+        match read_all_state(&server_args, config) {
+            Ok(all_state) => Arc::new(all_state),
+            Err(err) => {
+                tracing::error!("{}", err);
+                process::exit(1);
+            }
+        };
 
-    let all_state = AllState {
-        query_exec,
-        search,
-        stats_plots,
-        static_file_state,
-        config,
-    };
+    let admin_token = env::var("POMBASE_SERVER_ADMIN_TOKEN").ok()
+        .filter(|token| !token.is_empty());
 
-    println!("Starting server ...");
-    let app = Router::new()
-        .route("/*path", get(get_misc))
-        .route("/", get(get_index))
-        .route("/structure_view/:structure_type/:id", get(structure_view))
-        .route("/rna_2d_structure/:gene_uniquename/:urs_id", get(rna_2d_structure))
-        .route("/protein_feature_view/:full_or_widget/:gene_uniquename", get(protein_feature_view))
-        .route("/gocam_viz/:full_or_widget/:gocam_id", get(gocam_viz))
-        .route("/simple/gene/:id", get(get_simple_gene))
-        .route("/simple/genotype/:id", get(get_simple_genotype))
-        .route("/simple/reference/:id", get(get_simple_reference))
-        .route("/simple/term/:id", get(get_simple_term))
-        .route("/api/v1/dataset/latest/complete/allele/*q", get(allele_complete))
-        .route("/api/v1/dataset/latest/complete/ref/:q", get(ref_complete))
-        .route("/api/v1/dataset/latest/complete/term/:cv_name/:q", get(term_complete))
-        .route("/api/v1/dataset/latest/data/allele/:id", get(get_allele))
-        .route("/api/v1/dataset/latest/data/gene/:id", get(get_gene))
-        .route("/api/v1/dataset/latest/data/genotype/:id", get(get_genotype))
-        .route("/api/v1/dataset/latest/data/reference/:id", get(get_reference))
-        .route("/api/v1/dataset/latest/data/seq_feature_page_features", get(seq_feature_page_features))
-        .route("/api/v1/dataset/latest/data/term/:id", get(get_term))
-        .route("/api/v1/dataset/latest/data/gocam/:full_or_widget/:gene_uniquename", get(get_gocam_data))
-        .route("/api/v1/dataset/latest/data/gocam/all", get(get_all_gocam_data))
-        .route("/api/v1/dataset/latest/data/gocam/by_id/:gocam_id", get(get_all_gocam_data_by_id))
-        .route("/api/v1/dataset/latest/gene_ex_violin_plot/:plot_size/:genes", get(gene_ex_violin_plot))
-        .route("/api/v1/dataset/latest/stats/:type", get(get_stats))
-        .route("/api/v1/dataset/latest/motif_search/:scope/:q/:max_gene_details", get(motif_search))
-        .route("/api/v1/dataset/latest/protein_features/:full_or_widget/:gene_uniquename", get(get_protein_features))
-        .route("/api/v1/dataset/latest/query/:q", get(query_get))
-        .route("/api/v1/dataset/latest/query", post(query_post))
-        .route("/api/v1/dataset/latest/search/:scope/:q", get(solr_search))
-        .route("/api/v1/dataset/latest/summary/term/:id", get(get_term_summary_by_id))
-        .route("/ping", get(ping))
-        .fallback(not_found)
-        .with_state(Arc::new(all_state));
+    if admin_token.is_none() {
+        tracing::warn!("POMBASE_SERVER_ADMIN_TOKEN isn't set, /admin/reload is disabled");
+    }
 
-    let app = NormalizePathLayer::trim_trailing_slash().layer(app);
+    let server_state = Arc::new(ServerState {
+        current: RwLock::new(CurrentApp {
+            router: make_router(all_state.clone()),
+            all_state,
+        }),
+        server_args,
+        admin_token,
+        reload_in_progress: AtomicBool::new(false),
+        last_reload_error: RwLock::new(None),
+        metrics,
+    });
+
+    let current_state = server_state.clone();
+    let current_app = tower::service_fn(move |request: Request| {
+        tower::ServiceExt::oneshot(current_state.current_router(), request)
+    });
+
+    tracing::info!("Starting server ...");
+    let app = add_server_routes(Router::new())
+        .fallback_service(current_app)
+        .layer(middleware::from_fn_with_state(server_state.clone(), track_request))
+        .with_state(server_state);
 
-//    tracing_subscriber::fmt()
-//        .with_max_level(tracing::Level::DEBUG)
-//        .init();
+    let app = NormalizePathLayer::trim_trailing_slash().layer(app);
 
     axum::serve(listener, ServiceExt::<Request>::into_make_service(app))
         .await
         .unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pombase::api::openapi::API_ROUTES;
+
+    // fails if a route is added to the API without being added to the
+    // OpenAPI description, or if the description has a route that the
+    // server doesn't have
+    #[test]
+    fn test_routes_are_described() {
+        let is_api_path = |path: &str| {
+            path.starts_with("/api/") || path.starts_with("/admin/") || path == "/ping"
+        };
+
+        let mut server_routes = DATA_ROUTES.iter().chain(SERVER_ROUTES)
+            .filter(|(_, path)| is_api_path(path))
+            .copied()
+            .collect::<Vec<_>>();
+        server_routes.sort();
+
+        let mut described_routes = API_ROUTES.iter()
+            .map(|api_route| (api_route.method, api_route.path))
+            .collect::<Vec<_>>();
+        described_routes.sort();
+
+        assert_eq!(server_routes, described_routes);
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DetailsCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub entry_count: usize,
+    pub max_entries: usize,
+}
+
+struct DetailsCacheEntry<V> {
+    value: Arc<V>,
+    last_used: AtomicU64,
+}
+
+// A bounded cache of the gene, term, allele etc. details read from the
+// maps database.  Lookups only take a read lock so concurrent requests for
+// cached details don't wait for each other.  When the cache is full the
+// least recently used tenth of the entries is removed so that the cost of
+// finding them is shared between many inserts.
+pub struct DetailsCache<K, V> {
+    max_entries: usize,
+    entries: RwLock<HashMap<K, DetailsCacheEntry<V>>>,
+    // incremented on every access and used to find the least recently
+    // used entries
+    counter: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl<K, V> DetailsCache<K, V>
+    where K: Hash + Eq + Clone
+{
+    pub fn new(max_entries: usize) -> DetailsCache<K, V> {
+        DetailsCache {
+            max_entries,
+            entries: RwLock::new(HashMap::new()),
+            counter: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<Arc<V>> {
+        let entries = self.entries.read().unwrap();
+
+        if let Some(entry) = entries.get(key) {
+            let counter = self.counter.fetch_add(1, Ordering::Relaxed) + 1;
+            entry.last_used.store(counter, Ordering::Relaxed);
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            Some(entry.value.clone())
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            None
+        }
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.entries.read().unwrap().contains_key(key)
+    }
+
+    pub fn insert(&self, key: K, value: Arc<V>) {
+        self.insert_all(vec![(key, value)]);
+    }
+
+    // Insert several values while holding the write lock once
+    pub fn insert_all(&self, new_entries: Vec<(K, Arc<V>)>) {
+        if self.max_entries == 0 || new_entries.is_empty() {
+            return;
+        }
+
+        let mut entries = self.entries.write().unwrap();
+
+        for (key, value) in new_entries {
+            if entries.len() >= self.max_entries && !entries.contains_key(&key) {
+                self.evict(&mut entries);
+            }
+
+            let counter = self.counter.fetch_add(1, Ordering::Relaxed) + 1;
+
+            entries.insert(key, DetailsCacheEntry {
+                value,
+                last_used: AtomicU64::new(counter),
+            });
+        }
+    }
+
+    fn evict(&self, entries: &mut HashMap<K, DetailsCacheEntry<V>>) {
+        let evict_count = (self.max_entries / 10).clamp(1, entries.len());
+
+        let mut by_last_used = entries.iter()
+            .map(|(key, entry)| (entry.last_used.load(Ordering::Relaxed), key.clone()))
+            .collect::<Vec<_>>();
+
+        if evict_count < by_last_used.len() {
+            by_last_used.select_nth_unstable_by_key(evict_count, |(last_used, _)| *last_used);
+        }
+
+        for (_, key) in by_last_used.into_iter().take(evict_count) {
+            entries.remove(&key);
+        }
+
+        self.evictions.fetch_add(evict_count as u64, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> DetailsCacheStats {
+        let entry_count = self.entries.read().unwrap().len();
+
+        DetailsCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            entry_count,
+            max_entries: self.max_entries,
+        }
+    }
+}
@@ -0,0 +1,427 @@
+// Term, reference, allele and documentation search without Solr.  The
+// indexes are built from the files that WebData::write_solr_data() writes
+// for loading into Solr.  The fields and boosts match the Solr queries in
+// term_search.rs, ref_search.rs, allele_search.rs and doc_search.rs.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+
+use anyhow::{Result, Context};
+use flate2::read::GzDecoder;
+use regex::Regex;
+use serde::de::DeserializeOwned;
+
+use crate::api::doc_search::DocSearchMatch;
+use crate::api::search_index::{TextIndex, MatchOptions, PrefixMatch, highlight, sort_results,
+                               tokenize};
+use crate::api::search_types::SolrMatchHighlight;
+use crate::api::search_utils::clean_words;
+use crate::api::term_search::TERMID_RE;
+use crate::data_types::{SolrTermSummary, SolrReferenceSummary, SolrAlleleSummary};
+use crate::web::config::ServerConfig;
+
+use flexstr::SharedStr as FlexStr;
+
+// the number of matches returned, the same as the Solr default
+pub const SEARCH_RESULT_ROWS: usize = 10;
+
+// added to the score of a term or allele whose name is exactly the query
+const EXACT_NAME_MATCH_BOOST: f32 = 100.0;
+
+const TERM_FIELDS: [&str; 4] =
+    ["name", "close_synonym_words", "distant_synonym_words", "definition"];
+const REF_FIELDS: [&str; 6] =
+    ["title", "citation", "authors", "pubmed_abstract", "authors_abbrev", "publication_year"];
+const ALLELE_FIELDS: [&str; 6] =
+    ["name", "synonyms", "allele_type", "gene_name", "gene_uniquename", "description"];
+const DOC_FIELDS: [&str; 2] = ["heading", "content"];
+
+const REF_FIELD_WEIGHTS: [f32; 6] = [2.0, 0.5, 2.0, 0.5, 0.5, 20.0];
+const ALLELE_FIELD_WEIGHTS: [f32; 6] = [5.0, 4.0, 4.0, 3.0, 3.0, 1.0];
+const DOC_FIELD_WEIGHTS: [f32; 2] = [1.0, 0.2];
+
+lazy_static! {
+    static ref REF_ID_RE: Regex =
+        Regex::new(r"^(?:(?P<prefix>[\w_]+):\s*)?(?P<rest>\d\d\d\d\d+)$").unwrap();
+    static ref DELTA_RE: Regex = Regex::new(r"[Δδ]").unwrap();
+}
+
+// A documentation page or section, read from the optional docs.json.gz
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EmbeddedSearchDoc {
+    pub id: String,
+    pub heading: String,
+    pub content: String,
+}
+
+pub struct EmbeddedSearch {
+    close_synonym_boost: f32,
+    distant_synonym_boost: f32,
+    term_definition_boost: f32,
+    cv_name_for_terms_search: String,
+    gene_uniquename_re: Regex,
+
+    terms: Vec<SolrTermSummary>,
+    term_index: TextIndex,
+    // term IDs and secondary IDs to indexes in terms
+    term_ids: HashMap<FlexStr, Vec<usize>>,
+
+    references: Vec<SolrReferenceSummary>,
+    reference_index: TextIndex,
+    reference_ids: HashMap<FlexStr, usize>,
+
+    alleles: Vec<SolrAlleleSummary>,
+    allele_index: TextIndex,
+
+    docs: Vec<EmbeddedSearchDoc>,
+    doc_index: TextIndex,
+}
+
+fn read_gzipped_json<T: DeserializeOwned>(file_name: &str) -> Result<T> {
+    let file = File::open(file_name)
+        .with_context(|| format!("failed to open {}", file_name))?;
+    let reader = BufReader::new(GzDecoder::new(file));
+    serde_json::from_reader(reader)
+        .with_context(|| format!("failed to parse {}", file_name))
+}
+
+// replace "Δ" with "delta" and "wild type" with "wild_type" so that allele
+// names and queries match
+fn normalise_allele_text(text: &str) -> String {
+    DELTA_RE.replace_all(&text.to_lowercase(), "delta").replace("wild type", "wild_type")
+}
+
+fn add_highlight(highlighting: &mut SolrMatchHighlight, field_name: &str,
+                 text: Option<&str>, words: &[String], prefix: PrefixMatch) {
+    if let Some(highlighted) = text.and_then(|text| highlight(text, words, prefix)) {
+        highlighting.entry(field_name.to_owned()).or_default().push(highlighted);
+    }
+}
+
+impl EmbeddedSearch {
+    pub fn new(config: &ServerConfig, terms: Vec<SolrTermSummary>,
+               references: Vec<SolrReferenceSummary>, alleles: Vec<SolrAlleleSummary>,
+               docs: Vec<EmbeddedSearchDoc>)
+               -> EmbeddedSearch
+    {
+        let mut term_index = TextIndex::new(TERM_FIELDS.len());
+        let mut term_ids: HashMap<FlexStr, Vec<usize>> = HashMap::new();
+
+        for term in &terms {
+            let definition = term.definition.as_ref().map(|def| def.as_ref()).unwrap_or("");
+            let idx = term_index.add_document(&[term.name.as_ref(),
+                                                term.close_synonym_words.as_ref(),
+                                                term.distant_synonym_words.as_ref(),
+                                                definition]);
+            term_ids.entry(term.id.clone()).or_default().push(idx);
+            for secondary_id in &term.secondary_identifiers {
+                term_ids.entry(secondary_id.clone()).or_default().push(idx);
+            }
+        }
+
+        let mut reference_index = TextIndex::new(REF_FIELDS.len());
+        let mut reference_ids = HashMap::new();
+
+        for reference in &references {
+            let field = |value: &Option<FlexStr>| value.as_ref().map(|v| v.to_string()).unwrap_or_default();
+            let year = reference.publication_year.map(|year| year.to_string()).unwrap_or_default();
+            let idx = reference_index.add_document(&[&field(&reference.title),
+                                                     &field(&reference.citation),
+                                                     &field(&reference.authors),
+                                                     &field(&reference.pubmed_abstract),
+                                                     &field(&reference.authors_abbrev),
+                                                     &year]);
+            reference_ids.insert(reference.id.clone(), idx);
+        }
+
+        let mut allele_index = TextIndex::new(ALLELE_FIELDS.len());
+
+        for allele in &alleles {
+            let field = |value: &Option<FlexStr>| {
+                value.as_ref().map(|v| normalise_allele_text(v)).unwrap_or_default()
+            };
+            let synonyms = allele.synonyms.iter()
+                .map(|synonym| normalise_allele_text(synonym))
+                .collect::<Vec<_>>().join(" ");
+            allele_index.add_document(&[&field(&allele.name), &synonyms,
+                                        &normalise_allele_text(&allele.allele_type),
+                                        &field(&allele.gene_name),
+                                        &allele.gene_uniquename.to_lowercase(),
+                                        &field(&allele.description)]);
+        }
+
+        let mut doc_index = TextIndex::new(DOC_FIELDS.len());
+
+        for doc in &docs {
+            doc_index.add_document(&[&doc.heading, &doc.content]);
+        }
+
+        EmbeddedSearch {
+            close_synonym_boost: config.close_synonym_boost,
+            distant_synonym_boost: config.distant_synonym_boost,
+            term_definition_boost: config.term_definition_boost,
+            cv_name_for_terms_search: config.cv_name_for_terms_search.clone(),
+            gene_uniquename_re: Regex::new(&config.gene_uniquename_re.to_lowercase()).unwrap(),
+            terms,
+            term_index,
+            term_ids,
+            references,
+            reference_index,
+            reference_ids,
+            alleles,
+            allele_index,
+            docs,
+            doc_index,
+        }
+    }
+
+    // Read the data files from data_dir and build the indexes
+    pub fn read(config: &ServerConfig, data_dir: &str) -> Result<EmbeddedSearch> {
+        let terms = read_gzipped_json(&format!("{}/terms.json.gz", data_dir))?;
+        let references = read_gzipped_json(&format!("{}/references.json.gz", data_dir))?;
+        let alleles = read_gzipped_json(&format!("{}/alleles.json.gz", data_dir))?;
+
+        let docs_file_name = format!("{}/docs.json.gz", data_dir);
+        let docs =
+            if std::path::Path::new(&docs_file_name).exists() {
+                read_gzipped_json(&docs_file_name)?
+            } else {
+                // the documentation isn't part of the Chado data so this
+                // file isn't written by WebData::write_solr_data()
+                tracing::warn!("{} not found, documentation searches will return no results",
+                               docs_file_name);
+                vec![]
+            };
+
+        Ok(EmbeddedSearch::new(config, terms, references, alleles, docs))
+    }
+
+    // cv_name can be a CV name or a term ID in square brackets, which
+    // restricts the search to that term and terms with it as an
+    // "interesting parent"
+    fn term_in_scope(term: &SolrTermSummary, cv_name: &str) -> bool {
+        if let Some(parent_id) = cv_name.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            term.id == parent_id || term.interesting_parent_ids.contains(parent_id)
+        } else {
+            cv_name.is_empty() || term.cv_name == cv_name
+        }
+    }
+
+    pub fn term_complete(&self, cv_name: &str, q: &str) -> Vec<SolrTermSummary> {
+        let q = q.trim();
+
+        if TERMID_RE.is_match(q) {
+            let Some(indexes) = self.term_ids.get(q)
+            else {
+                return vec![];
+            };
+
+            return indexes.iter()
+                .map(|idx| &self.terms[*idx])
+                .filter(|term| Self::term_in_scope(term, cv_name))
+                .take(SEARCH_RESULT_ROWS)
+                .cloned()
+                .collect();
+        }
+
+        let words = clean_words(q);
+
+        if words.is_empty() {
+            return vec![];
+        }
+
+        let field_weights = [1.0, self.close_synonym_boost, self.distant_synonym_boost,
+                             self.term_definition_boost];
+        let options = MatchOptions {
+            require_all: false,
+            prefix: PrefixMatch::LastWord,
+        };
+
+        let lower_q = q.to_lowercase();
+
+        let mut results = self.term_index.search(&words, &field_weights, options)
+            .into_iter()
+            .filter(|(idx, _)| {
+                let term = &self.terms[*idx];
+                term.annotation_count > 0 && Self::term_in_scope(term, cv_name)
+            })
+            .map(|(idx, score)| {
+                if self.terms[idx].name.to_lowercase() == lower_q {
+                    (idx, score + EXACT_NAME_MATCH_BOOST)
+                } else {
+                    (idx, score)
+                }
+            })
+            .collect::<Vec<_>>();
+
+        sort_results(&mut results);
+
+        results.into_iter()
+            .take(SEARCH_RESULT_ROWS)
+            .map(|(idx, _)| {
+                let mut term = self.terms[idx].clone();
+                add_highlight(&mut term.highlighting, "name", Some(&term.name),
+                              &words, options.prefix);
+                add_highlight(&mut term.highlighting, "definition",
+                              term.definition.as_deref(), &words, options.prefix);
+                term
+            })
+            .collect()
+    }
+
+    pub fn search_terms(&self, q: &str) -> Vec<SolrTermSummary> {
+        self.term_complete(&self.cv_name_for_terms_search, q)
+    }
+
+    pub fn term_summary_by_id(&self, termid: &str) -> Option<SolrTermSummary> {
+        self.term_ids.get(termid)?.iter()
+            .map(|idx| &self.terms[*idx])
+            .find(|term| term.id == termid)
+            .cloned()
+    }
+
+    fn highlighted_reference(&self, idx: usize, words: &[String], prefix: PrefixMatch)
+                             -> SolrReferenceSummary
+    {
+        let mut reference = self.references[idx].clone();
+        let year = reference.publication_year.map(|year| year.to_string());
+
+        for (field_name, text) in [("title", reference.title.as_deref()),
+                                   ("citation", reference.citation.as_deref()),
+                                   ("authors", reference.authors.as_deref()),
+                                   ("pubmed_abstract", reference.pubmed_abstract.as_deref()),
+                                   ("authors_abbrev", reference.authors_abbrev.as_deref()),
+                                   ("publication_year", year.as_deref())] {
+            add_highlight(&mut reference.highlighting, field_name, text, words, prefix);
+        }
+
+        reference
+    }
+
+    pub fn search_refs(&self, q: &str) -> Vec<SolrReferenceSummary> {
+        let q = q.trim();
+
+        if let Some(captures) = REF_ID_RE.captures(q) {
+            let prefix = captures.name("prefix").map(|prefix| prefix.as_str()).unwrap_or("PMID");
+            let accession = captures.name("rest").unwrap().as_str();
+            let id = format!("{}:{}", prefix, accession);
+
+            return self.reference_ids.get(id.as_str())
+                .map(|idx| self.references[*idx].clone())
+                .into_iter()
+                .collect();
+        }
+
+        let lower_q = q.chars().take(200).collect::<String>().to_lowercase();
+
+        // gene IDs must match as a phrase, otherwise the last word can be
+        // incomplete
+        let (words, options) =
+            if self.gene_uniquename_re.is_match(&lower_q) {
+                (tokenize(&lower_q), MatchOptions {
+                    require_all: true,
+                    prefix: PrefixMatch::None,
+                })
+            } else {
+                (clean_words(&lower_q), MatchOptions {
+                    require_all: false,
+                    prefix: PrefixMatch::LastWord,
+                })
+            };
+
+        if words.is_empty() {
+            return vec![];
+        }
+
+        self.reference_index.search(&words, &REF_FIELD_WEIGHTS, options)
+            .into_iter()
+            .take(SEARCH_RESULT_ROWS)
+            .map(|(idx, _)| self.highlighted_reference(idx, &words, options.prefix))
+            .collect()
+    }
+
+    pub fn search_alleles(&self, q: &str) -> Vec<SolrAlleleSummary> {
+        let lower_q = q.trim().chars().take(100).collect::<String>();
+        let normalised_q = normalise_allele_text(&lower_q);
+
+        let mut words = tokenize(&normalised_q);
+
+        if words.is_empty() {
+            return vec![];
+        }
+
+        if words.iter().any(|word| word == "delete" || word == "delta" || word == "deletion") {
+            words.push("deletion".to_owned());
+            words.push("delta".to_owned());
+        }
+
+        let options = MatchOptions {
+            require_all: false,
+            prefix: PrefixMatch::LastWord,
+        };
+
+        let mut results = self.allele_index.search(&words, &ALLELE_FIELD_WEIGHTS, options)
+            .into_iter()
+            .map(|(idx, score)| {
+                let name = self.alleles[idx].name.as_ref().map(|name| normalise_allele_text(name));
+                if name.as_ref() == Some(&normalised_q) {
+                    (idx, score + EXACT_NAME_MATCH_BOOST)
+                } else {
+                    (idx, score)
+                }
+            })
+            .collect::<Vec<_>>();
+
+        sort_results(&mut results);
+
+        results.into_iter()
+            .take(SEARCH_RESULT_ROWS)
+            .map(|(idx, _)| {
+                let mut allele = self.alleles[idx].clone();
+                add_highlight(&mut allele.highlighting, "name", allele.name.as_deref(),
+                              &words, options.prefix);
+                for synonym in &allele.synonyms {
+                    add_highlight(&mut allele.highlighting, "synonyms", Some(synonym),
+                                  &words, options.prefix);
+                }
+                add_highlight(&mut allele.highlighting, "description",
+                              allele.description.as_deref(), &words, options.prefix);
+                add_highlight(&mut allele.highlighting, "gene_name",
+                              allele.gene_name.as_deref(), &words, options.prefix);
+                add_highlight(&mut allele.highlighting, "gene_uniquename",
+                              Some(&allele.gene_uniquename), &words, options.prefix);
+                allele
+            })
+            .collect()
+    }
+
+    pub fn search_docs(&self, q: &str) -> Vec<DocSearchMatch> {
+        let words = clean_words(q);
+
+        if words.is_empty() {
+            return vec![];
+        }
+
+        let options = MatchOptions {
+            require_all: false,
+            prefix: PrefixMatch::AllWords,
+        };
+
+        self.doc_index.search(&words, &DOC_FIELD_WEIGHTS, options)
+            .into_iter()
+            .take(SEARCH_RESULT_ROWS)
+            .map(|(idx, _)| {
+                let doc = &self.docs[idx];
+                let mut hl = SolrMatchHighlight::new();
+                add_highlight(&mut hl, "heading", Some(&doc.heading), &words, options.prefix);
+                add_highlight(&mut hl, "content", Some(&doc.content), &words, options.prefix);
+                DocSearchMatch {
+                    id: doc.id.clone(),
+                    heading: doc.heading.clone(),
+                    hl,
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,325 @@
+// Gene set enrichment: find the terms that are annotated to more of the
+// genes in a gene list than would be expected by chance, using a
+// hypergeometric test.
+//
+// The genes of a term come from APIMaps.termid_genes, which includes the
+// genes annotated to descendant terms.  APIMaps.children_by_termid is used
+// to optionally remove terms that have a more specific enriched term.
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+use crate::api::query::serialize_sorted_set;
+use crate::api_data::APIData;
+use crate::data_types::DataLookup;
+use crate::types::{GeneUniquename, TermId};
+
+use flexstr::{SharedStr as FlexStr, shared_fmt as flex_fmt};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(tag = "type")]
+pub enum EnrichmentBackground {
+    // all genes from the organism being loaded (load_organism_taxonid)
+#[serde(rename = "organism")]
+    #[default]
+    Organism,
+#[serde(rename = "subset")]
+    Subset {
+        subset_name: FlexStr,
+    },
+#[serde(rename = "genes")]
+    Genes {
+        genes: Vec<GeneUniquename>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum MultipleTestingCorrection {
+#[serde(rename = "benjamini_hochberg")]
+    #[default]
+    BenjaminiHochberg,
+#[serde(rename = "bonferroni")]
+    Bonferroni,
+#[serde(rename = "none")]
+    NoCorrection,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EnrichmentOptions {
+    // only test terms with these ID prefixes, eg. "GO" or "FYPO",
+    // test all terms if empty
+    #[serde(skip_serializing_if="HashSet::is_empty", default,
+            serialize_with="serialize_sorted_set")]
+    pub termid_prefixes: HashSet<FlexStr>,
+    #[serde(default)]
+    pub background: EnrichmentBackground,
+    #[serde(default)]
+    pub correction: MultipleTestingCorrection,
+    // terms with a larger corrected p-value aren't returned
+    #[serde(default="EnrichmentOptions::default_max_p_value")]
+    pub max_p_value: f64,
+    // if true, don't return terms that have an enriched descendant term
+    #[serde(default)]
+    pub only_most_specific: bool,
+}
+
+impl EnrichmentOptions {
+    pub fn default_max_p_value() -> f64 {
+        0.05
+    }
+}
+
+impl Default for EnrichmentOptions {
+    fn default() -> Self {
+        EnrichmentOptions {
+            termid_prefixes: HashSet::new(),
+            background: EnrichmentBackground::default(),
+            correction: MultipleTestingCorrection::default(),
+            max_p_value: EnrichmentOptions::default_max_p_value(),
+            only_most_specific: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EnrichedTerm {
+    pub termid: TermId,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub name: Option<FlexStr>,
+    // the number of genes from the gene list annotated to the term
+    pub study_count: usize,
+    // the number of background genes annotated to the term
+    pub background_count: usize,
+    pub fold_enrichment: f64,
+    pub p_value: f64,
+    pub corrected_p_value: f64,
+    // the genes from the gene list annotated to the term
+    pub genes: Vec<GeneUniquename>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EnrichmentResult {
+    // the number of genes from the gene list that are in the background
+    pub study_count: usize,
+    pub background_count: usize,
+    // the number of terms tested, used in the multiple testing correction
+    pub tested_term_count: usize,
+    pub terms: Vec<EnrichedTerm>,
+}
+
+fn background_genes(api_data: &APIData, background: &EnrichmentBackground)
+                    -> Result<HashSet<GeneUniquename>, FlexStr>
+{
+    let genes: HashSet<GeneUniquename> =
+        match background {
+            EnrichmentBackground::Organism => {
+                // the gene summaries are only made for genes of the
+                // load_organism_taxonid organism
+                api_data.get_maps().gene_summaries.keys().cloned().collect()
+            },
+            EnrichmentBackground::Subset { subset_name } => {
+                api_data.genes_of_subset(subset_name).into_iter().collect()
+            },
+            EnrichmentBackground::Genes { genes } => {
+                genes.iter().cloned().collect()
+            },
+        };
+
+    if genes.is_empty() {
+        Err(flex_fmt!("enrichment background has no genes: {:?}", background))
+    } else {
+        Ok(genes)
+    }
+}
+
+// ln(n!) for 0..=max_n
+fn ln_factorials(max_n: usize) -> Vec<f64> {
+    let mut ln_factorials = Vec::with_capacity(max_n + 1);
+    ln_factorials.push(0.0);
+
+    for n in 1..=max_n {
+        ln_factorials.push(ln_factorials[n - 1] + (n as f64).ln());
+    }
+
+    ln_factorials
+}
+
+fn ln_choose(ln_factorials: &[f64], n: usize, k: usize) -> f64 {
+    ln_factorials[n] - ln_factorials[k] - ln_factorials[n - k]
+}
+
+// The probability of seeing study_term_count or more genes annotated to a
+// term in a sample of study_count genes from the background, where
+// background_term_count of the background genes are annotated to the term
+pub fn hypergeometric_p_value(background_count: usize, background_term_count: usize,
+                              study_count: usize, study_term_count: usize) -> f64 {
+    let ln_factorials = ln_factorials(background_count);
+    upper_tail_p_value(&ln_factorials, background_count, background_term_count,
+                       study_count, study_term_count)
+}
+
+fn upper_tail_p_value(ln_factorials: &[f64],
+                      background_count: usize, background_term_count: usize,
+                      study_count: usize, study_term_count: usize) -> f64 {
+    let ln_total = ln_choose(ln_factorials, background_count, study_count);
+    let max_term_count = study_count.min(background_term_count);
+    let background_other_count = background_count - background_term_count;
+
+    let mut p_value = 0.0;
+
+    for term_count in study_term_count..=max_term_count {
+        let other_count = study_count - term_count;
+        if other_count > background_other_count {
+            continue;
+        }
+        p_value +=
+            (ln_choose(ln_factorials, background_term_count, term_count) +
+             ln_choose(ln_factorials, background_other_count, other_count) -
+             ln_total).exp();
+    }
+
+    p_value.min(1.0)
+}
+
+fn correct_p_values(p_values: &[f64], correction: MultipleTestingCorrection) -> Vec<f64> {
+    let test_count = p_values.len() as f64;
+
+    match correction {
+        MultipleTestingCorrection::NoCorrection => p_values.to_vec(),
+        MultipleTestingCorrection::Bonferroni => {
+            p_values.iter().map(|p| (p * test_count).min(1.0)).collect()
+        },
+        MultipleTestingCorrection::BenjaminiHochberg => {
+            let mut indices = (0..p_values.len()).collect::<Vec<_>>();
+            indices.sort_by(|a, b| {
+                p_values[*a].partial_cmp(&p_values[*b]).unwrap_or(Ordering::Equal)
+            });
+
+            let mut corrected = vec![1.0; p_values.len()];
+            let mut min_so_far: f64 = 1.0;
+
+            // from the largest p-value down so that the corrected values
+            // are monotonic
+            for (rank, index) in indices.iter().enumerate().rev() {
+                let adjusted = p_values[*index] * test_count / (rank + 1) as f64;
+                min_so_far = min_so_far.min(adjusted);
+                corrected[*index] = min_so_far;
+            }
+
+            corrected
+        },
+    }
+}
+
+fn termid_has_prefix(termid: &TermId, prefixes: &HashSet<FlexStr>) -> bool {
+    prefixes.is_empty() ||
+        termid.split_once(':')
+        .map(|(prefix, _)| prefixes.iter().any(|p| p.as_ref() == prefix))
+        .unwrap_or(false)
+}
+
+// Find the terms that are over-represented in the genes
+pub fn gene_set_enrichment(api_data: &APIData, genes: &[GeneUniquename],
+                           options: &EnrichmentOptions)
+                           -> Result<EnrichmentResult, FlexStr>
+{
+    let background = background_genes(api_data, &options.background)?;
+
+    let study_genes = genes.iter()
+        .filter(|gene_uniquename| background.contains(*gene_uniquename))
+        .cloned()
+        .collect::<HashSet<_>>();
+
+    let background_count = background.len();
+    let study_count = study_genes.len();
+
+    let ln_factorials = ln_factorials(background_count);
+
+    let mut tested_terms = vec![];
+
+    for (termid, term_genes) in &api_data.get_maps().termid_genes {
+        if !termid_has_prefix(termid, &options.termid_prefixes) {
+            continue;
+        }
+
+        let mut term_study_genes = term_genes.iter()
+            .filter(|gene_uniquename| study_genes.contains(*gene_uniquename))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if term_study_genes.is_empty() {
+            continue;
+        }
+
+        term_study_genes.sort();
+
+        let background_term_count = term_genes.iter()
+            .filter(|gene_uniquename| background.contains(*gene_uniquename))
+            .count();
+
+        let p_value =
+            upper_tail_p_value(&ln_factorials, background_count, background_term_count,
+                               study_count, term_study_genes.len());
+
+        let fold_enrichment =
+            (term_study_genes.len() as f64 / study_count as f64) /
+            (background_term_count as f64 / background_count as f64);
+
+        tested_terms.push(EnrichedTerm {
+            termid: termid.clone(),
+            name: None,
+            study_count: term_study_genes.len(),
+            background_count: background_term_count,
+            fold_enrichment,
+            p_value,
+            corrected_p_value: p_value,
+            genes: term_study_genes,
+        });
+    }
+
+    let tested_term_count = tested_terms.len();
+
+    let p_values = tested_terms.iter().map(|term| term.p_value).collect::<Vec<_>>();
+    let corrected_p_values = correct_p_values(&p_values, options.correction);
+
+    let mut enriched_terms = tested_terms.into_iter().zip(corrected_p_values)
+        .filter(|(_, corrected_p_value)| *corrected_p_value <= options.max_p_value)
+        .map(|(mut term, corrected_p_value)| {
+            term.corrected_p_value = corrected_p_value;
+            term
+        })
+        .collect::<Vec<_>>();
+
+    if options.only_most_specific {
+        let enriched_termids = enriched_terms.iter()
+            .map(|term| term.termid.clone())
+            .collect::<HashSet<_>>();
+        let children_by_termid = &api_data.get_maps().children_by_termid;
+
+        enriched_terms.retain(|term| {
+            match children_by_termid.get(&term.termid) {
+                Some(child_termids) =>
+                    !child_termids.iter().any(|child| enriched_termids.contains(child)),
+                None => true,
+            }
+        });
+    }
+
+    enriched_terms.sort_by(|a, b| {
+        a.corrected_p_value.partial_cmp(&b.corrected_p_value).unwrap_or(Ordering::Equal)
+            .then_with(|| a.p_value.partial_cmp(&b.p_value).unwrap_or(Ordering::Equal))
+            .then_with(|| a.termid.cmp(&b.termid))
+    });
+
+    for term in enriched_terms.iter_mut() {
+        term.name = api_data.get_term(&term.termid)
+            .map(|term_details| term_details.name.clone());
+    }
+
+    Ok(EnrichmentResult {
+        study_count,
+        background_count,
+        tested_term_count,
+        terms: enriched_terms,
+    })
+}
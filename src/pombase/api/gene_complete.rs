@@ -0,0 +1,174 @@
+// Gene autocompletion using the systematic IDs, names, exact synonyms,
+// UniProt accessions and secondary identifiers of the genes in
+// APIMaps.gene_summaries.
+
+use std::collections::HashMap;
+
+use crate::data_types::APIMaps;
+use crate::types::{GeneUniquename, OrganismTaxonId};
+
+use flexstr::SharedStr as FlexStr;
+
+// the maximum number of completions returned
+pub const MAX_GENE_COMPLETIONS: usize = 20;
+
+// The kinds of identifier that a query can match, in ranking order
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum GeneCompletionField {
+#[serde(rename = "uniquename")]
+    Uniquename,
+#[serde(rename = "name")]
+    Name,
+#[serde(rename = "synonym")]
+    Synonym,
+#[serde(rename = "uniprot_identifier")]
+    UniprotIdentifier,
+#[serde(rename = "secondary_identifier")]
+    SecondaryIdentifier,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GeneCompletionMatch {
+    pub uniquename: GeneUniquename,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub name: Option<FlexStr>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub product: Option<FlexStr>,
+    pub taxonid: OrganismTaxonId,
+    // the field and the identifier that matched the query
+    pub matched_field: GeneCompletionField,
+    pub matched_value: FlexStr,
+}
+
+#[derive(Debug, Clone)]
+struct CompletionKey {
+    lower_value: String,
+    value: FlexStr,
+    field: GeneCompletionField,
+    gene_uniquename: GeneUniquename,
+}
+
+// The identifiers of all genes, sorted by lower case value so that prefix
+// matches can be found with a binary search
+#[derive(Debug, Clone, Default)]
+pub struct GeneCompletionIndex {
+    keys: Vec<CompletionKey>,
+}
+
+impl GeneCompletionIndex {
+    pub fn new(maps: &APIMaps) -> GeneCompletionIndex {
+        let mut keys = vec![];
+
+        let mut add_key = |value: &FlexStr, field, gene_uniquename: &GeneUniquename| {
+            if !value.is_empty() {
+                keys.push(CompletionKey {
+                    lower_value: value.to_lowercase(),
+                    value: value.clone(),
+                    field,
+                    gene_uniquename: gene_uniquename.clone(),
+                });
+            }
+        };
+
+        for (gene_name, gene_uniquename) in &maps.gene_name_gene_map {
+            add_key(gene_name, GeneCompletionField::Name, gene_uniquename);
+        }
+
+        for gene_summary in maps.gene_summaries.values() {
+            let gene_uniquename = &gene_summary.uniquename;
+
+            add_key(gene_uniquename, GeneCompletionField::Uniquename, gene_uniquename);
+
+            if let Some(ref name) = gene_summary.name {
+                if !maps.gene_name_gene_map.contains_key(name) {
+                    add_key(name, GeneCompletionField::Name, gene_uniquename);
+                }
+            }
+            for synonym in &gene_summary.exact_synonyms {
+                add_key(synonym, GeneCompletionField::Synonym, gene_uniquename);
+            }
+            if let Some(ref uniprot_identifier) = gene_summary.uniprot_identifier {
+                add_key(uniprot_identifier, GeneCompletionField::UniprotIdentifier,
+                        gene_uniquename);
+            }
+            if let Some(ref secondary_identifier) = gene_summary.secondary_identifier {
+                add_key(secondary_identifier, GeneCompletionField::SecondaryIdentifier,
+                        gene_uniquename);
+            }
+        }
+
+        keys.sort_by(|a, b| a.lower_value.cmp(&b.lower_value));
+
+        GeneCompletionIndex {
+            keys,
+        }
+    }
+
+    // Return the genes with an identifier that starts with q, ignoring
+    // case.  Exact matches are ranked first, then by the field that
+    // matched (systematic ID, name, synonym, UniProt accession, secondary
+    // ID) and then by the length of the identifier.  If taxonid is set,
+    // only genes from that organism are returned.
+    pub fn complete(&self, maps: &APIMaps, q: &str, taxonid: Option<OrganismTaxonId>,
+                    max_results: usize)
+                    -> Vec<GeneCompletionMatch>
+    {
+        let lower_q = q.trim().to_lowercase();
+
+        if lower_q.is_empty() {
+            return vec![];
+        }
+
+        let start = self.keys.partition_point(|key| key.lower_value.as_str() < lower_q.as_str());
+
+        let mut best_matches: HashMap<&GeneUniquename, &CompletionKey> = HashMap::new();
+
+        let rank = |key: &CompletionKey| {
+            (key.lower_value != lower_q, key.field, key.lower_value.len())
+        };
+
+        for key in self.keys[start..].iter()
+            .take_while(|key| key.lower_value.starts_with(&lower_q))
+        {
+            let Some(gene_summary) = maps.gene_summaries.get(&key.gene_uniquename)
+            else {
+                continue;
+            };
+
+            if let Some(taxonid) = taxonid {
+                if gene_summary.taxonid != taxonid {
+                    continue;
+                }
+            }
+
+            best_matches.entry(&key.gene_uniquename)
+                .and_modify(|best_key| {
+                    if rank(key) < rank(best_key) {
+                        *best_key = key;
+                    }
+                })
+                .or_insert(key);
+        }
+
+        let mut matches = best_matches.into_values().collect::<Vec<_>>();
+
+        matches.sort_by(|a, b| {
+            rank(a).cmp(&rank(b)).then_with(|| a.gene_uniquename.cmp(&b.gene_uniquename))
+        });
+
+        matches.into_iter()
+            .take(max_results)
+            .map(|key| {
+                let gene_summary = &maps.gene_summaries[&key.gene_uniquename];
+                GeneCompletionMatch {
+                    uniquename: gene_summary.uniquename.clone(),
+                    name: gene_summary.name.clone(),
+                    product: gene_summary.product.clone(),
+                    taxonid: gene_summary.taxonid,
+                    matched_field: key.field,
+                    matched_value: key.value.clone(),
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,215 @@
+// Map lists of gene identifiers, names and synonyms to genes.  Every
+// identifier that could refer to a gene is indexed so that pasted gene
+// lists containing old names, UniProt accessions, ORFeome IDs etc. can be
+// resolved, and identifiers shared by more than one gene can be reported
+// as ambiguous.
+
+use std::collections::HashMap;
+
+use crate::data_types::APIMaps;
+use crate::types::GeneUniquename;
+
+use flexstr::SharedStr as FlexStr;
+
+// How an identifier matched a gene, best first
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IdMatchType {
+#[serde(rename = "systematic_id")]
+    SystematicId,
+#[serde(rename = "name")]
+    Name,
+#[serde(rename = "synonym")]
+    Synonym,
+    // non-exact synonyms, including obsolete names and previous
+    // systematic IDs
+#[serde(rename = "other_synonym")]
+    OtherSynonym,
+#[serde(rename = "uniprot_identifier")]
+    UniprotIdentifier,
+#[serde(rename = "secondary_identifier")]
+    SecondaryIdentifier,
+#[serde(rename = "orfeome_identifier")]
+    OrfeomeIdentifier,
+#[serde(rename = "dbxref")]
+    Dbxref,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdMappingStatus {
+#[serde(rename = "matched")]
+    Matched,
+    // the identifier matches more than one gene
+#[serde(rename = "ambiguous")]
+    Ambiguous,
+#[serde(rename = "unmatched")]
+    Unmatched,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IdMatch {
+    pub gene_uniquename: GeneUniquename,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub gene_name: Option<FlexStr>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub product: Option<FlexStr>,
+    pub match_type: IdMatchType,
+    // the identifier of the gene that matched, with the original case
+    pub matched_id: FlexStr,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IdMappingResult {
+    pub input: FlexStr,
+    pub status: IdMappingStatus,
+    // the gene to use for this input: the only match, or for an ambiguous
+    // input the only gene with the best kind of match (eg. a gene whose
+    // current name is the input, rather than another gene with the input
+    // as a synonym)
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub best_match: Option<GeneUniquename>,
+    // all matching genes, best match first
+    pub matches: Vec<IdMatch>,
+}
+
+#[derive(Debug, Clone)]
+struct IndexEntry {
+    gene_uniquename: GeneUniquename,
+    match_type: IdMatchType,
+    matched_id: FlexStr,
+}
+
+// Lower case identifiers to the genes they could refer to
+#[derive(Debug, Clone, Default)]
+pub struct IdMappingIndex {
+    entries: HashMap<String, Vec<IndexEntry>>,
+}
+
+impl IdMappingIndex {
+    pub fn new(maps: &APIMaps) -> IdMappingIndex {
+        let mut entries: HashMap<String, Vec<IndexEntry>> = HashMap::new();
+
+        let mut add = |id: &FlexStr, match_type, gene_uniquename: &GeneUniquename| {
+            let id = id.trim();
+            if id.is_empty() {
+                return;
+            }
+
+            entries.entry(id.to_lowercase()).or_default().push(IndexEntry {
+                gene_uniquename: gene_uniquename.clone(),
+                match_type,
+                matched_id: id.into(),
+            });
+        };
+
+        for (gene_name, gene_uniquename) in &maps.gene_name_gene_map {
+            add(gene_name, IdMatchType::Name, gene_uniquename);
+        }
+
+        for gene_summary in maps.gene_summaries.values() {
+            let gene_uniquename = &gene_summary.uniquename;
+
+            add(gene_uniquename, IdMatchType::SystematicId, gene_uniquename);
+
+            if let Some(ref name) = gene_summary.name {
+                if !maps.gene_name_gene_map.contains_key(name) {
+                    add(name, IdMatchType::Name, gene_uniquename);
+                }
+            }
+            for synonym in &gene_summary.exact_synonyms {
+                add(synonym, IdMatchType::Synonym, gene_uniquename);
+            }
+            for synonym in &gene_summary.other_synonyms {
+                add(synonym, IdMatchType::OtherSynonym, gene_uniquename);
+            }
+            if let Some(ref uniprot_identifier) = gene_summary.uniprot_identifier {
+                add(uniprot_identifier, IdMatchType::UniprotIdentifier, gene_uniquename);
+            }
+            if let Some(ref secondary_identifier) = gene_summary.secondary_identifier {
+                add(secondary_identifier, IdMatchType::SecondaryIdentifier, gene_uniquename);
+            }
+
+            // dbxrefs match with or without the database prefix
+            for dbxref in &gene_summary.dbxrefs {
+                if let Some(orfeome_identifier) = dbxref.strip_prefix("SPD:") {
+                    add(&orfeome_identifier.into(), IdMatchType::OrfeomeIdentifier,
+                        gene_uniquename);
+                    continue;
+                }
+
+                add(dbxref, IdMatchType::Dbxref, gene_uniquename);
+                if let Some((_, accession)) = dbxref.split_once(':') {
+                    add(&accession.into(), IdMatchType::Dbxref, gene_uniquename);
+                }
+            }
+        }
+
+        IdMappingIndex {
+            entries,
+        }
+    }
+
+    // Find the genes matching one identifier, ignoring case
+    pub fn map_id(&self, maps: &APIMaps, input: &str) -> IdMappingResult {
+        let mut best_by_gene: HashMap<&GeneUniquename, &IndexEntry> = HashMap::new();
+
+        if let Some(entries) = self.entries.get(&input.trim().to_lowercase()) {
+            for entry in entries {
+                best_by_gene.entry(&entry.gene_uniquename)
+                    .and_modify(|best| {
+                        if entry.match_type < best.match_type {
+                            *best = entry;
+                        }
+                    })
+                    .or_insert(entry);
+            }
+        }
+
+        let mut best_entries = best_by_gene.into_values().collect::<Vec<_>>();
+        best_entries.sort_by(|a, b| {
+            a.match_type.cmp(&b.match_type)
+                .then_with(|| a.gene_uniquename.cmp(&b.gene_uniquename))
+        });
+
+        let matches = best_entries.iter()
+            .map(|entry| {
+                let gene_summary = maps.gene_summaries.get(&entry.gene_uniquename);
+                IdMatch {
+                    gene_uniquename: entry.gene_uniquename.clone(),
+                    gene_name: gene_summary.and_then(|summary| summary.name.clone()),
+                    product: gene_summary.and_then(|summary| summary.product.clone()),
+                    match_type: entry.match_type,
+                    matched_id: entry.matched_id.clone(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let status = match matches.len() {
+            0 => IdMappingStatus::Unmatched,
+            1 => IdMappingStatus::Matched,
+            _ => IdMappingStatus::Ambiguous,
+        };
+
+        let best_match =
+            match (matches.first(), matches.get(1)) {
+                (Some(first), Some(second)) if first.match_type == second.match_type => None,
+                (Some(first), _) => Some(first.gene_uniquename.clone()),
+                _ => None,
+            };
+
+        IdMappingResult {
+            input: input.into(),
+            status,
+            best_match,
+            matches,
+        }
+    }
+
+    // Map each of the ids, ignoring blank entries
+    pub fn map_ids(&self, maps: &APIMaps, ids: &[impl AsRef<str>]) -> Vec<IdMappingResult> {
+        ids.iter()
+            .map(|id| id.as_ref())
+            .filter(|id| !id.trim().is_empty())
+            .map(|id| self.map_id(maps, id))
+            .collect()
+    }
+}
@@ -0,0 +1,247 @@
+// Request, query and cache metrics for pombase-server, returned by
+// ServerMetrics::render() in the Prometheus text format.  The request
+// metrics are labelled with the route that matched, eg.
+// "/api/v1/dataset/:release/data/gene/:id", rather than the path so that
+// there isn't a separate series for every gene.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::api::query_cache::QueryCacheStats;
+use crate::api_data::MapsDatabaseCacheStats;
+use crate::api::details_cache::DetailsCacheStats;
+
+struct Histogram {
+    // the number of observations less than or equal to each bucket bound
+    bucket_counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bucket_count: usize) -> Histogram {
+        Histogram {
+            bucket_counts: vec![0; bucket_count],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, bucket_bounds: &[f64], value: f64) {
+        for (bound, bucket_count) in bucket_bounds.iter().zip(self.bucket_counts.iter_mut()) {
+            if value <= *bound {
+                *bucket_count += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Default)]
+struct MetricsData {
+    // keyed by (method, route, status)
+    request_counts: BTreeMap<(String, String, u16), u64>,
+    // keyed by (method, route)
+    request_durations: BTreeMap<(String, String), Histogram>,
+    // keyed by release name
+    query_durations: BTreeMap<String, Histogram>,
+    // keyed by the name of the upstream service, eg. "django"
+    upstream_errors: BTreeMap<String, u64>,
+}
+
+// The cache statistics of a release, collected when the metrics are
+// rendered
+pub struct ReleaseCacheStats {
+    pub release_name: String,
+    pub maps_cache: MapsDatabaseCacheStats,
+    pub query_cache: QueryCacheStats,
+}
+
+pub struct ServerMetrics {
+    latency_buckets: Vec<f64>,
+    data: Mutex<MetricsData>,
+}
+
+// the name, type and help text of a cache metric, and a function that
+// returns its value from the cache statistics
+type CacheMetric<S> = (&'static str, &'static str, &'static str, fn(&S) -> String);
+
+// escape a label value as required by the Prometheus text format
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn write_header(out: &mut String, name: &str, metric_type: &str, help: &str) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} {}", name, metric_type).unwrap();
+}
+
+fn write_histogram(out: &mut String, name: &str, labels: &str, bucket_bounds: &[f64],
+                   histogram: &Histogram) {
+    for (bound, bucket_count) in bucket_bounds.iter().zip(&histogram.bucket_counts) {
+        writeln!(out, "{}_bucket{{{},le=\"{}\"}} {}", name, labels, bound, bucket_count).unwrap();
+    }
+    writeln!(out, "{}_bucket{{{},le=\"+Inf\"}} {}", name, labels, histogram.count).unwrap();
+    writeln!(out, "{}_sum{{{}}} {}", name, labels, histogram.sum).unwrap();
+    writeln!(out, "{}_count{{{}}} {}", name, labels, histogram.count).unwrap();
+}
+
+impl ServerMetrics {
+    pub fn new(latency_buckets: &[f64]) -> ServerMetrics {
+        let mut latency_buckets = latency_buckets.iter()
+            .filter(|bound| bound.is_finite())
+            .cloned()
+            .collect::<Vec<_>>();
+        latency_buckets.sort_by(|a, b| a.total_cmp(b));
+        latency_buckets.dedup();
+
+        ServerMetrics {
+            latency_buckets,
+            data: Mutex::new(MetricsData::default()),
+        }
+    }
+
+    pub fn record_request(&self, method: &str, route: &str, status: u16, duration: Duration) {
+        let mut data = self.data.lock().unwrap();
+
+        *data.request_counts.entry((method.to_owned(), route.to_owned(), status))
+            .or_default() += 1;
+
+        data.request_durations.entry((method.to_owned(), route.to_owned()))
+            .or_insert_with(|| Histogram::new(self.latency_buckets.len()))
+            .observe(&self.latency_buckets, duration.as_secs_f64());
+    }
+
+    pub fn record_query(&self, release_name: &str, duration: Duration) {
+        self.data.lock().unwrap().query_durations.entry(release_name.to_owned())
+            .or_insert_with(|| Histogram::new(self.latency_buckets.len()))
+            .observe(&self.latency_buckets, duration.as_secs_f64());
+    }
+
+    pub fn record_upstream_error(&self, upstream: &str) {
+        *self.data.lock().unwrap().upstream_errors.entry(upstream.to_owned())
+            .or_default() += 1;
+    }
+
+    pub fn render(&self, release_cache_stats: &[ReleaseCacheStats]) -> String {
+        let mut out = String::new();
+
+        {
+            let data = self.data.lock().unwrap();
+
+            write_header(&mut out, "pombase_http_requests_total", "counter",
+                         "The number of HTTP requests by route and status");
+            for ((method, route, status), count) in &data.request_counts {
+                writeln!(out, "pombase_http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+                         escape_label(method), escape_label(route), status, count).unwrap();
+            }
+
+            write_header(&mut out, "pombase_http_request_duration_seconds", "histogram",
+                         "The time taken to handle HTTP requests by route");
+            for ((method, route), histogram) in &data.request_durations {
+                let labels = format!("method=\"{}\",route=\"{}\"",
+                                     escape_label(method), escape_label(route));
+                write_histogram(&mut out, "pombase_http_request_duration_seconds", &labels,
+                                &self.latency_buckets, histogram);
+            }
+
+            write_header(&mut out, "pombase_query_duration_seconds", "histogram",
+                         "The time taken to run advanced search queries by release");
+            for (release_name, histogram) in &data.query_durations {
+                let labels = format!("release=\"{}\"", escape_label(release_name));
+                write_histogram(&mut out, "pombase_query_duration_seconds", &labels,
+                                &self.latency_buckets, histogram);
+            }
+
+            write_header(&mut out, "pombase_upstream_errors_total", "counter",
+                         "The number of failed requests to Django and the search backend");
+            for (upstream, count) in &data.upstream_errors {
+                writeln!(out, "pombase_upstream_errors_total{{upstream=\"{}\"}} {}",
+                         escape_label(upstream), count).unwrap();
+            }
+        }
+
+        self.render_cache_stats(&mut out, release_cache_stats);
+
+        out
+    }
+
+    fn render_cache_stats(&self, out: &mut String, release_cache_stats: &[ReleaseCacheStats]) {
+        let maps_caches = |stats: &ReleaseCacheStats| {
+            let maps_cache = &stats.maps_cache;
+            [("genes", maps_cache.genes.clone()),
+             ("terms", maps_cache.terms.clone()),
+             ("references", maps_cache.references.clone()),
+             ("alleles", maps_cache.alleles.clone()),
+             ("genotypes", maps_cache.genotypes.clone()),
+             ("termid_genotype_annotations", maps_cache.termid_genotype_annotations.clone()),
+             ("annotation_details", maps_cache.annotation_details.clone())]
+        };
+
+        let maps_cache_metrics: [CacheMetric<DetailsCacheStats>; 5] = [
+            ("pombase_maps_cache_hits_total", "counter",
+             "Lookups found in the maps database caches",
+             |stats| stats.hits.to_string()),
+            ("pombase_maps_cache_misses_total", "counter",
+             "Lookups not found in the maps database caches",
+             |stats| stats.misses.to_string()),
+            ("pombase_maps_cache_evictions_total", "counter",
+             "Entries removed from the full maps database caches",
+             |stats| stats.evictions.to_string()),
+            ("pombase_maps_cache_entries", "gauge",
+             "The number of entries in the maps database caches",
+             |stats| stats.entry_count.to_string()),
+            ("pombase_maps_cache_hit_ratio", "gauge",
+             "The fraction of lookups found in the maps database caches",
+             |stats| {
+                 let lookups = stats.hits + stats.misses;
+                 if lookups == 0 {
+                     "0".to_owned()
+                 } else {
+                     (stats.hits as f64 / lookups as f64).to_string()
+                 }
+             }),
+        ];
+
+        for (name, metric_type, help, value_of) in maps_cache_metrics {
+            write_header(out, name, metric_type, help);
+            for stats in release_cache_stats {
+                for (cache_name, cache_stats) in maps_caches(stats) {
+                    writeln!(out, "{}{{release=\"{}\",cache=\"{}\"}} {}", name,
+                             escape_label(&stats.release_name), cache_name,
+                             value_of(&cache_stats)).unwrap();
+                }
+            }
+        }
+
+        let query_cache_metrics: [CacheMetric<QueryCacheStats>; 3] = [
+            ("pombase_query_cache_hits_total", "counter",
+             "Query sub-results found in the query cache",
+             |stats| stats.hits.to_string()),
+            ("pombase_query_cache_misses_total", "counter",
+             "Query sub-results not found in the query cache",
+             |stats| stats.misses.to_string()),
+            ("pombase_query_cache_entries", "gauge",
+             "The number of entries in the query cache",
+             |stats| stats.entry_count.to_string()),
+        ];
+
+        for (name, metric_type, help, value_of) in query_cache_metrics {
+            write_header(out, name, metric_type, help);
+            for stats in release_cache_stats {
+                writeln!(out, "{}{{release=\"{}\"}} {}", name,
+                         escape_label(&stats.release_name), value_of(&stats.query_cache)).unwrap();
+            }
+        }
+
+        write_header(out, "pombase_maps_database_connections", "gauge",
+                     "The number of connections used to read the maps database");
+        for stats in release_cache_stats {
+            writeln!(out, "pombase_maps_database_connections{{release=\"{}\"}} {}",
+                     escape_label(&stats.release_name), stats.maps_cache.connection_count).unwrap();
+        }
+    }
+}
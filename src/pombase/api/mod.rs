@@ -1,4 +1,6 @@
 pub mod query;
+pub mod query_text;
+pub mod query_validation;
 pub mod search_types;
 pub mod term_search;
 pub mod ref_search;
@@ -6,7 +8,20 @@ pub mod allele_search;
 pub mod doc_search;
 pub mod search;
 pub mod search_utils;
+pub mod search_index;
+pub mod embedded_search;
 pub mod result;
 pub mod query_exec;
+pub mod query_cache;
+pub mod details_cache;
+pub mod metrics;
+pub mod server_log;
+pub mod enrichment;
+pub mod reverse_query;
+pub mod motif_search;
+pub mod gene_complete;
+pub mod id_mapping;
+pub mod openapi;
 pub mod site_db;
 pub mod stats_plot;
+pub mod svg_plot;
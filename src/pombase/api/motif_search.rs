@@ -0,0 +1,297 @@
+// Protein motif search over the protein sequences in
+// APIGeneSummary.transcripts.
+//
+// A pattern can be a regular expression, eg. "S[KR]..P", or a PROSITE
+// style pattern, eg. "[ST]-x(2)-{P}-[DE]".  Patterns that contain a "-"
+// outside of square brackets are treated as PROSITE patterns, as are
+// patterns that end with "." and use other PROSITE syntax: "<" or ">"
+// anchors, "x(n)" repeats or "{..}" exclusions.  In both styles "x" or "X"
+// matches any residue and matching ignores case.
+
+use std::collections::HashSet;
+
+use regex::{Regex, RegexBuilder};
+
+use crate::api_data::APIData;
+use crate::types::GeneUniquename;
+
+use flexstr::{SharedStr as FlexStr, shared_fmt as flex_fmt};
+
+// the maximum number of matches returned for each peptide
+pub const DEFAULT_MAX_MATCHES_PER_GENE: usize = 100;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MotifMatch {
+    // 1-based residue positions, end inclusive
+    pub start: usize,
+    pub end: usize,
+    #[serde(rename = "match")]
+    pub match_residues: FlexStr,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MotifPeptideResult {
+    pub peptide_id: FlexStr,
+    pub gene_id: GeneUniquename,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub gene_name: Option<FlexStr>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub gene_product: Option<FlexStr>,
+    // empty for peptides after the first max_gene_details genes
+    pub matches: Vec<MotifMatch>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MotifSearchResult {
+    pub status: FlexStr,
+    pub peptide_matches: Vec<MotifPeptideResult>,
+    // true if more than max_gene_details genes matched
+    pub too_many_genes: bool,
+}
+
+fn is_prosite_pattern(pattern: &str) -> bool {
+    let mut in_brackets = false;
+
+    for c in pattern.chars() {
+        match c {
+            '[' => in_brackets = true,
+            ']' => in_brackets = false,
+            '-' if !in_brackets => return true,
+            _ => (),
+        }
+    }
+
+    let body =
+        match pattern.strip_suffix('.') {
+            Some(body) => body,
+            None => return false,
+        };
+
+    let bytes = body.as_bytes();
+
+    body.starts_with('<') || body.ends_with('>') ||
+        bytes.windows(3).any(|w| (w[0] == b'x' || w[0] == b'X') && w[1] == b'(' &&
+                             w[2].is_ascii_digit()) ||
+        bytes.windows(2).any(|w| w[0] == b'{' && w[1].is_ascii_alphabetic())
+}
+
+// replace the "x" and "X" residues of a regular expression with "." but
+// not the "x" of escapes like "\x41" or residues in character classes
+fn replace_any_residue(pattern: &str) -> String {
+    let mut regex = String::new();
+    let mut class_depth = 0;
+    let mut chars = pattern.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                regex.push(c);
+                if let Some(escaped) = chars.next() {
+                    regex.push(escaped);
+                }
+            },
+            '[' => {
+                class_depth += 1;
+                regex.push(c);
+            },
+            ']' if class_depth > 0 => {
+                class_depth -= 1;
+                regex.push(c);
+            },
+            'x' | 'X' if class_depth == 0 => regex.push('.'),
+            _ => regex.push(c),
+        }
+    }
+
+    regex
+}
+
+// convert one element of a PROSITE pattern, eg. "x(2,4)", "[ST]" or "{P}"
+fn prosite_element_to_regex(element: &str) -> Result<String, String> {
+    let (residues, repeat) =
+        match element.find('(') {
+            Some(idx) => {
+                let repeat = element[idx + 1..].strip_suffix(')')
+                    .ok_or_else(|| format!("unmatched bracket in: {}", element))?;
+                (&element[..idx], Some(repeat))
+            },
+            None => (element, None),
+        };
+
+    let residues_regex =
+        if residues == "x" || residues == "X" {
+            ".".to_owned()
+        } else if let Some(excluded) = residues.strip_prefix('{') {
+            let excluded = excluded.strip_suffix('}')
+                .ok_or_else(|| format!("unmatched brace in: {}", element))?;
+            format!("[^{}]", excluded)
+        } else if residues.starts_with('[') {
+            if !residues.ends_with(']') {
+                return Err(format!("unmatched bracket in: {}", element));
+            }
+            residues.to_owned()
+        } else if !residues.is_empty() && residues.chars().all(|c| c.is_ascii_alphabetic()) {
+            residues.to_owned()
+        } else {
+            return Err(format!("can't parse pattern element: {}", element));
+        };
+
+    match repeat {
+        Some(repeat) => {
+            if !repeat.chars().all(|c| c.is_ascii_digit() || c == ',') {
+                return Err(format!("can't parse repeat count in: {}", element));
+            }
+            Ok(format!("{}{{{}}}", residues_regex, repeat))
+        },
+        None => Ok(residues_regex),
+    }
+}
+
+// convert a PROSITE pattern like "<M-x(2)-[ST]-{P}." to a regular expression
+pub fn prosite_to_regex(pattern: &str) -> Result<String, String> {
+    let mut pattern = pattern.trim().strip_suffix('.').unwrap_or(pattern.trim());
+
+    let mut regex = String::new();
+
+    if let Some(rest) = pattern.strip_prefix('<') {
+        regex.push('^');
+        pattern = rest;
+    }
+
+    let at_end = pattern.ends_with('>');
+    if at_end {
+        pattern = &pattern[..pattern.len() - 1];
+    }
+
+    for element in pattern.split('-') {
+        regex.push_str(&prosite_element_to_regex(element.trim())?);
+    }
+
+    if at_end {
+        // the sequence may end with a stop codon
+        regex.push_str(r"\*?$");
+    }
+
+    Ok(regex)
+}
+
+// make a case insensitive Regex from a PROSITE or regular expression pattern
+pub fn motif_regex(pattern: &str) -> Result<Regex, FlexStr> {
+    let pattern = pattern.trim();
+
+    if pattern.is_empty() {
+        return Err("empty motif pattern".into());
+    }
+
+    let regex_string =
+        if is_prosite_pattern(pattern) {
+            prosite_to_regex(pattern)
+                .map_err(|err| flex_fmt!("invalid PROSITE pattern \"{}\": {}", pattern, err))?
+        } else {
+            replace_any_residue(pattern)
+        };
+
+    RegexBuilder::new(&regex_string)
+        .case_insensitive(true)
+        .build()
+        .map_err(|err| flex_fmt!("invalid motif pattern \"{}\": {}", pattern, err))
+}
+
+fn find_matches(regex: &Regex, sequence: &str, max_matches: usize) -> Vec<MotifMatch> {
+    regex.find_iter(sequence)
+        .filter(|found| !found.as_str().is_empty())
+        .take(max_matches)
+        .map(|found| MotifMatch {
+            start: found.start() + 1,
+            end: found.end(),
+            match_residues: found.as_str().into(),
+        })
+        .collect()
+}
+
+// the genes with a protein that matches the regex
+pub fn genes_matching_motif(api_data: &APIData, regex: &Regex) -> Vec<GeneUniquename> {
+    api_data.get_maps().gene_summaries.values()
+        .filter(|gene_summary| {
+            gene_summary.transcripts.iter()
+                .filter_map(|transcript| transcript.protein.as_ref())
+                .any(|protein| regex.is_match(&protein.sequence))
+        })
+        .map(|gene_summary| gene_summary.uniquename.clone())
+        .collect()
+}
+
+// Search the proteins of the genes in the scope, which is "all" or the
+// name of a gene subset.  Match details are returned for the first
+// max_gene_details genes, sorted by gene uniquename.
+pub fn motif_search(api_data: &APIData, scope: &str, pattern: &str,
+                    max_gene_details: usize, max_matches_per_gene: usize)
+                    -> MotifSearchResult
+{
+    let regex =
+        match motif_regex(pattern) {
+            Ok(regex) => regex,
+            Err(err) => {
+                return MotifSearchResult {
+                    status: err,
+                    peptide_matches: vec![],
+                    too_many_genes: false,
+                };
+            }
+        };
+
+    let scope_genes: Option<HashSet<GeneUniquename>> =
+        if scope == "all" {
+            None
+        } else {
+            Some(api_data.genes_of_subset(&scope.into()).into_iter().collect())
+        };
+
+    let mut gene_uniquenames = genes_matching_motif(api_data, &regex);
+    if let Some(ref scope_genes) = scope_genes {
+        gene_uniquenames.retain(|gene_uniquename| scope_genes.contains(gene_uniquename));
+    }
+    gene_uniquenames.sort();
+
+    let mut peptide_matches = vec![];
+
+    for (idx, gene_uniquename) in gene_uniquenames.iter().enumerate() {
+        let Some(gene_summary) = api_data.get_gene_summary(gene_uniquename)
+        else {
+            continue;
+        };
+
+        for transcript in &gene_summary.transcripts {
+            let Some(ref protein) = transcript.protein
+            else {
+                continue;
+            };
+
+            let matches =
+                if idx < max_gene_details {
+                    find_matches(&regex, &protein.sequence, max_matches_per_gene)
+                } else {
+                    vec![]
+                };
+
+            if matches.is_empty() && idx < max_gene_details {
+                // only some of the transcripts of the gene match
+                continue;
+            }
+
+            peptide_matches.push(MotifPeptideResult {
+                peptide_id: protein.uniquename.clone(),
+                gene_id: gene_uniquename.clone(),
+                gene_name: gene_summary.name.clone(),
+                gene_product: gene_summary.product.clone(),
+                matches,
+            });
+        }
+    }
+
+    MotifSearchResult {
+        status: "OK".into(),
+        peptide_matches,
+        too_many_genes: gene_uniquenames.len() > max_gene_details,
+    }
+}
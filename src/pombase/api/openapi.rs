@@ -0,0 +1,599 @@
+// An OpenAPI 3 description of the pombase-server API.  The routes are
+// listed in API_ROUTES, which pombase-server checks against its router.
+// The properties of the data types come from their serde Deserialize
+// implementations so the field names always match the JSON that the
+// server returns.
+
+use std::cell::Cell;
+
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde_json::{json, Map, Value};
+
+use crate::api::details_cache::DetailsCacheStats;
+use crate::api::doc_search::DocSearchMatch;
+use crate::api::enrichment::{EnrichmentOptions, EnrichmentResult};
+use crate::api::gene_complete::GeneCompletionMatch;
+use crate::api::id_mapping::{IdMappingResult, IdMatch};
+use crate::api::motif_search::MotifSearchResult;
+use crate::api::query::Query;
+use crate::api::query_cache::QueryCacheStats;
+use crate::api::query_validation::QueryValidationError;
+use crate::api::result::{AlleleResultRow, GeneExValue, GenotypeResultRow, QueryAPIResult,
+                         QueryNodeExplanation, ResultRow};
+use crate::api_data::MapsDatabaseCacheStats;
+use crate::data_types::{AlleleDetails, FeatureShort, GeneDetails, GenotypeDetails, GoCamDetails,
+                        Metadata, ProteinViewData, ReferenceDetails, ReferenceShort,
+                        SolrAlleleSummary, SolrReferenceSummary, SolrTermSummary, TermDetails,
+                        TermShort};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiResponse {
+    // the name of a schema in components/schemas, or "[Name]" for an
+    // array of them
+    Json(&'static str),
+    Svg,
+    Text,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ApiRoute {
+    pub method: &'static str,
+    // in axum syntax, eg. "/api/v1/dataset/:release/data/gene/:id"
+    pub path: &'static str,
+    pub summary: &'static str,
+    // (name, JSON type) of optional query string parameters
+    pub query_params: &'static [(&'static str, &'static str)],
+    pub request_body: Option<&'static str>,
+    pub response: ApiResponse,
+    pub admin_only: bool,
+    // true for the routes that use the search index, which is built from
+    // the latest release
+    pub latest_release_only: bool,
+}
+
+const fn route(method: &'static str, path: &'static str, summary: &'static str,
+               response: ApiResponse)
+               -> ApiRoute
+{
+    ApiRoute {
+        method,
+        path,
+        summary,
+        query_params: &[],
+        request_body: None,
+        response,
+        admin_only: false,
+        latest_release_only: false,
+    }
+}
+
+const fn post_route(path: &'static str, summary: &'static str, request_body: &'static str,
+                    response: ApiResponse)
+                    -> ApiRoute
+{
+    ApiRoute {
+        request_body: Some(request_body),
+        ..route("post", path, summary, response)
+    }
+}
+
+use ApiResponse::{Json, Svg, Text};
+
+pub const API_ROUTES: &[ApiRoute] = &[
+    ApiRoute {
+        latest_release_only: true,
+        ..route("get", "/api/v1/dataset/:release/complete/allele/*q",
+                "Complete allele names, descriptions and synonyms",
+                Json("AlleleCompletionResponse"))
+    },
+    ApiRoute {
+        latest_release_only: true,
+        ..route("get", "/api/v1/dataset/:release/complete/ref/:q",
+                "Complete reference IDs, titles and authors",
+                Json("RefCompletionResponse"))
+    },
+    ApiRoute {
+        query_params: &[("taxonid", "integer")],
+        ..route("get", "/api/v1/dataset/:release/complete/gene/:q",
+                "Complete gene IDs, names, synonyms, UniProt accessions and secondary IDs",
+                Json("GeneCompletionResponse"))
+    },
+    ApiRoute {
+        latest_release_only: true,
+        ..route("get", "/api/v1/dataset/:release/complete/term/:cv_name/:q",
+                "Complete term names in a CV",
+                Json("TermCompletionResponse"))
+    },
+    route("get", "/api/v1/dataset/:release/data/allele/:id",
+          "Allele page data", Json("AlleleDetails")),
+    route("get", "/api/v1/dataset/:release/data/gene/:id",
+          "Gene page data", Json("GeneDetails")),
+    route("get", "/api/v1/dataset/:release/data/genotype/:id",
+          "Genotype page data", Json("GenotypeDetails")),
+    route("get", "/api/v1/dataset/:release/data/reference/:id",
+          "Reference page data", Json("ReferenceDetails")),
+    ApiRoute {
+        query_params: &[("format", "string")],
+        ..post_route("/api/v1/dataset/:release/batch/gene", "Gene page data of a list of genes",
+                     "BatchRequest", Json("GeneBatchResponse"))
+    },
+    ApiRoute {
+        query_params: &[("format", "string")],
+        ..post_route("/api/v1/dataset/:release/batch/term", "Term page data of a list of terms",
+                     "BatchRequest", Json("TermBatchResponse"))
+    },
+    ApiRoute {
+        query_params: &[("format", "string")],
+        ..post_route("/api/v1/dataset/:release/batch/allele", "Allele page data of a list of alleles",
+                     "BatchRequest", Json("AlleleBatchResponse"))
+    },
+    ApiRoute {
+        query_params: &[("format", "string")],
+        ..post_route("/api/v1/dataset/:release/batch/genotype",
+                     "Genotype page data of a list of genotypes",
+                     "BatchRequest", Json("GenotypeBatchResponse"))
+    },
+    ApiRoute {
+        query_params: &[("format", "string")],
+        ..post_route("/api/v1/dataset/:release/batch/reference",
+                     "Reference page data of a list of references",
+                     "BatchRequest", Json("ReferenceBatchResponse"))
+    },
+    route("get", "/api/v1/dataset/:release/data/seq_feature_page_features",
+          "The features shown on the sequence feature page", Json("[FeatureShort]")),
+    route("get", "/api/v1/dataset/:release/data/term/:id",
+          "Term page data", Json("TermDetails")),
+    route("get", "/api/v1/dataset/:release/data/gocam/:full_or_widget/:gene_uniquename",
+          "The IDs of the GO-CAM models of a gene", Json("[string]")),
+    route("get", "/api/v1/dataset/:release/data/gocam/all",
+          "All GO-CAM models", Json("[GoCamDetails]")),
+    route("get", "/api/v1/dataset/:release/data/gocam/by_id/:gocam_id",
+          "One GO-CAM model", Json("GoCamDetails")),
+    route("get", "/api/v1/dataset/:release/gene_ex_violin_plot/:plot_size/:genes",
+          "Gene expression violin plot of a comma separated list of genes", Svg),
+    route("get", "/api/v1/dataset/:release/stats/:type",
+          "A graph of the curation statistics", Svg),
+    route("get", "/api/v1/dataset/:release/motif_search/:scope/:q/:max_gene_details",
+          "Search protein sequences for a motif", Json("MotifSearchResult")),
+    route("get", "/api/v1/dataset/:release/protein_features/:full_or_widget/:gene_uniquename",
+          "The protein feature viewer tracks of a gene", Json("ProteinViewData")),
+    route("get", "/api/v1/dataset/:release/query/:q",
+          "Run a query given as JSON in the path", Json("QueryAPIResult")),
+    post_route("/api/v1/dataset/:release/query", "Run a query",
+               "Query", Json("QueryAPIResult")),
+    post_route("/api/v1/dataset/:release/query/validate", "Check a query without running it",
+               "Query", Json("QueryValidationResponse")),
+    route("get", "/api/v1/dataset/:release/query_cache/stats",
+          "Query cache statistics", Json("QueryCacheStatsResponse")),
+    route("get", "/api/v1/dataset/:release/maps_cache/stats",
+          "Statistics of the gene, term, allele etc. details caches",
+          Json("MapsCacheStatsResponse")),
+    post_route("/api/v1/dataset/:release/enrichment",
+               "Find the terms that are over-represented in a list of genes",
+               "EnrichmentRequest", Json("EnrichmentResponse")),
+    post_route("/api/v1/dataset/:release/id_mapping",
+               "Map a list of gene identifiers, names and synonyms to genes",
+               "IdMappingRequest", Json("IdMappingResponse")),
+    route("get", "/api/v1/dataset/:release/text_query/:q",
+          "Run a query written in the text query syntax", Json("QueryAPIResult")),
+    ApiRoute {
+        latest_release_only: true,
+        ..route("get", "/api/v1/dataset/:release/search/:scope/:q",
+                "Search terms, references and documentation", Json("SearchResponse"))
+    },
+    ApiRoute {
+        latest_release_only: true,
+        ..route("get", "/api/v1/dataset/:release/summary/term/:id",
+                "A short summary of a term", Json("TermLookupResponse"))
+    },
+    route("get", "/api/v1/releases",
+          "The names of the releases that can be used in the dataset paths",
+          Json("ReleasesResponse")),
+    route("get", "/api/v1/status",
+          "The loaded releases and the state of the last reload", Json("ServerStatusResponse")),
+    route("get", "/api/v1/openapi.json", "This document", Json("object")),
+    ApiRoute {
+        admin_only: true,
+        ..route("post", "/admin/reload", "Read the data files again in the background",
+                Json("ReloadResponse"))
+    },
+    ApiRoute {
+        admin_only: true,
+        ..route("post", "/admin/dataset/:release/query_cache/clear",
+                "Empty the query cache of a release", Json("QueryCacheStatsResponse"))
+    },
+    route("get", "/ping", "Check that the server is running", Text),
+];
+
+// Collects the field names that a derived Deserialize implementation
+// passes to deserialize_struct(), then stops with an error
+struct FieldNames<'a> {
+    fields: &'a Cell<Option<&'static [&'static str]>>,
+}
+
+impl<'de> Deserializer<'de> for FieldNames<'_> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self, _: &'static str,
+                                           fields: &'static [&'static str], _: V)
+                                           -> Result<V::Value, Self::Error>
+    {
+        self.fields.set(Some(fields));
+        Err(de::Error::custom("finished"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+// The JSON field names of a struct that derives Deserialize
+pub fn struct_field_names<'de, T: Deserialize<'de>>() -> &'static [&'static str] {
+    let fields = Cell::new(None);
+    let _ = T::deserialize(FieldNames { fields: &fields });
+    fields.get().unwrap_or(&[])
+}
+
+fn schema_of(name: &str) -> Value {
+    if let Some(item_name) = name.strip_prefix('[').and_then(|name| name.strip_suffix(']')) {
+        return json!({ "type": "array", "items": schema_of(item_name) });
+    }
+
+    match name {
+        "string" | "integer" | "number" | "boolean" | "object" => json!({ "type": name }),
+        _ => json!({ "$ref": format!("#/components/schemas/{}", name) }),
+    }
+}
+
+// A schema with a property for each field of T.  properties gives the
+// schemas of the fields that are worth describing, the others can be any
+// JSON value.
+fn struct_schema<'de, T: Deserialize<'de>>(properties: &[(&str, &str)]) -> Value {
+    let field_names = struct_field_names::<T>();
+
+    debug_assert!(properties.iter().all(|(name, _)| field_names.contains(name)),
+                  "unknown field in {:?}", properties);
+
+    let property_schemas = field_names.iter()
+        .map(|field_name| {
+            let schema = properties.iter()
+                .find(|(name, _)| name == field_name)
+                .map(|(_, schema_name)| schema_of(schema_name))
+                .unwrap_or_else(|| json!({}));
+            (field_name.to_string(), schema)
+        })
+        .collect::<Map<_, _>>();
+
+    json!({ "type": "object", "properties": property_schemas })
+}
+
+// A schema for the response types defined in pombase-server
+fn object_schema(properties: &[(&str, &str)]) -> Value {
+    let property_schemas = properties.iter()
+        .map(|(name, schema_name)| (name.to_string(), schema_of(schema_name)))
+        .collect::<Map<_, _>>();
+
+    json!({ "type": "object", "properties": property_schemas })
+}
+
+// the response of the batch endpoints: a map from ID to details, and the
+// IDs that weren't found
+fn batch_response_schema(details_name: &str) -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "status": { "type": "string" },
+            "found": {
+                "type": "object",
+                "additionalProperties": schema_of(details_name),
+            },
+            "missing": { "type": "array", "items": { "type": "string" } },
+        },
+    })
+}
+
+fn component_schemas() -> Map<String, Value> {
+    let schemas = [
+        ("QueryAPIResult", struct_schema::<QueryAPIResult>(&[
+            ("query", "Query"), ("id", "string"), ("status", "string"),
+            ("total_count", "integer"), ("rows", "[ResultRow]"),
+            ("allele_rows", "[AlleleResultRow]"), ("genotype_rows", "[GenotypeResultRow]"),
+            ("explanation", "QueryNodeExplanation"), ("enrichment", "EnrichmentResult"),
+            ("reference_rows", "[ReferenceShort]"), ("term_rows", "[TermShort]"),
+        ])),
+        ("ResultRow", struct_schema::<ResultRow>(&[
+            ("gene_uniquename", "string"), ("characterisation_status", "string"),
+            ("taxonomic_distribution", "string"), ("ortholog_taxonids", "[integer]"),
+            ("physical_interactors", "[string]"), ("molecular_weight", "number"),
+            ("protein_length", "integer"), ("spliced_rna_length", "integer"),
+            ("unspliced_rna_length", "integer"), ("sequence", "string"),
+            ("gaf_lines", "string"), ("reference_uniquenames", "[string]"),
+            ("pdb_ids", "[string]"), ("rnacentral_id", "string"), ("gocam_ids", "[string]"),
+            ("paralogs", "[string]"), ("subsets", "[string]"),
+            ("gene_expression", "[GeneExValue]"),
+        ])),
+        ("GeneExValue", struct_schema::<GeneExValue>(&[
+            ("dataset_name", "string"), ("value", "string"),
+        ])),
+        ("AlleleResultRow", struct_schema::<AlleleResultRow>(&[
+            ("allele_uniquename", "string"), ("name", "string"), ("allele_type", "string"),
+            ("description", "string"), ("gene_uniquename", "string"), ("gene_name", "string"),
+        ])),
+        ("GenotypeResultRow", struct_schema::<GenotypeResultRow>(&[
+            ("genotype_uniquename", "string"), ("display_name", "string"), ("name", "string"),
+            ("allele_uniquenames", "[string]"), ("gene_uniquenames", "[string]"),
+        ])),
+        ("QueryNodeExplanation", struct_schema::<QueryNodeExplanation>(&[
+            ("node_name", "string"), ("node_type", "string"), ("result_count", "integer"),
+            ("exec_time_ms", "number"), ("children", "[QueryNodeExplanation]"),
+        ])),
+        ("Query", struct_schema::<Query>(&[])),
+        ("QueryValidationError", struct_schema::<QueryValidationError>(&[])),
+        ("QueryCacheStats", struct_schema::<QueryCacheStats>(&[
+            ("hits", "integer"), ("misses", "integer"), ("entry_count", "integer"),
+            ("max_entries", "integer"),
+        ])),
+        ("DetailsCacheStats", struct_schema::<DetailsCacheStats>(&[
+            ("hits", "integer"), ("misses", "integer"), ("evictions", "integer"),
+            ("entry_count", "integer"), ("max_entries", "integer"),
+        ])),
+        ("MapsDatabaseCacheStats", struct_schema::<MapsDatabaseCacheStats>(&[
+            ("connection_count", "integer"), ("genes", "DetailsCacheStats"),
+            ("terms", "DetailsCacheStats"), ("references", "DetailsCacheStats"),
+            ("alleles", "DetailsCacheStats"), ("genotypes", "DetailsCacheStats"),
+            ("termid_genotype_annotations", "DetailsCacheStats"),
+            ("annotation_details", "DetailsCacheStats"),
+        ])),
+        ("EnrichmentOptions", struct_schema::<EnrichmentOptions>(&[])),
+        ("EnrichmentResult", struct_schema::<EnrichmentResult>(&[])),
+        ("MotifSearchResult", struct_schema::<MotifSearchResult>(&[])),
+        ("GeneCompletionMatch", struct_schema::<GeneCompletionMatch>(&[
+            ("uniquename", "string"), ("name", "string"), ("product", "string"),
+            ("taxonid", "integer"), ("matched_field", "string"), ("matched_value", "string"),
+        ])),
+        ("IdMappingResult", struct_schema::<IdMappingResult>(&[
+            ("input", "string"), ("status", "string"), ("best_match", "string"),
+            ("matches", "[IdMatch]"),
+        ])),
+        ("IdMatch", struct_schema::<IdMatch>(&[
+            ("gene_uniquename", "string"), ("gene_name", "string"), ("product", "string"),
+            ("match_type", "string"), ("matched_id", "string"),
+        ])),
+        ("GeneDetails", struct_schema::<GeneDetails>(&[
+            ("uniquename", "string"), ("name", "string"), ("taxonid", "integer"),
+            ("product", "string"), ("name_descriptions", "[string]"),
+            ("synonyms", "[object]"), ("dbxrefs", "[string]"),
+            ("feature_type", "string"), ("characterisation_status", "string"),
+        ])),
+        ("GenotypeDetails", struct_schema::<GenotypeDetails>(&[])),
+        ("AlleleDetails", struct_schema::<AlleleDetails>(&[])),
+        ("ReferenceDetails", struct_schema::<ReferenceDetails>(&[
+            ("uniquename", "string"), ("title", "string"), ("citation", "string"),
+            ("authors", "string"), ("publication_year", "string"),
+        ])),
+        ("TermDetails", struct_schema::<TermDetails>(&[
+            ("termid", "string"), ("name", "string"), ("cv_name", "string"),
+            ("definition", "string"), ("is_obsolete", "boolean"),
+        ])),
+        ("ReferenceShort", struct_schema::<ReferenceShort>(&[])),
+        ("TermShort", struct_schema::<TermShort>(&[])),
+        ("FeatureShort", struct_schema::<FeatureShort>(&[])),
+        ("GoCamDetails", struct_schema::<GoCamDetails>(&[])),
+        ("ProteinViewData", struct_schema::<ProteinViewData>(&[("sequence", "string")])),
+        ("Metadata", struct_schema::<Metadata>(&[
+            ("db_creation_datetime", "string"), ("date_version", "string"),
+        ])),
+        ("SolrTermSummary", struct_schema::<SolrTermSummary>(&[
+            ("id", "string"), ("name", "string"), ("cv_name", "string"),
+            ("definition", "string"), ("close_synonyms", "[string]"),
+            ("distant_synonyms", "[string]"), ("interesting_parent_ids", "[string]"),
+            ("definition_xrefs", "[string]"), ("secondary_identifiers", "[string]"),
+            ("gocam_ids", "[string]"), ("annotation_count", "integer"),
+            ("gene_count", "integer"), ("genotype_count", "integer"),
+            ("highlighting", "object"),
+        ])),
+        ("SolrReferenceSummary", struct_schema::<SolrReferenceSummary>(&[
+            ("id", "string"), ("title", "string"), ("citation", "string"),
+            ("authors", "string"), ("highlighting", "object"),
+        ])),
+        ("SolrAlleleSummary", struct_schema::<SolrAlleleSummary>(&[
+            ("id", "string"), ("name", "string"), ("allele_type", "string"),
+            ("description", "string"), ("gene_name", "string"), ("gene_uniquename", "string"),
+            ("highlighting", "object"),
+        ])),
+        ("DocSearchMatch", struct_schema::<DocSearchMatch>(&[
+            ("id", "string"), ("heading", "string"), ("hl", "object"),
+        ])),
+
+        // the request and response types of pombase-server
+        ("TermCompletionResponse", object_schema(&[
+            ("status", "string"), ("matches", "[SolrTermSummary]"),
+        ])),
+        ("RefCompletionResponse", object_schema(&[
+            ("status", "string"), ("matches", "[SolrReferenceSummary]"),
+        ])),
+        ("AlleleCompletionResponse", object_schema(&[
+            ("status", "string"), ("matches", "[SolrAlleleSummary]"),
+        ])),
+        ("GeneCompletionResponse", object_schema(&[
+            ("status", "string"), ("matches", "[GeneCompletionMatch]"),
+        ])),
+        ("SearchResponse", object_schema(&[
+            ("status", "string"), ("term_matches", "[SolrTermSummary]"),
+            ("ref_matches", "[SolrReferenceSummary]"), ("doc_matches", "[DocSearchMatch]"),
+        ])),
+        ("TermLookupResponse", object_schema(&[
+            ("status", "string"), ("summary", "SolrTermSummary"),
+        ])),
+        ("QueryValidationResponse", object_schema(&[
+            ("status", "string"), ("errors", "[QueryValidationError]"),
+        ])),
+        ("QueryCacheStatsResponse", object_schema(&[
+            ("status", "string"), ("stats", "QueryCacheStats"),
+        ])),
+        ("MapsCacheStatsResponse", object_schema(&[
+            ("status", "string"), ("stats", "MapsDatabaseCacheStats"),
+        ])),
+        ("EnrichmentRequest", object_schema(&[
+            ("genes", "[string]"), ("options", "EnrichmentOptions"),
+        ])),
+        ("EnrichmentResponse", object_schema(&[
+            ("status", "string"), ("unknown_ids", "[string]"),
+            ("enrichment", "EnrichmentResult"),
+        ])),
+        ("IdMappingRequest", object_schema(&[("ids", "[string]")])),
+        ("BatchRequest", object_schema(&[("ids", "[string]")])),
+        ("GeneBatchResponse", batch_response_schema("GeneDetails")),
+        ("TermBatchResponse", batch_response_schema("TermDetails")),
+        ("AlleleBatchResponse", batch_response_schema("AlleleDetails")),
+        ("GenotypeBatchResponse", batch_response_schema("GenotypeDetails")),
+        ("ReferenceBatchResponse", batch_response_schema("ReferenceDetails")),
+        ("IdMappingResponse", object_schema(&[
+            ("status", "string"), ("matched_count", "integer"),
+            ("ambiguous_count", "integer"), ("unmatched_count", "integer"),
+            ("results", "[IdMappingResult]"),
+        ])),
+        ("ReleasesResponse", object_schema(&[
+            ("status", "string"), ("latest", "string"), ("releases", "[string]"),
+        ])),
+        ("ReleaseStatus", object_schema(&[
+            ("name", "string"), ("db_creation_datetime", "string"), ("date_version", "string"),
+        ])),
+        ("ServerStatusResponse", object_schema(&[
+            ("status", "string"), ("version", "string"), ("loaded_at", "string"),
+            ("latest_release", "string"), ("releases", "[ReleaseStatus]"),
+            ("reload_in_progress", "boolean"), ("last_reload_error", "string"),
+        ])),
+        ("ReloadResponse", object_schema(&[("status", "string")])),
+    ];
+
+    schemas.into_iter()
+        .map(|(name, schema)| (name.to_owned(), schema))
+        .collect()
+}
+
+// Convert an axum path to an OpenAPI path and its parameter names, eg.
+// "/data/gene/:id" to "/data/gene/{id}"
+pub fn openapi_path(axum_path: &str) -> (String, Vec<String>) {
+    let mut params = vec![];
+
+    let path_parts = axum_path.split('/')
+        .map(|part| {
+            match part.strip_prefix(':').or_else(|| part.strip_prefix('*')) {
+                Some(param_name) => {
+                    params.push(param_name.to_owned());
+                    format!("{{{}}}", param_name)
+                },
+                None => part.to_owned(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    (path_parts.join("/"), params)
+}
+
+fn operation(api_route: &ApiRoute) -> Value {
+    let (_, path_params) = openapi_path(api_route.path);
+
+    let mut parameters = path_params.iter()
+        .map(|param_name| {
+            let mut param = json!({
+                "name": param_name,
+                "in": "path",
+                "required": true,
+                "schema": { "type": "string" },
+            });
+            if param_name == "release" {
+                param["description"] =
+                    if api_route.latest_release_only {
+                        json!("\"latest\" or the name of the latest release")
+                    } else {
+                        json!("a release name or \"latest\"")
+                    };
+            }
+            param
+        })
+        .collect::<Vec<_>>();
+
+    for (param_name, param_type) in api_route.query_params {
+        parameters.push(json!({
+            "name": param_name,
+            "in": "query",
+            "required": false,
+            "schema": { "type": param_type },
+        }));
+    }
+
+    let content =
+        match api_route.response {
+            ApiResponse::Json(schema_name) =>
+                json!({ "application/json": { "schema": schema_of(schema_name) } }),
+            ApiResponse::Svg => json!({ "image/svg+xml": { "schema": { "type": "string" } } }),
+            ApiResponse::Text => json!({ "text/plain": { "schema": { "type": "string" } } }),
+        };
+
+    let mut operation = json!({
+        "summary": api_route.summary,
+        "parameters": parameters,
+        "responses": {
+            "200": {
+                "description": "OK",
+                "content": content,
+            },
+        },
+    });
+
+    if let Some(request_body) = api_route.request_body {
+        operation["requestBody"] = json!({
+            "required": true,
+            "content": { "application/json": { "schema": schema_of(request_body) } },
+        });
+    }
+
+    if api_route.admin_only {
+        operation["security"] = json!([{ "adminToken": [] }]);
+    }
+
+    if api_route.latest_release_only {
+        operation["responses"]["404"] = json!({
+            "description": "The release isn't the latest release",
+        });
+    }
+
+    operation
+}
+
+// The OpenAPI document served at /api/v1/openapi.json
+pub fn openapi_spec(version: &str) -> Value {
+    let mut paths = Map::new();
+
+    for api_route in API_ROUTES {
+        let (path, _) = openapi_path(api_route.path);
+        let path_item = paths.entry(path).or_insert_with(|| json!({}));
+        path_item[api_route.method] = operation(api_route);
+    }
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "PomBase API",
+            "version": version,
+        },
+        "paths": paths,
+        "components": {
+            "schemas": component_schemas(),
+            "securitySchemes": {
+                "adminToken": {
+                    "type": "http",
+                    "scheme": "bearer",
+                },
+            },
+        },
+    })
+}
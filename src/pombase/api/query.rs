@@ -1,20 +1,33 @@
 use std::cmp;
+use std::cmp::Ordering;
 use std::str;
+use std::time::Instant;
 
 use std::collections::HashSet;
 
 use async_recursion::async_recursion;
 
+use serde::{Serialize, Serializer};
+use serde::ser::SerializeSeq;
+
 use itertools::Itertools;
 use uuid::Uuid;
 
-use crate::api_data::APIData;
+use crate::api_data::{APIData, MatchingGenotypeAnnotations};
 use crate::api::site_db::SiteDB;
+use crate::api::query_cache::QueryCache;
+use crate::api::enrichment::{EnrichmentOptions, gene_set_enrichment};
+use crate::api::reverse_query::{ReverseQueryOptions, references_of_genes, terms_of_genes};
+use crate::api::motif_search::{motif_regex, genes_matching_motif};
 use crate::api::result::*;
 use crate::data_types::DataLookup;
 use crate::data_types::{APIGeneSummary, TranscriptDetails, FeatureType, GeneShort, InteractionType,
-                       ChromosomeDetails, Strand, Ploidiness, GeneQueryPropFlag};
-use crate::types::CvName;
+                       ChromosomeDetails, Strand, Ploidiness, GeneQueryPropFlag,
+                       GeneQueryTermData, OntAnnotationDetail,
+                       Throughput, CuratorOrcid, ExtPart, ExtRange, GeneExDataSetName,
+                       ProteinFeatureType};
+use crate::types::{AlleleUniquename, CvName, GenotypeDisplayUniquename, ReferenceUniquename,
+                   Residue};
 use crate::types::TermId;
 use crate::web::config::TermAndName;
 
@@ -26,6 +39,74 @@ use crate::types::GeneUniquename;
 
 use flexstr::{SharedStr as FlexStr, ToSharedStr, shared_str as flex_str, shared_fmt as flex_fmt};
 
+// Serialise a HashSet in a fixed order so that equal queries always give
+// the same JSON, see Query::canonical_json()
+pub(crate) fn serialize_sorted_set<T, S>(set: &HashSet<T>, serializer: S) -> Result<S::Ok, S::Error>
+    where T: Serialize, S: Serializer
+{
+    let mut keyed_elements = set.iter()
+        .map(|element| (serde_json::to_string(element).unwrap_or_default(), element))
+        .collect::<Vec<_>>();
+    keyed_elements.sort_by(|(key_a, _), (key_b, _)| key_a.cmp(key_b));
+
+    let mut seq = serializer.serialize_seq(Some(keyed_elements.len()))?;
+    for (_, element) in keyed_elements {
+        seq.serialize_element(element)?;
+    }
+    seq.end()
+}
+
+// a GeneShort with only the uniquename, which is all that's needed to
+// execute a query
+fn id_only_gene(gene: &GeneShort) -> GeneShort {
+    GeneShort {
+        uniquename: gene.uniquename.clone(),
+        name: None,
+        product: None,
+        transcript_count: 1,
+        flags: HashSet::new(),
+    }
+}
+
+fn normalise_genes(genes: &[GeneShort]) -> Vec<GeneShort> {
+    genes.iter()
+        .map(id_only_gene)
+        .sorted_by(|gene_a, gene_b| gene_a.uniquename.cmp(&gene_b.uniquename))
+        .dedup_by(|gene_a, gene_b| gene_a.uniquename == gene_b.uniquename)
+        .collect()
+}
+
+// Combine the normalised operands of an "or" or "and" node.  Operands with
+// the same operator as the parent are merged into the parent and the result
+// is sorted so that operand order doesn't matter.
+fn normalise_operands(nodes: Vec<QueryNode>,
+                      same_operator: fn(&QueryNode) -> &Option<Vec<QueryNode>>)
+                      -> Vec<QueryNode>
+{
+    let mut operands = vec![];
+
+    for node in nodes {
+        match same_operator(&node) {
+            Some(child_nodes) if node.set_node_types().len() == 1 =>
+                operands.extend(child_nodes.iter().cloned()),
+            _ => operands.push(node),
+        }
+    }
+
+    // the operands are already normalised so don't use canonical_key(),
+    // which would normalise them again
+    operands.into_iter()
+        .map(|node| {
+            let key = serde_json::to_string(&node)
+                .unwrap_or_else(|err| panic!("failed to serialise query node: {}", err));
+            (key, node)
+        })
+        .sorted_by(|(key_a, _), (key_b, _)| key_a.cmp(key_b))
+        .dedup_by(|(key_a, _), (key_b, _)| key_a == key_b)
+        .map(|(_, node)| node)
+        .collect()
+}
+
 #[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
 pub enum IntRangeType {
 #[serde(rename = "spliced_rna_length")]
@@ -58,6 +139,38 @@ pub enum FloatRangeType {
     ProteinMolWeight,
 }
 
+// a named part of the genes in a gene expression dataset, ordered by
+// average copies per cell
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Copy, Clone)]
+pub enum GeneExQuantile {
+#[serde(rename = "first_quartile")]
+    FirstQuartile,
+#[serde(rename = "second_quartile")]
+    SecondQuartile,
+#[serde(rename = "third_quartile")]
+    ThirdQuartile,
+#[serde(rename = "fourth_quartile")]
+    FourthQuartile,
+#[serde(rename = "bottom_decile")]
+    BottomDecile,
+#[serde(rename = "top_decile")]
+    TopDecile,
+}
+
+impl GeneExQuantile {
+    // the start and end of the quantile as fractions of the ordered genes
+    pub fn bounds(&self) -> (f64, f64) {
+        match self {
+            GeneExQuantile::FirstQuartile => (0.0, 0.25),
+            GeneExQuantile::SecondQuartile => (0.25, 0.5),
+            GeneExQuantile::ThirdQuartile => (0.5, 0.75),
+            GeneExQuantile::FourthQuartile => (0.75, 1.0),
+            GeneExQuantile::BottomDecile => (0.0, 0.1),
+            GeneExQuantile::TopDecile => (0.9, 1.0),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Copy, Clone)]
 pub enum TargetOfType {
 #[serde(rename = "go")]
@@ -90,8 +203,28 @@ pub enum QueryExpressionFilter {
 
 type TermName = FlexStr;
 type QueryRowsResult = Result<Vec<ResultRow>, FlexStr>;
+type QueryRowsPageResult = Result<QueryRowsPage, FlexStr>;
 type GeneUniquenameVecResult = Result<Vec<GeneUniquename>, FlexStr>;
 
+// the genes from a node and, in explain mode, the explanations of its
+// child nodes
+type ExplainedGenesResult = Result<(Vec<GeneUniquename>, Vec<QueryNodeExplanation>), FlexStr>;
+
+// The query cache keys of a node and its sub-nodes, in the same shape as the
+// node.  The key of a node is the JSON of its normalised form.  They are made
+// by QueryNode::cache_keys(), which normalises each node once.
+struct NodeCacheKeys {
+    key: String,
+    or: Vec<NodeCacheKeys>,
+    and: Vec<NodeCacheKeys>,
+    // node_a then node_b
+    not: Vec<NodeCacheKeys>,
+}
+
+fn operand_keys(keys: Option<&[NodeCacheKeys]>, index: usize) -> Option<&NodeCacheKeys> {
+    keys.and_then(|keys| keys.get(index))
+}
+
 #[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
 pub struct NotNode {
     pub node_a: Box<QueryNode>,
@@ -106,10 +239,132 @@ pub struct TermNode {
     pub single_or_multi_locus: Option<SingleOrMultiLocus>,
     pub ploidiness: Option<Ploidiness>,
     pub expression: Option<QueryExpressionFilter>,
-    #[serde(skip_serializing_if="HashSet::is_empty", default)]
+    #[serde(skip_serializing_if="HashSet::is_empty", default,
+            serialize_with="serialize_sorted_set")]
     pub conditions: HashSet<TermAndName>,
-    #[serde(skip_serializing_if="HashSet::is_empty", default)]
+    #[serde(skip_serializing_if="HashSet::is_empty", default,
+            serialize_with="serialize_sorted_set")]
     pub excluded_conditions: HashSet<TermAndName>,
+
+    // Filters on the provenance of the annotations.  If any are set, only
+    // annotations that pass all of the filters are used.
+    #[serde(skip_serializing_if="HashSet::is_empty", default,
+            serialize_with="serialize_sorted_set")]
+    pub evidence_codes: HashSet<FlexStr>,
+    #[serde(skip_serializing_if="Option::is_none", default)]
+    pub throughput: Option<Throughput>,
+    // inclusive annotation date range, as "YYYY", "YYYY-MM" or "YYYY-MM-DD"
+    #[serde(skip_serializing_if="Option::is_none", default)]
+    pub date_from: Option<FlexStr>,
+    #[serde(skip_serializing_if="Option::is_none", default)]
+    pub date_to: Option<FlexStr>,
+    #[serde(skip_serializing_if="Option::is_none", default)]
+    pub reference_uniquename: Option<ReferenceUniquename>,
+    #[serde(skip_serializing_if="Option::is_none", default)]
+    pub assigned_by: Option<FlexStr>,
+    // the ORCID of the curator, for annotations from Canto
+    #[serde(skip_serializing_if="Option::is_none", default)]
+    pub curator: Option<CuratorOrcid>,
+}
+
+// Compare an annotation date with a filter date at the precision of the
+// shorter one, as either may only have a year or a year and month.  A
+// filter of "2020" matches all dates in 2020 and an annotation date of
+// "2020" matches filters in 2020.  Returns None for malformed dates.
+fn compare_annotation_date(annotation_date: &str, filter_date: &str) -> Option<Ordering> {
+    if !annotation_date.is_ascii() {
+        return None;
+    }
+    let len = filter_date.len().min(annotation_date.len());
+    Some(annotation_date.get(..len)?.cmp(filter_date.get(..len)?))
+}
+
+// true if there is no filter or if the value is equal to the filter
+fn option_matches<T: PartialEq>(filter: &Option<T>, value: &Option<T>) -> bool {
+    match filter {
+        Some(filter) => value.as_ref() == Some(filter),
+        None => true,
+    }
+}
+
+impl TermNode {
+    pub fn has_annotation_filters(&self) -> bool {
+        !self.evidence_codes.is_empty() || self.throughput.is_some() ||
+            self.date_from.is_some() || self.date_to.is_some() ||
+            self.reference_uniquename.is_some() || self.assigned_by.is_some() ||
+            self.curator.is_some()
+    }
+
+    // true if the annotation passes the provenance filters
+    pub fn annotation_matches(&self, annotation_detail: &OntAnnotationDetail) -> bool {
+        if !self.evidence_codes.is_empty() {
+            match annotation_detail.evidence {
+                Some(ref evidence) =>
+                    if !self.evidence_codes.contains(evidence) {
+                        return false;
+                    },
+                None => return false,
+            }
+        }
+
+        if !option_matches(&self.throughput, &annotation_detail.throughput) ||
+            !option_matches(&self.reference_uniquename, &annotation_detail.reference) ||
+            !option_matches(&self.assigned_by, &annotation_detail.assigned_by) ||
+            !option_matches(&self.curator, &annotation_detail.curator) {
+            return false;
+        }
+
+        if self.date_from.is_some() || self.date_to.is_some() {
+            let date =
+                match annotation_detail.date {
+                    Some(ref date) => date,
+                    None => return false,
+                };
+
+            if let Some(ref date_from) = self.date_from {
+                match compare_annotation_date(date, date_from) {
+                    Some(Ordering::Less) | None => return false,
+                    _ => (),
+                }
+            }
+
+            if let Some(ref date_to) = self.date_to {
+                match compare_annotation_date(date, date_to) {
+                    Some(Ordering::Greater) | None => return false,
+                    _ => (),
+                }
+            }
+        }
+
+        true
+    }
+
+    // the genotype annotations of the term that match the locus,
+    // ploidiness, expression, condition and provenance filters
+    fn matching_genotype_annotations(&self, api_data: &APIData)
+                                     -> MatchingGenotypeAnnotations
+    {
+        let single_or_multi_locus =
+            self.single_or_multi_locus.clone().unwrap_or(SingleOrMultiLocus::Both);
+        let ploidiness = self.ploidiness.clone().unwrap_or(Ploidiness::Any);
+
+        let mut annotations =
+            api_data.matching_genotype_annotations(&self.termid, &single_or_multi_locus,
+                                                   &ploidiness, &self.expression,
+                                                   &self.conditions,
+                                                   &self.excluded_conditions);
+
+        if self.has_annotation_filters() {
+            annotations.retain(|annotation| {
+                annotation.annotation_id
+                    .and_then(|annotation_id| api_data.get_annotation_detail(annotation_id))
+                    .map(|annotation_detail| self.annotation_matches(&annotation_detail))
+                    .unwrap_or(false)
+            });
+        }
+
+        annotations
+    }
 }
 
 #[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
@@ -142,7 +397,8 @@ pub struct IntRangeNode {
     pub range_type: IntRangeType,
     pub start: Option<usize>,
     pub end: Option<usize>,
-    #[serde(skip_serializing_if="HashSet::is_empty", default)]
+    #[serde(skip_serializing_if="HashSet::is_empty", default,
+            serialize_with="serialize_sorted_set")]
     pub options: HashSet<String>,
 }
 
@@ -151,7 +407,8 @@ pub struct FloatRangeNode {
     pub range_type: FloatRangeType,
     pub start: Option<f64>,
     pub end: Option<f64>,
-    #[serde(skip_serializing_if="HashSet::is_empty", default)]
+    #[serde(skip_serializing_if="HashSet::is_empty", default,
+            serialize_with="serialize_sorted_set")]
     pub options: HashSet<String>,
 }
 
@@ -178,6 +435,74 @@ impl PartialEq for FloatRangeNode {
     }
 }
 
+// Genes with an average copies per cell in the given range, or in the
+// given quantile, in a dataset from GeneExpressionConfig
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GeneExRangeNode {
+    pub dataset_name: GeneExDataSetName,
+    #[serde(skip_serializing_if="Option::is_none", default)]
+    pub start: Option<f64>,
+    #[serde(skip_serializing_if="Option::is_none", default)]
+    pub end: Option<f64>,
+    #[serde(skip_serializing_if="Option::is_none", default)]
+    pub quantile: Option<GeneExQuantile>,
+}
+
+impl Eq for GeneExRangeNode {
+}
+impl PartialEq for GeneExRangeNode {
+    fn eq(&self, other: &Self) -> bool {
+        let float_eq = |a: Option<f64>, b: Option<f64>| {
+            match (a, b) {
+                (Some(a), Some(b)) => (a - b).abs() <= 1e-8,
+                (None, None) => true,
+                _ => false,
+            }
+        };
+
+        self.dataset_name == other.dataset_name &&
+            float_eq(self.start, other.start) && float_eq(self.end, other.end) &&
+            self.quantile == other.quantile
+    }
+}
+
+// Genes with a protein domain match with the given InterPro or member
+// database ID, eg. "IPR000719" or "PF00069".  If start or end are set,
+// the match must be within those residues.
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
+pub struct DomainNode {
+    pub domain_id: FlexStr,
+    #[serde(skip_serializing_if="Option::is_none", default)]
+    pub start: Option<usize>,
+    #[serde(skip_serializing_if="Option::is_none", default)]
+    pub end: Option<usize>,
+}
+
+// Genes with a UniProt sequence feature of the given type, optionally
+// within a residue range
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
+pub struct ProteinFeatureNode {
+    pub feature_type: ProteinFeatureType,
+    #[serde(skip_serializing_if="Option::is_none", default)]
+    pub start: Option<usize>,
+    #[serde(skip_serializing_if="Option::is_none", default)]
+    pub end: Option<usize>,
+}
+
+// Genes with a protein that matches a PROSITE style or regular expression
+// pattern, see motif_search::motif_regex()
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
+pub struct MotifNode {
+    pub pattern: FlexStr,
+}
+
+// true if the feature from feature_start to feature_end is inside the range
+fn feature_in_range(range_start: Option<usize>, range_end: Option<usize>,
+                    feature_start: usize, feature_end: usize) -> bool {
+    range_start.map(|range_start| feature_start >= range_start).unwrap_or(true) &&
+        range_end.map(|range_end| feature_end <= range_end).unwrap_or(true)
+}
+
 #[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
 pub struct GenomeRangeNode {
     #[serde(skip_serializing_if="Option::is_none")]
@@ -211,8 +536,60 @@ pub struct GenesTargetingNode {
     pub target_of_type: TargetOfType,
 }
 
+// the range of an annotation extension part to match
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
+pub enum ExtensionRangeFilter {
+#[serde(rename = "gene_uniquename")]
+    Gene(GeneUniquename),
+    // matches the term and its descendants
+#[serde(rename = "termid")]
+    Term(TermId),
+#[serde(rename = "residue")]
+    Residue(Residue),
+}
+
+// Genes annotated to a term (or its descendants) where the annotation
+// extension has a part with the given relation and, optionally, range
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
+pub struct ExtensionNode {
+    pub termid: TermId,
+    pub rel_type_name: FlexStr,
+    #[serde(skip_serializing_if="Option::is_none", default)]
+    pub range: Option<ExtensionRangeFilter>,
+}
+
+impl ExtensionNode {
+    fn ext_part_matches(&self, api_data: &APIData, ext_part: &ExtPart) -> bool {
+        if ext_part.rel_type_name != self.rel_type_name {
+            return false;
+        }
+
+        let range_filter =
+            match self.range {
+                Some(ref range_filter) => range_filter,
+                None => return true,
+            };
+
+        match (range_filter, &ext_part.ext_range) {
+            (ExtensionRangeFilter::Gene(gene_uniquename),
+             ExtRange::Gene(range_gene_uniquename) |
+             ExtRange::Promoter(range_gene_uniquename)) =>
+                gene_uniquename == range_gene_uniquename,
+            (ExtensionRangeFilter::Gene(gene_uniquename),
+             ExtRange::GeneAndGeneProduct(gene_and_product)) =>
+                *gene_uniquename == gene_and_product.gene_uniquename,
+            (ExtensionRangeFilter::Term(termid), ExtRange::Term(range_termid)) =>
+                api_data.term_is_a_or_descendant(range_termid, termid),
+            (ExtensionRangeFilter::Residue(residue), ExtRange::ModifiedResidues(residues)) =>
+                residues.contains(residue),
+            _ => false,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
 pub struct QueryGenePropNode {
+    #[serde(serialize_with="serialize_sorted_set")]
     pub property_flags: HashSet<GeneQueryPropFlag>,
 }
 
@@ -250,6 +627,14 @@ pub struct QueryNode {
     #[serde(skip_serializing_if="Option::is_none")]
     pub genome_range: Option<GenomeRangeNode>,
     #[serde(skip_serializing_if="Option::is_none")]
+    pub gene_ex_range: Option<GeneExRangeNode>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub domain: Option<DomainNode>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub protein_feature: Option<ProteinFeatureNode>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub motif: Option<MotifNode>,
+    #[serde(skip_serializing_if="Option::is_none")]
     pub interactors: Option<InteractorsNode>,
     #[serde(skip_serializing_if="Option::is_none")]
     pub substrates: Option<SubstratesNode>,
@@ -260,21 +645,28 @@ pub struct QueryNode {
     #[serde(skip_serializing_if="Option::is_none")]
     pub gene_properties: Option<QueryGenePropNode>,
     #[serde(skip_serializing_if="Option::is_none")]
+    pub extension: Option<ExtensionNode>,
+    #[serde(skip_serializing_if="Option::is_none")]
     pub query_id: Option<QueryIdNode>,
 }
 
 #[async_recursion]
-async fn exec_or(api_data: &APIData, site_db: &Option<SiteDB>,
-           nodes: &[QueryNode]) -> GeneUniquenameVecResult {
+async fn exec_or(api_data: &APIData, site_db: &Option<SiteDB>, cache: Option<&QueryCache>,
+                 result_type: QueryResultType, nodes: &[QueryNode],
+                 keys: Option<&[NodeCacheKeys]>, explain: bool) -> ExplainedGenesResult {
     if nodes.is_empty() {
         return Err(flex_str!("illegal query: OR operator has no nodes"));
     }
 
     let mut seen_genes = HashSet::new();
     let mut or_rows = vec![];
+    let mut explanations = vec![];
 
-    for node in nodes {
-        let exec_rows = node.exec(api_data, site_db).await?;
+    for (index, node) in nodes.iter().enumerate() {
+        let (exec_rows, explanation) =
+            node.exec_helper(api_data, site_db, cache, operand_keys(keys, index),
+                             result_type, explain).await?;
+        explanations.extend(explanation);
 
         for row_gene_uniquename in &exec_rows {
             if !seen_genes.contains(row_gene_uniquename) {
@@ -284,43 +676,57 @@ async fn exec_or(api_data: &APIData, site_db: &Option<SiteDB>,
         }
     }
 
-    Ok(or_rows)
+    Ok((or_rows, explanations))
 }
 
 #[async_recursion]
-async fn exec_and(api_data: &APIData, site_db: &Option<SiteDB>,
-                  nodes: &[QueryNode]) -> GeneUniquenameVecResult {
+async fn exec_and(api_data: &APIData, site_db: &Option<SiteDB>, cache: Option<&QueryCache>,
+                  result_type: QueryResultType, nodes: &[QueryNode],
+                  keys: Option<&[NodeCacheKeys]>, explain: bool) -> ExplainedGenesResult {
     if nodes.is_empty() {
         return Err("illegal query: AND operator has no nodes".into());
     }
 
-    let first_node_genes = nodes[0].exec(api_data, site_db).await?;
+    let mut explanations = vec![];
+
+    let (first_node_genes, explanation) =
+        nodes[0].exec_helper(api_data, site_db, cache, operand_keys(keys, 0),
+                             result_type, explain).await?;
+    explanations.extend(explanation);
 
     let current_genes = first_node_genes;
 
     let mut current_gene_set = HashSet::from_iter(current_genes);
 
-    for node in nodes[1..].iter() {
-        let node_result_rows = node.exec(api_data, site_db).await?;
+    for (index, node) in nodes.iter().enumerate().skip(1) {
+        let (node_result_rows, explanation) =
+            node.exec_helper(api_data, site_db, cache, operand_keys(keys, index),
+                             result_type, explain).await?;
+        explanations.extend(explanation);
         let node_genes = node_result_rows.into_iter().collect::<HashSet<_>>();
 
         current_gene_set = current_gene_set.intersection(&node_genes).cloned().collect();
     }
 
-    Ok(current_gene_set.into_iter().collect())
+    Ok((current_gene_set.into_iter().collect(), explanations))
 }
 
 #[async_recursion]
-async fn exec_not(api_data: &APIData, site_db: &Option<SiteDB>,
-                  node_a: &QueryNode, node_b: &QueryNode)
-                  -> GeneUniquenameVecResult
+async fn exec_not(api_data: &APIData, site_db: &Option<SiteDB>, cache: Option<&QueryCache>,
+                  result_type: QueryResultType,
+                  not_node: &NotNode, keys: Option<&[NodeCacheKeys]>, explain: bool)
+                  -> ExplainedGenesResult
 {
-    let node_b_result = node_b.exec(api_data, site_db).await?;
+    let (node_b_result, node_b_explanation) =
+        not_node.node_b.exec_helper(api_data, site_db, cache, operand_keys(keys, 1),
+                                    result_type, explain).await?;
 
     let node_b_gene_set: HashSet<GeneUniquename> =
         HashSet::from_iter(node_b_result);
 
-    let node_a_result = node_a.exec(api_data, site_db).await?;
+    let (node_a_result, node_a_explanation) =
+        not_node.node_a.exec_helper(api_data, site_db, cache, operand_keys(keys, 0),
+                                    result_type, explain).await?;
 
     let mut not_rows = vec![];
 
@@ -330,23 +736,30 @@ async fn exec_not(api_data: &APIData, site_db: &Option<SiteDB>,
         }
     }
 
-    Ok(not_rows)
+    let explanations =
+        node_a_explanation.into_iter().chain(node_b_explanation).collect();
+
+    Ok((not_rows, explanations))
 }
 
-fn exec_termid(api_data: &APIData, term_id: &FlexStr,
-               maybe_single_or_multi_locus: &Option<SingleOrMultiLocus>,
-               maybe_ploidiness: &Option<Ploidiness>,
-               expression: &Option<QueryExpressionFilter>,
-               conditions: &HashSet<TermAndName>,
-               excluded_conditions: &HashSet<TermAndName>)  -> GeneUniquenameVecResult {
-    if let Some(ref single_or_multi_locus) = *maybe_single_or_multi_locus {
-        let ploidiness = maybe_ploidiness.clone().unwrap_or(Ploidiness::Any);
-        let genes = api_data.genes_of_genotypes(term_id, single_or_multi_locus,
-                                                &ploidiness,
-                                                expression, conditions, excluded_conditions);
+fn exec_termid(api_data: &APIData, term: &TermNode) -> GeneUniquenameVecResult {
+    if term.single_or_multi_locus.is_some() {
+        let genes = term.matching_genotype_annotations(api_data).iter()
+            .flat_map(|annotation| annotation.alleles.iter())
+            .map(|allele_details| allele_details.gene.clone())
+            .unique()
+            .collect();
+        Ok(genes)
+    } else if term.has_annotation_filters() {
+        let genes = api_data.termid_annotation_details(&term.termid).iter()
+            .filter(|annotation_detail| term.annotation_matches(annotation_detail))
+            .flat_map(|annotation_detail| annotation_detail.genes.iter())
+            .cloned()
+            .unique()
+            .collect();
         Ok(genes)
     } else {
-        Ok(api_data.genes_of_termid(term_id))
+        Ok(api_data.genes_of_termid(&term.termid))
     }
 }
 
@@ -381,8 +794,12 @@ fn exec_target_of(api_data: &APIData, genes: &[GeneShort])
     let mut ret = vec![];
 
     for gene in genes {
-        let gene_details = api_data.get_gene_details(&gene.uniquename)
-            .unwrap_or_else(|| panic!("failed to find gene_details for {}", gene.uniquename));
+        let gene_details =
+            match api_data.get_gene_details(&gene.uniquename) {
+                Some(gene_details) => gene_details,
+                None => return Err(flex_fmt!("failed to find gene_details for {}",
+                                             gene.uniquename)),
+            };
         for target_of_annotation in &gene_details.target_of_annotations {
             ret.push(target_of_annotation.gene.clone());
         }
@@ -632,6 +1049,79 @@ fn exec_float_range(api_data: &APIData, range_type: &FloatRangeType,
     }
 }
 
+fn exec_gene_ex_range(api_data: &APIData, gene_ex_range: &GeneExRangeNode)
+    -> GeneUniquenameVecResult
+{
+    let mut values = api_data.gene_ex_dataset_values(&gene_ex_range.dataset_name);
+
+    if let Some(quantile) = gene_ex_range.quantile {
+        values.sort_by(|(gene_a, value_a), (gene_b, value_b)| {
+            value_a.total_cmp(value_b).then_with(|| gene_a.cmp(gene_b))
+        });
+
+        let (quantile_start, quantile_end) = quantile.bounds();
+        let count = values.len() as f64;
+        let start_idx = (count * quantile_start).round() as usize;
+        let end_idx = (count * quantile_end).round() as usize;
+
+        values = values.drain(start_idx..end_idx).collect();
+    }
+
+    let genes = values.into_iter()
+        .filter(|(_, value)| {
+            gene_ex_range.start.map(|start| *value >= start).unwrap_or(true) &&
+                gene_ex_range.end.map(|end| *value <= end).unwrap_or(true)
+        })
+        .map(|(gene_uniquename, _)| gene_uniquename)
+        .collect();
+
+    Ok(genes)
+}
+
+fn exec_domain(api_data: &APIData, domain_node: &DomainNode) -> GeneUniquenameVecResult {
+    let domain_id = &domain_node.domain_id;
+
+    let genes = api_data.get_maps().gene_query_data_map.values()
+        .filter(|gene_query_data| {
+            gene_query_data.domain_matches.iter()
+                .filter(|domain_match| {
+                    domain_match.id == *domain_id ||
+                        domain_match.interpro_id.as_ref() == Some(domain_id)
+                })
+                .flat_map(|domain_match| domain_match.locations.iter())
+                .any(|(start, end)| {
+                    feature_in_range(domain_node.start, domain_node.end, *start, *end)
+                })
+        })
+        .map(|gene_query_data| gene_query_data.gene_uniquename.clone())
+        .collect();
+
+    Ok(genes)
+}
+
+fn exec_protein_feature(api_data: &APIData, protein_feature_node: &ProteinFeatureNode)
+    -> GeneUniquenameVecResult
+{
+    let genes = api_data.get_maps().gene_query_data_map.values()
+        .filter(|gene_query_data| {
+            gene_query_data.protein_features.iter()
+                .any(|feature| {
+                    feature.feature_type == protein_feature_node.feature_type &&
+                        feature_in_range(protein_feature_node.start, protein_feature_node.end,
+                                         feature.start, feature.end)
+                })
+        })
+        .map(|gene_query_data| gene_query_data.gene_uniquename.clone())
+        .collect();
+
+    Ok(genes)
+}
+
+fn exec_motif(api_data: &APIData, pattern: &str) -> GeneUniquenameVecResult {
+    let regex = motif_regex(pattern)?;
+    Ok(genes_matching_motif(api_data, &regex))
+}
+
 fn exec_interactors_of_gene(api_data: &APIData, gene_uniquename: &GeneUniquename,
                             interaction_type: InteractionType) -> GeneUniquenameVecResult {
     Ok(api_data.interactors_of_genes(gene_uniquename, interaction_type))
@@ -660,6 +1150,22 @@ fn exec_genes_targeting(api_data: &APIData, gene_uniquename: &GeneUniquename,
     Ok(genes_targeting.drain().collect())
 }
 
+fn exec_extension(api_data: &APIData, extension_node: &ExtensionNode)
+    -> GeneUniquenameVecResult
+{
+    let genes = api_data.termid_annotation_details_with_extension(&extension_node.termid).iter()
+        .filter(|annotation_detail| {
+            annotation_detail.extension.iter()
+                .any(|ext_part| extension_node.ext_part_matches(api_data, ext_part))
+        })
+        .flat_map(|annotation_detail| annotation_detail.genes.iter())
+        .cloned()
+        .unique()
+        .collect();
+
+    Ok(genes)
+}
+
 fn exec_gene_properties(api_data: &APIData, query_properties: &HashSet<GeneQueryPropFlag>)
     -> GeneUniquenameVecResult
 {
@@ -680,19 +1186,14 @@ fn exec_gene_properties(api_data: &APIData, query_properties: &HashSet<GeneQuery
 }
 
 async fn exec_query_id(api_data: &APIData,
-                       maybe_site_db: &Option<SiteDB>, id: &Uuid)
+                       maybe_site_db: &Option<SiteDB>, cache: Option<&QueryCache>,
+                       id: &Uuid)
                        -> GeneUniquenameVecResult
 {
     if let Some(site_db) = maybe_site_db {
         if let Some(query) = site_db.query_by_id(id).await {
-            match query.exec(api_data, maybe_site_db).await {
-                Ok(res) => {
-                    Ok(res.iter().map(|row| { row.gene_uniquename.clone() }).collect())
-                },
-                Err(err) => {
-                    Err(err)
-                }
-            }
+            // use all genes from the saved query, ignoring its sorting and paging
+            query.get_constraints().exec(api_data, maybe_site_db, cache, QueryResultType::Gene).await
         } else {
             Err(flex_fmt!("can't find query for ID {}", id))
         }
@@ -717,11 +1218,16 @@ impl QueryNode {
             int_range: None,
             float_range: None,
             genome_range: None,
+            gene_ex_range: None,
+            domain: None,
+            protein_feature: None,
+            motif: None,
             interactors: None,
             substrates: None,
             downstream_genes: None,
             genes_targeting: None,
             gene_properties: None,
+            extension: None,
             query_id: None,
         }
     }
@@ -730,23 +1236,323 @@ impl QueryNode {
         self.query_id.as_ref().map(|query_id_node| query_id_node.id)
     }
 
-    #[async_recursion]
-    pub async fn exec<'a>(&'a self, api_data: &'a APIData,
-                      site_db: &'a Option<SiteDB>) -> GeneUniquenameVecResult {
-        if let Some(ref nodes) = self.or {
-            return exec_or(api_data, site_db, nodes).await;
+    // the names of all the node types that are set in this node - a valid
+    // node has exactly one
+    pub fn set_node_types(&self) -> Vec<&'static str> {
+        let fields: [(&'static str, bool); 23] = [
+            ("or", self.or.is_some()),
+            ("and", self.and.is_some()),
+            ("not", self.not.is_some()),
+            ("term", self.term.is_some()),
+            ("ref_genes", self.ref_genes.is_some()),
+            ("subset", self.subset.is_some()),
+            ("gene_list", self.gene_list.is_some()),
+            ("target_of", self.target_of.is_some()),
+            ("has_ortholog", self.has_ortholog.is_some()),
+            ("int_range", self.int_range.is_some()),
+            ("float_range", self.float_range.is_some()),
+            ("genome_range", self.genome_range.is_some()),
+            ("gene_ex_range", self.gene_ex_range.is_some()),
+            ("domain", self.domain.is_some()),
+            ("protein_feature", self.protein_feature.is_some()),
+            ("motif", self.motif.is_some()),
+            ("interactors", self.interactors.is_some()),
+            ("substrates", self.substrates.is_some()),
+            ("downstream_genes", self.downstream_genes.is_some()),
+            ("genes_targeting", self.genes_targeting.is_some()),
+            ("gene_properties", self.gene_properties.is_some()),
+            ("extension", self.extension.is_some()),
+            ("query_id", self.query_id.is_some()),
+        ];
+
+        fields.iter()
+            .filter(|(_, is_set)| *is_set)
+            .map(|(node_type, _)| *node_type)
+            .collect()
+    }
+
+    // Return an equivalent node in a canonical form: "or" and "and"
+    // operands are flattened and sorted, gene lists are sorted and the
+    // names that are only used for display are removed
+    pub fn normalise(&self) -> QueryNode {
+        let normalise_all =
+            |nodes: &Vec<QueryNode>| nodes.iter().map(QueryNode::normalise).collect();
+
+        let not_nodes = self.not.as_ref()
+            .map(|not_node| (not_node.node_a.normalise(), not_node.node_b.normalise()));
+
+        self.normalise_with(not_nodes, self.or.as_ref().map(normalise_all),
+                            self.and.as_ref().map(normalise_all))
+    }
+
+    // Normalise this node given the normalised forms of its "not", "or" and
+    // "and" sub-nodes
+    fn normalise_with(&self, not_nodes: Option<(QueryNode, QueryNode)>,
+                      or_nodes: Option<Vec<QueryNode>>, and_nodes: Option<Vec<QueryNode>>)
+                      -> QueryNode
+    {
+        let mut node = self.clone();
+
+        node.node_name = None;
+
+        if let Some(ref mut term_node) = node.term {
+            term_node.name = None;
+        }
+        if let Some(ref mut gene_list_node) = node.gene_list {
+            gene_list_node.genes = normalise_genes(&gene_list_node.genes);
+        }
+        if let Some(ref mut target_of_node) = node.target_of {
+            target_of_node.genes = normalise_genes(&target_of_node.genes);
+        }
+        if let (Some(ref mut not_node), Some((node_a, node_b))) = (&mut node.not, not_nodes) {
+            *not_node.node_a = node_a;
+            *not_node.node_b = node_b;
+        }
+        node.or = or_nodes.map(|nodes| normalise_operands(nodes, |node| &node.or));
+        node.and = and_nodes.map(|nodes| normalise_operands(nodes, |node| &node.and));
+
+        // an "or" or "and" with one operand is the same as the operand
+        if node.set_node_types().len() == 1 {
+            for operands in [&node.or, &node.and].into_iter().flatten() {
+                if operands.len() == 1 {
+                    return operands[0].clone();
+                }
+            }
+        }
+
+        node
+    }
+
+    // Return a string that is the same for nodes that give the same
+    // results, regardless of operand order and display names
+    pub fn canonical_key(&self) -> String {
+        serde_json::to_string(&self.normalise())
+            .unwrap_or_else(|err| panic!("failed to serialise query node: {}", err))
+    }
+
+    // Return the normalised node and the canonical keys of it and its
+    // sub-nodes.  The normalised sub-nodes are reused for their parent
+    // rather than normalising each sub-tree again.
+    fn cache_keys(&self) -> (QueryNode, NodeCacheKeys) {
+        let keys_of_all = |nodes: &Option<Vec<QueryNode>>| {
+            match nodes {
+                Some(nodes) => {
+                    let (nodes, keys): (Vec<_>, Vec<_>) =
+                        nodes.iter().map(QueryNode::cache_keys).unzip();
+                    (Some(nodes), keys)
+                },
+                None => (None, vec![]),
+            }
+        };
+
+        let (or_nodes, or_keys) = keys_of_all(&self.or);
+        let (and_nodes, and_keys) = keys_of_all(&self.and);
+
+        let (not_nodes, not_keys) =
+            match self.not {
+                Some(ref not_node) => {
+                    let (node_a, node_a_keys) = not_node.node_a.cache_keys();
+                    let (node_b, node_b_keys) = not_node.node_b.cache_keys();
+                    (Some((node_a, node_b)), vec![node_a_keys, node_b_keys])
+                },
+                None => (None, vec![]),
+            };
+
+        let node = self.normalise_with(not_nodes, or_nodes, and_nodes);
+        let key = serde_json::to_string(&node)
+            .unwrap_or_else(|err| panic!("failed to serialise query node: {}", err));
+
+        (node, NodeCacheKeys {
+            key,
+            or: or_keys,
+            and: and_keys,
+            not: not_keys,
+        })
+    }
+
+    // Add the names of genes, terms and conditions.  The condition names are
+    // needed for matching conditions in matching_genotype_annotations().
+    pub fn fill_names(&mut self, api_data: &APIData) {
+        let term_name = |termid: &FlexStr| {
+            api_data.get_term(termid).map(|term_details| term_details.name.clone())
+        };
+
+        let fill_conditions = |conditions: &HashSet<TermAndName>| {
+            conditions.iter()
+                .map(|condition| TermAndName {
+                    termid: condition.termid.clone(),
+                    name: term_name(&condition.termid).unwrap_or_else(|| condition.name.clone()),
+                })
+                .collect::<HashSet<_>>()
+        };
+
+        let fill_genes = |genes: &mut Vec<GeneShort>| {
+            for gene in genes.iter_mut() {
+                if let Some(gene_summary) = api_data.get_gene_summary(&gene.uniquename) {
+                    if gene.name.is_none() {
+                        gene.name = gene_summary.name.clone();
+                    }
+                    if gene.product.is_none() {
+                        gene.product = gene_summary.product.clone();
+                    }
+                }
+            }
+        };
+
+        if let Some(ref mut term_node) = self.term {
+            if term_node.name.is_none() {
+                term_node.name = term_name(&term_node.termid);
+            }
+            term_node.conditions = fill_conditions(&term_node.conditions);
+            term_node.excluded_conditions = fill_conditions(&term_node.excluded_conditions);
+        }
+
+        if let Some(ref mut gene_list_node) = self.gene_list {
+            fill_genes(&mut gene_list_node.genes);
+        }
+        if let Some(ref mut target_of_node) = self.target_of {
+            fill_genes(&mut target_of_node.genes);
+        }
+
+        for child_nodes in [&mut self.or, &mut self.and].into_iter().flatten() {
+            for child_node in child_nodes.iter_mut() {
+                child_node.fill_names(api_data);
+            }
+        }
+
+        if let Some(ref mut not_node) = self.not {
+            not_node.node_a.fill_names(api_data);
+            not_node.node_b.fill_names(api_data);
+        }
+    }
+
+    // the name of the field that is set in this node, eg. "or" or "term"
+    pub fn node_type(&self) -> &'static str {
+        self.set_node_types().first().copied().unwrap_or("unknown")
+    }
+
+    pub async fn exec(&self, api_data: &APIData, site_db: &Option<SiteDB>,
+                      cache: Option<&QueryCache>, result_type: QueryResultType)
+        -> GeneUniquenameVecResult
+    {
+        let keys = cache.map(|_| self.cache_keys().1);
+        let (genes, _) =
+            self.exec_helper(api_data, site_db, cache, keys.as_ref(), result_type, false).await?;
+        Ok(genes)
+    }
+
+    // Execute this node and return an explanation tree with the result
+    // count and execution time of every sub-node, so that it's possible
+    // to see which part of a query removed the genes
+    pub async fn exec_explain(&self, api_data: &APIData,
+                              site_db: &Option<SiteDB>, cache: Option<&QueryCache>,
+                              result_type: QueryResultType)
+        -> Result<(Vec<GeneUniquename>, QueryNodeExplanation), FlexStr>
+    {
+        let (genes, explanation) =
+            self.exec_helper(api_data, site_db, cache, None, result_type, true).await?;
+
+        match explanation {
+            Some(explanation) => Ok((genes, explanation)),
+            None => Err(flex_str!("internal error: no explanation for query node")),
         }
-        if let Some(ref nodes) = self.and {
-            return exec_and(api_data, site_db, nodes).await;
+    }
+
+    #[async_recursion]
+    async fn exec_helper<'a>(&'a self, api_data: &'a APIData,
+                             site_db: &'a Option<SiteDB>, cache: Option<&'a QueryCache>,
+                             keys: Option<&'a NodeCacheKeys>,
+                             result_type: QueryResultType, explain: bool)
+        -> Result<(Vec<GeneUniquename>, Option<QueryNodeExplanation>), FlexStr>
+    {
+        let start_time = Instant::now();
+
+        // keys is None when explaining, so the cache isn't used, because the
+        // explanation needs the results of every sub-node
+        let cache_key =
+            match (cache, keys) {
+                (Some(_), Some(keys)) => Some(format!("{}:{}", result_type.name(), keys.key)),
+                _ => None,
+            };
+
+        let cached_genes =
+            match (cache, &cache_key) {
+                (Some(cache), Some(cache_key)) => cache.get(cache_key),
+                _ => None,
+            };
+
+        if let Some(cached_genes) = cached_genes {
+            return Ok((cached_genes, None));
         }
-        if let Some(ref not_node) = self.not {
-            return exec_not(api_data, site_db, &not_node.node_a, &not_node.node_b).await;
+
+        let (genes, children) =
+            if let Some(ref nodes) = self.or {
+                exec_or(api_data, site_db, cache, result_type, nodes,
+                        keys.map(|keys| &keys.or[..]), explain).await?
+            } else if let Some(ref nodes) = self.and {
+                exec_and(api_data, site_db, cache, result_type, nodes,
+                         keys.map(|keys| &keys.and[..]), explain).await?
+            } else if let Some(ref not_node) = self.not {
+                exec_not(api_data, site_db, cache, result_type,
+                         not_node, keys.map(|keys| &keys.not[..]), explain).await?
+            } else if result_type.is_gene() {
+                (self.exec_leaf(api_data, site_db, cache).await?, vec![])
+            } else {
+                (self.exec_genotype_leaf(api_data, result_type)?, vec![])
+            };
+
+        if let (Some(cache), Some(cache_key)) = (cache, cache_key) {
+            cache.insert(cache_key, &genes);
         }
+
+        let explanation =
+            if explain {
+                Some(QueryNodeExplanation {
+                    node_name: self.node_name.clone(),
+                    node_type: self.node_type().to_shared_str(),
+                    result_count: genes.len(),
+                    exec_time_ms: start_time.elapsed().as_secs_f64() * 1000.0,
+                    children,
+                })
+            } else {
+                None
+            };
+
+        Ok((genes, explanation))
+    }
+
+    // return the IDs of the alleles or genotypes matching a term node
+    fn exec_genotype_leaf(&self, api_data: &APIData, result_type: QueryResultType)
+        -> GeneUniquenameVecResult
+    {
+        let term =
+            match self.term {
+                Some(ref term) => term,
+                None => return Err(flex_fmt!("{} nodes can't be used in {} queries",
+                                             self.node_type(), result_type.name())),
+            };
+
+        let annotations = term.matching_genotype_annotations(api_data);
+
+        let ids =
+            if result_type == QueryResultType::Allele {
+                annotations.iter()
+                    .flat_map(|annotation| annotation.alleles.iter())
+                    .map(|allele_details| allele_details.uniquename.clone())
+                    .collect::<Vec<_>>()
+            } else {
+                annotations.iter()
+                    .map(|annotation| annotation.genotype_display_uniquename.clone())
+                    .collect::<Vec<_>>()
+            };
+
+        Ok(ids.into_iter().filter(|id| !id.is_empty()).unique().collect())
+    }
+
+    async fn exec_leaf(&self, api_data: &APIData, site_db: &Option<SiteDB>,
+                       cache: Option<&QueryCache>) -> GeneUniquenameVecResult {
         if let Some(ref term) = self.term {
-            return exec_termid(api_data, &term.termid, &term.single_or_multi_locus,
-                               &term.ploidiness,
-                               &term.expression, &term.conditions,
-                               &term.excluded_conditions);
+            return exec_termid(api_data, term);
         }
         if let Some(ref ref_genes) = self.ref_genes {
             return exec_ref_genes(api_data, &ref_genes.reference_uniquename);
@@ -802,15 +1608,30 @@ impl QueryNode {
                                     float_range_node.start, float_range_node.end,
                                     &float_range_node.options);
         }
+        if let Some(ref gene_ex_range_node) = self.gene_ex_range {
+            return exec_gene_ex_range(api_data, gene_ex_range_node);
+        }
+        if let Some(ref domain_node) = self.domain {
+            return exec_domain(api_data, domain_node);
+        }
+        if let Some(ref protein_feature_node) = self.protein_feature {
+            return exec_protein_feature(api_data, protein_feature_node);
+        }
+        if let Some(ref motif_node) = self.motif {
+            return exec_motif(api_data, &motif_node.pattern);
+        }
         if let Some(ref gene_properties_node) = self.gene_properties {
             return exec_gene_properties(api_data, &gene_properties_node.property_flags);
         }
+        if let Some(ref extension_node) = self.extension {
+            return exec_extension(api_data, extension_node);
+        }
         if let Some(ref query_id_node) = self.query_id {
-            return exec_query_id(api_data, site_db, &query_id_node.id).await;
+            return exec_query_id(api_data, site_db, cache, &query_id_node.id).await;
         }
 
         // fall through:
-        panic!("unsupported query node: {:?}", self);
+        Err(flex_fmt!("unsupported query node: {:?}", self))
     }
 }
 
@@ -824,24 +1645,90 @@ pub struct NucleotideDownloadOptions {
     downstream_bases: Option<usize>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub enum SeqType {
 #[serde(rename = "protein")]
     Protein,
 #[serde(rename = "nucleotide")]
     Nucleotide(NucleotideDownloadOptions),
 #[serde(rename = "none")]
+    #[default]
     None,
 }
 
+// The type of the IDs that a query returns.  Allele and genotype queries
+// can only use term nodes (using the genotype annotations) and the "or",
+// "and" and "not" operators.  Reference and term queries return the
+// references or terms of the annotations of the genes that match the
+// constraints, see ReverseQueryOptions.
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Copy, Clone, Default)]
+pub enum QueryResultType {
+#[serde(rename = "gene")]
+    #[default]
+    Gene,
+#[serde(rename = "allele")]
+    Allele,
+#[serde(rename = "genotype")]
+    Genotype,
+#[serde(rename = "reference")]
+    Reference,
+#[serde(rename = "term")]
+    Term,
+}
+
+impl QueryResultType {
+    pub fn is_gene(&self) -> bool {
+        *self == QueryResultType::Gene
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            QueryResultType::Gene => "gene",
+            QueryResultType::Allele => "allele",
+            QueryResultType::Genotype => "genotype",
+            QueryResultType::Reference => "reference",
+            QueryResultType::Term => "term",
+        }
+    }
+
+    // the type of the IDs returned by the constraints of the query
+    pub fn constraints_type(&self) -> QueryResultType {
+        match self {
+            QueryResultType::Reference | QueryResultType::Term => QueryResultType::Gene,
+            _ => *self,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct GAFOptions {
-    #[serde(skip_serializing_if="HashSet::is_empty", default)]
+    #[serde(skip_serializing_if="HashSet::is_empty", default,
+            serialize_with="serialize_sorted_set")]
     pub aspects: HashSet<FlexStr>,
 }
 
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Copy, Clone, Default)]
+pub enum SortDirection {
+#[serde(rename = "ascending")]
+    #[default]
+    Ascending,
+#[serde(rename = "descending")]
+    Descending,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct QuerySortOptions {
+    // a ResultRow field name, "gene_name", "product" or
+    // "gene_ex_avg_copies_per_cell:<dataset_name>"
+    pub field_name: FlexStr,
+    #[serde(default)]
+    pub direction: SortDirection,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct QueryOutputOptions {
+    #[serde(skip_serializing_if="QueryResultType::is_gene", default)]
+    pub result_type: QueryResultType,
     pub sequence: SeqType,
     #[serde(skip_serializing_if="Option::is_none")]
     pub gaf_options: Option<GAFOptions>,
@@ -850,10 +1737,31 @@ pub struct QueryOutputOptions {
     // If a gene in the results is directly or indirectly annotation with one of
     // the ancestor_terms, that term will be added to the "subsets" field of the
     // ResultRow
-    #[serde(skip_serializing_if="HashSet::is_empty", default)]
+    #[serde(skip_serializing_if="HashSet::is_empty", default,
+            serialize_with="serialize_sorted_set")]
     pub ancestor_terms: HashSet<FlexStr>,
-    #[serde(skip_serializing_if="HashSet::is_empty", default)]
+    #[serde(skip_serializing_if="HashSet::is_empty", default,
+            serialize_with="serialize_sorted_set")]
     pub flags: HashSet<FlexStr>,
+
+    // The rows are sorted before the offset and limit are applied.  If
+    // there is no sort field but there is an offset or limit, the rows are
+    // sorted by gene_uniquename so that the pages are stable.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub sort: Option<QuerySortOptions>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub offset: Option<usize>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub limit: Option<usize>,
+
+    // if set, find the terms that are enriched in the results (all of the
+    // matching genes, not just the current page)
+    #[serde(skip_serializing_if="Option::is_none", default)]
+    pub enrichment: Option<EnrichmentOptions>,
+
+    // filters for the annotations used by reference and term queries
+    #[serde(skip_serializing_if="Option::is_none", default)]
+    pub reverse: Option<ReverseQueryOptions>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -862,6 +1770,150 @@ pub struct Query {
     constraints: QueryNode,
 }
 
+fn make_allele_result_rows(api_data: &APIData, allele_uniquenames: &[AlleleUniquename])
+                           -> Result<Vec<AlleleResultRow>, FlexStr>
+{
+    allele_uniquenames.iter()
+        .map(|allele_uniquename| {
+            let allele_details = api_data.get_allele(allele_uniquename)
+                .ok_or_else(|| flex_fmt!("can't find allele: {}", allele_uniquename))?;
+
+            Ok(AlleleResultRow {
+                allele_uniquename: allele_uniquename.clone(),
+                name: allele_details.name.clone(),
+                allele_type: allele_details.allele_type.clone(),
+                description: allele_details.description.clone(),
+                gene_uniquename: allele_details.gene.uniquename.clone(),
+                gene_name: allele_details.gene.name.clone(),
+            })
+        })
+        .collect()
+}
+
+fn make_genotype_result_rows(api_data: &APIData,
+                             genotype_uniquenames: &[GenotypeDisplayUniquename])
+                             -> Result<Vec<GenotypeResultRow>, FlexStr>
+{
+    genotype_uniquenames.iter()
+        .map(|genotype_uniquename| {
+            let genotype_details = api_data.get_genotype(genotype_uniquename)
+                .ok_or_else(|| flex_fmt!("can't find genotype: {}", genotype_uniquename))?;
+
+            let allele_uniquenames = genotype_details.loci.iter()
+                .flat_map(|locus| locus.expressed_alleles.iter())
+                .map(|expressed_allele| expressed_allele.allele_uniquename.clone())
+                .collect::<Vec<_>>();
+
+            let gene_uniquenames = allele_uniquenames.iter()
+                .filter_map(|allele_uniquename| {
+                    genotype_details.alleles_by_uniquename.get(allele_uniquename)
+                        .map(|allele_short| allele_short.gene_uniquename.clone())
+                })
+                .unique()
+                .collect();
+
+            Ok(GenotypeResultRow {
+                genotype_uniquename: genotype_uniquename.clone(),
+                display_name: genotype_details.display_name.clone(),
+                name: genotype_details.name.clone(),
+                ploidiness: genotype_details.ploidiness.clone(),
+                allele_uniquenames,
+                gene_uniquenames,
+            })
+        })
+        .collect()
+}
+
+#[derive(PartialEq, PartialOrd)]
+enum SortValue {
+    Number(f64),
+    Text(FlexStr),
+}
+
+// the value of the serialised form of a unit enum, eg. "depends_on_conditions"
+fn enum_sort_value<T: serde::Serialize>(val: &T) -> Option<SortValue> {
+    serde_json::to_value(val).ok()
+        .and_then(|json_val| json_val.as_str().map(|s| SortValue::Text(s.to_shared_str())))
+}
+
+fn term_data_sort_value(term_data: &Option<GeneQueryTermData>) -> Option<SortValue> {
+    match term_data {
+        Some(GeneQueryTermData::Term(term_and_name)) =>
+            Some(SortValue::Text(term_and_name.name.clone())),
+        _ => None,
+    }
+}
+
+fn gene_sort_value(api_data: &APIData, field_name: &str,
+                   gene_uniquename: &GeneUniquename)
+    -> Result<Option<SortValue>, FlexStr>
+{
+    if let Some(dataset_name) = field_name.strip_prefix("gene_ex_avg_copies_per_cell:") {
+        let value = api_data.gene_ex_avg_copies_per_cell(gene_uniquename, dataset_name)
+            .map(SortValue::Number);
+        return Ok(value);
+    }
+
+    let summary = api_data.get_gene_summary(gene_uniquename);
+    let gene_data = api_data.get_gene_query_data(gene_uniquename);
+
+    let text = |s: Option<&FlexStr>| s.map(|s| SortValue::Text(s.clone()));
+    let count = |len: Option<usize>| len.map(|len| SortValue::Number(len as f64));
+
+    let value = match field_name {
+        "gene_uniquename" => Some(SortValue::Text(gene_uniquename.clone())),
+        "gene_name" => text(summary.and_then(|s| s.name.as_ref())),
+        "product" => text(summary.and_then(|s| s.product.as_ref())),
+        "deletion_viability" =>
+            gene_data.and_then(|d| enum_sort_value(&d.deletion_viability)),
+        "go_component" => gene_data.and_then(|d| term_data_sort_value(&d.go_component)),
+        "go_process_superslim" =>
+            gene_data.and_then(|d| term_data_sort_value(&d.go_process_superslim)),
+        "go_function" => gene_data.and_then(|d| term_data_sort_value(&d.go_function)),
+        "characterisation_status" =>
+            text(gene_data.and_then(|d| d.characterisation_status.as_ref())),
+        "taxonomic_distribution" =>
+            text(gene_data.and_then(|d| d.taxonomic_distribution.as_ref())),
+        "tmm" => gene_data.and_then(|d| d.tmm.as_ref()).and_then(enum_sort_value),
+        "rnacentral_id" => text(gene_data.and_then(|d| d.rnacentral_urs_identifier.as_ref())),
+        "protein_length_bin" => text(gene_data.and_then(|d| d.protein_length_bin.as_ref())),
+        "molecular_weight" =>
+            gene_data.and_then(|d| d.molecular_weight)
+                .map(|weight| SortValue::Number(f64::from(weight))),
+        "protein_length" => count(gene_data.and_then(|d| d.protein_length)),
+        "spliced_rna_length" => count(gene_data.and_then(|d| d.spliced_rna_length)),
+        "unspliced_rna_length" => count(gene_data.and_then(|d| d.unspliced_rna_length)),
+        "ortholog_taxonids" => count(gene_data.map(|d| d.ortholog_taxonids.len())),
+        "physical_interactors" => count(gene_data.map(|d| d.physical_interactors.len())),
+        "reference_uniquenames" => count(gene_data.map(|d| d.reference_uniquenames.len())),
+        "pdb_ids" => count(gene_data.map(|d| d.pdb_ids.len())),
+        "gocam_ids" => count(gene_data.map(|d| d.gocam_ids.len())),
+        "paralogs" => count(gene_data.map(|d| d.paralogs.len())),
+        "subsets" => count(gene_data.map(|d| d.subset_termids.len())),
+        _ => return Err(flex_fmt!("can't sort by unknown field: {}", field_name)),
+    };
+
+    Ok(value)
+}
+
+// genes with no value for the sort field go at the end, whatever the direction
+fn compare_sort_values(a: &Option<SortValue>, b: &Option<SortValue>,
+                       direction: SortDirection) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => {
+            let ord = a.partial_cmp(b).unwrap_or(Ordering::Equal);
+            if direction == SortDirection::Descending {
+                ord.reverse()
+            } else {
+                ord
+            }
+        },
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
 #[derive(PartialEq)]
 enum BeforeOrAfter {
     Before,
@@ -904,6 +1956,28 @@ impl Query {
         }
     }
 
+    // the query with the constraints in canonical form, see
+    // QueryNode::normalise()
+    pub fn normalise(&self) -> Query {
+        Query {
+            output_options: self.output_options.clone(),
+            constraints: self.constraints.normalise(),
+        }
+    }
+
+    // A JSON string that is the same for queries that differ only in
+    // operand order, set order and display names.  Used for finding the
+    // ID of a saved query.
+    pub fn canonical_json(&self) -> String {
+        serde_json::to_string(&self.normalise())
+            .unwrap_or_else(|err| panic!("failed to serialise query: {}", err))
+    }
+
+    // add display names after loading a query saved in canonical form
+    pub fn fill_names(&mut self, api_data: &APIData) {
+        self.constraints.fill_names(api_data);
+    }
+
     fn make_nucl_seq(&self, transcript: &TranscriptDetails,
                      maybe_chr_details: Option<&ChromosomeDetails>,
                      options: &NucleotideDownloadOptions) -> String {
@@ -1208,19 +2282,161 @@ impl Query {
            }).collect::<Vec<_>>())
     }
 
+    fn sort_and_page_genes(&self, api_data: &APIData, genes: Vec<GeneUniquename>)
+        -> GeneUniquenameVecResult
+    {
+        let options = &self.output_options;
+
+        if options.sort.is_none() && options.offset.is_none() && options.limit.is_none() {
+            return Ok(genes);
+        }
+
+        let result_type = options.result_type;
+
+        let (field_name, direction) =
+            if let Some(ref sort) = options.sort {
+                (sort.field_name.as_ref(), sort.direction)
+            } else if result_type.is_gene() {
+                ("gene_uniquename", SortDirection::Ascending)
+            } else {
+                ("uniquename", SortDirection::Ascending)
+            };
+
+        let mut keyed_genes = genes.into_iter()
+            .map(|gene_uniquename| {
+                let value =
+                    if result_type.is_gene() {
+                        gene_sort_value(api_data, field_name, &gene_uniquename)?
+                    } else if field_name == "uniquename" {
+                        Some(SortValue::Text(gene_uniquename.clone()))
+                    } else {
+                        return Err(flex_fmt!("can't sort {} results by: {}",
+                                             result_type.name(), field_name));
+                    };
+                Ok((value, gene_uniquename))
+            })
+            .collect::<Result<Vec<_>, FlexStr>>()?;
+
+        keyed_genes.sort_by(|(a_value, a_gene), (b_value, b_gene)| {
+            compare_sort_values(a_value, b_value, direction)
+                .then_with(|| a_gene.cmp(b_gene))
+        });
+
+        let offset = options.offset.unwrap_or(0);
+        let limit = options.limit.unwrap_or(usize::MAX);
+
+        Ok(keyed_genes.into_iter()
+           .skip(offset)
+           .take(limit)
+           .map(|(_, gene_uniquename)| gene_uniquename)
+           .collect())
+    }
+
+    // apply the offset and limit to the rows of a reference or term query,
+    // which are sorted by ID
+    fn page_rows<T>(&self, rows: Vec<T>) -> Result<Vec<T>, FlexStr> {
+        let options = &self.output_options;
+
+        if let Some(ref sort) = options.sort {
+            return Err(flex_fmt!("can't sort {} results by: {}",
+                                 options.result_type.name(), sort.field_name));
+        }
+
+        Ok(rows.into_iter()
+           .skip(options.offset.unwrap_or(0))
+           .take(options.limit.unwrap_or(usize::MAX))
+           .collect())
+    }
+
     pub async fn exec(&self, api_data: &APIData,
-                      site_db: &Option<SiteDB>)
-                -> QueryRowsResult
+                      site_db: &Option<SiteDB>, cache: Option<&QueryCache>)
+                -> QueryRowsPageResult
     {
-        let genes_result = self.constraints.exec(api_data, site_db).await;
+        let result_type = self.output_options.result_type;
+        let constraints_type = result_type.constraints_type();
+
+        let (genes, explanation) =
+            if self.output_options.flags.contains(&flex_str!("explain")) {
+                let (genes, explanation) =
+                    self.constraints.exec_explain(api_data, site_db, cache, constraints_type).await?;
+                (genes, Some(explanation))
+            } else {
+                (self.constraints.exec(api_data, site_db, cache, constraints_type).await?, None)
+            };
+
+        let enrichment =
+            match self.output_options.enrichment {
+                Some(ref enrichment_options) => {
+                    if !result_type.is_gene() {
+                        return Err(flex_fmt!("enrichment is only available for gene queries, not {} queries",
+                                             result_type.name()));
+                    }
+                    Some(gene_set_enrichment(api_data, &genes, enrichment_options)?)
+                },
+                None => None,
+            };
+
+        let default_reverse_options = ReverseQueryOptions::default();
+        let reverse_options =
+            match self.output_options.reverse {
+                Some(ref reverse_options) => {
+                    if result_type != QueryResultType::Reference &&
+                        result_type != QueryResultType::Term {
+                        return Err(flex_fmt!("reverse options are only used by reference and term queries, not {} queries",
+                                             result_type.name()));
+                    }
+                    reverse_options
+                },
+                None => &default_reverse_options,
+            };
 
-        match genes_result {
-            Ok(genes) => self.make_result_rows(api_data, genes),
-            Err(err) => Err(err)
+        let mut page = QueryRowsPage {
+            total_count: 0,
+            rows: vec![],
+            allele_rows: vec![],
+            genotype_rows: vec![],
+            explanation,
+            enrichment,
+            reference_rows: vec![],
+            term_rows: vec![],
+        };
+
+        match result_type {
+            QueryResultType::Reference => {
+                let reference_rows = references_of_genes(api_data, &genes, reverse_options)?;
+                page.total_count = reference_rows.len();
+                page.reference_rows = self.page_rows(reference_rows)?;
+            },
+            QueryResultType::Term => {
+                let term_rows = terms_of_genes(api_data, &genes, reverse_options)?;
+                page.total_count = term_rows.len();
+                page.term_rows = self.page_rows(term_rows)?;
+            },
+            QueryResultType::Gene => {
+                page.total_count = genes.len();
+                let page_ids = self.sort_and_page_genes(api_data, genes)?;
+                page.rows = self.make_result_rows(api_data, page_ids)?;
+            },
+            QueryResultType::Allele => {
+                page.total_count = genes.len();
+                let page_ids = self.sort_and_page_genes(api_data, genes)?;
+                page.allele_rows = make_allele_result_rows(api_data, &page_ids)?;
+            },
+            QueryResultType::Genotype => {
+                page.total_count = genes.len();
+                let page_ids = self.sort_and_page_genes(api_data, genes)?;
+                page.genotype_rows = make_genotype_result_rows(api_data, &page_ids)?;
+            },
         }
+
+        Ok(page)
     }
 
     pub fn get_constraints(&self) -> &QueryNode {
         &self.constraints
     }
+
+    pub fn get_output_options(&self) -> &QueryOutputOptions {
+        &self.output_options
+    }
 }
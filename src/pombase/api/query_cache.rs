@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::types::GeneUniquename;
+
+pub const DEFAULT_QUERY_CACHE_SIZE: usize = 500;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct QueryCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entry_count: usize,
+    pub max_entries: usize,
+}
+
+struct QueryCacheEntry {
+    genes: Vec<GeneUniquename>,
+    last_used: u64,
+}
+
+struct QueryCacheEntries {
+    entries: HashMap<String, QueryCacheEntry>,
+    // incremented on every access and used to find the least recently
+    // used entry
+    counter: u64,
+}
+
+// A bounded cache of the results of QueryNode sub-trees, keyed by the
+// result type and the JSON of the normalised sub-tree.  When the cache is
+// full the least recently used entry is removed.
+pub struct QueryCache {
+    max_entries: usize,
+    entries: Mutex<QueryCacheEntries>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl QueryCache {
+    pub fn new(max_entries: usize) -> QueryCache {
+        QueryCache {
+            max_entries,
+            entries: Mutex::new(QueryCacheEntries {
+                entries: HashMap::new(),
+                counter: 0,
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<Vec<GeneUniquename>> {
+        let mut cache = self.entries.lock().unwrap();
+
+        cache.counter += 1;
+        let counter = cache.counter;
+
+        if let Some(entry) = cache.entries.get_mut(key) {
+            entry.last_used = counter;
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            Some(entry.genes.clone())
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            None
+        }
+    }
+
+    pub fn insert(&self, key: String, genes: &[GeneUniquename]) {
+        if self.max_entries == 0 {
+            return;
+        }
+
+        let mut cache = self.entries.lock().unwrap();
+
+        if cache.entries.len() >= self.max_entries && !cache.entries.contains_key(&key) {
+            let lru_key = cache.entries.iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(lru_key) = lru_key {
+                cache.entries.remove(&lru_key);
+            }
+        }
+
+        cache.counter += 1;
+        let last_used = cache.counter;
+
+        cache.entries.insert(key, QueryCacheEntry {
+            genes: genes.to_vec(),
+            last_used,
+        });
+    }
+
+    pub fn clear(&self) {
+        let mut cache = self.entries.lock().unwrap();
+        cache.entries.clear();
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> QueryCacheStats {
+        let entry_count = self.entries.lock().unwrap().entries.len();
+
+        QueryCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entry_count,
+            max_entries: self.max_entries,
+        }
+    }
+}
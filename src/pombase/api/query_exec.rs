@@ -3,6 +3,7 @@ use uuid::Uuid;
 use crate::api::result::*;
 use crate::api::query::*;
 use crate::api::site_db::SiteDB;
+use crate::api::query_cache::{QueryCache, QueryCacheStats};
 use crate::api_data::APIData;
 
 use flexstr::{ToSharedStr, shared_str as flex_str, shared_fmt as flex_fmt};
@@ -10,13 +11,18 @@ use flexstr::{ToSharedStr, shared_str as flex_str, shared_fmt as flex_fmt};
 pub struct QueryExec {
     api_data: APIData,
     site_db: Option<SiteDB>,
+    // results of QueryNode sub-trees, shared by all queries
+    cache: QueryCache,
 }
 
 impl QueryExec {
     pub fn new(api_data: APIData, site_db: Option<SiteDB>) -> QueryExec {
+        let cache_size = api_data.get_config().server.query_cache_size;
+
         QueryExec {
             api_data,
             site_db,
+            cache: QueryCache::new(cache_size),
         }
     }
 
@@ -25,7 +31,9 @@ impl QueryExec {
         let query =
             if let Some(ref site_db) = self.site_db {
                 if let Some(ref query_id) = query.get_constraints().get_query_id() {
-                    if let Some(existing_query) = site_db.query_by_id(query_id).await {
+                    if let Some(mut existing_query) = site_db.query_by_id(query_id).await {
+                        // saved queries don't include the display names
+                        existing_query.fill_names(&self.api_data);
                         existing_query
                     } else {
                         let message =
@@ -39,6 +47,12 @@ impl QueryExec {
                 query.clone()
             };
 
+        let validation_errors = query.validate(&self.api_data);
+
+        if !validation_errors.is_empty() {
+            return QueryAPIResult::new_validation_error(&query, validation_errors);
+        }
+
         let uuid =
             if let Some(ref site_db) = self.site_db {
                 if let Some(existing_uuid) = site_db.id_from_query(&query).await {
@@ -58,15 +72,23 @@ impl QueryExec {
 
         let id = uuid.as_hyphenated().to_string().to_shared_str();
 
-        let rows_result = query.exec(&self.api_data, &self.site_db).await;
+        let rows_result = query.exec(&self.api_data, &self.site_db, Some(&self.cache)).await;
 
         match rows_result {
-            Ok(rows) => {
+            Ok(page) => {
                 QueryAPIResult {
                     query,
                     id,
                     status: flex_str!("ok"),
-                    rows,
+                    total_count: page.total_count,
+                    rows: page.rows,
+                    allele_rows: page.allele_rows,
+                    genotype_rows: page.genotype_rows,
+                    explanation: page.explanation,
+                    enrichment: page.enrichment,
+                    reference_rows: page.reference_rows,
+                    term_rows: page.term_rows,
+                    validation_errors: vec![],
                 }
             },
             Err(mess) => QueryAPIResult::new_error(&query, mess),
@@ -76,4 +98,12 @@ impl QueryExec {
     pub fn get_api_data(&self) -> &APIData {
         &self.api_data
     }
+
+    pub fn cache_stats(&self) -> QueryCacheStats {
+        self.cache.stats()
+    }
+
+    pub fn clear_cache(&self) {
+        self.cache.clear();
+    }
 }
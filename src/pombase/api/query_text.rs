@@ -0,0 +1,903 @@
+// A small text syntax for queries, as an alternative to writing QueryNode
+// JSON by hand.  Example:
+//
+//   term(GO:0005634) AND NOT subset(SPAC*) AND protein_length(100..500)
+//
+// Operators are AND, OR and "AND NOT" (case insensitive), with AND binding
+// more tightly than OR.  Brackets can be used for grouping.  Leaf nodes are
+// written as a function call with positional and name=value arguments.
+// Values can be quoted with double quotes if they contain spaces, brackets,
+// commas or "=".
+//
+// Node names and term names are not part of the text form.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use uuid::Uuid;
+
+use crate::api::query::*;
+use crate::data_types::{GeneShort, GeneQueryPropFlag, Ploidiness, Throughput,
+                        ProteinFeatureType};
+use crate::web::config::TermAndName;
+
+use flexstr::{SharedStr as FlexStr, ToSharedStr, shared_fmt as flex_fmt};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    OpenBracket,
+    CloseBracket,
+    Comma,
+    Equals,
+    Word(String),
+    Quoted(String),
+}
+
+fn is_word_char(c: char) -> bool {
+    !c.is_whitespace() && !"(),=\"".contains(c)
+}
+
+// split the text into tokens, recording the character position of each
+fn tokenise(text: &str) -> Result<Vec<(usize, Token)>, FlexStr> {
+    let mut tokens = vec![];
+    let mut chars = text.chars().enumerate().peekable();
+
+    while let Some((pos, c)) = chars.next() {
+        let token =
+            match c {
+                '(' => Token::OpenBracket,
+                ')' => Token::CloseBracket,
+                ',' => Token::Comma,
+                '=' => Token::Equals,
+                '"' => {
+                    let mut value = String::new();
+                    let mut closed = false;
+                    while let Some((_, c)) = chars.next() {
+                        match c {
+                            '"' => {
+                                closed = true;
+                                break;
+                            },
+                            '\\' => {
+                                if let Some((_, escaped)) = chars.next() {
+                                    value.push(escaped);
+                                }
+                            },
+                            _ => value.push(c),
+                        }
+                    }
+                    if !closed {
+                        return Err(flex_fmt!("unterminated quoted string starting at position {}",
+                                             pos));
+                    }
+                    Token::Quoted(value)
+                },
+                _ if c.is_whitespace() => continue,
+                _ => {
+                    let mut word = String::from(c);
+                    while let Some((_, next_c)) = chars.peek() {
+                        if !is_word_char(*next_c) {
+                            break;
+                        }
+                        word.push(*next_c);
+                        chars.next();
+                    }
+                    Token::Word(word)
+                },
+            };
+
+        tokens.push((pos, token));
+    }
+
+    Ok(tokens)
+}
+
+#[derive(Default)]
+struct LeafArgs {
+    positional: Vec<String>,
+    named: Vec<(String, String)>,
+}
+
+impl LeafArgs {
+    fn check(&self, leaf_name: &str, min_positional: usize, max_positional: usize,
+             allowed_names: &[&str])
+        -> Result<(), String>
+    {
+        let count = self.positional.len();
+        if count < min_positional || count > max_positional {
+            let expected =
+                if min_positional == max_positional {
+                    format!("{}", min_positional)
+                } else if max_positional == usize::MAX {
+                    format!("at least {}", min_positional)
+                } else {
+                    format!("{} to {}", min_positional, max_positional)
+                };
+            return Err(format!("{}() takes {} argument(s) but was given {}",
+                               leaf_name, expected, count));
+        }
+
+        for (name, _) in &self.named {
+            if !allowed_names.contains(&name.as_str()) {
+                return Err(format!("{}() has no argument named \"{}\"", leaf_name, name));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn named_values(&self, arg_name: &str) -> Vec<&str> {
+        self.named.iter()
+            .filter(|(name, _)| name == arg_name)
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    fn named_value(&self, arg_name: &str) -> Option<&str> {
+        self.named_values(arg_name).last().copied()
+    }
+}
+
+// convert a string to an enum using the enum's serde names, so that the
+// text syntax stays in step with the JSON
+fn enum_from_str<T: DeserializeOwned>(kind: &str, value: &str) -> Result<T, String> {
+    serde_json::from_value(serde_json::Value::String(value.to_owned()))
+        .map_err(|_| format!("unknown {}: {}", kind, value))
+}
+
+fn enum_to_string<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(s)) => s,
+        _ => String::from("?"),
+    }
+}
+
+fn parse_range<T: std::str::FromStr>(range: &str) -> Result<(Option<T>, Option<T>), String> {
+    let parse_part = |part: &str| {
+        if part.is_empty() {
+            Ok(None)
+        } else {
+            part.parse::<T>().map(Some)
+                .map_err(|_| format!("can't parse \"{}\" in range: {}", part, range))
+        }
+    };
+
+    if let Some((start, end)) = range.split_once("..") {
+        Ok((parse_part(start)?, parse_part(end)?))
+    } else {
+        let value = parse_part(range)?;
+        Ok((value, parse_part(range)?))
+    }
+}
+
+fn make_gene_shorts(uniquenames: &[String]) -> Vec<GeneShort> {
+    uniquenames.iter()
+        .map(|uniquename| GeneShort {
+            uniquename: uniquename.to_shared_str(),
+            name: None,
+            product: None,
+            transcript_count: 1,
+            flags: HashSet::new(),
+        })
+        .collect()
+}
+
+fn conditions_from_args(args: &LeafArgs, arg_name: &str) -> HashSet<TermAndName> {
+    args.named_values(arg_name).iter()
+        .map(|termid| TermAndName {
+            termid: termid.to_shared_str(),
+            // filled in later by QueryNode::fill_names()
+            name: "".to_shared_str(),
+        })
+        .collect()
+}
+
+fn make_leaf(leaf_name: &str, args: LeafArgs) -> Result<QueryNode, String> {
+    let template = QueryNode::template_node();
+    let pos = &args.positional;
+
+    let node =
+        match leaf_name {
+            "term" => {
+                args.check(leaf_name, 1, 1, &["locus", "ploidiness", "expression",
+                                              "condition", "excluded_condition",
+                                              "evidence", "throughput", "date_from",
+                                              "date_to", "reference", "assigned_by",
+                                              "curator"])?;
+                let single_or_multi_locus = args.named_value("locus")
+                    .map(|locus| enum_from_str::<SingleOrMultiLocus>("locus", locus))
+                    .transpose()?;
+                let ploidiness = args.named_value("ploidiness")
+                    .map(|ploidiness| enum_from_str::<Ploidiness>("ploidiness", ploidiness))
+                    .transpose()?;
+                let expression = args.named_value("expression")
+                    .map(|expr| enum_from_str::<QueryExpressionFilter>("expression", expr))
+                    .transpose()?;
+                let throughput = args.named_value("throughput")
+                    .map(|throughput| enum_from_str::<Throughput>("throughput", throughput))
+                    .transpose()?;
+                let named_shared_str = |arg_name: &str| {
+                    args.named_value(arg_name).map(|value| value.to_shared_str())
+                };
+                QueryNode {
+                    term: Some(TermNode {
+                        termid: pos[0].to_shared_str(),
+                        name: None,
+                        single_or_multi_locus,
+                        ploidiness,
+                        expression,
+                        conditions: conditions_from_args(&args, "condition"),
+                        excluded_conditions: conditions_from_args(&args, "excluded_condition"),
+                        evidence_codes: args.named_values("evidence").iter()
+                            .map(|evidence| evidence.to_shared_str())
+                            .collect(),
+                        throughput,
+                        date_from: named_shared_str("date_from"),
+                        date_to: named_shared_str("date_to"),
+                        reference_uniquename: named_shared_str("reference"),
+                        assigned_by: named_shared_str("assigned_by"),
+                        curator: named_shared_str("curator"),
+                    }),
+                    .. template
+                }
+            },
+            "ref" => {
+                args.check(leaf_name, 1, 1, &[])?;
+                QueryNode {
+                    ref_genes: Some(RefGenesNode {
+                        reference_uniquename: pos[0].to_shared_str(),
+                    }),
+                    .. template
+                }
+            },
+            "subset" => {
+                args.check(leaf_name, 1, 1, &[])?;
+                QueryNode {
+                    subset: Some(SubsetNode {
+                        subset_name: pos[0].to_shared_str(),
+                    }),
+                    .. template
+                }
+            },
+            "genes" => {
+                args.check(leaf_name, 1, usize::MAX, &[])?;
+                QueryNode {
+                    gene_list: Some(GeneListNode {
+                        genes: make_gene_shorts(pos),
+                    }),
+                    .. template
+                }
+            },
+            "target_of" => {
+                args.check(leaf_name, 1, usize::MAX, &[])?;
+                QueryNode {
+                    target_of: Some(TargetOfNode {
+                        genes: make_gene_shorts(pos),
+                    }),
+                    .. template
+                }
+            },
+            "has_ortholog" => {
+                args.check(leaf_name, 1, 1, &[])?;
+                let taxonid = pos[0].parse::<u32>()
+                    .map_err(|_| format!("not a taxon ID: {}", pos[0]))?;
+                QueryNode {
+                    has_ortholog: Some(HasOrthologNode {
+                        taxonid,
+                    }),
+                    .. template
+                }
+            },
+            "genome_range" => {
+                args.check(leaf_name, 1, 2, &[])?;
+                let (start, end) =
+                    if let Some(range) = pos.get(1) {
+                        parse_range::<usize>(range)?
+                    } else {
+                        (None, None)
+                    };
+                QueryNode {
+                    genome_range: Some(GenomeRangeNode {
+                        start,
+                        end,
+                        chromosome_name: pos[0].clone(),
+                    }),
+                    .. template
+                }
+            },
+            "gene_ex_range" => {
+                args.check(leaf_name, 1, 2, &["quantile"])?;
+                let (start, end) =
+                    if let Some(range) = pos.get(1) {
+                        parse_range::<f64>(range)?
+                    } else {
+                        (None, None)
+                    };
+                let quantile = args.named_value("quantile")
+                    .map(|quantile| enum_from_str::<GeneExQuantile>("quantile", quantile))
+                    .transpose()?;
+                QueryNode {
+                    gene_ex_range: Some(GeneExRangeNode {
+                        dataset_name: pos[0].to_shared_str(),
+                        start,
+                        end,
+                        quantile,
+                    }),
+                    .. template
+                }
+            },
+            "domain" | "protein_feature" => {
+                args.check(leaf_name, 1, 2, &[])?;
+                let (start, end) =
+                    if let Some(range) = pos.get(1) {
+                        parse_range::<usize>(range)?
+                    } else {
+                        (None, None)
+                    };
+                if leaf_name == "domain" {
+                    QueryNode {
+                        domain: Some(DomainNode {
+                            domain_id: pos[0].to_shared_str(),
+                            start,
+                            end,
+                        }),
+                        .. template
+                    }
+                } else {
+                    let feature_type =
+                        enum_from_str::<ProteinFeatureType>("protein feature type", &pos[0])?;
+                    QueryNode {
+                        protein_feature: Some(ProteinFeatureNode {
+                            feature_type,
+                            start,
+                            end,
+                        }),
+                        .. template
+                    }
+                }
+            },
+            "motif" => {
+                args.check(leaf_name, 1, 1, &[])?;
+                QueryNode {
+                    motif: Some(MotifNode {
+                        pattern: pos[0].to_shared_str(),
+                    }),
+                    .. template
+                }
+            },
+            "interactors" => {
+                args.check(leaf_name, 2, 2, &[])?;
+                QueryNode {
+                    interactors: Some(InteractorsNode {
+                        gene_uniquename: pos[0].to_shared_str(),
+                        interaction_type: pos[1].clone(),
+                    }),
+                    .. template
+                }
+            },
+            "substrates" => {
+                args.check(leaf_name, 1, 1, &["phase"])?;
+                QueryNode {
+                    substrates: Some(SubstratesNode {
+                        gene_uniquename: pos[0].to_shared_str(),
+                        phase_term: args.named_value("phase").map(|phase| phase.to_shared_str()),
+                    }),
+                    .. template
+                }
+            },
+            "downstream_genes" => {
+                args.check(leaf_name, 2, 2, &[])?;
+                QueryNode {
+                    downstream_genes: Some(DownstreamGenesNode {
+                        gene_uniquename: pos[0].to_shared_str(),
+                        cv_name: pos[1].to_shared_str(),
+                    }),
+                    .. template
+                }
+            },
+            "genes_targeting" => {
+                args.check(leaf_name, 2, 2, &[])?;
+                QueryNode {
+                    genes_targeting: Some(GenesTargetingNode {
+                        gene_uniquename: pos[0].to_shared_str(),
+                        target_of_type: enum_from_str("target type", &pos[1])?,
+                    }),
+                    .. template
+                }
+            },
+            "gene_properties" => {
+                args.check(leaf_name, 1, usize::MAX, &[])?;
+                let property_flags = pos.iter()
+                    .map(|flag| enum_from_str::<GeneQueryPropFlag>("gene property", flag))
+                    .collect::<Result<HashSet<_>, _>>()?;
+                QueryNode {
+                    gene_properties: Some(QueryGenePropNode {
+                        property_flags,
+                    }),
+                    .. template
+                }
+            },
+            "extension" => {
+                args.check(leaf_name, 2, 2, &["gene", "term", "residue"])?;
+                let ranges = [
+                    args.named_value("gene")
+                        .map(|gene| ExtensionRangeFilter::Gene(gene.to_shared_str())),
+                    args.named_value("term")
+                        .map(|termid| ExtensionRangeFilter::Term(termid.to_shared_str())),
+                    args.named_value("residue")
+                        .map(|residue| ExtensionRangeFilter::Residue(residue.to_shared_str())),
+                ];
+                let mut ranges = ranges.into_iter().flatten().collect::<Vec<_>>();
+                if ranges.len() > 1 {
+                    return Err("extension() takes only one of gene, term or residue".into());
+                }
+                QueryNode {
+                    extension: Some(ExtensionNode {
+                        termid: pos[0].to_shared_str(),
+                        rel_type_name: pos[1].to_shared_str(),
+                        range: ranges.pop(),
+                    }),
+                    .. template
+                }
+            },
+            "query_id" => {
+                args.check(leaf_name, 1, 1, &[])?;
+                let id = Uuid::parse_str(&pos[0])
+                    .map_err(|_| format!("not a query ID: {}", pos[0]))?;
+                QueryNode {
+                    query_id: Some(QueryIdNode {
+                        id,
+                    }),
+                    .. template
+                }
+            },
+            _ => {
+                if let Ok(range_type) = enum_from_str::<IntRangeType>("range", leaf_name) {
+                    args.check(leaf_name, 1, 1, &["option"])?;
+                    let (start, end) = parse_range::<usize>(&pos[0])?;
+                    QueryNode {
+                        int_range: Some(IntRangeNode {
+                            range_type,
+                            start,
+                            end,
+                            options: args.named_values("option").iter()
+                                .map(|option| option.to_string()).collect(),
+                        }),
+                        .. template
+                    }
+                } else if let Ok(range_type) = enum_from_str::<FloatRangeType>("range", leaf_name) {
+                    args.check(leaf_name, 1, 1, &["option"])?;
+                    let (start, end) = parse_range::<f64>(&pos[0])?;
+                    QueryNode {
+                        float_range: Some(FloatRangeNode {
+                            range_type,
+                            start,
+                            end,
+                            options: args.named_values("option").iter()
+                                .map(|option| option.to_string()).collect(),
+                        }),
+                        .. template
+                    }
+                } else {
+                    return Err(format!("unknown query node type: {}", leaf_name));
+                }
+            },
+        };
+
+    Ok(node)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    index: usize,
+    text_len: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index).map(|(_, token)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.index).map(|(_, token)| token.clone());
+        self.index += 1;
+        token
+    }
+
+    fn error(&self, message: &str) -> FlexStr {
+        let position = self.tokens.get(self.index)
+            .map(|(pos, _)| *pos)
+            .unwrap_or(self.text_len);
+        flex_fmt!("{} at position {}", message, position)
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn parse_or(&mut self) -> Result<QueryNode, FlexStr> {
+        let mut nodes = vec![self.parse_and()?];
+
+        while self.peek_keyword("OR") {
+            self.next();
+            nodes.push(self.parse_and()?);
+        }
+
+        if nodes.len() == 1 {
+            Ok(nodes.remove(0))
+        } else {
+            Ok(QueryNode {
+                or: Some(nodes),
+                .. QueryNode::template_node()
+            })
+        }
+    }
+
+    // "a AND b AND NOT c AND NOT d" becomes: NOT(AND(a, b), OR(c, d))
+    fn parse_and(&mut self) -> Result<QueryNode, FlexStr> {
+        let mut included = vec![self.parse_primary()?];
+        let mut excluded = vec![];
+
+        while self.peek_keyword("AND") {
+            self.next();
+            if self.peek_keyword("NOT") {
+                self.next();
+                excluded.push(self.parse_primary()?);
+            } else {
+                included.push(self.parse_primary()?);
+            }
+        }
+
+        let included_node =
+            if included.len() == 1 {
+                included.remove(0)
+            } else {
+                QueryNode {
+                    and: Some(included),
+                    .. QueryNode::template_node()
+                }
+            };
+
+        if excluded.is_empty() {
+            return Ok(included_node);
+        }
+
+        let excluded_node =
+            if excluded.len() == 1 {
+                excluded.remove(0)
+            } else {
+                QueryNode {
+                    or: Some(excluded),
+                    .. QueryNode::template_node()
+                }
+            };
+
+        Ok(QueryNode {
+            not: Some(NotNode {
+                node_a: Box::new(included_node),
+                node_b: Box::new(excluded_node),
+            }),
+            .. QueryNode::template_node()
+        })
+    }
+
+    fn parse_primary(&mut self) -> Result<QueryNode, FlexStr> {
+        if self.peek_keyword("NOT") {
+            return Err(self.error("NOT must follow AND, as in \"a AND NOT b\","));
+        }
+
+        match self.peek().cloned() {
+            Some(Token::OpenBracket) => {
+                self.next();
+                let node = self.parse_or()?;
+                if self.next() != Some(Token::CloseBracket) {
+                    self.index -= 1;
+                    return Err(self.error("expected \")\""));
+                }
+                Ok(node)
+            },
+            Some(Token::Word(leaf_name)) => {
+                self.next();
+                let leaf_position = self.index - 1;
+                let args = self.parse_args()?;
+                make_leaf(&leaf_name, args)
+                    .map_err(|message| {
+                        let position = self.tokens[leaf_position].0;
+                        flex_fmt!("{} at position {}", message, position)
+                    })
+            },
+            Some(_) => Err(self.error("expected a query node or \"(\"")),
+            None => Err(self.error("unexpected end of query")),
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<String, FlexStr> {
+        match self.next() {
+            Some(Token::Word(value)) | Some(Token::Quoted(value)) => Ok(value),
+            _ => {
+                self.index -= 1;
+                Err(self.error("expected an argument value"))
+            },
+        }
+    }
+
+    fn parse_args(&mut self) -> Result<LeafArgs, FlexStr> {
+        if self.next() != Some(Token::OpenBracket) {
+            self.index -= 1;
+            return Err(self.error("expected \"(\""));
+        }
+
+        let mut args = LeafArgs::default();
+
+        if self.peek() == Some(&Token::CloseBracket) {
+            self.next();
+            return Ok(args);
+        }
+
+        loop {
+            let value = self.parse_value()?;
+
+            if self.peek() == Some(&Token::Equals) {
+                self.next();
+                let named_value = self.parse_value()?;
+                args.named.push((value, named_value));
+            } else {
+                args.positional.push(value);
+            }
+
+            match self.next() {
+                Some(Token::Comma) => continue,
+                Some(Token::CloseBracket) => break,
+                _ => {
+                    self.index -= 1;
+                    return Err(self.error("expected \",\" or \")\""));
+                },
+            }
+        }
+
+        Ok(args)
+    }
+}
+
+// Parse the text form of a query into a QueryNode.  Condition and term
+// names aren't part of the text, use QueryNode::fill_names() to add them.
+pub fn parse_query_text(text: &str) -> Result<QueryNode, FlexStr> {
+    let tokens = tokenise(text)?;
+
+    let mut parser = Parser {
+        tokens,
+        index: 0,
+        text_len: text.chars().count(),
+    };
+
+    let node = parser.parse_or()?;
+
+    if parser.peek().is_some() {
+        return Err(parser.error("unexpected text after query"));
+    }
+
+    Ok(node)
+}
+
+fn quote_if_needed(value: &str) -> String {
+    if !value.is_empty() && value.chars().all(is_word_char) {
+        value.to_owned()
+    } else {
+        let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+        format!("\"{}\"", escaped)
+    }
+}
+
+fn format_range<T: fmt::Display>(start: &Option<T>, end: &Option<T>) -> String {
+    let format_part = |part: &Option<T>| {
+        part.as_ref().map(|part| part.to_string()).unwrap_or_default()
+    };
+    format!("{}..{}", format_part(start), format_part(end))
+}
+
+fn is_compound(node: &QueryNode) -> bool {
+    node.or.is_some() || node.and.is_some() || node.not.is_some()
+}
+
+fn write_bracketed(f: &mut fmt::Formatter<'_>, node: &QueryNode, bracket: bool) -> fmt::Result {
+    if bracket {
+        write!(f, "({})", node)
+    } else {
+        write!(f, "{}", node)
+    }
+}
+
+fn write_leaf(f: &mut fmt::Formatter<'_>, name: &str, positional: &[String],
+              mut named: Vec<(&str, String)>) -> fmt::Result {
+    // sort so that the output doesn't depend on HashSet order
+    named.sort();
+
+    let args = positional.iter()
+        .map(|value| quote_if_needed(value))
+        .chain(named.iter()
+               .map(|(name, value)| format!("{}={}", name, quote_if_needed(value))))
+        .collect::<Vec<_>>();
+
+    write!(f, "{}({})", name, args.join(", "))
+}
+
+fn sorted_strings<'a>(values: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut values = values.map(|s| s.to_owned()).collect::<Vec<_>>();
+    values.sort();
+    values
+}
+
+// The inverse of parse_query_text().  Compound operands are bracketed so
+// that parsing the output gives back the same tree.
+impl fmt::Display for QueryNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (nodes, operator) in [(&self.and, " AND "), (&self.or, " OR ")] {
+            if let Some(ref nodes) = nodes {
+                if nodes.is_empty() {
+                    return write!(f, "()");
+                }
+                for (i, node) in nodes.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "{}", operator)?;
+                    }
+                    write_bracketed(f, node, is_compound(node))?;
+                }
+                return Ok(());
+            }
+        }
+
+        if let Some(ref not_node) = self.not {
+            let node_a = &not_node.node_a;
+            write_bracketed(f, node_a, node_a.or.is_some() || node_a.not.is_some())?;
+            write!(f, " AND NOT ")?;
+            return write_bracketed(f, &not_node.node_b, is_compound(&not_node.node_b));
+        }
+
+        if let Some(ref term) = self.term {
+            let mut named = vec![];
+            if let Some(ref locus) = term.single_or_multi_locus {
+                named.push(("locus", enum_to_string(locus)));
+            }
+            if let Some(ref ploidiness) = term.ploidiness {
+                named.push(("ploidiness", enum_to_string(ploidiness)));
+            }
+            if let Some(ref expression) = term.expression {
+                named.push(("expression", enum_to_string(expression)));
+            }
+            for condition in &term.conditions {
+                named.push(("condition", condition.termid.to_string()));
+            }
+            for condition in &term.excluded_conditions {
+                named.push(("excluded_condition", condition.termid.to_string()));
+            }
+            let mut evidence_codes = term.evidence_codes.iter().collect::<Vec<_>>();
+            evidence_codes.sort();
+            for evidence in evidence_codes {
+                named.push(("evidence", evidence.to_string()));
+            }
+            if let Some(ref throughput) = term.throughput {
+                named.push(("throughput", enum_to_string(throughput)));
+            }
+            for (arg_name, value) in [("date_from", &term.date_from), ("date_to", &term.date_to),
+                                      ("reference", &term.reference_uniquename),
+                                      ("assigned_by", &term.assigned_by),
+                                      ("curator", &term.curator)] {
+                if let Some(ref value) = value {
+                    named.push((arg_name, value.to_string()));
+                }
+            }
+            return write_leaf(f, "term", &[term.termid.to_string()], named);
+        }
+        if let Some(ref ref_genes) = self.ref_genes {
+            return write_leaf(f, "ref", &[ref_genes.reference_uniquename.to_string()], vec![]);
+        }
+        if let Some(ref subset) = self.subset {
+            return write_leaf(f, "subset", &[subset.subset_name.to_string()], vec![]);
+        }
+        if let Some(ref gene_list) = self.gene_list {
+            let genes = gene_list.genes.iter()
+                .map(|gene| gene.uniquename.to_string()).collect::<Vec<_>>();
+            return write_leaf(f, "genes", &genes, vec![]);
+        }
+        if let Some(ref target_of) = self.target_of {
+            let genes = target_of.genes.iter()
+                .map(|gene| gene.uniquename.to_string()).collect::<Vec<_>>();
+            return write_leaf(f, "target_of", &genes, vec![]);
+        }
+        if let Some(ref has_ortholog) = self.has_ortholog {
+            return write_leaf(f, "has_ortholog", &[has_ortholog.taxonid.to_string()], vec![]);
+        }
+        if let Some(ref int_range) = self.int_range {
+            let named = sorted_strings(int_range.options.iter().map(String::as_str))
+                .into_iter().map(|option| ("option", option)).collect();
+            return write_leaf(f, &enum_to_string(&int_range.range_type),
+                              &[format_range(&int_range.start, &int_range.end)], named);
+        }
+        if let Some(ref float_range) = self.float_range {
+            let named = sorted_strings(float_range.options.iter().map(String::as_str))
+                .into_iter().map(|option| ("option", option)).collect();
+            return write_leaf(f, &enum_to_string(&float_range.range_type),
+                              &[format_range(&float_range.start, &float_range.end)], named);
+        }
+        if let Some(ref genome_range) = self.genome_range {
+            let mut positional = vec![genome_range.chromosome_name.clone()];
+            if genome_range.start.is_some() || genome_range.end.is_some() {
+                positional.push(format_range(&genome_range.start, &genome_range.end));
+            }
+            return write_leaf(f, "genome_range", &positional, vec![]);
+        }
+        if let Some(ref gene_ex_range) = self.gene_ex_range {
+            let mut positional = vec![gene_ex_range.dataset_name.to_string()];
+            if gene_ex_range.start.is_some() || gene_ex_range.end.is_some() {
+                positional.push(format_range(&gene_ex_range.start, &gene_ex_range.end));
+            }
+            let named = gene_ex_range.quantile.iter()
+                .map(|quantile| ("quantile", enum_to_string(quantile))).collect();
+            return write_leaf(f, "gene_ex_range", &positional, named);
+        }
+        if let Some(ref domain) = self.domain {
+            let mut positional = vec![domain.domain_id.to_string()];
+            if domain.start.is_some() || domain.end.is_some() {
+                positional.push(format_range(&domain.start, &domain.end));
+            }
+            return write_leaf(f, "domain", &positional, vec![]);
+        }
+        if let Some(ref protein_feature) = self.protein_feature {
+            let mut positional = vec![enum_to_string(&protein_feature.feature_type)];
+            if protein_feature.start.is_some() || protein_feature.end.is_some() {
+                positional.push(format_range(&protein_feature.start, &protein_feature.end));
+            }
+            return write_leaf(f, "protein_feature", &positional, vec![]);
+        }
+        if let Some(ref motif) = self.motif {
+            return write_leaf(f, "motif", &[motif.pattern.to_string()], vec![]);
+        }
+        if let Some(ref interactors) = self.interactors {
+            return write_leaf(f, "interactors",
+                              &[interactors.gene_uniquename.to_string(),
+                                interactors.interaction_type.clone()], vec![]);
+        }
+        if let Some(ref substrates) = self.substrates {
+            let named = substrates.phase_term.iter()
+                .map(|phase| ("phase", phase.to_string())).collect();
+            return write_leaf(f, "substrates", &[substrates.gene_uniquename.to_string()], named);
+        }
+        if let Some(ref downstream_genes) = self.downstream_genes {
+            return write_leaf(f, "downstream_genes",
+                              &[downstream_genes.gene_uniquename.to_string(),
+                                downstream_genes.cv_name.to_string()], vec![]);
+        }
+        if let Some(ref genes_targeting) = self.genes_targeting {
+            return write_leaf(f, "genes_targeting",
+                              &[genes_targeting.gene_uniquename.to_string(),
+                                enum_to_string(&genes_targeting.target_of_type)], vec![]);
+        }
+        if let Some(ref gene_properties) = self.gene_properties {
+            let flags = gene_properties.property_flags.iter()
+                .map(enum_to_string).collect::<Vec<_>>();
+            let flags = sorted_strings(flags.iter().map(String::as_str));
+            return write_leaf(f, "gene_properties", &flags, vec![]);
+        }
+        if let Some(ref extension) = self.extension {
+            let named = extension.range.iter()
+                .map(|range| match range {
+                    ExtensionRangeFilter::Gene(gene_uniquename) =>
+                        ("gene", gene_uniquename.to_string()),
+                    ExtensionRangeFilter::Term(termid) => ("term", termid.to_string()),
+                    ExtensionRangeFilter::Residue(residue) => ("residue", residue.to_string()),
+                })
+                .collect();
+            return write_leaf(f, "extension",
+                              &[extension.termid.to_string(),
+                                extension.rel_type_name.to_string()], named);
+        }
+        if let Some(ref query_id) = self.query_id {
+            return write_leaf(f, "query_id", &[query_id.id.to_string()], vec![]);
+        }
+
+        write!(f, "()")
+    }
+}
@@ -0,0 +1,298 @@
+use crate::api::query::{Query, QueryNode, QueryResultType, TermNode, ExtensionRangeFilter};
+use crate::api::motif_search::motif_regex;
+use crate::api_data::APIData;
+use crate::data_types::{DataLookup, GeneShort};
+use crate::types::TermId;
+
+use flexstr::{SharedStr as FlexStr, shared_fmt as flex_fmt};
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Copy, Clone)]
+pub enum QueryValidationErrorType {
+#[serde(rename = "empty_node")]
+    EmptyNode,
+#[serde(rename = "multiple_node_types")]
+    MultipleNodeTypes,
+#[serde(rename = "unknown_term")]
+    UnknownTerm,
+#[serde(rename = "unknown_subset")]
+    UnknownSubset,
+#[serde(rename = "unknown_reference")]
+    UnknownReference,
+#[serde(rename = "unknown_chromosome")]
+    UnknownChromosome,
+#[serde(rename = "unknown_gene")]
+    UnknownGene,
+#[serde(rename = "unknown_interaction_type")]
+    UnknownInteractionType,
+#[serde(rename = "unsupported_node_type")]
+    UnsupportedNodeType,
+#[serde(rename = "invalid_date")]
+    InvalidDate,
+#[serde(rename = "unknown_gene_ex_dataset")]
+    UnknownGeneExDataset,
+#[serde(rename = "invalid_motif")]
+    InvalidMotif,
+}
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
+pub struct QueryValidationError {
+    pub error_type: QueryValidationErrorType,
+    // the location of the problem in the query JSON, like:
+    //   constraints.and[1].term.termid
+    pub path: String,
+    pub message: FlexStr,
+}
+
+struct Validator<'a> {
+    api_data: &'a APIData,
+    result_type: QueryResultType,
+    errors: Vec<QueryValidationError>,
+}
+
+impl<'a> Validator<'a> {
+    fn add_error(&mut self, error_type: QueryValidationErrorType, path: String,
+                 message: FlexStr) {
+        self.errors.push(QueryValidationError {
+            error_type,
+            path,
+            message,
+        });
+    }
+
+    fn check_termid(&mut self, termid: &TermId, path: String) {
+        let maps = self.api_data.get_maps();
+        if self.api_data.get_term(termid).is_none() &&
+            !maps.secondary_identifiers_map.contains_key(termid) {
+            self.add_error(QueryValidationErrorType::UnknownTerm, path,
+                           flex_fmt!("no such term: {}", termid));
+        }
+    }
+
+    fn check_gene(&mut self, gene_uniquename: &FlexStr, path: String) {
+        if self.api_data.get_gene_summary(gene_uniquename).is_none() {
+            self.add_error(QueryValidationErrorType::UnknownGene, path,
+                           flex_fmt!("no such gene: {}", gene_uniquename));
+        }
+    }
+
+    fn check_genes(&mut self, genes: &[GeneShort], path: String) {
+        for (idx, gene) in genes.iter().enumerate() {
+            self.check_gene(&gene.uniquename, format!("{}[{}].uniquename", path, idx));
+        }
+    }
+
+    // subset names can start with "!" and end with "*", see
+    // APIData::genes_of_subset()
+    fn check_subset(&mut self, subset_name: &FlexStr, path: String) {
+        let trimmed_name = subset_name.strip_prefix('!').unwrap_or(subset_name);
+
+        let gene_subsets = &self.api_data.get_maps().gene_subsets;
+
+        let exists =
+            if let Some(prefix) = trimmed_name.strip_suffix('*') {
+                gene_subsets.keys().any(|name| name.starts_with(prefix))
+            } else {
+                gene_subsets.keys().any(|name| name.as_ref() == trimmed_name)
+            };
+
+        if !exists {
+            self.add_error(QueryValidationErrorType::UnknownSubset, path,
+                           flex_fmt!("no such subset: {}", subset_name));
+        }
+    }
+
+    fn check_reference(&mut self, reference_uniquename: &FlexStr, path: String) {
+        if self.api_data.get_reference(reference_uniquename).is_none() {
+            self.add_error(QueryValidationErrorType::UnknownReference, path,
+                           flex_fmt!("no such reference: {}", reference_uniquename));
+        }
+    }
+
+    // dates can be "YYYY", "YYYY-MM" or "YYYY-MM-DD"
+    fn check_date(&mut self, date: &FlexStr, path: String) {
+        let parts = date.split('-').collect::<Vec<_>>();
+
+        let valid = parts.len() <= 3 &&
+            parts.iter().enumerate().all(|(idx, part)| {
+                let expected_len = if idx == 0 { 4 } else { 2 };
+                part.len() == expected_len && part.chars().all(|c| c.is_ascii_digit())
+            });
+
+        if !valid {
+            self.add_error(QueryValidationErrorType::InvalidDate, path,
+                           flex_fmt!("invalid date, expected YYYY, YYYY-MM or YYYY-MM-DD: {}",
+                                     date));
+        }
+    }
+
+    fn check_term_node(&mut self, term_node: &TermNode, path: &str) {
+        self.check_termid(&term_node.termid, format!("{}.termid", path));
+
+        for condition in &term_node.conditions {
+            self.check_termid(&condition.termid, format!("{}.conditions", path));
+        }
+        for condition in &term_node.excluded_conditions {
+            self.check_termid(&condition.termid, format!("{}.excluded_conditions", path));
+        }
+
+        if let Some(ref date_from) = term_node.date_from {
+            self.check_date(date_from, format!("{}.date_from", path));
+        }
+        if let Some(ref date_to) = term_node.date_to {
+            self.check_date(date_to, format!("{}.date_to", path));
+        }
+        if let Some(ref reference_uniquename) = term_node.reference_uniquename {
+            self.check_reference(reference_uniquename, format!("{}.reference_uniquename", path));
+        }
+    }
+
+    fn check_node(&mut self, node: &QueryNode, path: &str) {
+        let node_types = node.set_node_types();
+
+        if node_types.is_empty() {
+            self.add_error(QueryValidationErrorType::EmptyNode, path.to_owned(),
+                           flex_fmt!("query node has no type"));
+            return;
+        }
+
+        if node_types.len() > 1 {
+            self.add_error(QueryValidationErrorType::MultipleNodeTypes, path.to_owned(),
+                           flex_fmt!("query node has more than one type: {}",
+                                     node_types.join(", ")));
+        }
+
+        if !self.result_type.is_gene() {
+            for node_type in &node_types {
+                if !["or", "and", "not", "term"].contains(node_type) {
+                    self.add_error(QueryValidationErrorType::UnsupportedNodeType,
+                                   format!("{}.{}", path, node_type),
+                                   flex_fmt!("{} nodes can't be used in {} queries",
+                                             node_type, self.result_type.name()));
+                }
+            }
+        }
+
+        for (operator, maybe_nodes) in [("or", &node.or), ("and", &node.and)] {
+            if let Some(ref nodes) = maybe_nodes {
+                if nodes.is_empty() {
+                    self.add_error(QueryValidationErrorType::EmptyNode,
+                                   format!("{}.{}", path, operator),
+                                   flex_fmt!("{} operator has no nodes",
+                                             operator.to_uppercase()));
+                }
+                for (idx, child_node) in nodes.iter().enumerate() {
+                    self.check_node(child_node, &format!("{}.{}[{}]", path, operator, idx));
+                }
+            }
+        }
+
+        if let Some(ref not_node) = node.not {
+            self.check_node(&not_node.node_a, &format!("{}.not.node_a", path));
+            self.check_node(&not_node.node_b, &format!("{}.not.node_b", path));
+        }
+
+        if let Some(ref term_node) = node.term {
+            self.check_term_node(term_node, &format!("{}.term", path));
+        }
+
+        if let Some(ref ref_genes) = node.ref_genes {
+            self.check_reference(&ref_genes.reference_uniquename,
+                                 format!("{}.ref_genes.reference_uniquename", path));
+        }
+
+        if let Some(ref subset) = node.subset {
+            self.check_subset(&subset.subset_name, format!("{}.subset.subset_name", path));
+        }
+
+        if let Some(ref gene_list) = node.gene_list {
+            self.check_genes(&gene_list.genes, format!("{}.gene_list.genes", path));
+        }
+
+        if let Some(ref target_of) = node.target_of {
+            self.check_genes(&target_of.genes, format!("{}.target_of.genes", path));
+        }
+
+        if let Some(ref genome_range) = node.genome_range {
+            let chromosome_name = &genome_range.chromosome_name;
+            if !self.api_data.get_maps().chromosomes.contains_key(chromosome_name.as_str()) {
+                self.add_error(QueryValidationErrorType::UnknownChromosome,
+                               format!("{}.genome_range.chromosome_name", path),
+                               flex_fmt!("no such chromosome: {}", chromosome_name));
+            }
+        }
+
+        if let Some(ref gene_ex_range) = node.gene_ex_range {
+            let dataset_name = &gene_ex_range.dataset_name;
+            let datasets = &self.api_data.get_config().gene_expression.datasets;
+            if !datasets.iter().any(|dataset| dataset.name == *dataset_name) {
+                self.add_error(QueryValidationErrorType::UnknownGeneExDataset,
+                               format!("{}.gene_ex_range.dataset_name", path),
+                               flex_fmt!("no such gene expression dataset: {}", dataset_name));
+            }
+        }
+
+        if let Some(ref motif) = node.motif {
+            if let Err(err) = motif_regex(&motif.pattern) {
+                self.add_error(QueryValidationErrorType::InvalidMotif,
+                               format!("{}.motif.pattern", path), err);
+            }
+        }
+
+        if let Some(ref interactors) = node.interactors {
+            self.check_gene(&interactors.gene_uniquename,
+                            format!("{}.interactors.gene_uniquename", path));
+            let interaction_type = &interactors.interaction_type;
+            if interaction_type != "physical" && interaction_type != "genetic" {
+                self.add_error(QueryValidationErrorType::UnknownInteractionType,
+                               format!("{}.interactors.interaction_type", path),
+                               flex_fmt!("no such interaction type: {}", interaction_type));
+            }
+        }
+
+        if let Some(ref substrates) = node.substrates {
+            self.check_gene(&substrates.gene_uniquename,
+                            format!("{}.substrates.gene_uniquename", path));
+            if let Some(ref phase_term) = substrates.phase_term {
+                self.check_termid(phase_term, format!("{}.substrates.phase_term", path));
+            }
+        }
+
+        if let Some(ref downstream_genes) = node.downstream_genes {
+            self.check_gene(&downstream_genes.gene_uniquename,
+                            format!("{}.downstream_genes.gene_uniquename", path));
+        }
+
+        if let Some(ref genes_targeting) = node.genes_targeting {
+            self.check_gene(&genes_targeting.gene_uniquename,
+                            format!("{}.genes_targeting.gene_uniquename", path));
+        }
+
+        if let Some(ref extension) = node.extension {
+            self.check_termid(&extension.termid, format!("{}.extension.termid", path));
+            match extension.range {
+                Some(ExtensionRangeFilter::Gene(ref gene_uniquename)) =>
+                    self.check_gene(gene_uniquename, format!("{}.extension.range", path)),
+                Some(ExtensionRangeFilter::Term(ref termid)) =>
+                    self.check_termid(termid, format!("{}.extension.range", path)),
+                _ => (),
+            }
+        }
+    }
+}
+
+impl Query {
+    // Check that the query is well formed and that the terms, genes,
+    // subsets etc. that it refers to exist.  An empty Vec means the query
+    // is valid.
+    pub fn validate(&self, api_data: &APIData) -> Vec<QueryValidationError> {
+        let mut validator = Validator {
+            api_data,
+            result_type: self.get_output_options().result_type.constraints_type(),
+            errors: vec![],
+        };
+
+        validator.check_node(self.get_constraints(), "constraints");
+
+        validator.errors
+    }
+}
@@ -1,8 +1,11 @@
 use std::collections::HashSet;
 
 use crate::api::query::Query;
-use crate::data_types::{DeletionViability, GeneQueryAttrName, GeneQueryTermData, GoCamId, PresentAbsent};
-use crate::types::{TermId, GeneUniquename, ReferenceUniquename, PdbId};
+use crate::api::query_validation::QueryValidationError;
+use crate::api::enrichment::EnrichmentResult;
+use crate::data_types::{Ploidiness, DeletionViability, GeneQueryAttrName, GeneQueryTermData, GoCamId, PresentAbsent,
+                        ReferenceShort, TermShort};
+use crate::types::{AlleleUniquename, GenotypeDisplayUniquename, TermId, GeneUniquename, ReferenceUniquename, PdbId};
 
 use flexstr::{SharedStr as FlexStr, shared_str as flex_str};
 
@@ -63,12 +66,82 @@ pub struct ResultRow {
     pub gene_expression: Vec<GeneExValue>,
 }
 
+// returned for each QueryNode when the "explain" output flag is set
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct QueryNodeExplanation {
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub node_name: Option<String>,
+    pub node_type: FlexStr,
+    pub result_count: usize,
+    // includes the time taken by the child nodes
+    pub exec_time_ms: f64,
+    #[serde(skip_serializing_if="Vec::is_empty", default)]
+    pub children: Vec<QueryNodeExplanation>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct AlleleResultRow {
+    pub allele_uniquename: AlleleUniquename,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub name: Option<FlexStr>,
+    pub allele_type: FlexStr,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub description: Option<FlexStr>,
+    pub gene_uniquename: GeneUniquename,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub gene_name: Option<FlexStr>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct GenotypeResultRow {
+    pub genotype_uniquename: GenotypeDisplayUniquename,
+    pub display_name: FlexStr,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub name: Option<FlexStr>,
+    pub ploidiness: Ploidiness,
+    pub allele_uniquenames: Vec<AlleleUniquename>,
+    pub gene_uniquenames: Vec<GeneUniquename>,
+}
+
+// one page of the results of a Query, with the number of genes before paging
+#[derive(Debug)]
+pub struct QueryRowsPage {
+    pub total_count: usize,
+    pub rows: Vec<ResultRow>,
+    pub allele_rows: Vec<AlleleResultRow>,
+    pub genotype_rows: Vec<GenotypeResultRow>,
+    pub explanation: Option<QueryNodeExplanation>,
+    pub enrichment: Option<EnrichmentResult>,
+    pub reference_rows: Vec<ReferenceShort>,
+    pub term_rows: Vec<TermShort>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct QueryAPIResult {
     pub query: Query,
     pub id: FlexStr,
     pub status: FlexStr,
+    // the number of matching genes, which can be more than the number
+    // of rows if the output options include an offset or limit
+    #[serde(default)]
+    pub total_count: usize,
     pub rows: Vec<ResultRow>,
+    // the results of allele and genotype queries, see QueryResultType
+    #[serde(skip_serializing_if="Vec::is_empty", default)]
+    pub allele_rows: Vec<AlleleResultRow>,
+    #[serde(skip_serializing_if="Vec::is_empty", default)]
+    pub genotype_rows: Vec<GenotypeResultRow>,
+    #[serde(skip_serializing_if="Option::is_none", default)]
+    pub explanation: Option<QueryNodeExplanation>,
+    #[serde(skip_serializing_if="Option::is_none", default)]
+    pub enrichment: Option<EnrichmentResult>,
+    // the results of reference and term queries, see QueryResultType
+    #[serde(skip_serializing_if="Vec::is_empty", default)]
+    pub reference_rows: Vec<ReferenceShort>,
+    #[serde(skip_serializing_if="Vec::is_empty", default)]
+    pub term_rows: Vec<TermShort>,
+    #[serde(skip_serializing_if="Vec::is_empty", default)]
+    pub validation_errors: Vec<QueryValidationError>,
 }
 
 impl QueryAPIResult {
@@ -77,7 +150,25 @@ impl QueryAPIResult {
             query: query.clone(),
             id: flex_str!("error"),
             status: error_message,
+            total_count: 0,
             rows: vec![],
+            allele_rows: vec![],
+            genotype_rows: vec![],
+            explanation: None,
+            enrichment: None,
+            reference_rows: vec![],
+            term_rows: vec![],
+            validation_errors: vec![],
         }
     }
+
+    pub fn new_validation_error(query: &Query,
+                                validation_errors: Vec<QueryValidationError>)
+        -> QueryAPIResult
+    {
+        let mut result =
+            QueryAPIResult::new_error(query, flex_str!("query is invalid"));
+        result.validation_errors = validation_errors;
+        result
+    }
 }
@@ -0,0 +1,302 @@
+// Reverse queries: find the references or terms that are used in the
+// annotations of the genes returned by a query, eg. "which publications
+// annotate any of these genes".  These are the "reference" and "term"
+// QueryResultTypes.
+//
+// If there are no annotation filters, the references of a gene come from
+// GeneQueryData.reference_uniquenames and the terms come from
+// APIMaps.termid_genes, so a term is returned if one of the genes is
+// annotated with the term or with one of its descendants.  Otherwise the
+// annotations in the GeneDetails are checked and only the terms used in
+// the matching annotations are returned.  Genes that can't be found are
+// skipped.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::api::query::serialize_sorted_set;
+use crate::api_data::APIData;
+use crate::data_types::{DataLookup, GeneDetails, OntAnnotationDetail,
+                        ReferenceShort, TermShort};
+use crate::types::{CvName, GeneUniquename, ReferenceUniquename, TermId};
+
+use flexstr::{SharedStr as FlexStr, shared_fmt as flex_fmt};
+
+// Filters for the annotations used by reference and term queries
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct ReverseQueryOptions {
+    // only use annotations from these CVs, eg. "biological_process"
+    #[serde(skip_serializing_if="HashSet::is_empty", default,
+            serialize_with="serialize_sorted_set")]
+    pub cv_names: HashSet<CvName>,
+    #[serde(skip_serializing_if="HashSet::is_empty", default,
+            serialize_with="serialize_sorted_set")]
+    pub evidence_codes: HashSet<FlexStr>,
+    // the publication year of the reference of the annotation
+    #[serde(skip_serializing_if="Option::is_none", default)]
+    pub min_year: Option<u32>,
+    #[serde(skip_serializing_if="Option::is_none", default)]
+    pub max_year: Option<u32>,
+}
+
+impl ReverseQueryOptions {
+    fn has_annotation_filters(&self) -> bool {
+        !self.cv_names.is_empty() || !self.evidence_codes.is_empty()
+    }
+
+    fn has_year_filters(&self) -> bool {
+        self.min_year.is_some() || self.max_year.is_some()
+    }
+}
+
+struct ReverseQuery<'a> {
+    api_data: &'a APIData,
+    options: &'a ReverseQueryOptions,
+    // None if the reference can't be found
+    references: HashMap<ReferenceUniquename, Option<ReferenceShort>>,
+}
+
+impl<'a> ReverseQuery<'a> {
+    fn reference_short(&mut self, gene_details: Option<&GeneDetails>,
+                       reference_uniquename: &ReferenceUniquename)
+                       -> Option<ReferenceShort>
+    {
+        if let Some(reference_short) = self.references.get(reference_uniquename) {
+            return reference_short.clone();
+        }
+
+        let reference_short =
+            match self.api_data.get_reference(reference_uniquename) {
+                Some(reference_details) =>
+                    Some(ReferenceShort::from_reference_details(&reference_details)),
+                None => {
+                    gene_details
+                        .and_then(|gene_details| {
+                            gene_details.references_by_uniquename.get(reference_uniquename)
+                        })
+                        .and_then(|maybe_reference_short| maybe_reference_short.clone())
+                },
+            };
+
+        self.references.insert(reference_uniquename.clone(), reference_short.clone());
+
+        reference_short
+    }
+
+    fn year_matches(&self, reference_short: &ReferenceShort) -> bool {
+        if !self.options.has_year_filters() {
+            return true;
+        }
+
+        let year: u32 =
+            match reference_short.publication_year.as_ref().and_then(|year| year.parse().ok()) {
+                Some(year) => year,
+                None => return false,
+            };
+
+        self.options.min_year.map(|min_year| year >= min_year).unwrap_or(true) &&
+            self.options.max_year.map(|max_year| year <= max_year).unwrap_or(true)
+    }
+
+    fn annotation_matches(&mut self, gene_details: &GeneDetails,
+                          annotation_detail: &OntAnnotationDetail) -> bool {
+        if !self.options.evidence_codes.is_empty() {
+            match annotation_detail.evidence {
+                Some(ref evidence) =>
+                    if !self.options.evidence_codes.contains(evidence) {
+                        return false;
+                    },
+                None => return false,
+            }
+        }
+
+        if self.options.has_year_filters() {
+            let reference_short =
+                annotation_detail.reference.as_ref()
+                .and_then(|reference_uniquename| {
+                    self.reference_short(Some(gene_details), reference_uniquename)
+                });
+
+            match reference_short {
+                Some(ref reference_short) =>
+                    if !self.year_matches(reference_short) {
+                        return false;
+                    },
+                None => return false,
+            }
+        }
+
+        true
+    }
+
+    // return the (termid, reference) pairs of the matching annotations of
+    // a gene, ignoring NOT annotations
+    fn annotation_term_refs(&mut self, gene_details: &GeneDetails)
+                            -> Result<Vec<(TermId, Option<ReferenceUniquename>)>, FlexStr>
+    {
+        let mut ret = vec![];
+
+        for (cv_name, term_annotations) in &gene_details.cv_annotations {
+            if !self.options.cv_names.is_empty() && !self.options.cv_names.contains(cv_name) {
+                continue;
+            }
+
+            for term_annotation in term_annotations {
+                if term_annotation.is_not {
+                    continue;
+                }
+
+                for annotation_id in &term_annotation.annotations {
+                    let annotation_detail =
+                        self.api_data.get_annotation_detail(*annotation_id)
+                        .ok_or_else(|| flex_fmt!("can't find annotation: {}", annotation_id))?;
+
+                    if self.annotation_matches(gene_details, &annotation_detail) {
+                        ret.push((term_annotation.term.clone(),
+                                  annotation_detail.reference.clone()));
+                    }
+                }
+            }
+        }
+
+        Ok(ret)
+    }
+
+    fn references_of_genes(&mut self, genes: &[GeneUniquename])
+                           -> Result<Vec<ReferenceShort>, FlexStr>
+    {
+        let mut seen_references = HashSet::new();
+        let mut rows = vec![];
+
+        for gene_uniquename in genes {
+            // only needed for checking annotations
+            let mut gene_details = None;
+
+            let reference_uniquenames: Vec<ReferenceUniquename> =
+                if self.options.has_annotation_filters() {
+                    let Some(details) = self.api_data.get_gene(gene_uniquename)
+                    else {
+                        continue;
+                    };
+                    let reference_uniquenames = self.annotation_term_refs(&details)?
+                        .into_iter()
+                        .filter_map(|(_, maybe_reference)| maybe_reference)
+                        .collect();
+                    gene_details = Some(details);
+                    reference_uniquenames
+                } else {
+                    match self.api_data.get_gene_query_data(gene_uniquename) {
+                        Some(gene_query_data) =>
+                            gene_query_data.reference_uniquenames.iter().cloned().collect(),
+                        None => continue,
+                    }
+                };
+
+            for reference_uniquename in reference_uniquenames {
+                if !seen_references.insert(reference_uniquename.clone()) {
+                    continue;
+                }
+
+                if let Some(reference_short) =
+                    self.reference_short(gene_details.as_deref(), &reference_uniquename)
+                {
+                    if self.year_matches(&reference_short) {
+                        rows.push(reference_short);
+                    }
+                }
+            }
+        }
+
+        rows.sort_by(|a, b| a.uniquename.cmp(&b.uniquename));
+
+        Ok(rows)
+    }
+
+    // the terms from APIMaps.termid_genes that have any of the genes
+    fn terms_of_genes_from_maps(&self, genes: &[GeneUniquename]) -> Vec<TermShort> {
+        let gene_set = genes.iter().collect::<HashSet<_>>();
+
+        self.api_data.get_maps().termid_genes.iter()
+            .filter(|(_, term_genes)| {
+                if term_genes.len() < gene_set.len() {
+                    term_genes.iter().any(|gene_uniquename| gene_set.contains(gene_uniquename))
+                } else {
+                    gene_set.iter().any(|gene_uniquename| term_genes.contains(*gene_uniquename))
+                }
+            })
+            .filter_map(|(termid, _)| self.api_data.get_term(termid))
+            .filter(|term_details| {
+                self.options.cv_names.is_empty() ||
+                    self.options.cv_names.contains(&term_details.cv_name)
+            })
+            .map(|term_details| TermShort::from_term_details(&term_details))
+            .collect()
+    }
+
+    fn terms_of_genes(&mut self, genes: &[GeneUniquename])
+                      -> Result<Vec<TermShort>, FlexStr>
+    {
+        if self.options.evidence_codes.is_empty() && !self.options.has_year_filters() {
+            let mut rows = self.terms_of_genes_from_maps(genes);
+            rows.sort_by(|a, b| a.termid.cmp(&b.termid));
+            return Ok(rows);
+        }
+
+        let mut seen_termids = HashSet::new();
+        let mut rows = vec![];
+
+        for gene_uniquename in genes {
+            let Some(gene_details) = self.api_data.get_gene(gene_uniquename)
+            else {
+                continue;
+            };
+
+            for (termid, _) in self.annotation_term_refs(&gene_details)? {
+                if !seen_termids.insert(termid.clone()) {
+                    continue;
+                }
+
+                let term_short =
+                    match gene_details.terms_by_termid.get(&termid) {
+                        Some(Some(term_short)) => term_short.clone(),
+                        _ => {
+                            let term_details = self.api_data.get_term(&termid)
+                                .ok_or_else(|| flex_fmt!("can't find term: {}", termid))?;
+                            TermShort::from_term_details(&term_details)
+                        },
+                    };
+
+                rows.push(term_short);
+            }
+        }
+
+        rows.sort_by(|a, b| a.termid.cmp(&b.termid));
+
+        Ok(rows)
+    }
+}
+
+fn new_reverse_query<'a>(api_data: &'a APIData, options: &'a ReverseQueryOptions)
+                         -> ReverseQuery<'a>
+{
+    ReverseQuery {
+        api_data,
+        options,
+        references: HashMap::new(),
+    }
+}
+
+// Find the references of the annotations of the genes
+pub fn references_of_genes(api_data: &APIData, genes: &[GeneUniquename],
+                           options: &ReverseQueryOptions)
+                           -> Result<Vec<ReferenceShort>, FlexStr>
+{
+    new_reverse_query(api_data, options).references_of_genes(genes)
+}
+
+// Find the terms of the annotations of the genes
+pub fn terms_of_genes(api_data: &APIData, genes: &[GeneUniquename],
+                      options: &ReverseQueryOptions)
+                      -> Result<Vec<TermShort>, FlexStr>
+{
+    new_reverse_query(api_data, options).terms_of_genes(genes)
+}
@@ -2,9 +2,9 @@ use bytes::Bytes;
 use reqwest::Client;
 use std::error::Error;
 
-use anyhow::Result;
+use anyhow::{Result, Context};
 
-use crate::web::config::{Config, ServerConfig};
+use crate::web::config::{Config, ServerConfig, SearchBackend};
 
 use crate::data_types::{SolrTermSummary, SolrReferenceSummary, SolrAlleleSummary};
 
@@ -17,9 +17,13 @@ use crate::api::allele_search::search_alleles;
 use crate::api::doc_search::search_docs;
 pub use crate::api::doc_search::DocSearchMatch;
 
+use crate::api::embedded_search::EmbeddedSearch;
+
 pub struct Search {
     config: ServerConfig,
     reqwest_client: Client,
+    // if set, searches use this instead of Solr
+    embedded_search: Option<EmbeddedSearch>,
 }
 
 
@@ -70,43 +74,73 @@ pub struct SolrSearchResult {
 }
 
 impl Search {
-    pub fn new(config: &Config) -> Search {
+    pub fn new(config: &Config) -> Result<Search> {
+        let embedded_search =
+            if config.server.search_backend == SearchBackend::Embedded {
+                let Some(ref data_dir) = config.server.embedded_search_data_dir
+                else {
+                    anyhow::bail!("embedded_search_data_dir must be set in the server \
+                                   configuration when search_backend is \"embedded\"");
+                };
+
+                let embedded_search = EmbeddedSearch::read(&config.server, data_dir)
+                    .context("failed to create search index")?;
+
+                Some(embedded_search)
+            } else {
+                None
+            };
+
+        Ok(Search::new_with_embedded_search(config, embedded_search))
+    }
+
+    pub fn new_with_embedded_search(config: &Config, embedded_search: Option<EmbeddedSearch>)
+                                    -> Search
+    {
         Search {
             config: config.server.clone(),
             reqwest_client: Client::new(),
+            embedded_search,
         }
     }
 
-    pub async fn motif_search(&self, scope: &str, pattern: &str, max_gene_details: &str) -> Result<String> {
-        let search_url = self.config.django_url.to_owned() + "/motifsearch/query/";
-        let params = [("scope", scope), ("pattern", pattern),
-                      ("max_gene_details", max_gene_details)];
-        let client = reqwest::Client::new();
-        let text = client.get(search_url).query(&params).send().await?.text().await?;
-        Ok(text)
-    }
-
     pub async fn term_complete(&self, cv_name: &str, q: &str)
                          -> Result<Vec<SolrTermSummary>>
     {
+        if let Some(ref embedded_search) = self.embedded_search {
+            return Ok(embedded_search.term_complete(cv_name, q));
+        }
+
         Ok(term_complete(&self.config, cv_name, q).await?)
     }
 
     pub async fn term_summary_by_id(&self, termid: &str)
                              -> Result<Option<SolrTermSummary>>
     {
+        if let Some(ref embedded_search) = self.embedded_search {
+            return Ok(embedded_search.term_summary_by_id(termid));
+        }
+
         Ok(term_summary_by_id(&self.config, termid).await?)
     }
 
     pub async fn ref_complete(&self, q: &str)
                         -> Result<Vec<SolrReferenceSummary>>
     {
+        if let Some(ref embedded_search) = self.embedded_search {
+            return Ok(embedded_search.search_refs(q));
+        }
+
         Ok(search_refs(&self.config, q).await?)
     }
 
     pub async fn allele_complete(&self, q: &str)
                            -> Result<Vec<SolrAlleleSummary>, Box<dyn Error + Send + Sync>>
     {
+        if let Some(ref embedded_search) = self.embedded_search {
+            return Ok(embedded_search.search_alleles(q));
+        }
+
         search_alleles(&self.config, &self.reqwest_client, q).await
     }
 
@@ -115,6 +149,17 @@ impl Search {
     {
         let trimmed_query = q.trim();
 
+        if let Some(ref embedded_search) = self.embedded_search {
+            return Ok(SolrSearchResult {
+                term_matches:
+                    if scope.is_term() { embedded_search.search_terms(trimmed_query) } else { vec![] },
+                ref_matches:
+                    if scope.is_reference() { embedded_search.search_refs(trimmed_query) } else { vec![] },
+                doc_matches:
+                    if scope.is_documentation() { embedded_search.search_docs(trimmed_query) } else { vec![] },
+            });
+        }
+
         let term_matches =
             if scope.is_term() {
                 search_terms(&self.config, trimmed_query).await?
@@ -0,0 +1,291 @@
+// A small in-memory inverted index used by the embedded search backend.
+// Documents have a fixed number of text fields.  Searches are scored with
+// BM25 using a weight for each field, the way the Solr queries boost
+// fields.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Bound;
+
+const BM25_K1: f32 = 1.2;
+const BM25_B: f32 = 0.75;
+
+// matches of a word that is only a prefix of an indexed word count for
+// less than complete matches
+const PREFIX_MATCH_FACTOR: f32 = 0.5;
+
+// the maximum length of a highlighted fragment of a long field
+const HIGHLIGHT_FRAGMENT_LENGTH: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrefixMatch {
+    None,
+    // the last word of the query may be incomplete, for autocompletion
+    LastWord,
+    AllWords,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MatchOptions {
+    // if true, only documents that match every word are returned
+    pub require_all: bool,
+    pub prefix: PrefixMatch,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Posting {
+    doc_idx: u32,
+    field_idx: u16,
+    term_freq: u16,
+}
+
+// Split text into lower case words, on any character that isn't
+// alphanumeric or "_"
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TextIndex {
+    field_count: usize,
+    doc_count: usize,
+    postings: BTreeMap<String, Vec<Posting>>,
+    // the number of words in each field of each document, indexed by
+    // doc_idx * field_count + field_idx
+    field_lengths: Vec<u16>,
+    total_field_lengths: Vec<usize>,
+}
+
+impl TextIndex {
+    pub fn new(field_count: usize) -> TextIndex {
+        TextIndex {
+            field_count,
+            doc_count: 0,
+            postings: BTreeMap::new(),
+            field_lengths: vec![],
+            total_field_lengths: vec![0; field_count],
+        }
+    }
+
+    pub fn doc_count(&self) -> usize {
+        self.doc_count
+    }
+
+    // Add a document and return its index.  fields must have field_count
+    // elements.
+    pub fn add_document(&mut self, fields: &[&str]) -> usize {
+        assert_eq!(fields.len(), self.field_count);
+
+        let doc_idx = self.doc_count;
+        self.doc_count += 1;
+
+        for (field_idx, text) in fields.iter().enumerate() {
+            let words = tokenize(text);
+            let field_length = words.len().min(u16::MAX as usize) as u16;
+
+            self.field_lengths.push(field_length);
+            self.total_field_lengths[field_idx] += field_length as usize;
+
+            let mut term_freqs: HashMap<String, u16> = HashMap::new();
+            for word in words {
+                let count = term_freqs.entry(word).or_insert(0);
+                *count = count.saturating_add(1);
+            }
+
+            for (word, term_freq) in term_freqs {
+                self.postings.entry(word).or_default().push(Posting {
+                    doc_idx: doc_idx as u32,
+                    field_idx: field_idx as u16,
+                    term_freq,
+                });
+            }
+        }
+
+        doc_idx
+    }
+
+    // postings are added in document order so documents with the word in
+    // more than one field have adjacent postings
+    fn idf(&self, postings: &[Posting]) -> f32 {
+        let doc_freq = postings.iter()
+            .enumerate()
+            .filter(|(idx, posting)| *idx == 0 || postings[idx - 1].doc_idx != posting.doc_idx)
+            .count() as f32;
+        let doc_count = self.doc_count as f32;
+        (1.0 + (doc_count - doc_freq + 0.5) / (doc_freq + 0.5)).ln()
+    }
+
+    fn average_field_length(&self, field_idx: usize) -> f32 {
+        if self.doc_count == 0 {
+            1.0
+        } else {
+            (self.total_field_lengths[field_idx] as f32 / self.doc_count as f32).max(1.0)
+        }
+    }
+
+    fn score_posting(&self, posting: &Posting, idf: f32, field_weights: &[f32]) -> f32 {
+        let field_idx = posting.field_idx as usize;
+        let field_length =
+            self.field_lengths[posting.doc_idx as usize * self.field_count + field_idx] as f32;
+        let term_freq = posting.term_freq as f32;
+        let length_norm =
+            1.0 - BM25_B + BM25_B * field_length / self.average_field_length(field_idx);
+
+        field_weights[field_idx] * idf * term_freq * (BM25_K1 + 1.0) /
+            (term_freq + BM25_K1 * length_norm)
+    }
+
+    // the indexed words that match a query word, with a score factor
+    fn matching_words<'a>(&'a self, word: &'a str, allow_prefix: bool)
+                          -> Box<dyn Iterator<Item = (&'a Vec<Posting>, f32)> + 'a>
+    {
+        if allow_prefix {
+            Box::new(self.postings.range::<str, _>((Bound::Included(word), Bound::Unbounded))
+                .take_while(move |(indexed_word, _)| indexed_word.starts_with(word))
+                .map(move |(indexed_word, postings)| {
+                    if indexed_word == word {
+                        (postings, 1.0)
+                    } else {
+                        (postings, PREFIX_MATCH_FACTOR)
+                    }
+                }))
+        } else {
+            Box::new(self.postings.get(word).into_iter().map(|postings| (postings, 1.0)))
+        }
+    }
+
+    // Return the indexes and scores of the documents matching the words,
+    // best match first.  field_weights has a weight for each field; fields
+    // with weight 0.0 are ignored.
+    pub fn search(&self, words: &[String], field_weights: &[f32], options: MatchOptions)
+                  -> Vec<(usize, f32)>
+    {
+        assert_eq!(field_weights.len(), self.field_count);
+
+        let mut scores: HashMap<u32, f32> = HashMap::new();
+        let mut matched_word_counts: HashMap<u32, usize> = HashMap::new();
+
+        for (word_idx, word) in words.iter().enumerate() {
+            let allow_prefix = match options.prefix {
+                PrefixMatch::None => false,
+                PrefixMatch::LastWord => word_idx == words.len() - 1,
+                PrefixMatch::AllWords => true,
+            };
+
+            let mut word_scores: HashMap<u32, f32> = HashMap::new();
+
+            for (postings, factor) in self.matching_words(word, allow_prefix) {
+                let idf = self.idf(postings);
+
+                for posting in postings {
+                    if field_weights[posting.field_idx as usize] == 0.0 {
+                        continue;
+                    }
+                    let score = factor * self.score_posting(posting, idf, field_weights);
+                    *word_scores.entry(posting.doc_idx).or_insert(0.0) += score;
+                }
+            }
+
+            for (doc_idx, score) in word_scores {
+                *scores.entry(doc_idx).or_insert(0.0) += score;
+                *matched_word_counts.entry(doc_idx).or_insert(0) += 1;
+            }
+        }
+
+        let mut results = scores.into_iter()
+            .filter(|(doc_idx, _)| {
+                !options.require_all || matched_word_counts[doc_idx] == words.len()
+            })
+            .map(|(doc_idx, score)| (doc_idx as usize, score))
+            .collect::<Vec<_>>();
+
+        sort_results(&mut results);
+
+        results
+    }
+}
+
+// sort by score, highest first, then by document index so that results are
+// stable
+pub fn sort_results(results: &mut [(usize, f32)]) {
+    results.sort_by(|(a_idx, a_score), (b_idx, b_score)| {
+        b_score.total_cmp(a_score).then(a_idx.cmp(b_idx))
+    });
+}
+
+fn word_matches(word: &str, query_words: &[String], prefix: PrefixMatch) -> bool {
+    query_words.iter().enumerate().any(|(idx, query_word)| {
+        let allow_prefix = match prefix {
+            PrefixMatch::None => false,
+            PrefixMatch::LastWord => idx == query_words.len() - 1,
+            PrefixMatch::AllWords => true,
+        };
+
+        if allow_prefix {
+            word.starts_with(query_word.as_str())
+        } else {
+            word == query_word
+        }
+    })
+}
+
+// Return the text with the words that match the query surrounded by
+// <em></em>, the same as Solr highlighting.  Long text is trimmed to a
+// fragment around the first match.  Returns None if no word matches.
+pub fn highlight(text: &str, query_words: &[String], prefix: PrefixMatch) -> Option<String> {
+    let mut match_ranges = vec![];
+    let mut word_start = None;
+
+    for (idx, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+        let is_word_char = c.is_alphanumeric() || c == '_';
+
+        match (word_start, is_word_char && idx < text.len()) {
+            (None, true) => word_start = Some(idx),
+            (Some(start), false) => {
+                let word = text[start..idx].to_lowercase();
+                if word_matches(&word, query_words, prefix) {
+                    match_ranges.push((start, idx));
+                }
+                word_start = None;
+            },
+            _ => (),
+        }
+    }
+
+    let (first_start, _) = *match_ranges.first()?;
+
+    let (fragment_start, fragment_end) =
+        if text.len() <= HIGHLIGHT_FRAGMENT_LENGTH {
+            (0, text.len())
+        } else {
+            let mut start = first_start.saturating_sub(HIGHLIGHT_FRAGMENT_LENGTH / 4);
+            while !text.is_char_boundary(start) {
+                start -= 1;
+            }
+            let mut end = (start + HIGHLIGHT_FRAGMENT_LENGTH).min(text.len());
+            while !text.is_char_boundary(end) {
+                end += 1;
+            }
+            (start, end)
+        };
+
+    let mut highlighted = String::new();
+    let mut pos = fragment_start;
+
+    for (start, end) in match_ranges {
+        if start < fragment_start || end > fragment_end {
+            continue;
+        }
+        highlighted.push_str(&text[pos..start]);
+        highlighted.push_str("<em>");
+        highlighted.push_str(&text[start..end]);
+        highlighted.push_str("</em>");
+        pos = end;
+    }
+
+    highlighted.push_str(&text[pos..fragment_end]);
+
+    Some(highlighted)
+}
@@ -0,0 +1,97 @@
+// Logging setup for pombase-server.  Log messages and the access log are
+// written to stderr using tracing, either as text or as one JSON object per
+// line, depending on the "monitoring" section of the server config.
+
+use std::fmt;
+use std::io::IsTerminal;
+
+use chrono::{SecondsFormat, Utc};
+use serde_json::{Map, Value};
+use tracing::{Event, Level, Subscriber};
+use tracing::field::{Field, Visit};
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::web::config::{LogFormat, MonitoringConfig};
+
+struct JsonFieldVisitor<'a> {
+    fields: &'a mut Map<String, Value>,
+}
+
+impl<'a> Visit for JsonFieldVisitor<'a> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.fields.insert(field.name().to_owned(), Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.fields.insert(field.name().to_owned(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.fields.insert(field.name().to_owned(), Value::from(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.fields.insert(field.name().to_owned(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.fields.insert(field.name().to_owned(), Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.fields.insert(field.name().to_owned(), Value::from(format!("{:?}", value)));
+    }
+}
+
+// Write each event as a JSON object with "timestamp", "level", "target"
+// and the fields of the event, including "message"
+struct JsonEventFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonEventFormat
+    where S: Subscriber + for<'a> LookupSpan<'a>,
+          N: for<'a> FormatFields<'a> + 'static
+{
+    fn format_event(&self, _ctx: &FmtContext<'_, S, N>, mut writer: Writer<'_>,
+                    event: &Event<'_>)
+        -> fmt::Result
+    {
+        let metadata = event.metadata();
+
+        let mut fields = Map::new();
+        fields.insert("timestamp".into(),
+                      Value::from(Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)));
+        fields.insert("level".into(), Value::from(metadata.level().as_str()));
+        fields.insert("target".into(), Value::from(metadata.target()));
+
+        event.record(&mut JsonFieldVisitor { fields: &mut fields });
+
+        writeln!(writer, "{}", Value::Object(fields))
+    }
+}
+
+pub fn parse_log_level(log_level: &str) -> Option<Level> {
+    log_level.trim().parse().ok()
+}
+
+// Install the global tracing subscriber.  An unknown log_level falls back
+// to "info".  Does nothing if a subscriber is already installed.
+pub fn init_logging(monitoring_config: &MonitoringConfig) {
+    let level = parse_log_level(&monitoring_config.log_level);
+
+    let builder = tracing_subscriber::fmt()
+        .with_max_level(level.unwrap_or(Level::INFO))
+        .with_ansi(std::io::stderr().is_terminal())
+        .with_writer(std::io::stderr);
+
+    let result = match monitoring_config.log_format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder.event_format(JsonEventFormat).try_init(),
+    };
+
+    if result.is_ok() && level.is_none() {
+        tracing::warn!("unknown log_level \"{}\" in the server config, using \"info\"",
+                       monitoring_config.log_level);
+    }
+}
@@ -6,6 +6,7 @@ use crate::api::query::Query;
 
 use std::str::FromStr;
 
+#[derive(Clone)]
 pub struct SiteDB {
     pool: Pool,
 }
@@ -61,13 +62,8 @@ impl SiteDB {
     }
 
     pub async fn id_from_query(&self, query: &Query) -> Option<Uuid> {
-        let query_value: String = match serde_json::value::to_value(query) {
-            Ok(v) => v.to_string(),
-            Err(err) => {
-                eprintln!("error converting query to string: {:?}", err);
-                return None;
-            }
-        };
+        // the canonical form, so that equivalent queries get the same ID
+        let query_value = query.canonical_json();
 
         let client =
             match self.pool.get().await {
@@ -78,6 +74,30 @@ impl SiteDB {
                 }
             };
 
+        if let Some(id) = Self::id_from_query_json(&client, &query_value).await {
+            return Some(id);
+        }
+
+        // queries saved before the canonical form was used were stored as
+        // the JSON of the query as given
+        let raw_query_value: String = match serde_json::value::to_value(query) {
+            Ok(v) => v.to_string(),
+            Err(err) => {
+                eprintln!("error converting query to string: {:?}", err);
+                return None;
+            }
+        };
+
+        if raw_query_value == query_value {
+            return None;
+        }
+
+        Self::id_from_query_json(&client, &raw_query_value).await
+    }
+
+    async fn id_from_query_json(client: &tokio_postgres::Client, query_value: &str)
+        -> Option<Uuid>
+    {
         let rs =
             match client.query("SELECT id::uuid FROM query WHERE digest(query_json, 'sha256') = digest($1, 'sha256');",
                                &[&query_value]).await
@@ -111,10 +131,7 @@ impl SiteDB {
             Err(e) => return Err(format!("couldn't begin transaction: {}", e)),
         };
 
-        let serde_value: String = match serde_json::value::to_value(query) {
-            Ok(v) => v.to_string(),
-            Err(e) => return Err(format!("serde error: {}", e)),
-        };
+        let serde_value = query.canonical_json();
 
         match trans.execute("INSERT INTO query(id, query_json) values ($1, $2) ON CONFLICT DO NOTHING",
                             &[uuid, &serde_value]).await {
@@ -1,42 +1,210 @@
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 
-use crate::web::config::{ServerConfig, Config};
+use bytes::Bytes;
+
+use crate::api::svg_plot::{bar_chart, line_chart, violin_plot, PlotSeries, Violin, ViolinPoint};
+use crate::api_data::APIData;
+use crate::data_types::{StatsFloatTable, StatsIntegerTable};
+use crate::web::config::{Config, GeneExDatasetConfig};
 
 
 pub struct StatsPlots {
-    config: ServerConfig,
+    gene_ex_datasets: Vec<GeneExDatasetConfig>,
 }
 
 use crate::api::search::SVGPlot;
 
+const STATS_PLOT_WIDTH: f64 = 900.0;
+const STATS_PLOT_HEIGHT: f64 = 500.0;
+
+// return (width, height) for the plot_size argument of gene_ex_violin_plot()
+fn violin_plot_dimensions(plot_size: &str) -> Option<(f64, f64)> {
+    match plot_size {
+        "small" => Some((500.0, 300.0)),
+        "normal" => Some((900.0, 500.0)),
+        _ => None,
+    }
+}
+
+// The header of a table may or may not include a name for the first,
+// date column
+fn integer_table_series(table: &StatsIntegerTable, columns: Option<&[&str]>)
+                        -> (Vec<String>, Vec<PlotSeries>)
+{
+    let value_count = table.data.first().map(|(_, values)| values.len()).unwrap_or(0);
+    let names =
+        if table.header.len() > value_count {
+            &table.header[table.header.len() - value_count..]
+        } else {
+            &table.header[..]
+        };
+
+    let x_labels = table.data.iter().map(|(date, _)| date.clone()).collect();
+
+    let series = names.iter().enumerate()
+        .filter(|(_, name)| {
+            columns.map(|columns| columns.contains(&name.as_str())).unwrap_or(true)
+        })
+        .map(|(idx, name)| PlotSeries {
+            name: name.replace('_', " "),
+            values: table.data.iter()
+                .map(|(_, values)| values.get(idx).map(|v| *v as f64).unwrap_or(f64::NAN))
+                .collect(),
+        })
+        .collect();
+
+    (x_labels, series)
+}
+
+fn float_table_series(table: &StatsFloatTable) -> (Vec<String>, Vec<PlotSeries>) {
+    let name = table.header.last().cloned().unwrap_or_default().replace('_', " ");
+
+    let x_labels = table.data.iter().map(|(range, _)| range.clone()).collect();
+    let series = PlotSeries {
+        name,
+        values: table.data.iter().map(|(_, value)| *value as f64).collect(),
+    };
+
+    (x_labels, vec![series])
+}
+
 impl StatsPlots {
 
     pub fn new(config: &Config) -> Self {
         Self {
-            config: config.server.clone(),
+            gene_ex_datasets: config.gene_expression.datasets.clone(),
         }
     }
 
-    pub async fn gene_ex_violin_plot(&self, plot_size: &str, genes: &str)
+    // A violin plot of the average copies per cell of all genes in each
+    // gene expression dataset, with the genes (comma separated IDs or
+    // names) marked
+    pub fn gene_ex_violin_plot(&self, api_data: &APIData, plot_size: &str, genes: &str)
                                -> Result<SVGPlot>
     {
-        let plot_url = self.config.django_url.to_owned() + "/gene_ex/gene_ex_violin/";
-        let params = [("plot_size", plot_size), ("genes", genes)];
-        let client = reqwest::Client::new();
-        let bytes = client.get(plot_url).query(&params).send().await?.bytes().await?;
+        let (width, height) = violin_plot_dimensions(plot_size)
+            .ok_or_else(|| anyhow!("unknown plot size: {}", plot_size))?;
+
+        let maps = api_data.get_maps();
+
+        let mut plot_genes = vec![];
+
+        for gene_id in genes.split(',').map(str::trim).filter(|id| !id.is_empty()) {
+            let gene_uniquename =
+                if api_data.get_gene_summary(&gene_id.into()).is_some() {
+                    gene_id.into()
+                } else if let Some(gene_uniquename) = maps.gene_name_gene_map.get(gene_id) {
+                    gene_uniquename.clone()
+                } else {
+                    return Err(anyhow!("no such gene: {}", gene_id));
+                };
+
+            let label = api_data.get_gene_summary(&gene_uniquename)
+                .and_then(|gene_summary| gene_summary.name.clone())
+                .unwrap_or_else(|| gene_uniquename.clone());
+
+            plot_genes.push((gene_uniquename, label));
+        }
 
-        Ok(SVGPlot { bytes })
+        let violins = self.gene_ex_datasets.iter()
+            .map(|dataset| {
+                let values = api_data.gene_ex_dataset_values(&dataset.name).into_iter()
+                    .filter(|(_, value)| *value > 0.0)
+                    .map(|(_, value)| value.log10())
+                    .collect();
+
+                let points = plot_genes.iter()
+                    .filter_map(|(gene_uniquename, label)| {
+                        api_data.gene_ex_avg_copies_per_cell(gene_uniquename, &dataset.name)
+                            .filter(|value| *value > 0.0)
+                            .map(|value| ViolinPoint {
+                                label: label.to_string(),
+                                value: value.log10(),
+                            })
+                    })
+                    .collect();
+
+                Violin {
+                    name: dataset.name.to_string(),
+                    values,
+                    points,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let svg = violin_plot("Gene expression", "Average copies per cell", &violins,
+                              true, width, height);
+
+        Ok(SVGPlot { bytes: Bytes::from(svg) })
     }
 
 
-    pub async fn get_svg_graph(&self, graph_type: &str) -> Result<SVGPlot>
+    pub fn get_svg_graph(&self, api_data: &APIData, graph_type: &str) -> Result<SVGPlot>
     {
-        let plot_url = self.config.django_url.to_owned() + "/stats/" + graph_type;
-        let client = reqwest::Client::new();
-        let params: &[(String, String)] = &[];
-        let bytes = client.get(plot_url).query(params).send()
-            .await?.bytes().await?;
+        let stats = &api_data.get_maps().detailed_stats;
+
+        let (width, height) = (STATS_PLOT_WIDTH, STATS_PLOT_HEIGHT);
+
+        let svg = match graph_type {
+            "curated_by_year" => {
+                let (x_labels, series) =
+                    integer_table_series(&stats.curated_by_year,
+                                         Some(&["community_curated", "admin_curated"]));
+                bar_chart("Publications curated per year", "Publications",
+                          &x_labels, &series, width, height)
+            },
+            "curatable_by_year" => {
+                let (x_labels, series) =
+                    integer_table_series(&stats.curated_by_year, Some(&["curatable"]));
+                bar_chart("Curatable publications per year", "Publications",
+                          &x_labels, &series, width, height)
+            },
+            "cumulative_curated_by_year" => {
+                let (x_labels, series) =
+                    integer_table_series(&stats.cumulative_curated_by_year, None);
+                line_chart("Cumulative curated publications", "Publications",
+                           &x_labels, &series, width, height)
+            },
+            "ltp_genes_per_pub_per_year_range" => {
+                let (x_labels, series) =
+                    float_table_series(&stats.ltp_genes_per_pub_per_year_range);
+                bar_chart("Genes per low throughput publication", "Genes per publication",
+                          &x_labels, &series, width, height)
+            },
+            "ltp_annotations_per_pub_per_year_range" => {
+                let (x_labels, series) =
+                    float_table_series(&stats.ltp_annotations_per_pub_per_year_range);
+                bar_chart("Annotations per low throughput publication",
+                          "Annotations per publication",
+                          &x_labels, &series, width, height)
+            },
+            "htp_annotations_per_pub_per_year_range" => {
+                let (x_labels, series) =
+                    float_table_series(&stats.htp_annotations_per_pub_per_year_range);
+                bar_chart("Annotations per high throughput publication",
+                          "Annotations per publication",
+                          &x_labels, &series, width, height)
+            },
+            "community_response_rates" => {
+                let rates = &stats.community_response_rates;
+                let x_labels = rates.iter().map(|rate| rate.year.to_string()).collect::<Vec<_>>();
+                let series = PlotSeries {
+                    name: "response rate".to_owned(),
+                    values: rates.iter().map(|rate| rate.response_rate as f64).collect(),
+                };
+                bar_chart("Community curation response rate", "Response rate (%)",
+                          &x_labels, &[series], width, height)
+            },
+            "cumulative_annotation_type_counts_by_year" => {
+                let (x_labels, series) =
+                    integer_table_series(&stats.cumulative_annotation_type_counts_by_year,
+                                         None);
+                line_chart("Cumulative annotations by type", "Annotations",
+                           &x_labels, &series, width, height)
+            },
+            _ => return Err(anyhow!("unknown graph type: {}", graph_type)),
+        };
 
-        Ok(SVGPlot { bytes })
+        Ok(SVGPlot { bytes: Bytes::from(svg) })
     }
 }
@@ -0,0 +1,453 @@
+// Minimal SVG chart rendering for the stats graphs and the gene
+// expression violin plots.  The charts are written directly as SVG text
+// so the server doesn't need a plotting library.
+
+use std::fmt::Write;
+
+const COLOURS: [&str; 10] = [
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd",
+    "#8c564b", "#e377c2", "#7f7f7f", "#bcbd22", "#17becf",
+];
+
+const FONT_SIZE: f64 = 12.0;
+
+// space around the plot area for the title, axis labels and legend
+const MARGIN_TOP: f64 = 40.0;
+const MARGIN_RIGHT: f64 = 20.0;
+const MARGIN_BOTTOM: f64 = 70.0;
+const MARGIN_LEFT: f64 = 70.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlotSeries {
+    pub name: String,
+    pub values: Vec<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ViolinPoint {
+    pub label: String,
+    pub value: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violin {
+    pub name: String,
+    // the values of the whole distribution
+    pub values: Vec<f64>,
+    // highlighted values, eg. the genes the user asked about
+    pub points: Vec<ViolinPoint>,
+}
+
+pub fn colour(idx: usize) -> &'static str {
+    COLOURS[idx % COLOURS.len()]
+}
+
+pub fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+// Return "nice" tick values covering min..max, eg. 0, 250, 500, 750
+pub fn axis_ticks(min: f64, max: f64, max_ticks: usize) -> Vec<f64> {
+    if !min.is_finite() || !max.is_finite() || max_ticks < 2 {
+        return vec![];
+    }
+
+    let (min, max) =
+        if max > min {
+            (min, max)
+        } else {
+            (min - 1.0, min + 1.0)
+        };
+
+    let rough_step = (max - min) / (max_ticks - 1) as f64;
+    let magnitude = 10f64.powf(rough_step.log10().floor());
+
+    let step = [1.0, 2.0, 2.5, 5.0, 10.0].iter()
+        .map(|multiplier| multiplier * magnitude)
+        .find(|step| *step >= rough_step)
+        .unwrap_or(10.0 * magnitude);
+
+    let first = (min / step).floor() as i64;
+    let last = (max / step).ceil() as i64;
+
+    (first..=last).map(|idx| idx as f64 * step).collect()
+}
+
+fn format_tick(value: f64) -> String {
+    if value.fract() == 0.0 {
+        format!("{}", value as i64)
+    } else {
+        let formatted = format!("{:.2}", value);
+        formatted.trim_end_matches('0').trim_end_matches('.').to_owned()
+    }
+}
+
+// The plot area and scaling shared by all of the chart types
+struct Frame {
+    width: f64,
+    height: f64,
+    y_min: f64,
+    y_max: f64,
+    y_ticks: Vec<f64>,
+}
+
+impl Frame {
+    fn new(width: f64, height: f64, data_min: f64, data_max: f64) -> Frame {
+        let y_ticks = axis_ticks(data_min, data_max, 6);
+        let y_min = y_ticks.first().cloned().unwrap_or(0.0);
+        let y_max = y_ticks.last().cloned().unwrap_or(1.0);
+
+        Frame {
+            width,
+            height,
+            y_min,
+            y_max: if y_max > y_min { y_max } else { y_min + 1.0 },
+            y_ticks,
+        }
+    }
+
+    fn plot_left(&self) -> f64 {
+        MARGIN_LEFT
+    }
+
+    fn plot_right(&self) -> f64 {
+        self.width - MARGIN_RIGHT
+    }
+
+    fn plot_top(&self) -> f64 {
+        MARGIN_TOP
+    }
+
+    fn plot_bottom(&self) -> f64 {
+        self.height - MARGIN_BOTTOM
+    }
+
+    fn plot_width(&self) -> f64 {
+        self.plot_right() - self.plot_left()
+    }
+
+    fn y_pos(&self, value: f64) -> f64 {
+        let fraction = (value - self.y_min) / (self.y_max - self.y_min);
+        self.plot_bottom() - fraction * (self.plot_bottom() - self.plot_top())
+    }
+
+    // the centre of the idx'th of count equal width slots on the x axis
+    fn x_slot_centre(&self, idx: usize, count: usize) -> f64 {
+        let slot_width = self.plot_width() / count.max(1) as f64;
+        self.plot_left() + slot_width * (idx as f64 + 0.5)
+    }
+
+    fn start(&self, svg: &mut String, title: &str) {
+        let _ = writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="sans-serif" font-size="{FONT_SIZE}">"#,
+                         w = self.width, h = self.height);
+        let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="white"/>"#);
+        let _ = writeln!(svg, r#"<text x="{:.1}" y="{:.1}" text-anchor="middle" font-size="{}" font-weight="bold">{}</text>"#,
+                         self.width / 2.0, MARGIN_TOP / 2.0 + 4.0, FONT_SIZE + 2.0,
+                         escape_xml(title));
+    }
+
+    fn finish(&self, svg: &mut String) {
+        svg.push_str("</svg>\n");
+    }
+
+    // Draw the axes, the y tick labels and a y axis label.  tick_label
+    // formats the y tick values.
+    fn draw_axes(&self, svg: &mut String, y_label: &str, tick_label: impl Fn(f64) -> String) {
+        for tick in &self.y_ticks {
+            let y = self.y_pos(*tick);
+            let _ = writeln!(svg, r##"<line x1="{:.1}" y1="{y:.1}" x2="{:.1}" y2="{y:.1}" stroke="#e0e0e0"/>"##,
+                             self.plot_left(), self.plot_right());
+            let _ = writeln!(svg, r#"<text x="{:.1}" y="{:.1}" text-anchor="end">{}</text>"#,
+                             self.plot_left() - 6.0, y + FONT_SIZE / 3.0,
+                             escape_xml(&tick_label(*tick)));
+        }
+
+        let _ = writeln!(svg, r#"<line x1="{l:.1}" y1="{t:.1}" x2="{l:.1}" y2="{b:.1}" stroke="black"/>"#,
+                         l = self.plot_left(), t = self.plot_top(), b = self.plot_bottom());
+        let _ = writeln!(svg, r#"<line x1="{l:.1}" y1="{b:.1}" x2="{r:.1}" y2="{b:.1}" stroke="black"/>"#,
+                         l = self.plot_left(), r = self.plot_right(), b = self.plot_bottom());
+
+        let label_x = FONT_SIZE + 2.0;
+        let label_y = (self.plot_top() + self.plot_bottom()) / 2.0;
+        let _ = writeln!(svg, r#"<text x="{label_x:.1}" y="{label_y:.1}" text-anchor="middle" transform="rotate(-90 {label_x:.1} {label_y:.1})">{}</text>"#,
+                         escape_xml(y_label));
+    }
+
+    // label the x axis slots, rotating the labels if there are too many
+    // to fit horizontally
+    fn draw_x_labels(&self, svg: &mut String, x_labels: &[String]) {
+        let slot_width = self.plot_width() / x_labels.len().max(1) as f64;
+        let max_label_len = x_labels.iter().map(|label| label.len()).max().unwrap_or(0);
+        let rotate = max_label_len as f64 * FONT_SIZE * 0.6 > slot_width;
+
+        // avoid overlapping labels when there are many slots
+        let label_every = (FONT_SIZE * 1.5 / slot_width).ceil().max(1.0) as usize;
+
+        for (idx, label) in x_labels.iter().enumerate() {
+            if idx % label_every != 0 {
+                continue;
+            }
+
+            let x = self.x_slot_centre(idx, x_labels.len());
+            let y = self.plot_bottom() + FONT_SIZE + 4.0;
+
+            if rotate {
+                let _ = writeln!(svg, r#"<text x="{x:.1}" y="{y:.1}" text-anchor="end" transform="rotate(-45 {x:.1} {y:.1})">{}</text>"#,
+                                 escape_xml(label));
+            } else {
+                let _ = writeln!(svg, r#"<text x="{x:.1}" y="{y:.1}" text-anchor="middle">{}</text>"#,
+                                 escape_xml(label));
+            }
+        }
+    }
+
+    fn draw_legend(&self, svg: &mut String, names: &[&str]) {
+        if names.len() < 2 {
+            return;
+        }
+
+        let mut x = self.plot_left() + 10.0;
+        let y = self.plot_top() + 10.0;
+
+        for (idx, name) in names.iter().enumerate() {
+            let _ = writeln!(svg, r#"<rect x="{x:.1}" y="{:.1}" width="10" height="10" fill="{}"/>"#,
+                             y - 9.0, colour(idx));
+            let _ = writeln!(svg, r#"<text x="{:.1}" y="{y:.1}">{}</text>"#,
+                             x + 14.0, escape_xml(name));
+            x += 24.0 + name.len() as f64 * FONT_SIZE * 0.6;
+        }
+    }
+}
+
+fn series_range(series: &[PlotSeries]) -> (f64, f64) {
+    let values = series.iter().flat_map(|series| series.values.iter())
+        .filter(|value| value.is_finite());
+
+    let max = values.clone().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let min = values.cloned().fold(f64::INFINITY, f64::min);
+
+    if min.is_finite() && max.is_finite() {
+        (min.min(0.0), max)
+    } else {
+        (0.0, 1.0)
+    }
+}
+
+// A line for each series with one point per x label
+pub fn line_chart(title: &str, y_label: &str, x_labels: &[String],
+                  series: &[PlotSeries], width: f64, height: f64) -> String {
+    let (data_min, data_max) = series_range(series);
+    let frame = Frame::new(width, height, data_min, data_max);
+
+    let mut svg = String::new();
+    frame.start(&mut svg, title);
+    frame.draw_axes(&mut svg, y_label, format_tick);
+    frame.draw_x_labels(&mut svg, x_labels);
+
+    for (series_idx, series) in series.iter().enumerate() {
+        let points = series.values.iter().enumerate()
+            .filter(|(_, value)| value.is_finite())
+            .map(|(idx, value)| {
+                format!("{:.1},{:.1}", frame.x_slot_centre(idx, x_labels.len()),
+                        frame.y_pos(*value))
+            })
+            .collect::<Vec<_>>();
+
+        let _ = writeln!(svg, r#"<polyline fill="none" stroke="{}" stroke-width="2" points="{}"><title>{}</title></polyline>"#,
+                         colour(series_idx), points.join(" "), escape_xml(&series.name));
+    }
+
+    let names = series.iter().map(|series| series.name.as_str()).collect::<Vec<_>>();
+    frame.draw_legend(&mut svg, &names);
+    frame.finish(&mut svg);
+
+    svg
+}
+
+// Bars grouped by x label, with one bar per series in each group
+pub fn bar_chart(title: &str, y_label: &str, x_labels: &[String],
+                 series: &[PlotSeries], width: f64, height: f64) -> String {
+    let (data_min, data_max) = series_range(series);
+    let frame = Frame::new(width, height, data_min, data_max);
+
+    let mut svg = String::new();
+    frame.start(&mut svg, title);
+    frame.draw_axes(&mut svg, y_label, format_tick);
+    frame.draw_x_labels(&mut svg, x_labels);
+
+    let slot_width = frame.plot_width() / x_labels.len().max(1) as f64;
+    let bar_width = slot_width * 0.8 / series.len().max(1) as f64;
+    let zero_y = frame.y_pos(0.0);
+
+    for (series_idx, series) in series.iter().enumerate() {
+        for (idx, value) in series.values.iter().enumerate() {
+            if !value.is_finite() || idx >= x_labels.len() {
+                continue;
+            }
+
+            let x = frame.x_slot_centre(idx, x_labels.len()) - slot_width * 0.4 +
+                bar_width * series_idx as f64;
+            let y = frame.y_pos(*value);
+
+            let _ = writeln!(svg, r#"<rect x="{x:.1}" y="{:.1}" width="{bar_width:.1}" height="{:.1}" fill="{}"><title>{}: {}</title></rect>"#,
+                             y.min(zero_y), (zero_y - y).abs(), colour(series_idx),
+                             escape_xml(&x_labels[idx]), format_tick(*value));
+        }
+    }
+
+    let names = series.iter().map(|series| series.name.as_str()).collect::<Vec<_>>();
+    frame.draw_legend(&mut svg, &names);
+    frame.finish(&mut svg);
+
+    svg
+}
+
+// Gaussian kernel density estimate at each of the points, using
+// Silverman's rule of thumb for the bandwidth
+pub fn kernel_density(values: &[f64], points: &[f64]) -> Vec<f64> {
+    if values.is_empty() {
+        return vec![0.0; points.len()];
+    }
+
+    let count = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / count;
+    let variance = values.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / count;
+    let std_dev = variance.sqrt();
+
+    let bandwidth =
+        if std_dev > 0.0 {
+            1.06 * std_dev * count.powf(-0.2)
+        } else {
+            1.0
+        };
+
+    let norm = 1.0 / (count * bandwidth * (2.0 * std::f64::consts::PI).sqrt());
+
+    points.iter()
+        .map(|point| {
+            values.iter()
+                .map(|value| {
+                    let u = (point - value) / bandwidth;
+                    (-0.5 * u * u).exp()
+                })
+                .sum::<f64>() * norm
+        })
+        .collect()
+}
+
+// One violin per distribution.  If log_scale is true the values are log10
+// values and the axis is labelled with the unlogged values.
+pub fn violin_plot(title: &str, y_label: &str, violins: &[Violin], log_scale: bool,
+                   width: f64, height: f64) -> String {
+    const DENSITY_STEPS: usize = 100;
+
+    let all_values = violins.iter()
+        .flat_map(|violin| {
+            violin.values.iter().cloned()
+                .chain(violin.points.iter().map(|point| point.value))
+        })
+        .filter(|value| value.is_finite())
+        .collect::<Vec<_>>();
+
+    let data_min = all_values.iter().cloned().fold(f64::INFINITY, f64::min);
+    let data_max = all_values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let (data_min, data_max) =
+        if data_min.is_finite() { (data_min, data_max) } else { (0.0, 1.0) };
+
+    let frame = Frame::new(width, height, data_min, data_max);
+
+    let mut svg = String::new();
+    frame.start(&mut svg, title);
+
+    let tick_label = |tick: f64| {
+        if log_scale {
+            format_tick(10f64.powf(tick))
+        } else {
+            format_tick(tick)
+        }
+    };
+    frame.draw_axes(&mut svg, y_label, tick_label);
+
+    let x_labels = violins.iter().map(|violin| violin.name.clone()).collect::<Vec<_>>();
+    frame.draw_x_labels(&mut svg, &x_labels);
+
+    let slot_width = frame.plot_width() / violins.len().max(1) as f64;
+    let max_half_width = slot_width * 0.4;
+
+    for (idx, violin) in violins.iter().enumerate() {
+        let centre_x = frame.x_slot_centre(idx, violins.len());
+
+        let values = violin.values.iter().cloned()
+            .filter(|value| value.is_finite())
+            .collect::<Vec<_>>();
+
+        if !values.is_empty() {
+            let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+            let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+
+            let steps = (0..=DENSITY_STEPS)
+                .map(|step| min + (max - min) * step as f64 / DENSITY_STEPS as f64)
+                .collect::<Vec<_>>();
+            let densities = kernel_density(&values, &steps);
+            let max_density = densities.iter().cloned().fold(0.0, f64::max);
+
+            let half_width = |density: f64| {
+                if max_density > 0.0 {
+                    density / max_density * max_half_width
+                } else {
+                    max_half_width
+                }
+            };
+
+            let right_side = steps.iter().zip(densities.iter())
+                .map(|(value, density)| {
+                    format!("{:.1},{:.1}", centre_x + half_width(*density), frame.y_pos(*value))
+                });
+            let left_side = steps.iter().zip(densities.iter()).rev()
+                .map(|(value, density)| {
+                    format!("{:.1},{:.1}", centre_x - half_width(*density), frame.y_pos(*value))
+                });
+
+            let path = right_side.chain(left_side).collect::<Vec<_>>().join(" L");
+
+            let _ = writeln!(svg, r#"<path d="M{path} Z" fill="{}" fill-opacity="0.5" stroke="{}"><title>{}</title></path>"#,
+                             colour(idx), colour(idx), escape_xml(&violin.name));
+
+            let mut sorted_values = values.clone();
+            sorted_values.sort_by(|a, b| a.total_cmp(b));
+            let median = sorted_values[sorted_values.len() / 2];
+            let median_y = frame.y_pos(median);
+            let _ = writeln!(svg, r#"<line x1="{:.1}" y1="{median_y:.1}" x2="{:.1}" y2="{median_y:.1}" stroke="black" stroke-width="2"/>"#,
+                             centre_x - max_half_width / 4.0, centre_x + max_half_width / 4.0);
+        }
+
+        for point in &violin.points {
+            if !point.value.is_finite() {
+                continue;
+            }
+
+            let y = frame.y_pos(point.value);
+            let _ = writeln!(svg, r#"<circle cx="{centre_x:.1}" cy="{y:.1}" r="3" fill="black"><title>{}</title></circle>"#,
+                             escape_xml(&point.label));
+            let _ = writeln!(svg, r#"<text x="{:.1}" y="{:.1}">{}</text>"#,
+                             centre_x + 6.0, y + FONT_SIZE / 3.0, escape_xml(&point.label));
+        }
+    }
+
+    frame.finish(&mut svg);
+
+    svg
+}
@@ -61,7 +61,7 @@ const TERMID_RE_STRING: &str =      r"^(?P<prefix>[\w_]+):(?P<accession>\d+)$";
 const PARENT_ID_RE_STRING: &str = r"^\[(?P<prefix>[\w_]+):(?P<accession>\d+)\]$";
 
 lazy_static!{
-    static ref TERMID_RE: Regex = Regex::new(TERMID_RE_STRING).unwrap();
+    pub static ref TERMID_RE: Regex = Regex::new(TERMID_RE_STRING).unwrap();
     static ref PARENT_RE: Regex = Regex::new(PARENT_ID_RE_STRING).unwrap();
 }
 
@@ -1,24 +1,31 @@
 
 use std::process;
 use std::fs::File;
-use std::io::{Read, BufReader};
+use std::io::{Read, BufReader, Cursor};
 
-use std::sync::{Mutex, Arc, RwLock};
+use std::hash::Hash;
+use std::sync::{Mutex, Arc};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use zstd::stream::Decoder;
-use rusqlite::Connection;
+use rusqlite::{Connection, OpenFlags, OptionalExtension, ToSql};
 
 use std::collections::{HashMap, HashSet};
 
 use crate::data_types::{APIAlleleDetails, APIGeneSummary, APIGenotypeAnnotation, APIMaps, AlleleDetails, AlleleShort, ChromosomeDetails, DataLookup, ExtPart, ExtRange, FeatureShort, GeneAndGeneProduct, GeneDetails, GeneQueryData, GeneShort, GeneShortOptionMap, GenotypeDetails, GoCamDetails, GoCamId, IdGeneSubsetMap, IdOntAnnotationDetailMap, InteractionType, OntAnnotationDetail, OntAnnotationId, OntAnnotationMap, Ploidiness, ProteinViewData, ProteinViewType, ReferenceDetails, ReferenceShort, ReferenceShortOptionMap, TermDetails, TermShort, TermShortOptionMap, TranscriptDetailsOptionMap, WithFromValue};
 
 use crate::sort_annotations::sort_cv_annotation_details;
-use crate::web::config::{Config, TermAndName};
+use crate::binary_format::{self, BINARY_FORMAT_MAGIC};
+use crate::web::config::{Config, ServerConfig, TermAndName};
 use crate::api::query::{QueryExpressionFilter, SingleOrMultiLocus, TargetOfType};
+use crate::api::gene_complete::{GeneCompletionIndex, GeneCompletionMatch};
+use crate::api::id_mapping::{IdMappingIndex, IdMappingResult};
+use crate::api::details_cache::{DetailsCache, DetailsCacheStats};
 use crate::web::cv_summary::make_cv_summaries;
 
 use crate::types::{TermId, GeneUniquename, AlleleUniquename,
-                   GenotypeDisplayUniquename, GenotypeUniquename, ReferenceUniquename};
+                   GenotypeDisplayUniquename, GenotypeUniquename, ReferenceUniquename,
+                   OrganismTaxonId};
 
 use flexstr::{SharedStr as FlexStr, shared_str as flex_str, shared_fmt as flex_fmt, ToSharedStr};
 
@@ -26,6 +33,8 @@ pub struct APIData {
     config: Config,
     maps: APIMaps,
     maps_database: APIMapsDatabase,
+    gene_completion_index: GeneCompletionIndex,
+    id_mapping_index: IdMappingIndex,
 }
 
 impl APIData {
@@ -67,291 +76,493 @@ impl DataLookup for APIData {
     }
 }
 
-pub struct APIMapsDatabase {
-    api_maps_database_conn: Arc<Mutex<Connection>>,
-    genotype_cache: RwLock<HashMap<GenotypeUniquename, Arc<GenotypeDetails>>>,
-    term_cache: RwLock<HashMap<TermId, Arc<TermDetails>>>,
-    reference_cache: RwLock<HashMap<ReferenceUniquename, Arc<ReferenceDetails>>>,
-    gene_cache: RwLock<HashMap<GeneUniquename, Arc<GeneDetails>>>,
-    allele_cache: RwLock<HashMap<AlleleUniquename, Arc<AlleleDetails>>>,
-    termid_genotype_annotation_cache: RwLock<HashMap<TermId, Arc<Vec<APIGenotypeAnnotation>>>>,
-    annotation_detail_cache: RwLock<HashMap<OntAnnotationId, Arc<OntAnnotationDetail>>>,
+pub const DEFAULT_MAPS_DATABASE_CONNECTIONS: usize = 4;
+
+// Connections for reading the maps database.  A request uses the first
+// connection that isn't busy, so lookups from concurrent requests don't
+// wait for each other.
+struct MapsDatabasePool {
+    connections: Vec<Mutex<Connection>>,
+    next_connection: AtomicUsize,
 }
 
-impl APIMapsDatabase {
-    pub fn new(api_maps_database_conn: Connection) -> APIMapsDatabase {
-        APIMapsDatabase {
-            api_maps_database_conn: Arc::new(Mutex::new(api_maps_database_conn)),
-            genotype_cache: RwLock::new(HashMap::new()),
-            term_cache: RwLock::new(HashMap::new()),
-            reference_cache: RwLock::new(HashMap::new()),
-            gene_cache: RwLock::new(HashMap::new()),
-            allele_cache: RwLock::new(HashMap::new()),
-            termid_genotype_annotation_cache: RwLock::new(HashMap::new()),
-            annotation_detail_cache: RwLock::new(HashMap::new()),
+impl MapsDatabasePool {
+    // The extra connections are opened read-only on the same file as conn.
+    // An in-memory database can't be shared so only conn is used.
+    fn new(conn: Connection, connection_count: usize) -> MapsDatabasePool {
+        let path = conn.path()
+            .filter(|path| !path.is_empty())
+            .map(|path| path.to_owned());
+
+        let mut connections = vec![Mutex::new(conn)];
+
+        if let Some(path) = path {
+            let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX |
+                OpenFlags::SQLITE_OPEN_URI;
+
+            while connections.len() < connection_count {
+                match Connection::open_with_flags(&path, flags) {
+                    Ok(conn) => connections.push(Mutex::new(conn)),
+                    Err(err) => {
+                        eprintln!("failed to open a read-only connection to {}: {}",
+                                  path, err);
+                        break;
+                    }
+                }
+            }
+        }
+
+        MapsDatabasePool {
+            connections,
+            next_connection: AtomicUsize::new(0),
         }
     }
 
-    pub fn get_genotype(&self, genotype_display_uniquename: &GenotypeDisplayUniquename)
-           -> Option<Arc<GenotypeDetails>>
-    {
-        let mut cache = self.genotype_cache.write().unwrap();
+    fn with_connection<R>(&self, f: impl FnOnce(&Connection) -> R) -> R {
+        let start = self.next_connection.fetch_add(1, Ordering::Relaxed);
+        let count = self.connections.len();
 
-        if !cache.contains_key(genotype_display_uniquename) {
-            let conn = self.api_maps_database_conn.lock().unwrap();
+        let free_conn = (0..count)
+            .find_map(|offset| self.connections[(start + offset) % count].try_lock().ok());
 
-            let mut stmt = conn.prepare("SELECT data FROM genotypes WHERE id = :id").unwrap();
+        let conn = free_conn
+            .unwrap_or_else(|| self.connections[start % count].lock().unwrap());
 
-            let mut genotypes =
-                stmt.query_map(&[(":id", genotype_display_uniquename.as_ref())],
-                               |row| {
-                                   let json: String = row.get(0)?;
-                                   let genotype_details: GenotypeDetails =
-                                       serde_json::from_str(&json).unwrap();
-                                   Ok(genotype_details)
-                               }).unwrap();
+        f(&conn)
+    }
+}
 
-            let result_genotype = genotypes.next();
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MapsDatabaseCacheStats {
+    pub connection_count: usize,
+    pub genes: DetailsCacheStats,
+    pub terms: DetailsCacheStats,
+    pub references: DetailsCacheStats,
+    pub alleles: DetailsCacheStats,
+    pub genotypes: DetailsCacheStats,
+    pub termid_genotype_annotations: DetailsCacheStats,
+    pub annotation_details: DetailsCacheStats,
+}
 
-            let maybe_genotype = result_genotype.map(|g| g.unwrap());
+// The keys of the maps database tables, which are stored as TEXT
+trait MapsDatabaseKey: Hash + Eq + Clone {
+    fn to_db_id(&self) -> String;
+    fn from_db_id(id: &str) -> Self;
+}
 
-            if let Some(genotype_details) = maybe_genotype {
-                cache.insert(genotype_display_uniquename.clone(), Arc::new(genotype_details));
-            }
-        }
+impl MapsDatabaseKey for FlexStr {
+    fn to_db_id(&self) -> String {
+        self.to_string()
+    }
 
-        let genotype_value = cache.get(genotype_display_uniquename);
+    fn from_db_id(id: &str) -> Self {
+        FlexStr::from(id)
+    }
+}
 
-        genotype_value.map(|g| g.to_owned())
+impl MapsDatabaseKey for OntAnnotationId {
+    fn to_db_id(&self) -> String {
+        self.to_string()
     }
 
-    pub fn get_term(&self, termid: &TermId)
-           -> Option<Arc<TermDetails>>
+    fn from_db_id(id: &str) -> Self {
+        id.parse().unwrap()
+    }
+}
+
+pub struct APIMapsDatabase {
+    pool: MapsDatabasePool,
+    genotype_cache: DetailsCache<GenotypeUniquename, GenotypeDetails>,
+    term_cache: DetailsCache<TermId, TermDetails>,
+    reference_cache: DetailsCache<ReferenceUniquename, ReferenceDetails>,
+    gene_cache: DetailsCache<GeneUniquename, GeneDetails>,
+    allele_cache: DetailsCache<AlleleUniquename, AlleleDetails>,
+    termid_genotype_annotation_cache: DetailsCache<TermId, Vec<APIGenotypeAnnotation>>,
+    annotation_detail_cache: DetailsCache<OntAnnotationId, OntAnnotationDetail>,
+}
+
+impl APIMapsDatabase {
+    pub fn new(api_maps_database_conn: Connection, server_config: &ServerConfig)
+               -> APIMapsDatabase
     {
-        let mut cache = self.term_cache.write().unwrap();
+        let cache_sizes = &server_config.maps_cache_sizes;
+
+        APIMapsDatabase {
+            pool: MapsDatabasePool::new(api_maps_database_conn,
+                                        server_config.maps_database_connections),
+            genotype_cache: DetailsCache::new(cache_sizes.genotypes),
+            term_cache: DetailsCache::new(cache_sizes.terms),
+            reference_cache: DetailsCache::new(cache_sizes.references),
+            gene_cache: DetailsCache::new(cache_sizes.genes),
+            allele_cache: DetailsCache::new(cache_sizes.alleles),
+            termid_genotype_annotation_cache:
+                DetailsCache::new(cache_sizes.termid_genotype_annotations),
+            annotation_detail_cache: DetailsCache::new(cache_sizes.annotation_details),
+        }
+    }
 
-        if !cache.contains_key(termid) {
-            let conn = self.api_maps_database_conn.lock().unwrap();
+    // Return the value from the cache, or read it from the table and cache
+    // it.  No cache lock is held while the database is read.
+    fn get_details<K, V>(&self, table_name: &str, cache: &DetailsCache<K, V>,
+                         key: &K, id: &dyn ToSql)
+           -> Option<Arc<V>>
+        where K: Hash + Eq + Clone,
+              V: serde::de::DeserializeOwned
+    {
+        if let Some(value) = cache.get(key) {
+            return Some(value);
+        }
 
-            let mut stmt = conn.prepare("SELECT data FROM terms WHERE id = :id").unwrap();
+        let json = self.pool.with_connection(|conn| {
+            let sql = format!("SELECT data FROM {} WHERE id = :id", table_name);
+            let mut stmt = conn.prepare_cached(&sql).unwrap();
 
-            let mut terms =
-                stmt.query_map(&[(":id", termid.as_ref())],
-                               |row| {
-                                   let json: String = row.get(0)?;
-                                   let term_details: TermDetails =
-                                       serde_json::from_str(&json).unwrap();
-                                   Ok(term_details)
-                               }).unwrap();
+            stmt.query_row(&[(":id", id)], |row| row.get::<_, String>(0))
+                .optional().unwrap()
+        })?;
 
-            let result_term = terms.next();
+        let value: Arc<V> = Arc::new(serde_json::from_str(&json).unwrap());
 
-            let maybe_term = result_term.map(|g| g.unwrap());
+        cache.insert(key.clone(), value.clone());
 
-            if let Some(term_details) = maybe_term {
-                cache.insert(termid.clone(), Arc::new(term_details));
-            }
-        }
+        Some(value)
+    }
 
-        let term_value = cache.get(termid);
+    pub fn get_genotype(&self, genotype_display_uniquename: &GenotypeDisplayUniquename)
+           -> Option<Arc<GenotypeDetails>>
+    {
+        self.get_details("genotypes", &self.genotype_cache, genotype_display_uniquename,
+                         &genotype_display_uniquename.as_ref())
+    }
 
-        term_value.map(|t| t.to_owned())
+    pub fn get_term(&self, termid: &TermId)
+           -> Option<Arc<TermDetails>>
+    {
+        self.get_details("terms", &self.term_cache, termid, &termid.as_ref())
     }
 
     pub fn get_reference(&self, reference_uniquename: &ReferenceUniquename)
            -> Option<Arc<ReferenceDetails>>
     {
-        let mut cache = self.reference_cache.write().unwrap();
+        self.get_details("refs", &self.reference_cache, reference_uniquename,
+                         &reference_uniquename.as_ref())
+    }
 
-        if !cache.contains_key(reference_uniquename) {
-            let conn = self.api_maps_database_conn.lock().unwrap();
+    pub fn get_gene(&self, gene_uniquename: &GeneUniquename)
+           -> Option<Arc<GeneDetails>>
+    {
+        self.get_details("genes", &self.gene_cache, gene_uniquename,
+                         &gene_uniquename.as_ref())
+    }
 
-            let mut stmt = conn.prepare("SELECT data FROM refs WHERE id = :id").unwrap();
+    pub fn get_allele(&self, allele_uniquename: &AlleleUniquename)
+           -> Option<Arc<AlleleDetails>>
+    {
+        self.get_details("alleles", &self.allele_cache, allele_uniquename,
+                         &allele_uniquename.as_ref())
+    }
 
-            let mut references =
-                stmt.query_map(&[(":id", reference_uniquename.as_ref())],
-                               |row| {
-                                   let json: String = row.get(0)?;
-                                   let reference_details: ReferenceDetails =
-                                       serde_json::from_str(&json).unwrap();
-                                   Ok(reference_details)
-                               }).unwrap();
+    pub fn get_termid_genotype_annotation(&self, termid: &TermId)
+           -> Option<Arc<Vec<APIGenotypeAnnotation>>>
+    {
+        self.get_details("termid_genotype_annotations", &self.termid_genotype_annotation_cache,
+                         termid, &termid.as_ref())
+    }
 
-            let result_reference = references.next();
+    pub fn get_annotation_detail(&self, annotation_id: OntAnnotationId)
+           -> Option<Arc<OntAnnotationDetail>>
+    {
+        self.get_details("annotation_detail", &self.annotation_detail_cache,
+                         &annotation_id, &annotation_id)
+    }
 
-            let maybe_reference = result_reference.map(|g| g.unwrap());
+    // Return the values of the keys that are in the table.  The values that
+    // aren't in the cache are read using one query and then cached.
+    fn get_details_batch<'a, K, V>(&self, table_name: &str, cache: &DetailsCache<K, V>,
+                                   keys: impl IntoIterator<Item = &'a K>)
+           -> HashMap<K, Arc<V>>
+        where K: MapsDatabaseKey + 'a,
+              V: serde::de::DeserializeOwned
+    {
+        let mut details = HashMap::new();
+        let mut uncached_ids = HashSet::new();
 
-            if let Some(reference_details) = maybe_reference {
-                cache.insert(reference_uniquename.clone(), Arc::new(reference_details));
+        for key in keys {
+            if details.contains_key(key) {
+                continue;
+            }
+            match cache.get(key) {
+                Some(value) => {
+                    details.insert(key.clone(), value);
+                },
+                None => {
+                    uncached_ids.insert(key.to_db_id());
+                },
             }
         }
 
-        let reference_value = cache.get(reference_uniquename);
-
-        reference_value.map(|t| t.to_owned())
-    }
+        if uncached_ids.is_empty() {
+            return details;
+        }
 
-    pub fn get_gene(&self, gene_uniquename: &GeneUniquename)
-           -> Option<Arc<GeneDetails>>
-    {
-        let mut cache = self.gene_cache.write().unwrap();
+        let ids_json = serde_json::to_string(&uncached_ids).unwrap();
 
-        if !cache.contains_key(gene_uniquename) {
-            let conn = self.api_maps_database_conn.lock().unwrap();
+        let rows = self.pool.with_connection(|conn| {
+            let sql = format!("SELECT id, data FROM {} WHERE id IN (SELECT value FROM json_each(:ids))",
+                              table_name);
+            let mut stmt = conn.prepare_cached(&sql).unwrap();
 
-            let mut stmt = conn.prepare("SELECT data FROM genes WHERE id = :id").unwrap();
+            stmt.query_map(&[(":ids", &ids_json)],
+                           |row| {
+                               let id: String = row.get(0)?;
+                               let json: String = row.get(1)?;
+                               Ok((id, json))
+                           }).unwrap()
+                .map(|row| row.unwrap())
+                .collect::<Vec<_>>()
+        });
 
-            let mut genes =
-                stmt.query_map(&[(":id", gene_uniquename.as_ref())],
-                               |row| {
-                                   let json: String = row.get(0)?;
-                                   let gene_details: GeneDetails =
-                                       serde_json::from_str(&json).unwrap();
-                                   Ok(gene_details)
-                               }).unwrap();
+        let new_entries = rows.into_iter()
+            .map(|(id, json)| {
+                let value: V = serde_json::from_str(&json).unwrap();
+                (K::from_db_id(&id), Arc::new(value))
+            })
+            .collect::<Vec<_>>();
 
-            let result_gene = genes.next();
+        details.extend(new_entries.iter().cloned());
 
-            let maybe_gene = result_gene.map(|g| g.unwrap());
+        cache.insert_all(new_entries);
 
-            if let Some(gene_details) = maybe_gene {
-                cache.insert(gene_uniquename.clone(), Arc::new(gene_details));
-            }
-        }
-
-        let gene_value = cache.get(gene_uniquename);
-
-        gene_value.map(|t| t.to_owned())
+        details
     }
 
-    pub fn get_allele(&self, allele_uniquename: &AlleleUniquename)
-           -> Option<Arc<AlleleDetails>>
+    pub fn get_genes<'a>(&self, gene_uniquenames: impl IntoIterator<Item = &'a GeneUniquename>)
+           -> HashMap<GeneUniquename, Arc<GeneDetails>>
     {
-        let mut cache = self.allele_cache.write().unwrap();
+        self.get_details_batch("genes", &self.gene_cache, gene_uniquenames)
+    }
 
-        if !cache.contains_key(allele_uniquename) {
-            let conn = self.api_maps_database_conn.lock().unwrap();
+    pub fn get_alleles<'a>(&self, allele_uniquenames: impl IntoIterator<Item = &'a AlleleUniquename>)
+           -> HashMap<AlleleUniquename, Arc<AlleleDetails>>
+    {
+        self.get_details_batch("alleles", &self.allele_cache, allele_uniquenames)
+    }
 
-            let mut stmt = conn.prepare("SELECT data FROM alleles WHERE id = :id").unwrap();
+    pub fn get_genotypes<'a>(&self,
+                             genotype_display_uniquenames: impl IntoIterator<Item = &'a GenotypeDisplayUniquename>)
+           -> HashMap<GenotypeDisplayUniquename, Arc<GenotypeDetails>>
+    {
+        self.get_details_batch("genotypes", &self.genotype_cache, genotype_display_uniquenames)
+    }
 
-            let mut alleles =
-                stmt.query_map(&[(":id", allele_uniquename.as_ref())],
-                               |row| {
-                                   let json: String = row.get(0)?;
-                                   let allele_details: AlleleDetails =
-                                       serde_json::from_str(&json).unwrap();
-                                   Ok(allele_details)
-                               }).unwrap();
+    pub fn get_references<'a>(&self,
+                              reference_uniquenames: impl IntoIterator<Item = &'a ReferenceUniquename>)
+           -> HashMap<ReferenceUniquename, Arc<ReferenceDetails>>
+    {
+        self.get_details_batch("refs", &self.reference_cache, reference_uniquenames)
+    }
 
-            let result_allele = alleles.next();
+    pub fn get_terms<'a>(&self, termids: impl IntoIterator<Item = &'a TermId>)
+           -> HashMap<TermId, Arc<TermDetails>>
+    {
+        self.get_details_batch("terms", &self.term_cache, termids)
+    }
 
-            let maybe_allele = result_allele.map(|g| g.unwrap());
+    pub fn get_annotation_details<'a>(&self,
+                                      annotation_ids: impl IntoIterator<Item = &'a OntAnnotationId>)
+           -> HashMap<OntAnnotationId, Arc<OntAnnotationDetail>>
+    {
+        self.get_details_batch("annotation_detail", &self.annotation_detail_cache,
+                               annotation_ids)
+    }
 
-            if let Some(allele_details) = maybe_allele {
-                cache.insert(allele_uniquename.clone(), Arc::new(allele_details));
-            }
+    pub fn cache_stats(&self) -> MapsDatabaseCacheStats {
+        MapsDatabaseCacheStats {
+            connection_count: self.pool.connections.len(),
+            genes: self.gene_cache.stats(),
+            terms: self.term_cache.stats(),
+            references: self.reference_cache.stats(),
+            alleles: self.allele_cache.stats(),
+            genotypes: self.genotype_cache.stats(),
+            termid_genotype_annotations: self.termid_genotype_annotation_cache.stats(),
+            annotation_details: self.annotation_detail_cache.stats(),
         }
+    }
+}
 
-        let allele_value = cache.get(allele_uniquename);
+// The genotype annotations of a term that match a query, referring to the
+// cached list of annotations rather than copying them
+pub struct MatchingGenotypeAnnotations {
+    annotations: Option<Arc<Vec<APIGenotypeAnnotation>>>,
+    matching_indices: Vec<usize>,
+}
 
-        allele_value.map(|t| t.to_owned())
+impl MatchingGenotypeAnnotations {
+    pub fn iter(&self) -> impl Iterator<Item = &APIGenotypeAnnotation> {
+        self.matching_indices.iter()
+            .filter_map(|index| self.annotations.as_ref().map(|annotations| &annotations[*index]))
     }
 
-    pub fn get_termid_genotype_annotation(&self, termid: &TermId)
-           -> Option<Arc<Vec<APIGenotypeAnnotation>>>
+    pub fn retain<F>(&mut self, mut f: F)
+        where F: FnMut(&APIGenotypeAnnotation) -> bool
     {
-        let mut cache = self.termid_genotype_annotation_cache.write().unwrap();
-
-        if !cache.contains_key(termid) {
-            let conn = self.api_maps_database_conn.lock().unwrap();
+        if let Some(ref annotations) = self.annotations {
+            self.matching_indices.retain(|index| f(&annotations[*index]));
+        }
+    }
+}
 
-            let mut stmt = conn.prepare("SELECT data FROM termid_genotype_annotations WHERE id = :id").unwrap();
+// The IDs of the annotation details, terms, genes etc. needed to build the
+// full details of a batch of genes, terms, alleles, genotypes or references
+#[derive(Default)]
+struct BatchIds {
+    annotation_ids: HashSet<OntAnnotationId>,
+    termids: HashSet<TermId>,
+    gene_uniquenames: HashSet<GeneUniquename>,
+    reference_uniquenames: HashSet<ReferenceUniquename>,
+    allele_uniquenames: HashSet<AlleleUniquename>,
+    genotype_uniquenames: HashSet<GenotypeDisplayUniquename>,
+}
 
-            let mut termid_genotype_annotations =
-                stmt.query_map(&[(":id", termid.as_ref())],
-                               |row| {
-                                   let json: String = row.get(0)?;
-                                   let termid_genotype_annotations: Vec<APIGenotypeAnnotation> =
-                                       serde_json::from_str(&json).unwrap();
-                                   Ok(termid_genotype_annotations)
-                               }).unwrap();
+impl BatchIds {
+    fn add_annotations(&mut self, cv_annotations: &OntAnnotationMap,
+                       terms_by_termid: &TermShortOptionMap,
+                       genes_by_uniquename: &GeneShortOptionMap)
+    {
+        for term_annotation in cv_annotations.values().flatten() {
+            self.termids.insert(term_annotation.term.clone());
+            self.annotation_ids.extend(term_annotation.annotations.iter().cloned());
+        }
 
-            let result_termid_genotype_annotations = termid_genotype_annotations.next();
+        self.termids.extend(terms_by_termid.keys().cloned());
+        self.gene_uniquenames.extend(genes_by_uniquename.keys().cloned());
+    }
 
-            let maybe_termid_genotype_annotations =
-                result_termid_genotype_annotations.map(|v| v.unwrap());
+    // the genes, terms and genotypes used to display and sort an annotation
+    fn add_annotation_detail(&mut self, annotation_detail: &OntAnnotationDetail) {
+        self.gene_uniquenames.extend(annotation_detail.genes.iter().cloned());
 
-            if let Some(termid_genotype_annotations) = maybe_termid_genotype_annotations {
-                cache.insert(termid.clone(), Arc::new(termid_genotype_annotations));
-            }
+        if let Some(ref genotype_uniquename) = annotation_detail.genotype {
+            self.genotype_uniquenames.insert(genotype_uniquename.clone());
         }
 
-        let term_value = cache.get(termid);
+        if let Some(ref gene_product_form_id) = annotation_detail.gene_product_form_id {
+            self.termids.insert(gene_product_form_id.clone());
+        }
 
-        term_value.map(|t| t.to_owned())
+        for ext_part in &annotation_detail.extension {
+            match ext_part.ext_range {
+                ExtRange::Gene(ref gene_uniquename) |
+                ExtRange::Promoter(ref gene_uniquename) => {
+                    self.gene_uniquenames.insert(gene_uniquename.clone());
+                },
+                ExtRange::Term(ref termid) => {
+                    self.termids.insert(termid.clone());
+                },
+                _ => (),
+            }
+        }
     }
+}
 
-    pub fn get_annotation_detail(&self, annotation_id: OntAnnotationId)
-           -> Option<Arc<OntAnnotationDetail>>
-    {
-        let mut cache = self.annotation_detail_cache.write().unwrap();
-
-        if !cache.contains_key(&annotation_id) {
-            let conn = self.api_maps_database_conn.lock().unwrap();
-
-            let mut stmt = conn.prepare("SELECT data FROM annotation_detail WHERE id = :id").unwrap();
+// The details read for a batch by APIData::read_batch().  Details that
+// weren't read for the batch are read from the maps database.
+struct BatchLookup<'a> {
+    api_data: &'a APIData,
+    terms: HashMap<TermId, Arc<TermDetails>>,
+    genes: HashMap<GeneUniquename, Arc<GeneDetails>>,
+    references: HashMap<ReferenceUniquename, Arc<ReferenceDetails>>,
+    alleles: HashMap<AlleleUniquename, Arc<AlleleDetails>>,
+    genotypes: HashMap<GenotypeDisplayUniquename, Arc<GenotypeDetails>>,
+    annotation_details: HashMap<OntAnnotationId, Arc<OntAnnotationDetail>>,
+}
 
-            let mut annotation_detail =
-                stmt.query_map(&[(":id", &annotation_id)],
-                               |row| {
-                                   let json: String = row.get(0)?;
-                                   let annotation_details: OntAnnotationDetail =
-                                       serde_json::from_str(&json).unwrap();
-                                   Ok(annotation_details)
-                               }).unwrap();
+impl<'a> DataLookup for BatchLookup<'a> {
+    fn get_term(&self, termid: &TermId) -> Option<Arc<TermDetails>> {
+        self.terms.get(termid).cloned()
+            .or_else(|| self.api_data.get_term(termid))
+    }
 
-            let result_annotation_detail = annotation_detail.next();
+    fn get_gene(&self, gene_uniquename: &GeneUniquename) -> Option<Arc<GeneDetails>> {
+        self.genes.get(gene_uniquename).cloned()
+            .or_else(|| self.api_data.get_gene(gene_uniquename))
+    }
 
-            let maybe_annotation_detail =
-                result_annotation_detail.map(|v| v.unwrap());
+    fn get_allele(&self, allele_uniquename: &AlleleUniquename) -> Option<Arc<AlleleDetails>> {
+        self.alleles.get(allele_uniquename).cloned()
+            .or_else(|| self.api_data.get_allele(allele_uniquename))
+    }
 
-            if let Some(annotation_detail) = maybe_annotation_detail {
-                cache.insert(annotation_id, Arc::new(annotation_detail));
-            }
-        }
+    fn get_reference(&self, reference_uniquename: &ReferenceUniquename)
+           -> Option<Arc<ReferenceDetails>>
+    {
+        self.references.get(reference_uniquename).cloned()
+            .or_else(|| self.api_data.get_reference(reference_uniquename))
+    }
 
-        let term_value = cache.get(&annotation_id);
+    fn get_genotype(&self, genotype_display_uniquename: &GenotypeDisplayUniquename)
+           -> Option<Arc<GenotypeDetails>>
+    {
+        self.genotypes.get(genotype_display_uniquename).cloned()
+            .or_else(|| self.api_data.get_genotype(genotype_display_uniquename))
+    }
 
-        term_value.map(|t| t.to_owned())
+    fn get_annotation_detail(&self, annotation_id: OntAnnotationId)
+           -> Option<Arc<OntAnnotationDetail>> {
+        self.annotation_details.get(&annotation_id).cloned()
+            .or_else(|| self.api_data.get_annotation_detail(annotation_id))
     }
+}
 
+fn expressed_allele_uniquenames(allele_details: &AlleleDetails)
+                                -> impl Iterator<Item = &AlleleUniquename>
+{
+    allele_details.genotypes.iter()
+        .flat_map(|genotype_short| &genotype_short.loci)
+        .flat_map(|locus| &locus.expressed_alleles)
+        .map(|expressed_allele| &expressed_allele.allele_uniquename)
 }
 
-pub fn api_maps_from_file(search_maps_file_name: &str) -> APIMaps
+// Read the search maps, returning an error rather than exiting so that the
+// server can keep running if reloading fails.  The file can be JSON or the
+// faster to load binary format, both compressed with zstd.
+pub fn read_api_maps(search_maps_file_name: &str) -> Result<APIMaps, anyhow::Error>
 {
-    let file = match File::open(search_maps_file_name) {
-        Ok(file) => file,
-        Err(err) => {
-            eprintln!("Failed to read {}: {}", search_maps_file_name, err);
-            process::exit(1);
-        }
-    };
+    let file = File::open(search_maps_file_name)
+        .map_err(|err| anyhow::anyhow!("Failed to read {}: {}", search_maps_file_name, err))?;
 
     let reader = BufReader::new(file);
-    let mut decoder = Decoder::new(reader).unwrap();
+    let mut decoder = Decoder::new(reader)?;
+
+    let mut magic = vec![];
+    (&mut decoder).take(BINARY_FORMAT_MAGIC.len() as u64).read_to_end(&mut magic)
+        .map_err(|err| anyhow::anyhow!("failed to decompress {}: {}", search_maps_file_name, err))?;
+
+    let mut reader = Cursor::new(magic).chain(decoder);
+
+    if reader.get_ref().0.get_ref() == BINARY_FORMAT_MAGIC {
+        return binary_format::from_reader(reader)
+            .map_err(|err| anyhow::anyhow!("failed to read {}: {}", search_maps_file_name, err));
+    }
 
     //  this uses less peak memory but is 4X slower
     //  See: https://github.com/serde-rs/json/issues/160
     //        let serde_result = serde_json::de::from_reader(&mut decoder);
 
     let mut decoded_json = String::new();
-    decoder.read_to_string(&mut decoded_json).unwrap();
-    let serde_result = serde_json::from_str(&decoded_json);
+    reader.read_to_string(&mut decoded_json)
+        .map_err(|err| anyhow::anyhow!("failed to decompress {}: {}", search_maps_file_name, err))?;
+
+    serde_json::from_str(&decoded_json)
+        .map_err(|err| anyhow::anyhow!("failed to parse {}: {}", search_maps_file_name, err))
+}
 
-    match serde_result {
+pub fn api_maps_from_file(search_maps_file_name: &str) -> APIMaps
+{
+    match read_api_maps(search_maps_file_name) {
         Ok(results) => results,
         Err(err) => {
-            eprint!("failed to parse {}: {}", search_maps_file_name, err);
+            eprintln!("{}", err);
             process::exit(1);
         },
     }
@@ -379,12 +590,17 @@ impl APIData {
 
         maps.gene_subsets.extend(new_entries);
 
-        let maps_database = APIMapsDatabase::new(maps_database_conn);
+        let maps_database = APIMapsDatabase::new(maps_database_conn, &config.server);
+
+        let gene_completion_index = GeneCompletionIndex::new(&maps);
+        let id_mapping_index = IdMappingIndex::new(&maps);
 
         APIData {
             config: config.clone(),
             maps,
             maps_database,
+            gene_completion_index,
+            id_mapping_index,
         }
     }
 
@@ -419,6 +635,23 @@ impl APIData {
         self.maps.gene_summaries.get(gene_uniquename)
     }
 
+    // the genes with an identifier, name or synonym starting with q
+    pub fn gene_complete(&self, q: &str, taxonid: Option<OrganismTaxonId>,
+                         max_results: usize)
+                         -> Vec<GeneCompletionMatch>
+    {
+        self.gene_completion_index.complete(&self.maps, q, taxonid, max_results)
+    }
+
+    // find the genes that each of the IDs, names or synonyms could refer to
+    pub fn map_gene_ids(&self, ids: &[impl AsRef<str>]) -> Vec<IdMappingResult> {
+        self.id_mapping_index.map_ids(&self.maps, ids)
+    }
+
+    pub fn maps_database_cache_stats(&self) -> MapsDatabaseCacheStats {
+        self.maps_database.cache_stats()
+    }
+
     pub fn get_gene_details(&self, gene_uniquename: &FlexStr) -> Option<Arc<GeneDetails>> {
         self.get_gene(gene_uniquename)
     }
@@ -436,6 +669,25 @@ impl APIData {
         }
     }
 
+    pub fn gene_ex_avg_copies_per_cell(&self, gene_uniquename: &GeneUniquename,
+                                       dataset_name: &str) -> Option<f64> {
+        self.maps.gene_expression_measurements.get(gene_uniquename)
+            .and_then(|datasets| datasets.get(dataset_name))
+            .and_then(|measurement| measurement.avg_copies_per_cell.as_ref())
+            .and_then(|avg_copies_per_cell| avg_copies_per_cell.parse::<f64>().ok())
+    }
+
+    // the genes with an average copies per cell value in the dataset, with
+    // their values
+    pub fn gene_ex_dataset_values(&self, dataset_name: &str) -> Vec<(GeneUniquename, f64)> {
+        self.maps.gene_expression_measurements.keys()
+            .filter_map(|gene_uniquename| {
+                self.gene_ex_avg_copies_per_cell(gene_uniquename, dataset_name)
+                    .map(|value| (gene_uniquename.clone(), value))
+            })
+            .collect()
+    }
+
     pub fn genes_of_subset(&self, search_name: &FlexStr) -> Vec<GeneUniquename> {
         if search_name.starts_with('!') || search_name.ends_with('*') {
             let mut trimmed_search_name = search_name.to_string();
@@ -469,17 +721,41 @@ impl APIData {
         }
     }
 
-    pub fn genes_of_genotypes(&self, term_id: &FlexStr,
-                              single_or_multi_locus: &SingleOrMultiLocus,
-                              query_ploidiness: &Ploidiness,
-                              expression_filter: &Option<QueryExpressionFilter>,
-                              conditions_filter: &HashSet<TermAndName>,
-                              excluded_conditions_filter: &HashSet<TermAndName>)
-                              -> Vec<GeneUniquename>
+    // the annotations of a term and its descendants, not including NOT
+    // annotations
+    pub fn termid_annotation_details(&self, term_id: &FlexStr) -> Vec<Arc<OntAnnotationDetail>> {
+        let term_details =
+            match self.get_term(term_id) {
+                Some(term_details) => term_details,
+                None => return vec![],
+            };
+
+        let annotation_ids =
+            term_details.cv_annotations.values()
+            .flat_map(|term_annotations| term_annotations.iter())
+            .filter(|term_annotation| !term_annotation.is_not)
+            .flat_map(|term_annotation| term_annotation.annotations.iter())
+            .cloned()
+            .collect::<HashSet<_>>();
+
+        self.maps_database.get_annotation_details(&annotation_ids)
+            .into_values()
+            .collect()
+    }
+
+    // the genotype annotations of a term that match the locus, ploidiness,
+    // expression and condition filters
+    pub fn matching_genotype_annotations(&self, term_id: &FlexStr,
+                                         single_or_multi_locus: &SingleOrMultiLocus,
+                                         query_ploidiness: &Ploidiness,
+                                         expression_filter: &Option<QueryExpressionFilter>,
+                                         conditions_filter: &HashSet<TermAndName>,
+                                         excluded_conditions_filter: &HashSet<TermAndName>)
+                                         -> MatchingGenotypeAnnotations
     {
         if let Some(annotations) = self.get_termid_genotype_annotation(term_id) {
-            let mut genes: HashSet<GeneUniquename> = HashSet::new();
-            for annotation in annotations.as_ref() {
+            let mut matching_indices = vec![];
+            for (index, annotation) in annotations.iter().enumerate() {
 
                 let mut add_single = false;
                 let mut add_multi = false;
@@ -566,16 +842,18 @@ impl APIData {
                 }
 
                 if add_genotype_genes {
-                    for allele_details in &annotation.alleles {
-                        genes.insert(allele_details.gene.clone());
-                    }
+                    matching_indices.push(index);
                 }
-
-
             }
-            genes.into_iter().collect::<Vec<_>>()
+            MatchingGenotypeAnnotations {
+                annotations: Some(annotations),
+                matching_indices,
+            }
         } else {
-            vec![]
+            MatchingGenotypeAnnotations {
+                annotations: None,
+                matching_indices: vec![],
+            }
         }
     }
 
@@ -687,9 +965,9 @@ impl APIData {
         }
     }
 
-    fn get_gene_prod_extension(&self, prod_value: &FlexStr) -> ExtPart {
+    fn get_gene_prod_extension(&self, lookup: &dyn DataLookup, prod_value: &FlexStr) -> ExtPart {
       let ext_range =
-        if let Some(term_details) = self.get_term(prod_value) {
+        if let Some(term_details) = lookup.get_term(prod_value) {
           if let Some(ref pombase_gene_id) = term_details.pombase_gene_id {
             let gene_and_product = GeneAndGeneProduct {
               gene_uniquename: pombase_gene_id.clone(),
@@ -710,7 +988,83 @@ impl APIData {
       }
     }
 
-    fn detail_map_of_cv_annotations(&self, ont_annotation_map: &OntAnnotationMap)
+    // return the annotation with the with value moved to the extension and
+    // with the gene product form added, if needed
+    fn annotation_detail_with_extension(&self, lookup: &dyn DataLookup,
+                                        term_details: &TermDetails,
+                                        annotation_detail: &OntAnnotationDetail)
+                                        -> OntAnnotationDetail
+    {
+        let mut annotation = annotation_detail.clone();
+
+        self.maybe_move_with(term_details, &mut annotation);
+
+        if let Some(ref gene_product_form_id) = annotation.gene_product_form_id {
+            let gene_prod_extension = self.get_gene_prod_extension(lookup, gene_product_form_id);
+            annotation.extension.insert(0, gene_prod_extension);
+        }
+
+        annotation
+    }
+
+    // the annotations of a term and its descendants, with the extensions
+    // as they are displayed, not including NOT annotations
+    pub fn termid_annotation_details_with_extension(&self, term_id: &FlexStr)
+                                                    -> Vec<OntAnnotationDetail>
+    {
+        let term_details =
+            match self.get_term(term_id) {
+                Some(term_details) => term_details,
+                None => return vec![],
+            };
+
+        let term_annotations = term_details.cv_annotations.values().flatten()
+            .filter(|term_annotation| !term_annotation.is_not)
+            .collect::<Vec<_>>();
+
+        let annotated_terms =
+            self.maps_database.get_terms(term_annotations.iter()
+                                         .map(|term_annotation| &term_annotation.term));
+        let annotation_details =
+            self.maps_database.get_annotation_details(term_annotations.iter()
+                                                      .flat_map(|term_annotation| &term_annotation.annotations));
+
+        let mut seen_ids = HashSet::new();
+        let mut annotations = vec![];
+
+        for term_annotation in term_annotations {
+            let annotated_term_details =
+                match annotated_terms.get(&term_annotation.term) {
+                    Some(annotated_term_details) => annotated_term_details,
+                    None => continue,
+                };
+
+            for annotation_detail_id in &term_annotation.annotations {
+                if !seen_ids.insert(*annotation_detail_id) {
+                    continue;
+                }
+                if let Some(annotation_detail) = annotation_details.get(annotation_detail_id) {
+                    annotations.push(self.annotation_detail_with_extension(self,
+                                                                           annotated_term_details,
+                                                                           annotation_detail));
+                }
+            }
+        }
+
+        annotations
+    }
+
+    // true if the term is the ancestor term or one of its is_a or part_of
+    // descendants
+    pub fn term_is_a_or_descendant(&self, termid: &FlexStr, ancestor_termid: &FlexStr) -> bool {
+        termid == ancestor_termid ||
+            self.maps.extension_range_ancestors.get(termid)
+            .map(|ancestor_termids| ancestor_termids.contains(ancestor_termid))
+            .unwrap_or(false)
+    }
+
+    fn detail_map_of_cv_annotations(&self, lookup: &BatchLookup,
+                                    ont_annotation_map: &OntAnnotationMap)
                                     -> IdOntAnnotationDetailMap
     {
         let mut details_map = HashMap::new();
@@ -718,23 +1072,15 @@ impl APIData {
         for term_annotations in ont_annotation_map.values() {
             for term_annotation in term_annotations {
                 let termid = &term_annotation.term;
-                if let Some(term_details) = self.get_term(termid) {
+                if let Some(term_details) = lookup.get_term(termid) {
                     let annotations: Vec<OntAnnotationDetail> =
                         term_annotation.annotations
                         .iter()
                         .map(|annotation_detail_id| {
-                            let mut annotation =
-                                self.get_annotation_detail(*annotation_detail_id)
-                                    .unwrap().as_ref().to_owned();
-
-                           self.maybe_move_with(&term_details, &mut annotation);
-
-                           if let Some(ref gene_product_form_id) = annotation.gene_product_form_id {
-                               let gene_prod_extension = self.get_gene_prod_extension(gene_product_form_id);
-                               annotation.extension.insert(0, gene_prod_extension);
-                           }
-
-                           annotation
+                            let annotation_detail =
+                                lookup.get_annotation_detail(*annotation_detail_id).unwrap();
+                            self.annotation_detail_with_extension(lookup, &term_details,
+                                                                  &annotation_detail)
                         })
                         .collect();
 
@@ -751,10 +1097,12 @@ impl APIData {
         details_map
     }
 
-    fn fill_term_map(&self, term_map: &TermShortOptionMap) -> TermShortOptionMap {
+    fn fill_term_map(&self, lookup: &BatchLookup, term_map: &TermShortOptionMap)
+                     -> TermShortOptionMap
+    {
         let mut ret = term_map.clone();
         for termid in term_map.keys() {
-            if let Some(term_details) = self.get_term(termid) {
+            if let Some(term_details) = lookup.get_term(termid) {
                 let term_short = TermShort::from_term_details(&term_details);
                 ret.insert(termid.clone(), Some(term_short));
             } else {
@@ -765,10 +1113,12 @@ impl APIData {
         ret
     }
 
-    fn fill_gene_map(&self, gene_map: &GeneShortOptionMap) -> GeneShortOptionMap {
+    fn fill_gene_map(&self, lookup: &BatchLookup, gene_map: &GeneShortOptionMap)
+                     -> GeneShortOptionMap
+    {
         let mut ret = gene_map.clone();
         for gene_uniquename in gene_map.keys() {
-            let gene_details_arc = self.get_gene(gene_uniquename).unwrap();
+            let gene_details_arc = lookup.get_gene(gene_uniquename).unwrap();
             let gene_details = gene_details_arc.as_ref();
             let gene_short = GeneShort::from_gene_details(gene_details);
             ret.insert(gene_uniquename.clone(), Some(gene_short));
@@ -786,10 +1136,12 @@ impl APIData {
         ret
     }
 
-    fn fill_reference_map(&self, reference_map: &ReferenceShortOptionMap) -> ReferenceShortOptionMap {
+    fn fill_reference_map(&self, lookup: &BatchLookup, reference_map: &ReferenceShortOptionMap)
+                          -> ReferenceShortOptionMap
+    {
         let mut ret = reference_map.clone();
         for reference_uniquename in reference_map.keys() {
-            let reference_arc = &self.get_reference(reference_uniquename).unwrap();
+            let reference_arc = &lookup.get_reference(reference_uniquename).unwrap();
             let reference_details = reference_arc.as_ref();
             let reference_short = ReferenceShort::from_reference_details(reference_details);
             ret.insert(reference_uniquename.clone(), Some(reference_short));
@@ -797,133 +1149,256 @@ impl APIData {
         ret
     }
 
+    // Read the annotation details then the terms, genes, references,
+    // alleles and genotypes in ids, and those that the annotation details
+    // refer to, with one query for each table
+    fn read_batch(&self, mut ids: BatchIds) -> BatchLookup<'_> {
+        let annotation_details =
+            self.maps_database.get_annotation_details(&ids.annotation_ids);
+
+        for annotation_detail in annotation_details.values() {
+            ids.add_annotation_detail(annotation_detail);
+        }
+
+        BatchLookup {
+            api_data: self,
+            terms: self.maps_database.get_terms(&ids.termids),
+            genes: self.maps_database.get_genes(&ids.gene_uniquenames),
+            references: self.maps_database.get_references(&ids.reference_uniquenames),
+            alleles: self.maps_database.get_alleles(&ids.allele_uniquenames),
+            genotypes: self.maps_database.get_genotypes(&ids.genotype_uniquenames),
+            annotation_details,
+        }
+    }
+
     // return a GeneDetails object with the term, genes and references maps filled in
     pub fn get_full_gene_details(&self, gene_uniquename: &str) -> Option<GeneDetails> {
-        let gene_uniquename = gene_uniquename.to_shared_str();
-        if let Some(gene_arc) = self.get_gene(&gene_uniquename) {
-            let mut gene = gene_arc.as_ref().to_owned();
-            let details_map = self.detail_map_of_cv_annotations(&gene.cv_annotations);
-            gene.terms_by_termid = self.fill_term_map(&gene.terms_by_termid);
-            gene.genes_by_uniquename = self.fill_gene_map(&gene.genes_by_uniquename);
-            gene.transcripts_by_uniquename = self.fill_transcript_map(&gene.transcripts_by_uniquename);
-            gene.references_by_uniquename =
-                self.fill_reference_map(&gene.references_by_uniquename);
-            sort_cv_annotation_details(&mut gene, &self.config,
-                                       self,
-                                       &details_map);
-            make_cv_summaries(&mut gene, &self.config, &self.maps.children_by_termid,
-                              false, true, self,
-                              &details_map);
-
-            gene.annotation_details = details_map;
-            Some(gene)
-        } else {
-            None
-        }
+        self.get_full_gene_details_batch(&[gene_uniquename]).remove(gene_uniquename)
+    }
+
+    fn gene_details_helper(&self, lookup: &BatchLookup, gene_ref: &GeneDetails) -> GeneDetails {
+        let mut gene = gene_ref.clone();
+        let details_map = self.detail_map_of_cv_annotations(lookup, &gene.cv_annotations);
+        gene.terms_by_termid = self.fill_term_map(lookup, &gene.terms_by_termid);
+        gene.genes_by_uniquename = self.fill_gene_map(lookup, &gene.genes_by_uniquename);
+        gene.transcripts_by_uniquename = self.fill_transcript_map(&gene.transcripts_by_uniquename);
+        gene.references_by_uniquename =
+            self.fill_reference_map(lookup, &gene.references_by_uniquename);
+        sort_cv_annotation_details(&mut gene, &self.config,
+                                   lookup,
+                                   &details_map);
+        make_cv_summaries(&mut gene, &self.config, &self.maps.children_by_termid,
+                          false, true, lookup,
+                          &details_map);
+
+        gene.annotation_details = details_map;
+        gene
     }
 
     pub fn get_genotype_details(&self, genotype_uniquename: &str) -> Option<GenotypeDetails> {
-        let genotype_uniquename = genotype_uniquename.to_shared_str();
-        if let Some(genotype_ref) = self.get_genotype(&genotype_uniquename) {
-            let mut genotype = genotype_ref.as_ref().to_owned();
-            let details_map = self.detail_map_of_cv_annotations(&genotype.cv_annotations);
-            genotype.terms_by_termid = self.fill_term_map(&genotype.terms_by_termid);
-            genotype.genes_by_uniquename = self.fill_gene_map(&genotype.genes_by_uniquename);
-            genotype.transcripts_by_uniquename = self.fill_transcript_map(&genotype.transcripts_by_uniquename);
-            genotype.references_by_uniquename =
-                self.fill_reference_map(&genotype.references_by_uniquename);
-            sort_cv_annotation_details(&mut genotype, &self.config,
-                                       self,
-                                       &details_map);
-            make_cv_summaries(&mut genotype, &self.config, &self.maps.children_by_termid,
-                              false, false, self,
-                              &details_map);
-            genotype.annotation_details = details_map;
-            Some(genotype)
-        } else {
-            None
-        }
+        self.get_genotype_details_batch(&[genotype_uniquename]).remove(genotype_uniquename)
+    }
+
+    fn genotype_details_helper(&self, lookup: &BatchLookup, genotype_ref: &GenotypeDetails)
+                               -> GenotypeDetails
+    {
+        let mut genotype = genotype_ref.clone();
+        let details_map = self.detail_map_of_cv_annotations(lookup, &genotype.cv_annotations);
+        genotype.terms_by_termid = self.fill_term_map(lookup, &genotype.terms_by_termid);
+        genotype.genes_by_uniquename = self.fill_gene_map(lookup, &genotype.genes_by_uniquename);
+        genotype.transcripts_by_uniquename = self.fill_transcript_map(&genotype.transcripts_by_uniquename);
+        genotype.references_by_uniquename =
+            self.fill_reference_map(lookup, &genotype.references_by_uniquename);
+        sort_cv_annotation_details(&mut genotype, &self.config,
+                                   lookup,
+                                   &details_map);
+        make_cv_summaries(&mut genotype, &self.config, &self.maps.children_by_termid,
+                          false, false, lookup,
+                          &details_map);
+        genotype.annotation_details = details_map;
+        genotype
     }
 
     pub fn get_allele_details(&self, allele_uniquename: &str) -> Option<AlleleDetails> {
-        let allele_uniquename = allele_uniquename.to_shared_str();
-
-        if let Some(allele_arc) = self.get_allele(&allele_uniquename) {
-            let mut allele_details = allele_arc.as_ref().to_owned();
-            let mut alleles_by_uniquename = HashMap::new();
-
-            for genotype_short in &allele_details.genotypes {
-                for locus in &genotype_short.loci {
-                    for expressed_allele in &locus.expressed_alleles {
-                        let allele_uniquename = &expressed_allele.allele_uniquename;
-                        let allele_short: AlleleShort =
-                            self.get_allele(allele_uniquename).unwrap().as_ref().into();
-                        alleles_by_uniquename.insert(allele_uniquename.clone(), allele_short);
-                    }
-                }
-            }
+        self.get_allele_details_batch(&[allele_uniquename]).remove(allele_uniquename)
+    }
 
-            allele_details.alleles_by_uniquename = alleles_by_uniquename;
+    fn allele_details_helper(&self, lookup: &BatchLookup, allele_ref: &AlleleDetails)
+                             -> AlleleDetails
+    {
+        let mut allele_details = allele_ref.clone();
+        let mut alleles_by_uniquename = HashMap::new();
 
-            Some(allele_details)
-        } else {
-            None
+        for allele_uniquename in expressed_allele_uniquenames(&allele_details) {
+            let allele_short: AlleleShort =
+                lookup.get_allele(allele_uniquename).unwrap().as_ref().into();
+            alleles_by_uniquename.insert(allele_uniquename.clone(), allele_short);
         }
+
+        allele_details.alleles_by_uniquename = alleles_by_uniquename;
+
+        allele_details
     }
 
-    fn term_details_helper(&self, term_ref: &TermDetails) -> Option<TermDetails> {
+    fn term_details_helper(&self, lookup: &BatchLookup, term_ref: &TermDetails) -> TermDetails {
         let mut term = term_ref.clone();
-        let details_map = self.detail_map_of_cv_annotations(&term.cv_annotations);
-        term.terms_by_termid = self.fill_term_map(&term.terms_by_termid);
-        term.genes_by_uniquename = self.fill_gene_map(&term.genes_by_uniquename);
+        let details_map = self.detail_map_of_cv_annotations(lookup, &term.cv_annotations);
+        term.terms_by_termid = self.fill_term_map(lookup, &term.terms_by_termid);
+        term.genes_by_uniquename = self.fill_gene_map(lookup, &term.genes_by_uniquename);
         term.transcripts_by_uniquename = self.fill_transcript_map(&term.transcripts_by_uniquename);
         term.references_by_uniquename =
-            self.fill_reference_map(&term.references_by_uniquename);
+            self.fill_reference_map(lookup, &term.references_by_uniquename);
         sort_cv_annotation_details(&mut term, &self.config,
-                                   self,
+                                   lookup,
                                    &details_map);
         make_cv_summaries(&mut term, &self.config, &self.maps.children_by_termid,
-                          true, true, self,
+                          true, true, lookup,
                           &details_map);
         term.annotation_details = details_map;
-        Some(term)
+        term
     }
 
-    pub fn get_term_details(&self, termid: &str) -> Option<TermDetails> {
+    // the term ID of a term or of the term that has termid as a secondary
+    // identifier
+    fn real_termid(&self, termid: &str) -> TermId {
         let termid = termid.to_shared_str();
-        let termid =
-            if let Some(real_termid) =
-               self.maps.secondary_identifiers_map.get(&termid) {
-                real_termid
-            } else {
-                &termid
-            };
-        if let Some(term_ref) = self.get_term(termid) {
-            self.term_details_helper(&term_ref)
-        } else {
-            None
-        }
+        self.maps.secondary_identifiers_map.get(&termid).cloned().unwrap_or(termid)
+    }
+
+    pub fn get_term_details(&self, termid: &str) -> Option<TermDetails> {
+        self.get_term_details_batch(&[termid]).remove(termid)
     }
 
     pub fn get_reference_details(&self, reference_uniquename: &str) -> Option<ReferenceDetails> {
-        let reference_uniquename = reference_uniquename.to_shared_str();
-        if let Some(reference_ref) = self.get_reference(&reference_uniquename) {
-            let mut reference = reference_ref.as_ref().to_owned();
-            let details_map = self.detail_map_of_cv_annotations(&reference.cv_annotations);
-            reference.terms_by_termid = self.fill_term_map(&reference.terms_by_termid);
-            reference.genes_by_uniquename = self.fill_gene_map(&reference.genes_by_uniquename);
-            reference.transcripts_by_uniquename = self.fill_transcript_map(&reference.transcripts_by_uniquename);
-            sort_cv_annotation_details(&mut reference, &self.config,
-                                       self,
-                                       &details_map);
-            make_cv_summaries(&mut reference, &self.config, &self.maps.children_by_termid,
-                              true, true, self,
-                              &details_map);
-            reference.annotation_details = details_map;
-            Some(reference)
-        } else {
-            None
+        self.get_reference_details_batch(&[reference_uniquename]).remove(reference_uniquename)
+    }
+
+    fn reference_details_helper(&self, lookup: &BatchLookup, reference_ref: &ReferenceDetails)
+                                -> ReferenceDetails
+    {
+        let mut reference = reference_ref.clone();
+        let details_map = self.detail_map_of_cv_annotations(lookup, &reference.cv_annotations);
+        reference.terms_by_termid = self.fill_term_map(lookup, &reference.terms_by_termid);
+        reference.genes_by_uniquename = self.fill_gene_map(lookup, &reference.genes_by_uniquename);
+        reference.transcripts_by_uniquename = self.fill_transcript_map(&reference.transcripts_by_uniquename);
+        sort_cv_annotation_details(&mut reference, &self.config,
+                                   lookup,
+                                   &details_map);
+        make_cv_summaries(&mut reference, &self.config, &self.maps.children_by_termid,
+                          true, true, lookup,
+                          &details_map);
+        reference.annotation_details = details_map;
+        reference
+    }
+
+    // Return the full details of each of the IDs that was found, keyed by
+    // the ID.  The IDs are mapped to database IDs with db_id_of() then the
+    // ones that aren't cached are read with one query.  The annotation
+    // details, terms, genes etc. that the full details need are collected
+    // with add_ids() and read in one pass with read_batch() so a large batch
+    // doesn't need a query for each of them.
+    fn details_batch<R, T>(&self, ids: &[impl AsRef<str>],
+                           db_id_of: impl Fn(&str) -> FlexStr,
+                           read_details: impl FnOnce(&[FlexStr]) -> HashMap<FlexStr, Arc<R>>,
+                           add_ids: impl Fn(&mut BatchIds, &R),
+                           full_details: impl Fn(&BatchLookup, &R) -> T)
+           -> HashMap<String, T>
+    {
+        let db_ids = ids.iter()
+            .map(|id| (id.as_ref(), db_id_of(id.as_ref())))
+            .collect::<Vec<_>>();
+
+        let read_ids = db_ids.iter().map(|(_, db_id)| db_id.clone()).collect::<Vec<_>>();
+        let details = read_details(&read_ids);
+
+        let mut batch_ids = BatchIds::default();
+        for details in details.values() {
+            add_ids(&mut batch_ids, details);
+        }
+        let lookup = self.read_batch(batch_ids);
+
+        let mut ret = HashMap::new();
+
+        for (id, db_id) in db_ids {
+            if ret.contains_key(id) {
+                continue;
+            }
+            if let Some(details) = details.get(&db_id) {
+                ret.insert(id.to_owned(), full_details(&lookup, details));
+            }
         }
+
+        ret
+    }
+
+    pub fn get_full_gene_details_batch(&self, gene_uniquenames: &[impl AsRef<str>])
+           -> HashMap<String, GeneDetails>
+    {
+        self.details_batch(gene_uniquenames, |id| id.to_shared_str(),
+                           |ids| self.maps_database.get_genes(ids),
+                           |batch_ids, gene| {
+                               batch_ids.add_annotations(&gene.cv_annotations,
+                                                         &gene.terms_by_termid,
+                                                         &gene.genes_by_uniquename);
+                               batch_ids.reference_uniquenames
+                                   .extend(gene.references_by_uniquename.keys().cloned());
+                           },
+                           |lookup, gene| self.gene_details_helper(lookup, gene))
+    }
+
+    pub fn get_term_details_batch(&self, termids: &[impl AsRef<str>])
+           -> HashMap<String, TermDetails>
+    {
+        self.details_batch(termids, |id| self.real_termid(id),
+                           |ids| self.maps_database.get_terms(ids),
+                           |batch_ids, term| {
+                               batch_ids.add_annotations(&term.cv_annotations,
+                                                         &term.terms_by_termid,
+                                                         &term.genes_by_uniquename);
+                               batch_ids.reference_uniquenames
+                                   .extend(term.references_by_uniquename.keys().cloned());
+                           },
+                           |lookup, term| self.term_details_helper(lookup, term))
+    }
+
+    pub fn get_allele_details_batch(&self, allele_uniquenames: &[impl AsRef<str>])
+           -> HashMap<String, AlleleDetails>
+    {
+        self.details_batch(allele_uniquenames, |id| id.to_shared_str(),
+                           |ids| self.maps_database.get_alleles(ids),
+                           |batch_ids, allele| {
+                               batch_ids.allele_uniquenames
+                                   .extend(expressed_allele_uniquenames(allele).cloned());
+                           },
+                           |lookup, allele| self.allele_details_helper(lookup, allele))
+    }
+
+    pub fn get_genotype_details_batch(&self, genotype_uniquenames: &[impl AsRef<str>])
+           -> HashMap<String, GenotypeDetails>
+    {
+        self.details_batch(genotype_uniquenames, |id| id.to_shared_str(),
+                           |ids| self.maps_database.get_genotypes(ids),
+                           |batch_ids, genotype| {
+                               batch_ids.add_annotations(&genotype.cv_annotations,
+                                                         &genotype.terms_by_termid,
+                                                         &genotype.genes_by_uniquename);
+                               batch_ids.reference_uniquenames
+                                   .extend(genotype.references_by_uniquename.keys().cloned());
+                           },
+                           |lookup, genotype| self.genotype_details_helper(lookup, genotype))
+    }
+
+    pub fn get_reference_details_batch(&self, reference_uniquenames: &[impl AsRef<str>])
+           -> HashMap<String, ReferenceDetails>
+    {
+        self.details_batch(reference_uniquenames, |id| id.to_shared_str(),
+                           |ids| self.maps_database.get_references(ids),
+                           |batch_ids, reference| {
+                               batch_ids.add_annotations(&reference.cv_annotations,
+                                                         &reference.terms_by_termid,
+                                                         &reference.genes_by_uniquename);
+                           },
+                           |lookup, reference| self.reference_details_helper(lookup, reference))
     }
 
     pub fn interactors_of_genes(&self, gene_uniquename: &GeneUniquename,
@@ -0,0 +1,97 @@
+// A binary encoding of serde data, used for files that need to be read
+// quickly such as the APIMaps loaded by pombase-server.  A file is a header
+// followed by the value in CBOR, written and read with ciborium.  Values are
+// read directly from a stream so the whole file never needs to be held in
+// memory.
+//
+// Like JSON, CBOR is self-describing: structs are written as maps with the
+// field names as keys, so attributes like skip_serializing_if and default,
+// and internally tagged enums, behave the same as they do with JSON.
+
+use std::fmt::{self, Display};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+use serde::de::DeserializeOwned;
+use serde::ser::Serialize;
+
+// written at the start of every file, followed by the format version
+pub const BINARY_FORMAT_MAGIC: &[u8; 8] = b"PBBINARY";
+
+// increment if the encoding changes
+pub const BINARY_FORMAT_VERSION: u32 = 2;
+
+// The longest string that can be read.  It must be longer than the
+// chromosome residues.  Strings are read into a buffer of this size that is
+// allocated once, so a corrupt length in a file can't cause a larger
+// allocation.  The memory is only used as far as the longest string.
+pub const MAX_STRING_LEN: usize = 64 * 1024 * 1024;
+
+#[derive(Debug)]
+pub struct Error {
+    message: String,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error {
+            message: format!("I/O error: {}", err),
+        }
+    }
+}
+
+fn error(message: impl Into<String>) -> Error {
+    Error {
+        message: message.into(),
+    }
+}
+
+// Write the header then the value
+pub fn to_writer<W: Write, T: Serialize + ?Sized>(writer: W, value: &T) -> Result<()> {
+    let mut writer = BufWriter::new(writer);
+
+    writer.write_all(BINARY_FORMAT_MAGIC)?;
+    writer.write_all(&BINARY_FORMAT_VERSION.to_le_bytes())?;
+
+    ciborium::into_writer(value, &mut writer)
+        .map_err(|err| error(format!("failed to write value: {}", err)))?;
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+// Check the header then read the value
+pub fn from_reader<R: Read, T: DeserializeOwned>(reader: R) -> Result<T> {
+    let mut reader = BufReader::with_capacity(256 * 1024, reader);
+
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+
+    if &magic != BINARY_FORMAT_MAGIC {
+        return Err(error("not a binary format file"));
+    }
+
+    let mut version = [0u8; 4];
+    reader.read_exact(&mut version)?;
+    let version = u32::from_le_bytes(version);
+
+    if version != BINARY_FORMAT_VERSION {
+        return Err(error(format!("unsupported binary format version {}, expected {}",
+                                 version, BINARY_FORMAT_VERSION)));
+    }
+
+    let mut string_buffer = vec![0u8; MAX_STRING_LEN];
+
+    ciborium::from_reader_with_buffer(reader, &mut string_buffer)
+        .map_err(|err| error(format!("failed to read value: {}", err)))
+}
@@ -2245,6 +2245,8 @@ pub struct Metadata {
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct APIAlleleDetails {
+    #[serde(default)]
+    pub uniquename: AlleleUniquename,
     pub gene: GeneUniquename,
     pub allele_type: FlexStr,
     #[serde(skip_serializing_if="Option::is_none")]
@@ -2253,6 +2255,12 @@ pub struct APIAlleleDetails {
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct APIGenotypeAnnotation {
+    #[serde(default)]
+    pub genotype_display_uniquename: GenotypeDisplayUniquename,
+    // used to look up the OntAnnotationDetail when filtering on the
+    // evidence, date etc. of the annotation
+    #[serde(skip_serializing_if="Option::is_none", default)]
+    pub annotation_id: Option<OntAnnotationId>,
     pub is_multi: bool,
     pub ploidiness: Ploidiness,
     pub conditions: HashSet<TermAndName>,
@@ -2264,12 +2272,19 @@ pub struct APIGeneSummary {
     pub uniquename: GeneUniquename,
     #[serde(skip_serializing_if="Option::is_none")]
     pub name: Option<FlexStr>,
+    #[serde(default)]
+    pub taxonid: OrganismTaxonId,
     #[serde(skip_serializing_if="Option::is_none")]
     pub product: Option<FlexStr>,
     #[serde(skip_serializing_if="Option::is_none")]
     pub uniprot_identifier: Option<FlexStr>,
+    #[serde(skip_serializing_if="Option::is_none", default)]
+    pub secondary_identifier: Option<FlexStr>,
     #[serde(skip_serializing_if="Vec::is_empty", default)]
     pub exact_synonyms: Vec<FlexStr>,
+    // synonyms that aren't "exact", including obsolete names
+    #[serde(skip_serializing_if="Vec::is_empty", default)]
+    pub other_synonyms: Vec<FlexStr>,
     #[serde(skip_serializing_if="HashSet::is_empty", default)]
     pub dbxrefs: HashSet<FlexStr>,
     #[serde(skip_serializing_if="HashSet::is_empty", default)]
@@ -2309,6 +2324,40 @@ pub enum GeneQueryPropFlag {
     HasParalog,
 }
 
+// a match from InterPro or one of its member databases, eg. Pfam, with the
+// residue ranges of the match
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GeneQueryDomainMatch {
+    // eg. "PF00069"
+    pub id: FlexStr,
+    // eg. "PFAM"
+    pub dbname: FlexStr,
+#[serde(skip_serializing_if="Option::is_none")]
+    pub interpro_id: Option<FlexStr>,
+#[serde(skip_serializing_if="Vec::is_empty", default)]
+    pub locations: Vec<(usize, usize)>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ProteinFeatureType {
+    #[serde(rename = "signal_peptide")]
+    SignalPeptide,
+    #[serde(rename = "transit_peptide")]
+    TransitPeptide,
+    #[serde(rename = "binding_site")]
+    BindingSite,
+    #[serde(rename = "active_site")]
+    ActiveSite,
+}
+
+// a sequence feature from UniProt
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GeneQueryProteinFeature {
+    pub feature_type: ProteinFeatureType,
+    pub start: usize,
+    pub end: usize,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct GeneQueryData {
     pub gene_uniquename: GeneUniquename,
@@ -2355,6 +2404,10 @@ pub struct GeneQueryData {
     pub paralogs: HashSet<GeneUniquename>,
 #[serde(skip_serializing_if="HashSet::is_empty", default)]
     pub property_flags: HashSet<GeneQueryPropFlag>,
+#[serde(skip_serializing_if="Vec::is_empty", default)]
+    pub domain_matches: Vec<GeneQueryDomainMatch>,
+#[serde(skip_serializing_if="Vec::is_empty", default)]
+    pub protein_features: Vec<GeneQueryProteinFeature>,
 }
 
 #[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
@@ -2516,6 +2569,10 @@ pub struct APIMaps {
     pub term_subsets: IdTermSubsetMap,
     pub gene_subsets: IdGeneSubsetMap,
     pub children_by_termid: HashMap<TermId, HashSet<TermId>>,
+    // the is_a and part_of ancestors of the terms that are annotation
+    // extension ranges, used to match the term ranges of extension queries
+    #[serde(default)]
+    pub extension_range_ancestors: HashMap<TermId, HashSet<TermId>>,
     pub gene_expression_measurements: GeneExDataSetMeasurements,
     pub secondary_identifiers_map: HashMap<TermId, TermId>,
     pub protein_view_data: HashMap<GeneUniquename, ProteinViewData>,
@@ -2527,6 +2584,13 @@ pub struct APIMaps {
 
     // data from Complex Portal:
     pub protein_complexes: ProteinComplexMap,
+    // used to draw the stats graphs
+    #[serde(default)]
+    pub detailed_stats: DetailedStats,
+    // the database date and data versions, reported by the server
+    #[serde(default)]
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub metadata: Option<Metadata>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -2810,7 +2874,7 @@ pub struct Stats {
 
 pub type StatsIntegerTableRow = (DateString, Vec<usize>);
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct StatsIntegerTable {
     pub header: Vec<String>,
     pub data: Vec<StatsIntegerTableRow>,
@@ -2818,13 +2882,13 @@ pub struct StatsIntegerTable {
 
 pub type StatsFloatTableRow = (DateString, f32);
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct StatsFloatTable {
     pub header: Vec<String>,
     pub data: Vec<StatsFloatTableRow>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct DetailedStats {
     pub curated_by_month: StatsIntegerTable,
     pub curated_by_year: StatsIntegerTable,
@@ -25,6 +25,7 @@ pub mod rnacentral;
 pub mod annotation_util;
 pub mod data_types;
 pub mod api_data;
+pub mod binary_format;
 pub mod sort_annotations;
 pub mod utils;
 pub mod load;
@@ -4,6 +4,8 @@ use std::io::BufRead;
 use std::fs::File;
 
 use crate::data_types::TermShort;
+use crate::api::query_cache::DEFAULT_QUERY_CACHE_SIZE;
+use crate::api_data::DEFAULT_MAPS_DATABASE_CONNECTIONS;
 use crate::types::*;
 
 use flexstr::{SharedStr as FlexStr, shared_str as flex_str};
@@ -207,16 +209,126 @@ pub struct ServerSubsetConfig {
     pub prefixes_to_remove: Vec<FlexStr>,
 }
 
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SearchBackend {
+#[serde(rename = "solr")]
+    #[default]
+    Solr,
+#[serde(rename = "embedded")]
+    Embedded,
+}
+
 #[derive(Deserialize, Clone, Debug)]
 pub struct ServerConfig {
     pub subsets: ServerSubsetConfig,
+    // where term, reference, allele and documentation searches are done
+    #[serde(default)]
+    pub search_backend: SearchBackend,
     pub solr_url: String,
+    // the directory containing the terms.json.gz, references.json.gz and
+    // alleles.json.gz files written by WebData::write_solr_data(), used if
+    // search_backend is "embedded".  Documentation is only searched if the
+    // directory also contains a docs.json.gz file made from the website
+    // documentation, which isn't part of the Chado data.
+    #[serde(default)]
+    pub embedded_search_data_dir: Option<String>,
     pub close_synonym_boost: f32,
     pub distant_synonym_boost: f32,
     pub term_definition_boost: f32,
     pub django_url: String,
     pub cv_name_for_terms_search: String,
     pub gene_uniquename_re: String,
+    // the maximum number of query node results to keep in the QueryExec cache
+    #[serde(default="ServerConfig::default_query_cache_size")]
+    pub query_cache_size: usize,
+    // the maximum number of details of each type to keep in the
+    // APIMapsDatabase caches
+    #[serde(default)]
+    pub maps_cache_sizes: MapsCacheSizes,
+    // the number of connections used to read the maps database
+    #[serde(default="ServerConfig::default_maps_database_connections")]
+    pub maps_database_connections: usize,
+    // logging and the /metrics endpoint
+    #[serde(default)]
+    pub monitoring: MonitoringConfig,
+}
+
+impl ServerConfig {
+    pub fn default_query_cache_size() -> usize {
+        DEFAULT_QUERY_CACHE_SIZE
+    }
+
+    pub fn default_maps_database_connections() -> usize {
+        DEFAULT_MAPS_DATABASE_CONNECTIONS
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+#[serde(rename = "text")]
+    #[default]
+    Text,
+    // one JSON object per line
+#[serde(rename = "json")]
+    Json,
+}
+
+// log_level, log_format and latency_buckets are only read when the server
+// starts, the other settings are re-read by /admin/reload
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct MonitoringConfig {
+    // "error", "warn", "info", "debug" or "trace"
+    pub log_level: String,
+    pub log_format: LogFormat,
+    // log the method, path, matched route, status and latency of every
+    // request, plus the query ID of queries
+    pub access_log: bool,
+    // serve request counts and latencies, query times, cache statistics
+    // and upstream errors in the Prometheus text format at /metrics
+    pub metrics_enabled: bool,
+    // the upper bounds in seconds of the request latency and query time
+    // histogram buckets
+    pub latency_buckets: Vec<f64>,
+}
+
+impl Default for MonitoringConfig {
+    fn default() -> MonitoringConfig {
+        MonitoringConfig {
+            log_level: "info".to_owned(),
+            log_format: LogFormat::Text,
+            access_log: true,
+            metrics_enabled: true,
+            latency_buckets: vec![0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0],
+        }
+    }
+}
+
+// A size of 0 turns off caching for that type
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default)]
+pub struct MapsCacheSizes {
+    pub genes: usize,
+    pub terms: usize,
+    pub references: usize,
+    pub alleles: usize,
+    pub genotypes: usize,
+    pub termid_genotype_annotations: usize,
+    pub annotation_details: usize,
+}
+
+impl Default for MapsCacheSizes {
+    fn default() -> MapsCacheSizes {
+        MapsCacheSizes {
+            genes: 10000,
+            terms: 20000,
+            references: 10000,
+            alleles: 20000,
+            genotypes: 20000,
+            termid_genotype_annotations: 5000,
+            annotation_details: 500000,
+        }
+    }
 }
 
 #[derive(Deserialize, Clone, Debug)]
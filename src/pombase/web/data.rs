@@ -35,6 +35,7 @@ use crate::bio::phenotype_format_writer::write_phenotype_annotation_files;
 use crate::bio::macromolecular_complexes::write_macromolecular_complexes;
 
 use crate::utils::{join, make_maps_database_tables, store_maps_into_database};
+use crate::binary_format;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct WebData {
@@ -272,6 +273,20 @@ impl WebData {
         compressor.finish().unwrap();
     }
 
+    // the same data as write_api_maps() but in a format that
+    // pombase-server can load more quickly
+    fn write_binary_api_maps(&self, output_dir: &str) {
+        let file_name = String::new() + output_dir + "/api_maps.bin.zst";
+        let f = File::create(file_name).expect("Unable to open file");
+
+        let mut compressor = Encoder::new(f, 12).unwrap();
+        compressor.multithread(8).unwrap();
+        compressor.long_distance_matching(true).unwrap();
+        binary_format::to_writer(&mut compressor, &self.api_maps).unwrap();
+
+        compressor.finish().unwrap();
+    }
+
     fn write_solr_data(&self, output_dir: &str) {
         let new_path = self.create_dir(output_dir, "solr_data/");
 
@@ -1615,7 +1630,7 @@ impl WebData {
     }
 
     pub fn write(&self, config: &Config, go_eco_mappping: &GoEcoMapping,
-                 doc_config: &DocConfig, output_dir: &str)
+                 doc_config: &DocConfig, output_dir: &str, write_binary_api_maps: bool)
                  -> Result<(), io::Error>
     {
         let web_json_path = self.create_dir(output_dir, "web-json");
@@ -1637,6 +1652,10 @@ impl WebData {
 
         self.write_api_maps(&web_json_path);
         println!("wrote API maps");
+        if write_binary_api_maps {
+            self.write_binary_api_maps(&web_json_path);
+            println!("wrote binary API maps");
+        }
         self.write_solr_data(&web_json_path);
         println!("wrote search data");
 
@@ -117,6 +117,8 @@ pub struct WebDataBuild<'a> {
 
     // a set of child terms for each term from the cvtermpath table
     children_by_termid: HashMap<TermId, HashSet<TermId>>,
+    // the is_a and part_of ancestors of each term that is an extension range
+    extension_range_ancestors: HashMap<TermId, HashSet<TermId>>,
     dbxrefs_of_features: HashMap<FlexStr, HashSet<FlexStr>>,
 
     possible_interesting_parents: HashSet<InterestingParent>,
@@ -936,6 +938,7 @@ impl <'a> WebDataBuild<'a> {
             base_term_of_extensions: HashMap::new(),
 
             children_by_termid: HashMap::new(),
+            extension_range_ancestors: HashMap::new(),
             dbxrefs_of_features: HashMap::new(),
 
             possible_interesting_parents: get_possible_interesting_parents(config),
@@ -1127,6 +1130,11 @@ impl <'a> WebDataBuild<'a> {
             .filter(|synonym| synonym.synonym_type == "exact")
             .map(|synonym| synonym.name.clone())
             .collect::<Vec<FlexStr>>();
+        let other_synonyms =
+            gene_details.synonyms.iter()
+            .filter(|synonym| synonym.synonym_type != "exact")
+            .map(|synonym| synonym.name.clone())
+            .collect::<Vec<FlexStr>>();
         let mut coding_exon_count = 0;
         let mut five_prime_exon_count = 0;
         let mut three_prime_exon_count = 0;
@@ -1171,9 +1179,12 @@ impl <'a> WebDataBuild<'a> {
         APIGeneSummary {
             uniquename: gene_details.uniquename.clone(),
             name: gene_details.name.clone(),
+            taxonid: gene_details.taxonid,
             product: gene_details.product.clone(),
             uniprot_identifier: gene_details.uniprot_identifier.clone(),
+            secondary_identifier: gene_details.secondary_identifier.clone(),
             exact_synonyms: synonyms,
+            other_synonyms,
             dbxrefs: gene_details.dbxrefs.clone(),
             pdb_ids,
             gocam_ids,
@@ -4737,6 +4748,15 @@ phenotypes, so just the first part of this extension will be used:
             HashMap::new();
 
         let mut children_by_termid: HashMap<TermId, HashSet<TermId>> = HashMap::new();
+        let mut extension_range_ancestors: HashMap<TermId, HashSet<TermId>> = HashMap::new();
+
+        let extension_range_termids = self.annotation_details.values()
+            .flat_map(|annotation_detail| &annotation_detail.extension)
+            .filter_map(|ext_part| match ext_part.ext_range {
+                ExtRange::Term(ref termid) => Some(termid.clone()),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
 
         for cvtermpath in &self.raw.cvtermpaths {
             let subject_term = &cvtermpath.subject;
@@ -4766,6 +4786,15 @@ phenotypes, so just the first part of this extension will be used:
                     continue;
                 }
 
+                if (rel_term_name == "is_a" || rel_term_name == "part_of") &&
+                    extension_range_termids.contains(&subject_termid)
+                {
+                    extension_range_ancestors
+                        .entry(subject_termid.clone())
+                        .or_default()
+                        .insert(object_termid.clone());
+                }
+
                 if subject_term_details.cv_annotations.keys().len() > 0 ||
                     slim_termids.contains(&object_termid)
                 {
@@ -4868,6 +4897,7 @@ phenotypes, so just the first part of this extension will be used:
         }
 
         self.children_by_termid = children_by_termid;
+        self.extension_range_ancestors = extension_range_ancestors;
     }
 
     fn make_metadata(&mut self) -> Metadata {
@@ -4952,6 +4982,8 @@ phenotypes, so just the first part of this extension will be used:
                             })
                             .collect::<HashSet<_>>();
                         let mut api_annotation = APIGenotypeAnnotation {
+                            genotype_display_uniquename: genotype.display_uniquename.clone(),
+                            annotation_id: Some(*annotation_id),
                             is_multi: genotype.loci.len() > 1,
                             ploidiness: genotype.ploidiness(),
                             conditions,
@@ -4965,6 +4997,7 @@ phenotypes, so just the first part of this extension will be used:
                                 let allele_gene_uniquename =
                                     allele_short.gene.uniquename.clone();
                                 let allele_details = APIAlleleDetails {
+                                    uniquename: allele_uniquename.clone(),
                                     gene: allele_gene_uniquename,
                                     allele_type: allele_short.allele_type.clone(),
                                     expression: allele.expression.clone(),
@@ -5108,6 +5141,55 @@ phenotypes, so just the first part of this extension will be used:
     }
 
 
+    fn get_domain_matches(&self, gene_details: &GeneDetails) -> Vec<GeneQueryDomainMatch> {
+        gene_details.interpro_matches.iter()
+            .map(|interpro_match| {
+                let interpro_id =
+                    if interpro_match.interpro_id.is_empty() {
+                        None
+                    } else {
+                        Some(interpro_match.interpro_id.clone())
+                    };
+
+                GeneQueryDomainMatch {
+                    id: interpro_match.id.clone(),
+                    dbname: interpro_match.dbname.clone(),
+                    interpro_id,
+                    locations: interpro_match.locations.iter()
+                        .map(|location| (location.start, location.end))
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+
+    fn get_protein_features(&self, gene_details: &GeneDetails) -> Vec<GeneQueryProteinFeature> {
+        let make_feature = |feature_type, range: &PeptideRange| {
+            GeneQueryProteinFeature {
+                feature_type,
+                start: range.start,
+                end: range.end,
+            }
+        };
+
+        let mut features = vec![];
+
+        if let Some(ref signal_peptide) = gene_details.signal_peptide {
+            features.push(make_feature(ProteinFeatureType::SignalPeptide, &signal_peptide.range));
+        }
+        if let Some(ref transit_peptide) = gene_details.transit_peptide {
+            features.push(make_feature(ProteinFeatureType::TransitPeptide, &transit_peptide.range));
+        }
+        for binding_site in &gene_details.binding_sites {
+            features.push(make_feature(ProteinFeatureType::BindingSite, &binding_site.range));
+        }
+        for active_site in &gene_details.active_sites {
+            features.push(make_feature(ProteinFeatureType::ActiveSite, &active_site.range));
+        }
+
+        features
+    }
+
     fn make_gene_query_data_map(&self) -> HashMap<GeneUniquename, GeneQueryData> {
         let mut gene_query_data_map = HashMap::new();
 
@@ -5209,6 +5291,8 @@ phenotypes, so just the first part of this extension will be used:
                 subset_termids: gene_details.subset_termids.clone(),
 
                 property_flags,
+                domain_matches: self.get_domain_matches(gene_details),
+                protein_features: self.get_protein_features(gene_details),
             };
 
             gene_query_data_map.insert(gene_details.uniquename.clone(), gene_query_data);
@@ -5451,6 +5535,7 @@ phenotypes, so just the first part of this extension will be used:
             term_subsets,
             gene_subsets,
             children_by_termid,
+            extension_range_ancestors: self.extension_range_ancestors,
             gene_expression_measurements,
             secondary_identifiers_map,
             protein_view_data,
@@ -5458,6 +5543,8 @@ phenotypes, so just the first part of this extension will be used:
             gocam_data_by_gocam_id,
             protein_complex_data,
             protein_complexes: self.protein_complexes,
+            detailed_stats: DetailedStats::default(),
+            metadata: None,
        }
     }
 
@@ -7654,7 +7741,9 @@ phenotypes, so just the first part of this extension will be used:
 
         let termid_genotype_annotation = self.get_api_genotype_annotation();
 
-        let api_maps = self.make_api_maps();
+        let mut api_maps = self.make_api_maps();
+        api_maps.detailed_stats = detailed_stats.clone();
+        api_maps.metadata = Some(metadata.clone());
 
         set_has_protein_features(&mut genes, &api_maps.protein_view_data);
 
@@ -0,0 +1,116 @@
+extern crate pombase;
+
+use std::path::PathBuf;
+
+use flexstr::SharedStr as FlexStr;
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+use zstd::stream::Encoder;
+
+use self::pombase::api::enrichment::{EnrichmentBackground, EnrichmentOptions};
+use self::pombase::api_data::{api_maps_from_file, read_api_maps};
+use self::pombase::binary_format::{from_reader, to_writer, BINARY_FORMAT_MAGIC,
+                                   BINARY_FORMAT_VERSION};
+
+fn round_trip<T>(value: &T) -> T
+    where T: Serialize + serde::de::DeserializeOwned
+{
+    let mut buf = vec![];
+    to_writer(&mut buf, value).unwrap();
+    from_reader(buf.as_slice()).unwrap()
+}
+
+// serialized without a length, like an iterator with an inexact size_hint()
+struct EvenNumbers(Vec<u32>);
+
+impl Serialize for EvenNumbers {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.iter().filter(|n| *n % 2 == 0))
+    }
+}
+
+// sort arrays so that values containing HashSets can be compared
+fn sort_arrays(value: &mut Value) {
+    match value {
+        Value::Array(values) => {
+            values.iter_mut().for_each(sort_arrays);
+            values.sort_by_cached_key(|value| value.to_string());
+        },
+        Value::Object(map) => map.values_mut().for_each(sort_arrays),
+        _ => (),
+    }
+}
+
+#[test]
+fn test_binary_format_values() {
+    assert_eq!(round_trip(&(1u8, -2i64, 3.5f32, 'x', "text".to_owned())),
+               (1u8, -2i64, 3.5f32, 'x', "text".to_owned()));
+    assert_eq!(round_trip(&vec![Some(vec![1u32]), None, Some(vec![])]),
+               vec![Some(vec![1u32]), None, Some(vec![])]);
+
+    let background = EnrichmentBackground::Subset {
+        subset_name: "GO:0005634".into(),
+    };
+    assert_eq!(round_trip(&background), background);
+
+    let options = EnrichmentOptions {
+        background: EnrichmentBackground::Genes {
+            genes: vec!["SPAC1002.01".into()],
+        },
+        ..EnrichmentOptions::default()
+    };
+    assert_eq!(round_trip(&options), options);
+
+    let mut buf = vec![];
+    to_writer(&mut buf, &EvenNumbers(vec![1, 2, 3, 4])).unwrap();
+    let even_numbers: Vec<u32> = from_reader(buf.as_slice()).unwrap();
+    assert_eq!(even_numbers, vec![2, 4]);
+
+    // the wrong type
+    let mut buf = vec![];
+    to_writer(&mut buf, &vec!["a", "b"]).unwrap();
+    assert!(from_reader::<_, Vec<u32>>(buf.as_slice()).is_err());
+
+    // a file from an unknown version of the format
+    let mut buf = vec![];
+    to_writer(&mut buf, &1u32).unwrap();
+    buf[BINARY_FORMAT_MAGIC.len()] = 99;
+    let err = from_reader::<_, u32>(buf.as_slice()).unwrap_err();
+    assert!(err.to_string().contains("unsupported binary format version 99"));
+
+    // long strings, like chromosome residues, are read in one piece
+    let residues: FlexStr = "ACGT".repeat(100_000).into();
+    assert_eq!(round_trip(&residues), residues);
+
+    // a corrupt string length is an error, not a 4 GiB allocation
+    let mut buf = BINARY_FORMAT_MAGIC.to_vec();
+    buf.extend_from_slice(&BINARY_FORMAT_VERSION.to_le_bytes());
+    buf.extend_from_slice(&[0x7a, 0xff, 0xff, 0xff, 0xff]);
+    assert!(from_reader::<_, FlexStr>(buf.as_slice()).is_err());
+    assert!(from_reader::<_, String>(buf.as_slice()).is_err());
+}
+
+#[test]
+fn test_binary_api_maps() {
+    let mut search_maps_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    search_maps_path.push("tests/test_search_data.json.zst");
+    let api_maps = api_maps_from_file(search_maps_path.to_str().unwrap());
+
+    let binary_path = std::env::temp_dir()
+        .join(format!("test_binary_api_maps_{}.bin.zst", std::process::id()));
+
+    let file = std::fs::File::create(&binary_path).unwrap();
+    let mut compressor = Encoder::new(file, 3).unwrap();
+    to_writer(&mut compressor, &api_maps).unwrap();
+    compressor.finish().unwrap();
+
+    let binary_api_maps = read_api_maps(binary_path.to_str().unwrap()).unwrap();
+    std::fs::remove_file(&binary_path).unwrap();
+
+    let mut binary_api_maps_value = serde_json::to_value(&binary_api_maps).unwrap();
+    let mut api_maps_value = serde_json::to_value(&api_maps).unwrap();
+    sort_arrays(&mut binary_api_maps_value);
+    sort_arrays(&mut api_maps_value);
+
+    assert_eq!(binary_api_maps_value, api_maps_value);
+}
@@ -667,13 +667,19 @@ fn get_test_config() -> Config {
             subsets: ServerSubsetConfig {
                 prefixes_to_remove: vec![],
             },
+            search_backend: SearchBackend::Solr,
             solr_url: "http://localhost:8983/solr".to_owned(),
+            embedded_search_data_dir: None,
             close_synonym_boost: 0.6,
             distant_synonym_boost: 0.3,
             term_definition_boost: 0.1,
             django_url: String::from("http://localhost:8999"),
             cv_name_for_terms_search: String::from(""),
             gene_uniquename_re: String::from("^SP([ABCN][CTP]|RR|SN|MIT|MT)[\\d\\w]*\\.\\d\\d\\d?\\d?c?$"),
+            query_cache_size: ServerConfig::default_query_cache_size(),
+            maps_cache_sizes: MapsCacheSizes::default(),
+            maps_database_connections: ServerConfig::default_maps_database_connections(),
+            monitoring: MonitoringConfig::default(),
         },
         extra_database_aliases: HashMap::new(),
         chromosomes: vec![],
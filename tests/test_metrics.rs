@@ -0,0 +1,113 @@
+extern crate pombase;
+
+use std::time::Duration;
+
+use self::pombase::api::details_cache::DetailsCacheStats;
+use self::pombase::api::metrics::{ReleaseCacheStats, ServerMetrics};
+use self::pombase::api::query_cache::QueryCacheStats;
+use self::pombase::api::server_log::parse_log_level;
+use self::pombase::api_data::MapsDatabaseCacheStats;
+use self::pombase::web::config::MonitoringConfig;
+
+fn metric_lines(metrics: &str) -> Vec<&str> {
+    metrics.lines().filter(|line| !line.starts_with('#')).collect()
+}
+
+fn details_cache_stats(hits: u64, misses: u64) -> DetailsCacheStats {
+    DetailsCacheStats {
+        hits,
+        misses,
+        evictions: 0,
+        entry_count: misses as usize,
+        max_entries: 100,
+    }
+}
+
+#[test]
+fn test_request_metrics() {
+    // the buckets are sorted and non-finite bounds are ignored
+    let metrics = ServerMetrics::new(&[0.5, 0.1, f64::INFINITY]);
+
+    let route = "/api/v1/dataset/:release/data/gene/:id";
+    metrics.record_request("GET", route, 200, Duration::from_millis(50));
+    metrics.record_request("GET", route, 200, Duration::from_millis(200));
+    metrics.record_request("GET", route, 404, Duration::from_secs(2));
+    metrics.record_query("latest", Duration::from_millis(20));
+    metrics.record_upstream_error("django");
+    metrics.record_upstream_error("django");
+
+    let rendered = metrics.render(&[]);
+    let lines = metric_lines(&rendered);
+
+    let route_labels = "method=\"GET\",route=\"/api/v1/dataset/:release/data/gene/:id\"";
+
+    assert!(lines.contains(&format!("pombase_http_requests_total{{{},status=\"200\"}} 2",
+                                    route_labels).as_str()));
+    assert!(lines.contains(&format!("pombase_http_requests_total{{{},status=\"404\"}} 1",
+                                    route_labels).as_str()));
+
+    // histogram buckets are cumulative
+    for (le, count) in [("0.1", 1), ("0.5", 2), ("+Inf", 3)] {
+        let line = format!("pombase_http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                           route_labels, le, count);
+        assert!(lines.contains(&line.as_str()), "missing: {}", line);
+    }
+    assert!(lines.contains(&format!("pombase_http_request_duration_seconds_count{{{}}} 3",
+                                    route_labels).as_str()));
+    assert!(!rendered.contains("le=\"inf\""));
+
+    assert!(lines.contains(&"pombase_query_duration_seconds_bucket{release=\"latest\",le=\"0.1\"} 1"));
+    assert!(lines.contains(&"pombase_query_duration_seconds_count{release=\"latest\"} 1"));
+    assert!(lines.contains(&"pombase_upstream_errors_total{upstream=\"django\"} 2"));
+
+    // label values are escaped
+    metrics.record_request("GET", "/a\"b", 200, Duration::from_millis(1));
+    assert!(metrics.render(&[]).contains("route=\"/a\\\"b\""));
+}
+
+#[test]
+fn test_cache_metrics() {
+    let metrics = ServerMetrics::new(&MonitoringConfig::default().latency_buckets);
+
+    let release_cache_stats = ReleaseCacheStats {
+        release_name: "v2".to_owned(),
+        maps_cache: MapsDatabaseCacheStats {
+            connection_count: 4,
+            genes: details_cache_stats(3, 1),
+            terms: details_cache_stats(0, 0),
+            references: details_cache_stats(0, 2),
+            alleles: details_cache_stats(0, 0),
+            genotypes: details_cache_stats(0, 0),
+            termid_genotype_annotations: details_cache_stats(0, 0),
+            annotation_details: details_cache_stats(0, 0),
+        },
+        query_cache: QueryCacheStats {
+            hits: 5,
+            misses: 6,
+            entry_count: 6,
+            max_entries: 1000,
+        },
+    };
+
+    let rendered = metrics.render(&[release_cache_stats]);
+    let lines = metric_lines(&rendered);
+
+    assert!(lines.contains(&"pombase_maps_cache_hits_total{release=\"v2\",cache=\"genes\"} 3"));
+    assert!(lines.contains(&"pombase_maps_cache_misses_total{release=\"v2\",cache=\"genes\"} 1"));
+    assert!(lines.contains(&"pombase_maps_cache_hit_ratio{release=\"v2\",cache=\"genes\"} 0.75"));
+    assert!(lines.contains(&"pombase_maps_cache_hit_ratio{release=\"v2\",cache=\"terms\"} 0"));
+    assert!(lines.contains(&"pombase_maps_cache_entries{release=\"v2\",cache=\"references\"} 2"));
+    assert!(lines.contains(&"pombase_query_cache_hits_total{release=\"v2\"} 5"));
+    assert!(lines.contains(&"pombase_query_cache_misses_total{release=\"v2\"} 6"));
+    assert!(lines.contains(&"pombase_maps_database_connections{release=\"v2\"} 4"));
+
+    assert!(rendered.contains("# TYPE pombase_maps_cache_hit_ratio gauge\n"));
+    assert!(rendered.contains("# TYPE pombase_http_request_duration_seconds histogram\n"));
+}
+
+#[test]
+fn test_parse_log_level() {
+    assert_eq!(parse_log_level("debug"), Some(tracing::Level::DEBUG));
+    assert_eq!(parse_log_level("WARN"), Some(tracing::Level::WARN));
+    assert_eq!(parse_log_level("loud"), None);
+}
@@ -0,0 +1,70 @@
+extern crate pombase;
+
+use serde_json::Value;
+
+use self::pombase::api::openapi::{openapi_path, openapi_spec, struct_field_names, API_ROUTES};
+use self::pombase::api::result::QueryNodeExplanation;
+
+fn collect_refs<'a>(value: &'a Value, refs: &mut Vec<&'a str>) {
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map {
+                if key == "$ref" {
+                    refs.push(child.as_str().unwrap());
+                } else {
+                    collect_refs(child, refs);
+                }
+            }
+        },
+        Value::Array(values) => {
+            for child in values {
+                collect_refs(child, refs);
+            }
+        },
+        _ => (),
+    }
+}
+
+#[test]
+fn test_openapi_path() {
+    assert_eq!(openapi_path("/api/v1/dataset/:release/complete/allele/*q"),
+               ("/api/v1/dataset/{release}/complete/allele/{q}".to_owned(),
+                vec!["release".to_owned(), "q".to_owned()]));
+    assert_eq!(openapi_path("/ping"), ("/ping".to_owned(), vec![]));
+
+    assert_eq!(struct_field_names::<QueryNodeExplanation>(),
+               &["node_name", "node_type", "result_count", "exec_time_ms", "children"]);
+}
+
+#[test]
+fn test_openapi_spec() {
+    let spec = openapi_spec("1.0");
+
+    assert_eq!(spec["openapi"], "3.0.3");
+
+    for api_route in API_ROUTES {
+        let (path, _) = openapi_path(api_route.path);
+        assert!(spec["paths"][&path][api_route.method].is_object(), "{}", path);
+    }
+
+    let gene_op = &spec["paths"]["/api/v1/dataset/{release}/data/gene/{id}"]["get"];
+    assert_eq!(gene_op["parameters"].as_array().unwrap().len(), 2);
+    assert_eq!(gene_op["responses"]["200"]["content"]["application/json"]["schema"]["$ref"],
+               "#/components/schemas/GeneDetails");
+
+    assert!(gene_op["responses"]["404"].is_null());
+    let search_op = &spec["paths"]["/api/v1/dataset/{release}/search/{scope}/{q}"]["get"];
+    assert!(search_op["responses"]["404"].is_object());
+
+    let schemas = &spec["components"]["schemas"];
+    assert_eq!(schemas["ResultRow"]["properties"]["gene_uniquename"]["type"], "string");
+    assert!(schemas["GeneDetails"]["properties"]["uniquename"].is_object());
+
+    let mut refs = vec![];
+    collect_refs(&spec, &mut refs);
+
+    for schema_ref in refs {
+        let schema_name = schema_ref.strip_prefix("#/components/schemas/").unwrap();
+        assert!(schemas[schema_name].is_object(), "missing schema: {}", schema_name);
+    }
+}
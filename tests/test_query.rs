@@ -4,18 +4,30 @@ use pombase;
 
 use std::collections::HashSet;
 
-use crate::util::get_api_data;
+use crate::util::{get_api_data, get_api_data_with, make_test_protein_transcript};
 
 use self::pombase::api::query::*;
 use self::pombase::api::result::*;
 use self::pombase::api::query_exec::*;
-use self::pombase::web::config::TermAndName;
-use self::pombase::data_types::{GeneShort, DeletionViability, GeneQueryTermData};
+use self::pombase::api::query_text::parse_query_text;
+use self::pombase::api::query_validation::QueryValidationErrorType;
+use self::pombase::api::query_cache::QueryCache;
+use self::pombase::api::enrichment::*;
+use self::pombase::api::reverse_query::*;
+use self::pombase::api::motif_search::*;
+use self::pombase::api::gene_complete::GeneCompletionField;
+use self::pombase::api::id_mapping::{IdMappingStatus, IdMatchType};
+use self::pombase::api::stats_plot::StatsPlots;
+use self::pombase::api::svg_plot::axis_ticks;
+use self::pombase::web::config::{TermAndName, GeneExDatasetConfig};
+use self::pombase::data_types::{GeneShort, DeletionViability, GeneQueryTermData,
+                                GeneExMeasurement, GeneQueryDomainMatch, GeneQueryProteinFeature,
+                                ProteinFeatureType, StatsIntegerTable};
 use self::pombase::bio::go_format_writer::GO_ASPECT_NAMES;
 
 mod util;
 
-use flexstr::ToSharedStr;
+use flexstr::{SharedStr as FlexStr, ToSharedStr};
 
 async fn check_gene_result(query: &Query, genes: Vec<&str>) {
     let api_data = get_api_data();
@@ -100,10 +112,16 @@ async fn test_and_or_not() {
 
     let opts = QueryOutputOptions {
         field_names: vec!["gene_uniquename".to_shared_str()],
+        result_type: QueryResultType::Gene,
         sequence: SeqType::None,
         gaf_options: None,
         ancestor_terms: HashSet::new(),
         flags: HashSet::new(),
+        sort: None,
+        offset: None,
+        limit: None,
+        enrichment: None,
+        reverse: None,
     };
 
     let and_query_node =
@@ -153,10 +171,16 @@ async fn test_output_options() {
         field_names: vec!["gene_uniquename".to_shared_str(),
                           "deletion_viability".to_shared_str(),
                           "go_component".to_shared_str()],
+        result_type: QueryResultType::Gene,
         sequence: SeqType::None,
         gaf_options: None,
         ancestor_terms: HashSet::new(),
         flags: HashSet::new(),
+        sort: None,
+        offset: None,
+        limit: None,
+        enrichment: None,
+        reverse: None,
     };
 
     let expected_results =
@@ -266,15 +290,28 @@ async fn test_termid() {
             expression: None,
             conditions: HashSet::new(),
             excluded_conditions: HashSet::new(),
+            evidence_codes: HashSet::new(),
+            throughput: None,
+            date_from: None,
+            date_to: None,
+            reference_uniquename: None,
+            assigned_by: None,
+            curator: None,
         }),
         .. QueryNode::template_node()
     };
     let opts = QueryOutputOptions {
         field_names: vec!["gene_uniquename".to_shared_str()],
+        result_type: QueryResultType::Gene,
         sequence: SeqType::None,
         gaf_options: None,
         ancestor_terms: HashSet::new(),
         flags: HashSet::new(),
+        sort: None,
+        offset: None,
+        limit: None,
+        enrichment: None,
+        reverse: None,
     };
     let q1 = Query::new(qp1, opts);
 
@@ -291,10 +328,16 @@ async fn test_gene_subset() {
     };
     let opts = QueryOutputOptions {
         field_names: vec!["gene_uniquename".to_shared_str()],
+        result_type: QueryResultType::Gene,
         sequence: SeqType::None,
         gaf_options: None,
         ancestor_terms: HashSet::new(),
         flags: HashSet::new(),
+        sort: None,
+        offset: None,
+        limit: None,
+        enrichment: None,
+        reverse: None,
     };
     let q1 = Query::new(qp1, opts);
 
@@ -311,10 +354,16 @@ async fn test_gene_subset_invert() {
     };
     let opts = QueryOutputOptions {
         field_names: vec!["gene_uniquename".to_shared_str()],
+        result_type: QueryResultType::Gene,
         sequence: SeqType::None,
         gaf_options: None,
         ancestor_terms: HashSet::new(),
         flags: HashSet::new(),
+        sort: None,
+        offset: None,
+        limit: None,
+        enrichment: None,
+        reverse: None,
     };
     let q1 = Query::new(qp1, opts);
 
@@ -336,10 +385,16 @@ async fn test_gene_subset_wildcard() {
     };
     let opts = QueryOutputOptions {
         field_names: vec!["gene_uniquename".to_shared_str()],
+        result_type: QueryResultType::Gene,
         sequence: SeqType::None,
         gaf_options: None,
         ancestor_terms: HashSet::new(),
         flags: HashSet::new(),
+        sort: None,
+        offset: None,
+        limit: None,
+        enrichment: None,
+        reverse: None,
     };
     let q1 = Query::new(qp1, opts);
 
@@ -357,10 +412,16 @@ async fn test_gene_subset_not_wildcard() {
     };
     let opts = QueryOutputOptions {
         field_names: vec!["gene_uniquename".to_shared_str()],
+        result_type: QueryResultType::Gene,
         sequence: SeqType::None,
         gaf_options: None,
         ancestor_terms: HashSet::new(),
         flags: HashSet::new(),
+        sort: None,
+        offset: None,
+        limit: None,
+        enrichment: None,
+        reverse: None,
     };
     let q1 = Query::new(qp1, opts);
 
@@ -383,6 +444,13 @@ async fn test_gene_gaf() {
             expression: None,
             conditions: HashSet::new(),
             excluded_conditions: HashSet::new(),
+            evidence_codes: HashSet::new(),
+            throughput: None,
+            date_from: None,
+            date_to: None,
+            reference_uniquename: None,
+            assigned_by: None,
+            curator: None,
         }),
         .. QueryNode::template_node()
     };
@@ -393,12 +461,18 @@ async fn test_gene_gaf() {
 
     let opts = QueryOutputOptions {
         field_names: vec!["gene_uniquename".to_shared_str()],
+        result_type: QueryResultType::Gene,
         sequence: SeqType::None,
         gaf_options: Some(GAFOptions {
             aspects,
         }),
         ancestor_terms: HashSet::new(),
         flags: HashSet::new(),
+        sort: None,
+        offset: None,
+        limit: None,
+        enrichment: None,
+        reverse: None,
     };
     let query = Query::new(qp1, opts);
 
@@ -417,3 +491,1208 @@ async fn test_gene_gaf() {
 
     assert_eq!(gaf_lines, "PomBase\tSPAC27E2.05\tSPAC27E2.05\t\tGO:0044237\tPB_REF:0000001\tISS\t\tP\t\t\tprotein\ttaxon:4896\t20090213\tPomBase\t\t\n");
 }
+
+#[tokio::test]
+async fn test_sort_and_page() {
+    let qp1 = QueryNode {
+        gene_list: Some(GeneListNode {
+            genes: make_genes(vec!["SPAC19G12.04", "SPAC1805.15c", "SPAC27E2.05", "SPRRNA.26"])
+        }),
+        .. QueryNode::template_node()
+    };
+    let opts = QueryOutputOptions {
+        field_names: vec!["gene_uniquename".to_shared_str()],
+        result_type: QueryResultType::Gene,
+        sequence: SeqType::None,
+        gaf_options: None,
+        ancestor_terms: HashSet::new(),
+        flags: HashSet::new(),
+        sort: Some(QuerySortOptions {
+            field_name: "gene_name".to_shared_str(),
+            direction: SortDirection::Ascending,
+        }),
+        offset: Some(1),
+        limit: Some(2),
+        enrichment: None,
+        reverse: None,
+    };
+
+    let api_data = get_api_data();
+    let query_exec = QueryExec::new(api_data, None);
+
+    let result = query_exec.exec(&Query::new(qp1.clone(), opts.clone())).await;
+
+    // cdc1, dal1, pub2 then SPRRNA.26, which has no name
+    let result_genes = result.rows.into_iter()
+        .map(|row| row.gene_uniquename)
+        .collect::<Vec<_>>();
+    assert_eq!(result_genes, vec!["SPAC19G12.04", "SPAC1805.15c"]);
+    assert_eq!(result.total_count, 4);
+
+    let desc_opts = QueryOutputOptions {
+        sort: Some(QuerySortOptions {
+            field_name: "gene_name".to_shared_str(),
+            direction: SortDirection::Descending,
+        }),
+        offset: None,
+        limit: Some(1),
+        enrichment: None,
+        reverse: None,
+        .. opts.clone()
+    };
+
+    let result = query_exec.exec(&Query::new(qp1.clone(), desc_opts)).await;
+
+    assert_eq!(result.rows.len(), 1);
+    assert_eq!(result.rows[0].gene_uniquename, "SPAC1805.15c");
+
+    let bad_field_opts = QueryOutputOptions {
+        sort: Some(QuerySortOptions {
+            field_name: "no_such_field".to_shared_str(),
+            direction: SortDirection::Ascending,
+        }),
+        .. opts
+    };
+
+    let result = query_exec.exec(&Query::new(qp1, bad_field_opts)).await;
+
+    assert_eq!(result.id, "error");
+    assert!(result.rows.is_empty());
+}
+
+#[tokio::test]
+async fn test_explain() {
+    let qp1 = QueryNode {
+        node_name: Some("list one".into()),
+        gene_list: Some(GeneListNode {
+            genes: make_genes(vec!["SPAC19G12.04", "SPAC1805.15c", "SPAC27E2.05"])
+        }),
+        .. QueryNode::template_node()
+    };
+    let qp2 = QueryNode {
+        node_name: Some("list two".into()),
+        gene_list: Some(GeneListNode {
+            genes: make_genes(vec!["SPAC2F3.09"])
+        }),
+        .. QueryNode::template_node()
+    };
+    let and_query_node = QueryNode {
+        and: Some(vec![qp1, qp2]),
+        .. QueryNode::template_node()
+    };
+
+    let mut flags = HashSet::new();
+    flags.insert("explain".to_shared_str());
+
+    let opts = QueryOutputOptions {
+        field_names: vec!["gene_uniquename".to_shared_str()],
+        result_type: QueryResultType::Gene,
+        sequence: SeqType::None,
+        gaf_options: None,
+        ancestor_terms: HashSet::new(),
+        flags,
+        sort: None,
+        offset: None,
+        limit: None,
+        enrichment: None,
+        reverse: None,
+    };
+
+    let api_data = get_api_data();
+    let query_exec = QueryExec::new(api_data, None);
+    let result = query_exec.exec(&Query::new(and_query_node, opts)).await;
+
+    assert!(result.rows.is_empty());
+
+    let explanation = result.explanation.unwrap();
+
+    assert_eq!(explanation.node_type, "and");
+    assert_eq!(explanation.result_count, 0);
+    assert_eq!(explanation.children.len(), 2);
+    assert_eq!(explanation.children[0].node_name, Some("list one".into()));
+    assert_eq!(explanation.children[0].node_type, "gene_list");
+    assert_eq!(explanation.children[0].result_count, 3);
+    assert_eq!(explanation.children[1].node_name, Some("list two".into()));
+    assert_eq!(explanation.children[1].result_count, 1);
+}
+
+#[test]
+fn test_query_text_round_trip() {
+    let text = "term(GO:0005634) AND NOT subset(SPAC*) AND protein_length(100..500)";
+    let node = parse_query_text(text).unwrap();
+
+    let not_node = node.not.as_ref().unwrap();
+    let and_nodes = not_node.node_a.and.as_ref().unwrap();
+    assert_eq!(and_nodes[0].term.as_ref().unwrap().termid, "GO:0005634");
+    let int_range = and_nodes[1].int_range.as_ref().unwrap();
+    assert_eq!(int_range.range_type, IntRangeType::ProteinLength);
+    assert_eq!((int_range.start, int_range.end), (Some(100), Some(500)));
+    assert_eq!(not_node.node_b.subset.as_ref().unwrap().subset_name, "SPAC*");
+
+    assert_eq!(node.to_string(),
+               "term(GO:0005634) AND protein_length(100..500) AND NOT subset(SPAC*)");
+    assert_eq!(parse_query_text(&node.to_string()).unwrap(), node);
+
+    let texts = [
+        "(genes(SPAC19G12.04, SPAC1805.15c) OR ref(PMID:123)) AND has_ortholog(9606)",
+        "(subset(a) AND NOT subset(b)) AND NOT subset(c)",
+        "subset(\"name with (brackets)\") OR tm_domain_count(2..)",
+        "term(FYPO:0000001, condition=FYECO:0000005, locus=single, ploidiness=haploid)",
+        "genome_range(chromosome_1, ..2000) AND protein_mol_weight(10.5..20)",
+        "gene_properties(has_paralog) OR genes_targeting(SPAC27E2.05, go)",
+    ];
+
+    for text in texts {
+        let node = parse_query_text(text).unwrap();
+        assert_eq!(node.to_string(), text);
+    }
+
+    // keywords are case insensitive
+    assert_eq!(parse_query_text("subset(a) or subset(b)").unwrap(),
+               parse_query_text("subset(a) OR subset(b)").unwrap());
+}
+
+#[test]
+fn test_query_text_errors() {
+    assert_eq!(parse_query_text("subset(a) AND").unwrap_err(),
+               "unexpected end of query at position 13");
+    assert_eq!(parse_query_text("NOT subset(a)").unwrap_err(),
+               "NOT must follow AND, as in \"a AND NOT b\", at position 0");
+    assert_eq!(parse_query_text("colour(red)").unwrap_err(),
+               "unknown query node type: colour at position 0");
+    assert_eq!(parse_query_text("subset(a, b)").unwrap_err(),
+               "subset() takes 1 argument(s) but was given 2 at position 0");
+    assert_eq!(parse_query_text("protein_length(a..b)").unwrap_err(),
+               "can't parse \"a\" in range: a..b at position 0");
+    assert!(parse_query_text("(subset(a)").is_err());
+    assert!(parse_query_text("subset(\"a)").is_err());
+}
+
+#[tokio::test]
+async fn test_query_text_exec() {
+    let constraints =
+        parse_query_text("genes(SPAC19G12.04, SPAC1805.15c, SPAC27E2.05) AND NOT genes(SPAC1805.15c)")
+        .unwrap();
+    let query = Query::new(constraints, QueryOutputOptions::default());
+
+    check_gene_result(&query, vec!["SPAC19G12.04", "SPAC27E2.05"]).await;
+}
+
+#[tokio::test]
+async fn test_validate() {
+    let api_data = get_api_data();
+
+    let valid_constraints =
+        parse_query_text("genes(SPAC19G12.04) OR subset(interpro:IPR*) OR term(GO:0044237)")
+        .unwrap();
+    let valid_query = Query::new(valid_constraints, QueryOutputOptions::default());
+    assert!(valid_query.validate(&api_data).is_empty());
+
+    let text = "(genes(SPAC19G12.04, SPAC_NO_SUCH_GENE) OR term(GO:9999999)) AND \
+                ref(PMID:1) AND genome_range(no_such_chromosome, 1..100) AND \
+                interactors(SPAC19G12.04, telepathic) AND NOT subset(no_such_subset)";
+    let query = Query::new(parse_query_text(text).unwrap(), QueryOutputOptions::default());
+
+    let errors = query.validate(&api_data).into_iter()
+        .map(|err| (err.error_type, err.path))
+        .collect::<Vec<_>>();
+
+    assert_eq!(errors,
+               vec![(QueryValidationErrorType::UnknownGene,
+                     "constraints.not.node_a.and[0].or[0].gene_list.genes[1].uniquename".to_owned()),
+                    (QueryValidationErrorType::UnknownTerm,
+                     "constraints.not.node_a.and[0].or[1].term.termid".to_owned()),
+                    (QueryValidationErrorType::UnknownReference,
+                     "constraints.not.node_a.and[1].ref_genes.reference_uniquename".to_owned()),
+                    (QueryValidationErrorType::UnknownChromosome,
+                     "constraints.not.node_a.and[2].genome_range.chromosome_name".to_owned()),
+                    (QueryValidationErrorType::UnknownInteractionType,
+                     "constraints.not.node_a.and[3].interactors.interaction_type".to_owned()),
+                    (QueryValidationErrorType::UnknownSubset,
+                     "constraints.not.node_b.subset.subset_name".to_owned())]);
+
+    let node_json = r#"{"or": [{}, {"subset": {"subset_name": "interpro:IPR*"},
+                                     "gene_list": {"genes": []}}]}"#;
+    let constraints: QueryNode = serde_json::from_str(node_json).unwrap();
+    let query = Query::new(constraints, QueryOutputOptions::default());
+
+    let errors = query.validate(&api_data);
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].error_type, QueryValidationErrorType::EmptyNode);
+    assert_eq!(errors[0].path, "constraints.or[0]");
+    assert_eq!(errors[1].error_type, QueryValidationErrorType::MultipleNodeTypes);
+    assert_eq!(errors[1].path, "constraints.or[1]");
+
+    // exec() refuses to run an invalid query
+    let query_exec = QueryExec::new(api_data, None);
+    let result = query_exec.exec(&query).await;
+    assert_eq!(result.id, "error");
+    assert!(result.rows.is_empty());
+    assert_eq!(result.validation_errors, errors);
+}
+
+#[tokio::test]
+async fn test_query_cache() {
+    let node_a = parse_query_text("genes(SPAC19G12.04, SPAC1805.15c) OR genes(SPAC27E2.05)")
+        .unwrap();
+    let node_b = parse_query_text("genes(SPAC27E2.05) OR genes(SPAC1805.15c, SPAC19G12.04)")
+        .unwrap();
+    assert_ne!(node_a, node_b);
+    assert_eq!(node_a.canonical_key(), node_b.canonical_key());
+
+    let node_c = parse_query_text("genes(SPAC27E2.05) AND genes(SPAC1805.15c, SPAC19G12.04)")
+        .unwrap();
+    assert_ne!(node_a.canonical_key(), node_c.canonical_key());
+
+    let api_data = get_api_data();
+    let query_exec = QueryExec::new(api_data, None);
+
+    let query_a = Query::new(node_a, QueryOutputOptions::default());
+    let result = query_exec.exec(&query_a).await;
+    assert_eq!(result.rows.len(), 3);
+
+    let stats = query_exec.cache_stats();
+    assert_eq!((stats.hits, stats.misses, stats.entry_count), (0, 3, 3));
+
+    let query_b = Query::new(node_b, QueryOutputOptions::default());
+    let query_c = Query::new(node_c, QueryOutputOptions::default());
+    let (result_b, result_c) =
+        tokio::join!(query_exec.exec(&query_b), query_exec.exec(&query_c));
+    assert_eq!(result_b.rows.len(), 3);
+    assert_eq!(result_c.rows.len(), 0);
+
+    // query_b is answered from the cache, query_c only finds its operands
+    let stats = query_exec.cache_stats();
+    assert_eq!((stats.hits, stats.misses, stats.entry_count), (3, 4, 4));
+
+    query_exec.clear_cache();
+    let stats = query_exec.cache_stats();
+    assert_eq!((stats.hits, stats.misses, stats.entry_count), (0, 0, 0));
+}
+
+#[test]
+fn test_query_cache_eviction() {
+    let cache = QueryCache::new(2);
+
+    cache.insert("a".to_owned(), &["SPAC19G12.04".into()]);
+    cache.insert("b".to_owned(), &["SPAC1805.15c".into()]);
+    assert!(cache.get("a").is_some());
+
+    // "b" is the least recently used
+    cache.insert("c".to_owned(), &[]);
+    assert!(cache.get("b").is_none());
+    assert_eq!(cache.get("a").unwrap(), vec!["SPAC19G12.04"]);
+    assert_eq!(cache.get("c").unwrap(), Vec::<FlexStr>::new());
+    assert_eq!(cache.stats().entry_count, 2);
+}
+
+#[test]
+fn test_query_normalise() {
+    let normalise = |text: &str| parse_query_text(text).unwrap().normalise();
+
+    assert_eq!(normalise("subset(a) OR (subset(c) OR subset(b))"),
+               normalise("(subset(b) OR subset(a)) OR subset(c)"));
+    assert_eq!(normalise("subset(a) OR (subset(c) OR subset(b))").to_string(),
+               "subset(a) OR subset(b) OR subset(c)");
+    assert_ne!(normalise("subset(a) OR (subset(c) AND subset(b))"),
+               normalise("subset(a) OR subset(c) OR subset(b)"));
+    assert_eq!(normalise("subset(a) AND subset(a)").to_string(), "subset(a)");
+    assert_eq!(normalise("genes(SPAC27E2.05, SPAC19G12.04, SPAC27E2.05)").to_string(),
+               "genes(SPAC19G12.04, SPAC27E2.05)");
+
+    let api_data = get_api_data();
+
+    let mut named_node = parse_query_text("genes(SPAC19G12.04) AND term(GO:0044237)").unwrap();
+    named_node.node_name = Some("my query".to_owned());
+    named_node.fill_names(&api_data);
+
+    let and_nodes = named_node.and.as_ref().unwrap();
+    assert_eq!(and_nodes[0].gene_list.as_ref().unwrap().genes[0].name.as_ref().unwrap(), "dal1");
+    assert!(and_nodes[1].term.as_ref().unwrap().name.is_some());
+
+    let plain_node = parse_query_text("term(GO:0044237) AND genes(SPAC19G12.04)").unwrap();
+    assert_eq!(named_node.normalise(), plain_node.normalise());
+
+    let opts = QueryOutputOptions {
+        flags: HashSet::from(["b".into(), "a".into(), "c".into()]),
+        ..QueryOutputOptions::default()
+    };
+    let conditions_node =
+        parse_query_text("term(FYPO:0000001, condition=FYECO:0000005, condition=FYECO:0000004)")
+        .unwrap();
+    let query = Query::new(conditions_node, opts);
+    let canonical_json = query.canonical_json();
+    assert!(canonical_json.contains(r#""flags":["a","b","c"]"#));
+    assert!(canonical_json.find("FYECO:0000004").unwrap() <
+            canonical_json.find("FYECO:0000005").unwrap());
+    assert_eq!(canonical_json, query.normalise().canonical_json());
+}
+
+#[test]
+fn test_hypergeometric_p_value() {
+    assert!((hypergeometric_p_value(10, 5, 5, 5) - 1.0 / 252.0).abs() < 1e-12);
+    assert!((hypergeometric_p_value(10, 5, 5, 0) - 1.0).abs() < 1e-12);
+    // P(X >= 1) = 1 - C(5,3)/C(10,3)
+    assert!((hypergeometric_p_value(10, 5, 3, 1) - (1.0 - 10.0 / 120.0)).abs() < 1e-12);
+}
+
+#[tokio::test]
+async fn test_enrichment() {
+    let api_data = get_api_data();
+
+    let genes = ["SPAC24B11.06c", "SPAC19G12.03", "SPAC2F3.09", "SPAC27E2.05"]
+        .iter().map(|s| s.to_shared_str()).collect::<Vec<_>>();
+
+    let result = gene_set_enrichment(&api_data, &genes, &EnrichmentOptions::default()).unwrap();
+
+    assert_eq!((result.study_count, result.background_count), (4, 17));
+    assert_eq!(result.terms.len(), 1);
+    let term = &result.terms[0];
+    assert_eq!(term.termid, "GO:0044237");
+    assert!(term.name.is_some());
+    assert_eq!((term.study_count, term.background_count), (4, 4));
+    assert!((term.fold_enrichment - 4.25).abs() < 1e-9);
+    assert!((term.p_value - 1.0 / 2380.0).abs() < 1e-12);
+    assert!((term.corrected_p_value - term.p_value * result.tested_term_count as f64).abs() < 1e-12);
+
+    let fypo_options = EnrichmentOptions {
+        termid_prefixes: HashSet::from(["FYPO".into()]),
+        correction: MultipleTestingCorrection::NoCorrection,
+        max_p_value: 1.0,
+        ..EnrichmentOptions::default()
+    };
+    let result = gene_set_enrichment(&api_data, &genes, &fypo_options).unwrap();
+    assert!(result.terms.iter().all(|term| term.termid.starts_with("FYPO:")));
+    assert_eq!(result.terms.len(), result.tested_term_count);
+    assert!(!result.terms.is_empty());
+
+    let bad_background = EnrichmentOptions {
+        background: EnrichmentBackground::Subset { subset_name: "no_such_subset".into() },
+        ..EnrichmentOptions::default()
+    };
+    assert!(gene_set_enrichment(&api_data, &genes, &bad_background).is_err());
+
+    // as a query output option
+    let query_exec = QueryExec::new(api_data, None);
+    let opts = QueryOutputOptions {
+        enrichment: Some(EnrichmentOptions::default()),
+        limit: Some(1),
+        ..QueryOutputOptions::default()
+    };
+    let constraints =
+        parse_query_text("genes(SPAC24B11.06c, SPAC19G12.03, SPAC2F3.09, SPAC27E2.05)").unwrap();
+    let query = Query::new(constraints, opts);
+    let result = query_exec.exec(&query).await;
+    assert_eq!(result.rows.len(), 1);
+    assert_eq!(result.enrichment.unwrap().terms[0].termid, "GO:0044237");
+}
+
+#[tokio::test]
+async fn test_genotype_and_allele_queries() {
+    let api_data = get_api_data();
+
+    let make_query = |text: &str, result_type: QueryResultType| {
+        let mut constraints = parse_query_text(text).unwrap();
+        constraints.fill_names(&api_data);
+        let opts = QueryOutputOptions {
+            result_type,
+            ..QueryOutputOptions::default()
+        };
+        Query::new(constraints, opts)
+    };
+
+    let genotype_query = make_query("term(FYPO:0000013)", QueryResultType::Genotype);
+    let haploid_query = make_query("term(FYPO:0000013, ploidiness=haploid)",
+                                   QueryResultType::Genotype);
+    let condition_query = make_query("term(FYPO:0000013, condition=FYECO:0000005)",
+                                     QueryResultType::Genotype);
+    let not_query = make_query("term(FYPO:0000013) AND NOT term(FYPO:0000013, ploidiness=haploid)",
+                               QueryResultType::Allele);
+    let gene_query = make_query("term(FYPO:0000013, locus=single)", QueryResultType::Gene);
+    let invalid_query = make_query("term(FYPO:0000013) OR subset(interpro:IPR*)",
+                                   QueryResultType::Allele);
+
+    let errors = invalid_query.validate(&api_data);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].error_type, QueryValidationErrorType::UnsupportedNodeType);
+    assert_eq!(errors[0].path, "constraints.or[1].subset");
+    assert!(genotype_query.validate(&api_data).is_empty());
+
+    let query_exec = QueryExec::new(api_data, None);
+
+    let genotype_ids = |result: &QueryAPIResult| {
+        let mut ids = result.genotype_rows.iter()
+            .map(|row| row.genotype_uniquename.as_ref())
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids.join(" ")
+    };
+
+    let result = query_exec.exec(&genotype_query).await;
+    assert!(result.rows.is_empty());
+    assert_eq!(result.total_count, 3);
+    assert_eq!(genotype_ids(&result),
+               "65c76fa511461156-genotype-3 d6c914796c35e3b5-genotype-4 e674fe7ceba478aa-genotype-2");
+    let row = result.genotype_rows.iter()
+        .find(|row| row.genotype_uniquename == "e674fe7ceba478aa-genotype-2").unwrap();
+    assert_eq!(row.display_name, "G799D(G799D)");
+    assert_eq!(row.allele_uniquenames, vec!["SPBC16A3.11:allele-7"]);
+
+    let result = query_exec.exec(&haploid_query).await;
+    assert_eq!(genotype_ids(&result), "65c76fa511461156-genotype-3 e674fe7ceba478aa-genotype-2");
+
+    let result = query_exec.exec(&condition_query).await;
+    assert_eq!(genotype_ids(&result), "e674fe7ceba478aa-genotype-2");
+
+    let result = query_exec.exec(&not_query).await;
+    assert_eq!(result.allele_rows.len(), 1);
+    let allele_row = &result.allele_rows[0];
+    assert_eq!(allele_row.allele_uniquename, "SPCC1919.10c:allele-5");
+    assert_eq!(allele_row.gene_uniquename, "SPCC1919.10c");
+
+    let result = query_exec.exec(&gene_query).await;
+    let mut genes = result.rows.iter()
+        .map(|row| row.gene_uniquename.as_ref()).collect::<Vec<_>>();
+    genes.sort();
+    assert_eq!(genes, vec!["SPAC24H6.05", "SPBC16A3.11", "SPCC1919.10c"]);
+
+    let result = query_exec.exec(&invalid_query).await;
+    assert_eq!(result.id, "error");
+}
+
+#[tokio::test]
+async fn test_reverse_queries() {
+    let api_data = get_api_data();
+    let query_exec = QueryExec::new(api_data, None);
+
+    let run_query = |result_type: QueryResultType, reverse: Option<ReverseQueryOptions>| {
+        let constraints = parse_query_text("term(GO:0044237)").unwrap();
+        let opts = QueryOutputOptions {
+            result_type,
+            reverse,
+            ..QueryOutputOptions::default()
+        };
+        Query::new(constraints, opts)
+    };
+
+    // terms used to annotate the genes
+    let result = query_exec.exec(&run_query(QueryResultType::Term, None)).await;
+    assert_eq!(result.status, "ok");
+    let termids = result.term_rows.iter().map(|term| term.termid.as_ref()).collect::<Vec<_>>();
+    assert!(termids.contains(&"GO:0044237"));
+    assert_eq!(result.total_count, termids.len());
+    let term = result.term_rows.iter().find(|term| term.termid == "GO:0044237").unwrap();
+    assert_eq!(term.name, "cellular metabolic process");
+    assert!(result.rows.is_empty());
+
+    let mf_options = ReverseQueryOptions {
+        cv_names: HashSet::from(["molecular_function".into()]),
+        ..ReverseQueryOptions::default()
+    };
+    let result = query_exec.exec(&run_query(QueryResultType::Term, Some(mf_options))).await;
+    assert!(result.term_rows.is_empty());
+
+    // references, filtered by evidence code and publication year
+    let iss_options = ReverseQueryOptions {
+        evidence_codes: HashSet::from(["ISS".into()]),
+        ..ReverseQueryOptions::default()
+    };
+    let result = query_exec.exec(&run_query(QueryResultType::Reference,
+                                            Some(iss_options.clone()))).await;
+    let refs = result.reference_rows.iter().map(|r| r.uniquename.as_ref()).collect::<Vec<_>>();
+    assert_eq!(refs, vec!["PB_REF:0000001"]);
+    assert_eq!(result.total_count, 1);
+    assert!(result.term_rows.is_empty());
+
+    // the same terms are found with annotation filters that match everything
+    let iss_term_result = query_exec.exec(&run_query(QueryResultType::Term,
+                                                     Some(iss_options.clone()))).await;
+    assert!(iss_term_result.term_rows.iter().any(|term| term.termid == "GO:0044237"));
+
+    let old_options = ReverseQueryOptions {
+        max_year: Some(2005),
+        ..iss_options.clone()
+    };
+    let result = query_exec.exec(&run_query(QueryResultType::Reference, Some(old_options))).await;
+    assert_eq!(result.reference_rows.len(), 1);
+
+    let recent_options = ReverseQueryOptions {
+        min_year: Some(2010),
+        ..iss_options.clone()
+    };
+    let result = query_exec.exec(&run_query(QueryResultType::Reference, Some(recent_options))).await;
+    assert!(result.reference_rows.is_empty());
+
+    let ida_options = ReverseQueryOptions {
+        evidence_codes: HashSet::from(["IDA".into()]),
+        ..ReverseQueryOptions::default()
+    };
+    let result = query_exec.exec(&run_query(QueryResultType::Reference, Some(ida_options))).await;
+    assert!(result.reference_rows.is_empty());
+
+    // reverse options only apply to reference and term queries
+    let result = query_exec.exec(&run_query(QueryResultType::Gene, Some(iss_options))).await;
+    assert_eq!(result.id, "error");
+}
+
+#[test]
+fn test_reverse_query_unknown_genes() {
+    let api_data = get_api_data();
+
+    let genes: Vec<FlexStr> = vec!["SPAC27E2.05".into(), "NOT_A_GENE".into()];
+
+    let terms = terms_of_genes(&api_data, &genes, &ReverseQueryOptions::default()).unwrap();
+    assert!(terms.iter().any(|term| term.termid == "GO:0044237"));
+
+    let iss_options = ReverseQueryOptions {
+        evidence_codes: HashSet::from(["ISS".into()]),
+        ..ReverseQueryOptions::default()
+    };
+    let terms = terms_of_genes(&api_data, &genes, &iss_options).unwrap();
+    assert!(terms.iter().any(|term| term.termid == "GO:0044237"));
+
+    let refs = references_of_genes(&api_data, &genes, &iss_options).unwrap();
+    assert_eq!(refs.len(), 1);
+}
+
+#[tokio::test]
+async fn test_term_annotation_filters() {
+    let api_data = get_api_data();
+
+    let text = "term(GO:0044237, date_from=2009, evidence=ISS, throughput=high)";
+    assert_eq!(parse_query_text(text).unwrap().to_string(), text);
+
+    let invalid_query =
+        Query::new(parse_query_text("term(GO:0044237, date_to=2009-2, reference=PMID:1)").unwrap(),
+                   QueryOutputOptions::default());
+    let errors = invalid_query.validate(&api_data);
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].error_type, QueryValidationErrorType::InvalidDate);
+    assert_eq!(errors[0].path, "constraints.term.date_to");
+    assert_eq!(errors[1].error_type, QueryValidationErrorType::UnknownReference);
+
+    let query_exec = QueryExec::new(api_data, None);
+
+    let gene_ids = |result: &QueryAPIResult| {
+        let mut ids = result.rows.iter()
+            .map(|row| row.gene_uniquename.to_string())
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids
+    };
+
+    let make_query = |text: &str, result_type: QueryResultType| {
+        let opts = QueryOutputOptions {
+            result_type,
+            ..QueryOutputOptions::default()
+        };
+        Query::new(parse_query_text(text).unwrap(), opts)
+    };
+
+    let query = make_query("term(GO:0044237, evidence=ISS, evidence=IMP)",
+                           QueryResultType::Gene);
+    let result = query_exec.exec(&query).await;
+    assert_eq!(gene_ids(&result), vec!["SPAC27E2.05"]);
+
+    let query = make_query("term(GO:0044237, date_from=2009-02, date_to=2009, assigned_by=PomBase)",
+                           QueryResultType::Gene);
+    let result = query_exec.exec(&query).await;
+    assert_eq!(gene_ids(&result), vec!["SPAC27E2.05"]);
+
+    for text in ["term(GO:0044237, evidence=IMP)", "term(GO:0044237, date_from=2020)",
+                 "term(GO:0044237, throughput=low)"] {
+        let result = query_exec.exec(&make_query(text, QueryResultType::Gene)).await;
+        assert!(result.rows.is_empty(), "{}", text);
+    }
+
+    // genotype annotations are filtered too
+    let query = make_query("term(FYPO:0000013, evidence=\"Cell growth assay\")",
+                           QueryResultType::Genotype);
+    let result = query_exec.exec(&query).await;
+    let mut genotype_ids = result.genotype_rows.iter()
+        .map(|row| row.genotype_uniquename.as_ref())
+        .collect::<Vec<_>>();
+    genotype_ids.sort();
+    assert_eq!(genotype_ids, vec!["d6c914796c35e3b5-genotype-4", "e674fe7ceba478aa-genotype-2"]);
+
+    let query = make_query("term(FYPO:0000013, locus=single, date_to=2008)",
+                           QueryResultType::Gene);
+    let result = query_exec.exec(&query).await;
+    assert!(result.rows.is_empty());
+}
+
+#[test]
+fn test_annotation_date_filters() {
+    let api_data = get_api_data();
+    let details = api_data.termid_annotation_details(&"GO:0044237".into());
+    let mut detail = details[0].as_ref().clone();
+
+    let term_node = |text: &str| parse_query_text(text).unwrap().term.unwrap();
+
+    // partial dates are compared at the precision of the shorter date
+    detail.date = Some("2009".into());
+    assert!(term_node("term(GO:0044237, date_from=2009-02-01)").annotation_matches(&detail));
+    assert!(term_node("term(GO:0044237, date_to=2009-02-01)").annotation_matches(&detail));
+    assert!(!term_node("term(GO:0044237, date_from=2010-01)").annotation_matches(&detail));
+    assert!(!term_node("term(GO:0044237, date_to=2008)").annotation_matches(&detail));
+
+    // malformed dates don't match
+    detail.date = Some("20\u{e9}9-02-13".into());
+    assert!(!term_node("term(GO:0044237, date_from=2009-01)").annotation_matches(&detail));
+}
+
+#[tokio::test]
+async fn test_extension_query() {
+    let api_data = get_api_data();
+
+    let text = "extension(GO:0004674, has_direct_input, gene=SPBC646.13)";
+    let node = parse_query_text(text).unwrap();
+    let extension = node.extension.as_ref().unwrap();
+    assert_eq!(extension.rel_type_name, "has_direct_input");
+    assert_eq!(extension.range, Some(ExtensionRangeFilter::Gene("SPBC646.13".into())));
+    assert_eq!(node.to_string(), text);
+
+    assert_eq!(parse_query_text("extension(GO:0004674, part_of, gene=a, term=b)").unwrap_err(),
+               "extension() takes only one of gene, term or residue at position 0");
+
+    let invalid_query =
+        Query::new(parse_query_text("extension(GO:0004674, part_of, term=GO:9999999)").unwrap(),
+                   QueryOutputOptions::default());
+    let errors = invalid_query.validate(&api_data);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].error_type, QueryValidationErrorType::UnknownTerm);
+    assert_eq!(errors[0].path, "constraints.extension.range");
+
+    // the extension genes aren't in the test gene summaries, so execute the
+    // nodes directly rather than through the validating QueryExec
+    let matching_texts = [
+        "extension(GO:0004674, has_direct_input)",
+        "extension(GO:0004674, has_direct_input, gene=SPBC646.13)",
+        "extension(GO:0004674, happens_during, term=GO:0000089)",
+        "extension(GO:0004674, directly_negatively_regulates, gene=SPAC144.13c)",
+        // the range GO:0000080 is an unannotated descendant of these terms
+        "extension(GO:0004674, happens_during, term=GO:0051318)",
+        "extension(GO:0004674, happens_during, term=GO:0022403)",
+    ];
+
+    for text in matching_texts {
+        let node = parse_query_text(text).unwrap();
+        let genes = node.exec(&api_data, &None, None, QueryResultType::Gene).await.unwrap();
+        assert_eq!(genes, vec!["SPBC11B10.09"], "{}", text);
+    }
+
+    let non_matching_texts = [
+        "extension(GO:0004674, has_direct_input, gene=SPAC144.13c)",
+        "extension(GO:0004674, happens_during, term=GO:0088888)",
+        "extension(GO:0004674, has_direct_input, residue=S123)",
+        "extension(GO:0044237, has_direct_input)",
+    ];
+
+    for text in non_matching_texts {
+        let node = parse_query_text(text).unwrap();
+        let genes = node.exec(&api_data, &None, None, QueryResultType::Gene).await.unwrap();
+        assert!(genes.is_empty(), "{}", text);
+    }
+}
+
+#[tokio::test]
+async fn test_gene_ex_range() {
+    let api_data = get_api_data_with(|config, maps| {
+        config.gene_expression.datasets.push(GeneExDatasetConfig {
+            name: "test_dataset".into(),
+            pubmed_id: "PMID:123".into(),
+            level_type_termid: "PBO:0000001".into(),
+            during_termid: "GO:0072690".into(),
+            scale: "single_cell".into(),
+        });
+
+        let values = [("SPAC19G12.04", "1.5"), ("SPAC1805.15c", "20"),
+                      ("SPAC27E2.05", "300"), ("SPAC24B11.06c", "4000")];
+        for (gene_uniquename, avg_copies_per_cell) in values {
+            let measurement = GeneExMeasurement {
+                reference_uniquename: "PMID:123".into(),
+                level_type_termid: "PBO:0000001".into(),
+                during_termid: "GO:0072690".into(),
+                copies_per_cell: None,
+                avg_copies_per_cell: Some(avg_copies_per_cell.into()),
+                scale: "single_cell".into(),
+            };
+            maps.gene_expression_measurements.entry(gene_uniquename.into())
+                .or_default()
+                .insert("test_dataset".into(), measurement);
+        }
+    });
+
+    let texts = [
+        "gene_ex_range(test_dataset, 10..)",
+        "gene_ex_range(test_dataset, quantile=fourth_quartile)",
+        "gene_ex_range(test_dataset, 1..100, quantile=first_quartile)",
+    ];
+    for text in texts {
+        assert_eq!(parse_query_text(text).unwrap().to_string(), text);
+    }
+
+    let invalid_query = Query::new(parse_query_text("gene_ex_range(no_such_dataset, 10..)").unwrap(),
+                                   QueryOutputOptions::default());
+    let errors = invalid_query.validate(&api_data);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].error_type, QueryValidationErrorType::UnknownGeneExDataset);
+
+    let query_exec = QueryExec::new(api_data, None);
+
+    let run_query = |text: &str| {
+        Query::new(parse_query_text(text).unwrap(), QueryOutputOptions::default())
+    };
+
+    let gene_ids = |result: &QueryAPIResult| {
+        let mut ids = result.rows.iter()
+            .map(|row| row.gene_uniquename.to_string())
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids
+    };
+
+    let query = run_query("gene_ex_range(test_dataset, 10..1000)");
+    let result = query_exec.exec(&query).await;
+    assert_eq!(gene_ids(&result), vec!["SPAC1805.15c", "SPAC27E2.05"]);
+
+    let query = run_query("gene_ex_range(test_dataset, quantile=fourth_quartile)");
+    let result = query_exec.exec(&query).await;
+    assert_eq!(gene_ids(&result), vec!["SPAC24B11.06c"]);
+
+    let query = run_query("gene_ex_range(test_dataset, quantile=first_quartile) OR \
+                           gene_ex_range(test_dataset, quantile=second_quartile)");
+    let result = query_exec.exec(&query).await;
+    assert_eq!(gene_ids(&result), vec!["SPAC1805.15c", "SPAC19G12.04"]);
+
+    // highly expressed genes that aren't in a list
+    let query = run_query("gene_ex_range(test_dataset, quantile=third_quartile) AND \
+                           NOT genes(SPAC27E2.05)");
+    let result = query_exec.exec(&query).await;
+    assert!(result.rows.is_empty());
+}
+
+#[tokio::test]
+async fn test_domain_and_protein_feature() {
+    let api_data = get_api_data_with(|_, maps| {
+        let kinase_data = maps.gene_query_data_map.get_mut("SPAC19G12.04").unwrap();
+        kinase_data.domain_matches.push(GeneQueryDomainMatch {
+            id: "PF00069".into(),
+            dbname: "PFAM".into(),
+            interpro_id: Some("IPR000719".into()),
+            locations: vec![(10, 150)],
+        });
+        kinase_data.protein_features.push(GeneQueryProteinFeature {
+            feature_type: ProteinFeatureType::ActiveSite,
+            start: 120,
+            end: 120,
+        });
+
+        let other_data = maps.gene_query_data_map.get_mut("SPAC27E2.05").unwrap();
+        other_data.domain_matches.push(GeneQueryDomainMatch {
+            id: "PF00069".into(),
+            dbname: "PFAM".into(),
+            interpro_id: None,
+            locations: vec![(250, 400)],
+        });
+        other_data.protein_features.push(GeneQueryProteinFeature {
+            feature_type: ProteinFeatureType::SignalPeptide,
+            start: 1,
+            end: 22,
+        });
+    });
+
+    for text in ["domain(IPR000719, 1..200)", "protein_feature(signal_peptide)",
+                 "protein_feature(active_site, ..200)"] {
+        assert_eq!(parse_query_text(text).unwrap().to_string(), text);
+    }
+    assert_eq!(parse_query_text("protein_feature(helix)").unwrap_err(),
+               "unknown protein feature type: helix at position 0");
+
+    let query_exec = QueryExec::new(api_data, None);
+
+    let gene_ids = |result: &QueryAPIResult| {
+        let mut ids = result.rows.iter()
+            .map(|row| row.gene_uniquename.to_string())
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids
+    };
+
+    let expected_results = [
+        ("domain(PF00069)", vec!["SPAC19G12.04", "SPAC27E2.05"]),
+        ("domain(IPR000719)", vec!["SPAC19G12.04"]),
+        ("domain(PF00069, 1..200)", vec!["SPAC19G12.04"]),
+        ("domain(PF00069, 200..)", vec!["SPAC27E2.05"]),
+        ("domain(PF00001)", vec![]),
+        ("protein_feature(signal_peptide)", vec!["SPAC27E2.05"]),
+        ("protein_feature(active_site, 100..130)", vec!["SPAC19G12.04"]),
+        ("protein_feature(active_site, 130..)", vec![]),
+        ("protein_feature(transit_peptide)", vec![]),
+        ("domain(PF00069) AND NOT protein_feature(signal_peptide)", vec!["SPAC19G12.04"]),
+    ];
+
+    for (text, expected_genes) in expected_results {
+        let query = Query::new(parse_query_text(text).unwrap(), QueryOutputOptions::default());
+        let result = query_exec.exec(&query).await;
+        assert_eq!(gene_ids(&result), expected_genes, "{}", text);
+    }
+}
+
+#[test]
+fn test_prosite_to_regex() {
+    assert_eq!(prosite_to_regex("[ST]-x(2)-{P}-[DE].").unwrap(), "[ST].{2}[^P][DE]");
+    assert_eq!(prosite_to_regex("<M-x(1,3)-K>").unwrap(), r"^M.{1,3}K\*?$");
+    assert!(prosite_to_regex("[ST-x").is_err());
+    assert!(motif_regex("S[KR").is_err());
+    assert!(motif_regex("").is_err());
+}
+
+#[test]
+fn test_motif_regex() {
+    let matches = |pattern: &str, sequence: &str| motif_regex(pattern).unwrap().is_match(sequence);
+
+    // a regular expression that ends with "." isn't a PROSITE pattern
+    assert!(matches("RR.S.", "MRRASPK"));
+    assert!(!matches("RR.S.", "MRRAS"));
+
+    // PROSITE patterns without a "-"
+    assert!(matches("<x(2).", "MAKQ"));
+    assert!(!matches("<x(5).", "MAKQ"));
+    assert!(matches("<M.", "MAKQ"));
+    assert!(!matches("<K.", "MAKQ"));
+    assert!(!matches("{MAKQ}.", "MAKQ"));
+
+    // only bare "x" residues match any residue
+    assert!(matches("KxS", "MKASD"));
+    assert!(matches(r"K\x41S", "MKASD"));
+    assert!(!matches(r"K\x41S", "MKDSD"));
+    assert!(matches("K[^x]S", "MKASD"));
+    assert!(!matches("K[^x]S", "MKXSD"));
+}
+
+#[tokio::test]
+async fn test_motif_search() {
+    let api_data = get_api_data_with(|_, maps| {
+        let sequences = [("SPAC19G12.04", "MSKPTSPRSPLLDE*"), ("SPAC27E2.05", "MATSPKQDE*"),
+                         ("SPAC1805.15c", "MGGGGGG*")];
+        for (gene_uniquename, sequence) in sequences {
+            let gene_summary = maps.gene_summaries.get_mut(gene_uniquename).unwrap();
+            gene_summary.transcripts = vec![make_test_protein_transcript(gene_uniquename, sequence)];
+        }
+    });
+
+    let result = motif_search(&api_data, "all", "SP[KR]", 10, DEFAULT_MAX_MATCHES_PER_GENE);
+    assert_eq!(result.status, "OK");
+    assert!(!result.too_many_genes);
+    assert_eq!(result.peptide_matches.len(), 2);
+    let first = &result.peptide_matches[0];
+    assert_eq!(first.peptide_id, "SPAC19G12.04.1:pep");
+    assert_eq!(first.gene_id, "SPAC19G12.04");
+    assert_eq!(first.matches, vec![
+        MotifMatch { start: 6, end: 8, match_residues: "SPR".into() },
+    ]);
+    assert_eq!(result.peptide_matches[1].matches[0].start, 4);
+
+    // PROSITE style, with a per-gene match limit
+    let result = motif_search(&api_data, "all", "s-P-x.", 10, 1);
+    assert_eq!(result.peptide_matches[0].matches.len(), 1);
+    let result = motif_search(&api_data, "all", "s-P-x.", 10, DEFAULT_MAX_MATCHES_PER_GENE);
+    assert_eq!(result.peptide_matches[0].matches.len(), 2);
+
+    // only the first gene has match details
+    let result = motif_search(&api_data, "all", "SP", 1, DEFAULT_MAX_MATCHES_PER_GENE);
+    assert!(result.too_many_genes);
+    assert_eq!(result.peptide_matches.len(), 2);
+    assert!(result.peptide_matches[1].matches.is_empty());
+
+    let result = motif_search(&api_data, "all", "<M-x-T", 10, DEFAULT_MAX_MATCHES_PER_GENE);
+    let genes = result.peptide_matches.iter().map(|m| m.gene_id.as_ref()).collect::<Vec<_>>();
+    assert_eq!(genes, vec!["SPAC27E2.05"]);
+
+    let result = motif_search(&api_data, "all", "[KR", 10, DEFAULT_MAX_MATCHES_PER_GENE);
+    assert!(result.status.starts_with("invalid motif pattern"));
+
+    // as a query node
+    let text = "motif(\"[ST]-P-x(2)-[DE]\")";
+    assert_eq!(parse_query_text(text).unwrap().to_string(), text);
+
+    let invalid_query = Query::new(parse_query_text("motif(\"S(\")").unwrap(),
+                                   QueryOutputOptions::default());
+    let errors = invalid_query.validate(&api_data);
+    assert_eq!(errors[0].error_type, QueryValidationErrorType::InvalidMotif);
+
+    let query_exec = QueryExec::new(api_data, None);
+    let query = Query::new(parse_query_text("motif(SP) AND NOT motif(\"L-L-D-E\")").unwrap(),
+                           QueryOutputOptions::default());
+    let result = query_exec.exec(&query).await;
+    let genes = result.rows.iter().map(|row| row.gene_uniquename.as_ref()).collect::<Vec<_>>();
+    assert_eq!(genes, vec!["SPAC27E2.05"]);
+}
+
+#[test]
+fn test_axis_ticks() {
+    assert_eq!(axis_ticks(0.0, 1000.0, 6), vec![0.0, 200.0, 400.0, 600.0, 800.0, 1000.0]);
+    assert_eq!(axis_ticks(0.0, 9.0, 6), vec![0.0, 2.0, 4.0, 6.0, 8.0, 10.0]);
+    assert_eq!(axis_ticks(-0.3, 3.7, 6), vec![-1.0, 0.0, 1.0, 2.0, 3.0, 4.0]);
+    assert!(axis_ticks(f64::NAN, 1.0, 6).is_empty());
+}
+
+#[test]
+fn test_stats_plots() {
+    let api_data = get_api_data_with(|config, maps| {
+        config.gene_expression.datasets.push(GeneExDatasetConfig {
+            name: "test_dataset".into(),
+            pubmed_id: "PMID:123".into(),
+            level_type_termid: "PBO:0000001".into(),
+            during_termid: "GO:0072690".into(),
+            scale: "single_cell".into(),
+        });
+
+        let values = [("SPAC19G12.04", "1.5"), ("SPAC1805.15c", "20"),
+                      ("SPAC27E2.05", "300")];
+        for (gene_uniquename, avg_copies_per_cell) in values {
+            let measurement = GeneExMeasurement {
+                reference_uniquename: "PMID:123".into(),
+                level_type_termid: "PBO:0000001".into(),
+                during_termid: "GO:0072690".into(),
+                copies_per_cell: None,
+                avg_copies_per_cell: Some(avg_copies_per_cell.into()),
+                scale: "single_cell".into(),
+            };
+            maps.gene_expression_measurements.entry(gene_uniquename.into())
+                .or_default()
+                .insert("test_dataset".into(), measurement);
+        }
+
+        maps.detailed_stats.cumulative_curated_by_year = StatsIntegerTable {
+            header: vec!["date".to_owned(), "curatable".to_owned(),
+                         "community_curated".to_owned(), "admin_curated".to_owned()],
+            data: vec![("2020".to_owned(), vec![10, 2, 3]),
+                       ("2021".to_owned(), vec![25, 6, 7])],
+        };
+    });
+
+    let stats_plots = StatsPlots::new(api_data.get_config());
+
+    let plot = stats_plots.gene_ex_violin_plot(&api_data, "small", "SPAC27E2.05").unwrap();
+    let svg = String::from_utf8(plot.bytes.to_vec()).unwrap();
+    assert!(svg.starts_with("<svg "));
+    assert!(svg.trim_end().ends_with("</svg>"));
+    assert!(svg.contains("test_dataset"));
+    assert!(svg.contains("<circle "));
+
+    assert!(stats_plots.gene_ex_violin_plot(&api_data, "huge", "SPAC27E2.05").is_err());
+    assert!(stats_plots.gene_ex_violin_plot(&api_data, "small", "no_such_gene").is_err());
+
+    let plot = stats_plots.get_svg_graph(&api_data, "cumulative_curated_by_year").unwrap();
+    let svg = String::from_utf8(plot.bytes.to_vec()).unwrap();
+    assert_eq!(svg.matches("<polyline ").count(), 3);
+    assert!(svg.contains("community curated"));
+    assert!(svg.contains(">2021</text>"));
+
+    // empty stats still give a valid plot
+    let plot = stats_plots.get_svg_graph(&api_data, "community_response_rates").unwrap();
+    assert!(plot.bytes.starts_with(b"<svg "));
+
+    assert!(stats_plots.get_svg_graph(&api_data, "no_such_graph").is_err());
+}
+
+#[test]
+fn test_gene_complete() {
+    let api_data = get_api_data_with(|_, maps| {
+        for gene_summary in maps.gene_summaries.values_mut() {
+            gene_summary.taxonid = 4896;
+        }
+
+        let mut cdc2 = maps.gene_summaries["SPAC27E2.05"].clone();
+        cdc2.uniquename = "SPBC11B10.09".into();
+        cdc2.name = Some("cdc2".into());
+        cdc2.exact_synonyms = vec!["cdk1".into()];
+        cdc2.uniprot_identifier = Some("P04551".into());
+        maps.gene_name_gene_map.insert("cdc2".into(), "SPBC11B10.09".into());
+        maps.gene_summaries.insert("SPBC11B10.09".into(), cdc2);
+
+        let mut cdc28 = maps.gene_summaries["SPAC27E2.05"].clone();
+        cdc28.uniquename = "YBR160W".into();
+        cdc28.name = Some("CDC28".into());
+        cdc28.exact_synonyms = vec!["CDK1".into()];
+        cdc28.uniprot_identifier = Some("P00546".into());
+        cdc28.secondary_identifier = Some("S000000364".into());
+        cdc28.taxonid = 4932;
+        maps.gene_name_gene_map.insert("CDC28".into(), "YBR160W".into());
+        maps.gene_summaries.insert("YBR160W".into(), cdc28);
+    });
+
+    let completions = |q: &str, taxonid: Option<u32>| {
+        api_data.gene_complete(q, taxonid, 10).into_iter()
+            .map(|completion| (completion.uniquename.to_string(), completion.matched_field))
+            .collect::<Vec<_>>()
+    };
+
+    // an exact name match comes before a longer name with the same prefix
+    assert_eq!(completions("CDC2", None),
+               vec![("SPBC11B10.09".to_owned(), GeneCompletionField::Name),
+                    ("YBR160W".to_owned(), GeneCompletionField::Name)]);
+    assert_eq!(completions("cdc2", Some(4932)),
+               vec![("YBR160W".to_owned(), GeneCompletionField::Name)]);
+    assert_eq!(completions("cdk", Some(4896)),
+               vec![("SPBC11B10.09".to_owned(), GeneCompletionField::Synonym)]);
+    assert_eq!(completions("spbc11b", None),
+               vec![("SPBC11B10.09".to_owned(), GeneCompletionField::Uniquename)]);
+    assert_eq!(completions("P0455", None),
+               vec![("SPBC11B10.09".to_owned(), GeneCompletionField::UniprotIdentifier)]);
+    assert_eq!(completions("S0000003", None),
+               vec![("YBR160W".to_owned(), GeneCompletionField::SecondaryIdentifier)]);
+    assert!(completions("  ", None).is_empty());
+    assert!(completions("no_such_gene", None).is_empty());
+
+    let cdc2_matches = api_data.gene_complete("cdc2", None, 1);
+    assert_eq!(cdc2_matches.len(), 1);
+    assert_eq!(cdc2_matches[0].matched_value.as_ref(), "cdc2");
+    assert_eq!(cdc2_matches[0].taxonid, 4896);
+}
+
+#[test]
+fn test_map_gene_ids() {
+    let api_data = get_api_data_with(|_, maps| {
+        let mut cdc2 = maps.gene_summaries["SPAC27E2.05"].clone();
+        cdc2.uniquename = "SPBC11B10.09".into();
+        cdc2.name = Some("cdc2".into());
+        cdc2.exact_synonyms = vec!["cdk1".into()];
+        cdc2.other_synonyms = vec!["SPBC11B10.09-old".into()];
+        cdc2.uniprot_identifier = Some("P04551".into());
+        cdc2.dbxrefs = ["SPD:26-D10".into(), "EC:2.7.11.22".into()].into_iter().collect();
+        maps.gene_name_gene_map.insert("cdc2".into(), "SPBC11B10.09".into());
+        maps.gene_summaries.insert("SPBC11B10.09".into(), cdc2);
+
+        let mut cdc13 = maps.gene_summaries["SPAC27E2.05"].clone();
+        cdc13.uniquename = "SPBC582.03".into();
+        cdc13.name = Some("cdc13".into());
+        cdc13.exact_synonyms = vec!["cdk1".into(), "cdc2".into()];
+        cdc13.other_synonyms = vec![];
+        cdc13.dbxrefs = Default::default();
+        maps.gene_name_gene_map.insert("cdc13".into(), "SPBC582.03".into());
+        maps.gene_summaries.insert("SPBC582.03".into(), cdc13);
+    });
+
+    let results = api_data.map_gene_ids(&["spbc11b10.09", "CDC2", "cdk1", "", "P04551",
+                                          "26-D10", "2.7.11.22", "SPBC11B10.09-old",
+                                          "no_such_gene"]);
+
+    assert_eq!(results.len(), 8);
+
+    let summary = results.iter()
+        .map(|result| {
+            (result.input.as_ref(), result.status,
+             result.best_match.as_ref().map(|uniquename| uniquename.as_ref()),
+             result.matches.iter().map(|id_match| id_match.match_type).collect::<Vec<_>>())
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(summary, vec![
+        ("spbc11b10.09", IdMappingStatus::Matched, Some("SPBC11B10.09"),
+         vec![IdMatchType::SystematicId]),
+        // a current name of one gene and a synonym of another
+        ("CDC2", IdMappingStatus::Ambiguous, Some("SPBC11B10.09"),
+         vec![IdMatchType::Name, IdMatchType::Synonym]),
+        // a synonym of two genes
+        ("cdk1", IdMappingStatus::Ambiguous, None,
+         vec![IdMatchType::Synonym, IdMatchType::Synonym]),
+        ("P04551", IdMappingStatus::Matched, Some("SPBC11B10.09"),
+         vec![IdMatchType::UniprotIdentifier]),
+        ("26-D10", IdMappingStatus::Matched, Some("SPBC11B10.09"),
+         vec![IdMatchType::OrfeomeIdentifier]),
+        ("2.7.11.22", IdMappingStatus::Matched, Some("SPBC11B10.09"),
+         vec![IdMatchType::Dbxref]),
+        ("SPBC11B10.09-old", IdMappingStatus::Matched, Some("SPBC11B10.09"),
+         vec![IdMatchType::OtherSynonym]),
+        ("no_such_gene", IdMappingStatus::Unmatched, None, vec![]),
+    ]);
+
+    assert_eq!(results[1].matches[1].gene_uniquename.as_ref(), "SPBC582.03");
+    assert_eq!(results[1].matches[1].matched_id.as_ref(), "cdc2");
+}
+
+#[test]
+fn test_details_batch() {
+    // the batch doesn't depend on the caches, which are turned off here
+    let api_data = get_api_data_with(|config, _| {
+        config.server.maps_cache_sizes.genes = 0;
+        config.server.maps_cache_sizes.terms = 0;
+    });
+
+    let genes = api_data.get_full_gene_details_batch(&["SPBC11B10.09", "SPAC144.13c",
+                                                       "SPBC11B10.09", "no_such_gene"]);
+    assert_eq!(genes.len(), 2);
+    assert_eq!(genes["SPBC11B10.09"].uniquename.as_ref(), "SPBC11B10.09");
+    assert!(genes.contains_key("SPAC144.13c"));
+    assert!(!genes.contains_key("no_such_gene"));
+
+    let gene_stats = api_data.maps_database_cache_stats().genes;
+    assert_eq!(gene_stats.entry_count, 0);
+    assert_eq!(gene_stats.hits, 0);
+
+    let terms = api_data.get_term_details_batch(&["GO:0044237"]);
+    assert_eq!(terms["GO:0044237"].termid.as_ref(), "GO:0044237");
+
+    // the annotation details and term map are read for the whole batch
+    let term = &terms["GO:0044237"];
+    assert!(term.annotation_details.contains_key(&10000));
+    assert!(term.terms_by_termid.values().all(Option::is_some));
+
+    // nothing to read
+    assert!(api_data.get_reference_details_batch(&[] as &[&str]).is_empty());
+}
+
+#[test]
+fn test_maps_database_cache_stats() {
+    let api_data = get_api_data_with(|config, _| {
+        config.server.maps_cache_sizes.genes = 1;
+        config.server.maps_cache_sizes.terms = 0;
+    });
+
+    // an in-memory database can't be shared between connections
+    assert_eq!(api_data.maps_database_cache_stats().connection_count, 1);
+
+    assert!(api_data.get_full_gene_details("SPBC11B10.09").is_some());
+    assert!(api_data.get_full_gene_details("SPBC11B10.09").is_some());
+
+    let gene_stats = api_data.maps_database_cache_stats().genes;
+    assert_eq!(gene_stats.max_entries, 1);
+    assert_eq!(gene_stats.entry_count, 1);
+    assert!(gene_stats.hits >= 1);
+    assert_eq!(gene_stats.evictions, 0);
+
+    assert!(api_data.get_full_gene_details("SPAC144.13c").is_some());
+
+    let gene_stats = api_data.maps_database_cache_stats().genes;
+    assert_eq!(gene_stats.entry_count, 1);
+    assert!(gene_stats.evictions >= 1);
+
+    // the term cache is turned off but lookups still work
+    assert!(api_data.get_term_details("GO:0044237").is_some());
+    let term_stats = api_data.maps_database_cache_stats().terms;
+    assert_eq!(term_stats.entry_count, 0);
+    assert!(term_stats.misses >= 1);
+}
+
+#[test]
+fn test_termid_annotation_details() {
+    let api_data = get_api_data();
+
+    let details = api_data.termid_annotation_details(&"GO:0044237".into());
+    assert!(details.iter().any(|detail| detail.id == 10000));
+
+    let stats = api_data.maps_database_cache_stats().annotation_details;
+    assert_eq!(stats.entry_count, details.len());
+
+    // the second time the details come from the cache
+    let details_again = api_data.termid_annotation_details(&"GO:0044237".into());
+    assert_eq!(details_again.len(), details.len());
+    let stats_again = api_data.maps_database_cache_stats().annotation_details;
+    assert_eq!(stats_again.misses, stats.misses);
+    assert_eq!(stats_again.hits, stats.hits + details.len() as u64);
+
+    assert!(api_data.termid_annotation_details(&"GO:0000000".into()).is_empty());
+}
@@ -0,0 +1,211 @@
+extern crate pombase;
+
+use std::path::PathBuf;
+
+use serde_json::json;
+
+use self::pombase::api::embedded_search::{EmbeddedSearch, EmbeddedSearchDoc};
+use self::pombase::api::search::{Search, SolrSearchScope};
+use self::pombase::api::search_index::{highlight, tokenize, PrefixMatch};
+use self::pombase::data_types::{SolrTermSummary, SolrReferenceSummary, SolrAlleleSummary};
+use self::pombase::web::config::{Config, SearchBackend};
+
+fn get_test_config() -> Config {
+    let mut config_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    config_path.push("tests/test_config.json");
+    Config::read(config_path.to_str().expect("config"))
+}
+
+fn make_term(id: &str, name: &str, definition: &str, annotation_count: usize,
+             interesting_parent_ids: &[&str], secondary_identifiers: &[&str])
+             -> SolrTermSummary
+{
+    serde_json::from_value(json!({
+        "id": id,
+        "name": name,
+        "cv_name": "biological_process",
+        "definition": definition,
+        "close_synonym_words": "",
+        "distant_synonym_words": "",
+        "interesting_parent_ids": interesting_parent_ids,
+        "secondary_identifiers": secondary_identifiers,
+        "annotation_count": annotation_count,
+        "gene_count": annotation_count,
+        "genotype_count": 0,
+    })).unwrap()
+}
+
+fn make_reference(id: &str, title: &str, authors: &str, year: u32) -> SolrReferenceSummary {
+    serde_json::from_value(json!({
+        "id": id,
+        "title": title,
+        "authors": authors,
+        "publication_year": year,
+        "gene_count": 1,
+        "genotype_count": 0,
+        "annotation_count": 3,
+        "canto_curator_role": "community",
+    })).unwrap()
+}
+
+fn make_allele(id: &str, name: &str, allele_type: &str, gene_name: &str,
+               gene_uniquename: &str) -> SolrAlleleSummary
+{
+    serde_json::from_value(json!({
+        "id": id,
+        "name": name,
+        "allele_type": allele_type,
+        "gene_name": gene_name,
+        "gene_uniquename": gene_uniquename,
+    })).unwrap()
+}
+
+fn make_embedded_search(config: &Config) -> EmbeddedSearch {
+    let terms = vec![
+        make_term("GO:0007049", "cell cycle", "The progression of biochemical and morphological phases.",
+                  120, &[], &[]),
+        make_term("GO:0000278", "mitotic cell cycle", "Progression through the phases of the mitotic cell cycle.",
+                  80, &["GO:0007049"], &["GO:0007067"]),
+        make_term("GO:0051301", "cell division", "The process resulting in division of a cell.",
+                  40, &[], &[]),
+        make_term("GO:0044770", "cell cycle phase transition", "The cell cycle process by which a cell transitions.",
+                  0, &["GO:0007049"], &[]),
+    ];
+
+    let references = vec![
+        make_reference("PMID:12345678", "Fission yeast cell cycle control", "Nurse P", 1990),
+        make_reference("PMID:23456789", "Meiotic recombination hotspots", "Smith G, Jones K", 2020),
+    ];
+
+    let alleles = vec![
+        make_allele("SPBC11B10.09:allele-1", "cdc2Δ", "deletion", "cdc2", "SPBC11B10.09"),
+        make_allele("SPBC11B10.09:allele-2", "cdc2-33", "unknown", "cdc2", "SPBC11B10.09"),
+        make_allele("SPAC1F3.06c:allele-1", "spo15-1", "unknown", "spo15", "SPAC1F3.06c"),
+    ];
+
+    let docs = vec![
+        EmbeddedSearchDoc {
+            id: "documentation/gene-page-phenotypes".to_owned(),
+            heading: "Gene page: Phenotypes".to_owned(),
+            content: "Phenotype annotations are displayed for single and multi allele genotypes.".to_owned(),
+        },
+        EmbeddedSearchDoc {
+            id: "documentation/advanced-search".to_owned(),
+            heading: "Advanced search".to_owned(),
+            content: "The advanced search finds genes using queries.".to_owned(),
+        },
+    ];
+
+    EmbeddedSearch::new(&config.server, terms, references, alleles, docs)
+}
+
+#[test]
+fn test_tokenize_and_highlight() {
+    assert_eq!(tokenize("Cell-cycle (G2/M) cdc2_delta"),
+               vec!["cell", "cycle", "g2", "m", "cdc2_delta"]);
+
+    let words = vec!["cell".to_owned(), "cyc".to_owned()];
+    assert_eq!(highlight("Mitotic cell cycle", &words, PrefixMatch::LastWord),
+               Some("Mitotic <em>cell</em> <em>cycle</em>".to_owned()));
+    assert_eq!(highlight("Mitotic cell cycle", &words, PrefixMatch::None),
+               Some("Mitotic <em>cell</em> cycle".to_owned()));
+    assert_eq!(highlight("meiosis", &words, PrefixMatch::LastWord), None);
+}
+
+#[test]
+fn test_embedded_term_search() {
+    let config = get_test_config();
+    let search = make_embedded_search(&config);
+
+    let ids = |terms: Vec<SolrTermSummary>| {
+        terms.into_iter().map(|term| term.id.to_string()).collect::<Vec<_>>()
+    };
+
+    let results = search.term_complete("biological_process", "cell cyc");
+    // terms with no annotations are not returned
+    assert_eq!(ids(results.clone()), vec!["GO:0007049", "GO:0000278", "GO:0051301"]);
+    assert_eq!(results[0].highlighting["name"], vec!["<em>cell</em> <em>cycle</em>"]);
+
+    // exact name matches come first
+    let results = search.term_complete("biological_process", "cell division");
+    assert_eq!(ids(results)[0], "GO:0051301");
+
+    assert_eq!(ids(search.term_complete("[GO:0007049]", "cycle")),
+               vec!["GO:0007049", "GO:0000278"]);
+    assert!(search.term_complete("molecular_function", "cycle").is_empty());
+
+    // secondary IDs
+    assert_eq!(ids(search.term_complete("biological_process", "GO:0007067")),
+               vec!["GO:0000278"]);
+
+    assert_eq!(search.term_summary_by_id("GO:0051301").unwrap().name.as_ref(), "cell division");
+    assert!(search.term_summary_by_id("GO:0007067").is_none());
+}
+
+#[test]
+fn test_embedded_ref_allele_and_doc_search() {
+    let config = get_test_config();
+    let search = make_embedded_search(&config);
+
+    let ref_ids = |q: &str| {
+        search.search_refs(q).into_iter().map(|reference| reference.id.to_string())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(ref_ids("12345678"), vec!["PMID:12345678"]);
+    assert_eq!(ref_ids("PMID:23456789"), vec!["PMID:23456789"]);
+    assert_eq!(ref_ids("meiotic recomb"), vec!["PMID:23456789"]);
+    assert_eq!(ref_ids("nurse"), vec!["PMID:12345678"]);
+    assert_eq!(ref_ids("2020"), vec!["PMID:23456789"]);
+
+    let refs = search.search_refs("fission yeast");
+    assert_eq!(refs[0].highlighting["title"], vec!["<em>Fission</em> <em>yeast</em> cell cycle control"]);
+
+    let allele_names = |q: &str| {
+        search.search_alleles(q).into_iter()
+            .map(|allele| allele.name.unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(allele_names("cdc2Δ")[0], "cdc2Δ");
+    assert_eq!(allele_names("cdc2-33")[0], "cdc2-33");
+    assert_eq!(allele_names("spo15"), vec!["spo15-1"]);
+    assert!(allele_names("deletion").contains(&"cdc2Δ".to_owned()));
+
+    let docs = search.search_docs("phenot");
+    assert_eq!(docs.len(), 1);
+    assert_eq!(docs[0].id, "documentation/gene-page-phenotypes");
+    assert_eq!(docs[0].hl["heading"], vec!["Gene page: <em>Phenotypes</em>"]);
+
+    assert!(search.search_docs("").is_empty());
+}
+
+#[tokio::test]
+async fn test_search_with_embedded_backend() {
+    let config = get_test_config();
+    let embedded_search = make_embedded_search(&config);
+    let search = Search::new_with_embedded_search(&config, Some(embedded_search));
+
+    let result = search.solr_search(&SolrSearchScope::Term, "division").await.unwrap();
+    assert_eq!(result.term_matches.len(), 1);
+    assert!(result.ref_matches.is_empty());
+
+    let result = search.solr_search(&SolrSearchScope::Documentation, "advanced").await.unwrap();
+    assert_eq!(result.doc_matches[0].id, "documentation/advanced-search");
+
+    let alleles = search.allele_complete("cdc2").await.unwrap();
+    assert_eq!(alleles.len(), 2);
+}
+
+#[test]
+fn test_embedded_search_config_errors() {
+    let mut config = get_test_config();
+    config.server.search_backend = SearchBackend::Embedded;
+
+    config.server.embedded_search_data_dir = None;
+    assert!(Search::new(&config).is_err());
+
+    config.server.embedded_search_data_dir = Some("/no/such/directory".into());
+    let err = Search::new(&config).err().unwrap();
+    assert!(format!("{:#}", err).contains("/no/such/directory/terms.json.gz"));
+}
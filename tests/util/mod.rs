@@ -3,10 +3,10 @@ use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use flexstr::ToSharedStr;
 
 use pombase::api_data::{APIData, api_maps_from_file};
-use pombase::data_types::{APIGenotypeAnnotation, AlleleDetails, DeletionViability, DisplayUniquenameGenotypeMap, ExpressedAllele, ExtPart, ExtRange, GeneDetails, GenotypeDetails, GenotypeLocus, IdGenotypeMap, IdOntAnnotationDetailMap, OntAnnotationDetail, Ploidiness, TermDetails, TermIdDetailsMap, Throughput, UniquenameAlleleDetailsMap, UniquenameAlleleMap, UniquenameGeneMap, UniquenameReferenceMap};
+use pombase::data_types::{APIAlleleDetails, APIMaps, ChromosomeLocation, ProteinDetails, Strand, TranscriptDetails, APIGenotypeAnnotation, AlleleDetails, DeletionViability, DisplayUniquenameGenotypeMap, ExpressedAllele, ExtPart, ExtRange, GeneDetails, GenotypeDetails, GenotypeLocus, IdGenotypeMap, IdOntAnnotationDetailMap, OntAnnotationDetail, Ploidiness, ReferenceShort, TermDetails, TermIdDetailsMap, Throughput, UniquenameAlleleDetailsMap, UniquenameAlleleMap, UniquenameGeneMap, UniquenameReferenceMap};
 use pombase::types::TermId;
 use pombase::utils::{make_maps_database_tables, store_maps_into_database};
-use pombase::web::config::Config;
+use pombase::web::config::{Config, TermAndName};
 use rusqlite::Connection;
 
 #[allow(dead_code)]
@@ -26,13 +26,24 @@ pub fn setup_test_maps_database(mut conn: &mut Connection,
 
 #[allow(dead_code)]
 pub fn get_api_data() -> APIData {
+    get_api_data_with(|_, _| ())
+}
+
+// make the test APIData after letting the caller change the config and maps
+#[allow(dead_code)]
+pub fn get_api_data_with(update: impl FnOnce(&mut Config, &mut APIMaps)) -> APIData {
     use std::path::PathBuf;
     let mut search_maps_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
     search_maps_path.push("tests/test_search_data.json.zst");
     let mut config_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
     config_path.push("tests/test_config.json");
-    let config = Config::read(config_path.to_str().expect("config"));
-    let api_maps = api_maps_from_file(search_maps_path.to_str().expect("search maps"));
+    let mut config = Config::read(config_path.to_str().expect("config"));
+    let mut api_maps = api_maps_from_file(search_maps_path.to_str().expect("search maps"));
+    // the range of an extension but not used in annotations
+    api_maps.extension_range_ancestors.insert("GO:0000080".into(),
+                                              HashSet::from(["GO:0051318".into(),
+                                                             "GO:0022403".into()]));
+    update(&mut config, &mut api_maps);
     let mut maps_db_conn = Connection::open_in_memory().unwrap();
     let genes = get_test_genes_map();
     let genotypes = get_test_genotypes_map();
@@ -40,12 +51,47 @@ pub fn get_api_data() -> APIData {
     let terms = get_test_terms_map();
     let references = get_test_references_map();
     let annotation_details_maps = get_test_annotation_details_map();
-    let termid_genotype_annotation = HashMap::new();
+    let termid_genotype_annotation = get_test_termid_genotype_annotation();
     setup_test_maps_database(&mut maps_db_conn, &terms, &genes, &alleles, &references, &genotypes,
                              &annotation_details_maps, &termid_genotype_annotation);
     APIData::new(&config, maps_db_conn, api_maps)
 }
 
+// a transcript with just enough data for protein sequence searches
+#[allow(dead_code)]
+pub fn make_test_protein_transcript(gene_uniquename: &str, sequence: &str) -> TranscriptDetails {
+    let location = ChromosomeLocation {
+        chromosome_name: "chromosome_1".into(),
+        start_pos: 1,
+        end_pos: sequence.len() * 3,
+        strand: Strand::Forward,
+        phase: None,
+    };
+
+    TranscriptDetails {
+        uniquename: format!("{}.1", gene_uniquename).to_shared_str(),
+        name: None,
+        location,
+        parts: vec![],
+        transcript_type: "mRNA".into(),
+        protein: Some(ProteinDetails {
+            uniquename: format!("{}.1:pep", gene_uniquename).to_shared_str(),
+            sequence: sequence.into(),
+            number_of_residues: sequence.trim_end_matches('*').len(),
+            product: None,
+            molecular_weight: 0.0,
+            average_residue_weight: 0.0,
+            charge_at_ph7: 0.0,
+            isoelectric_point: 0.0,
+            codon_adaptation_index: 0.0,
+        }),
+        cds_location: None,
+        gene_uniquename: gene_uniquename.into(),
+        rna_seq_length_spliced: None,
+        rna_seq_length_unspliced: None,
+    }
+}
+
 #[warn(dead_code)]
 pub fn make_one_genotype(display_uniquename: &str, name: Option<&str>,
                      loci: Vec<GenotypeLocus>) -> GenotypeDetails {
@@ -180,12 +226,26 @@ pub fn get_test_genes_map() -> UniquenameGeneMap {
     let mut transcripts_by_uniquename = HashMap::new();
     transcripts_by_uniquename.insert("SPBC11C11.05.1".into(), None);
 
+    let mut references_by_uniquename = HashMap::new();
+    references_by_uniquename.insert("PB_REF:0000001".into(),
+                                    Some(ReferenceShort {
+                                        uniquename: "PB_REF:0000001".into(),
+                                        title: Some("Inferred from sequence similarity".into()),
+                                        citation: None,
+                                        authors_abbrev: None,
+                                        publication_year: Some("2003".into()),
+                                        approved_date: None,
+                                        gene_count: 1,
+                                        genotype_count: 0,
+                                    }));
+
     ret.insert("SPAC27E2.05".into(),
                GeneDetails {
                  deletion_viability: DeletionViability::DependsOnConditions,
                  cv_annotations,
                  transcripts: vec!["SPBC11C11.05.1".into()],
                  transcripts_by_uniquename,
+                 references_by_uniquename,
                  ..gene_spac27e2_05
                });
 
@@ -404,6 +464,8 @@ pub fn make_test_term_details(id: &str, name: &str, cv_name: &str) -> TermDetail
 }
 
 pub fn get_test_terms_map() -> TermIdDetailsMap {
+    use pombase::data_types::OntTermAnnotations;
+
     let mut ret = HashMap::new();
 
     let term_data = vec![
@@ -429,7 +491,19 @@ pub fn get_test_terms_map() -> TermIdDetailsMap {
     let term_0044237 = make_test_term_details("GO:0044237", "cellular metabolic process",
                                               "biological_process");
 
+    // the annotations used when filtering on evidence, date etc.
+    let mut cv_annotations_0044237 = HashMap::new();
+    cv_annotations_0044237.insert("biological_process".into(),
+                                  vec![OntTermAnnotations {
+                                      term: "GO:0044237".into(),
+                                      is_not: false,
+                                      rel_names: HashSet::new(),
+                                      annotations: vec![10000],
+                                      summary: None,
+                                  }]);
+
     ret.insert("GO:0044237".into(), TermDetails {
+      cv_annotations: cv_annotations_0044237,
       is_obsolete: false,
       gene_count: 10,
       genotype_count: 0,
@@ -437,9 +511,82 @@ pub fn get_test_terms_map() -> TermIdDetailsMap {
       ..term_0044237
     });
 
+    // this term is used to test querying by annotation extension
+    let term_0004674 = make_test_term_details("GO:0004674",
+                                              "protein serine/threonine kinase activity",
+                                              "molecular_function");
+    let mut cv_annotations_0004674 = HashMap::new();
+    cv_annotations_0004674.insert("molecular_function".into(),
+                                  vec![OntTermAnnotations {
+                                      term: "GO:0004674".into(),
+                                      is_not: false,
+                                      rel_names: HashSet::new(),
+                                      annotations: vec![41_717, 187_893, 193_221],
+                                      summary: None,
+                                  }]);
+    ret.insert("GO:0004674".into(), TermDetails {
+        cv_annotations: cv_annotations_0004674,
+        ..term_0004674
+    });
+
+    // these terms are used to test genotype and allele queries:
+    ret.insert("FYPO:0000013".into(),
+               make_test_term_details("FYPO:0000013", "sensitive to temperature",
+                                      "fission_yeast_phenotype"));
+    ret.insert("FYECO:0000005".into(),
+               make_test_term_details("FYECO:0000005", "high temperature",
+                                      "fission_yeast_experimental_conditions"));
+
     ret
 }
 
+fn make_test_genotype_annotation(genotype_display_uniquename: &str,
+                                 annotation_id: Option<i32>, ploidiness: Ploidiness,
+                                 conditions: Vec<(&str, &str)>,
+                                 alleles: Vec<(&str, &str)>) -> APIGenotypeAnnotation {
+    APIGenotypeAnnotation {
+        genotype_display_uniquename: genotype_display_uniquename.into(),
+        annotation_id,
+        is_multi: false,
+        ploidiness,
+        conditions: conditions.into_iter()
+            .map(|(termid, name)| TermAndName {
+                termid: termid.into(),
+                name: name.into(),
+            })
+            .collect(),
+        alleles: alleles.into_iter()
+            .map(|(allele_uniquename, gene_uniquename)| APIAlleleDetails {
+                uniquename: allele_uniquename.into(),
+                gene: gene_uniquename.into(),
+                allele_type: "amino_acid_mutation".into(),
+                expression: Some("Not assayed".into()),
+            })
+            .collect(),
+    }
+}
+
+#[allow(dead_code)]
+pub fn get_test_termid_genotype_annotation() -> HashMap<TermId, Vec<APIGenotypeAnnotation>> {
+    let annotations = vec![
+        make_test_genotype_annotation("e674fe7ceba478aa-genotype-2", Some(223_656),
+                                      Ploidiness::Haploid,
+                                      vec![("FYECO:0000005", "high temperature")],
+                                      vec![("SPBC16A3.11:allele-7", "SPBC16A3.11")]),
+        make_test_genotype_annotation("65c76fa511461156-genotype-3", None,
+                                      Ploidiness::Haploid,
+                                      vec![],
+                                      vec![("SPAC24H6.05:allele-3", "SPAC24H6.05")]),
+        make_test_genotype_annotation("d6c914796c35e3b5-genotype-4", Some(201_099),
+                                      Ploidiness::Diploid,
+                                      vec![],
+                                      vec![("SPCC1919.10c:allele-5", "SPCC1919.10c"),
+                                           ("SPCC1919.10c:allele-5", "SPCC1919.10c")]),
+    ];
+
+    HashMap::from([("FYPO:0000013".into(), annotations)])
+}
+
 #[allow(dead_code)]
 fn make_one_detail(id: i32, gene_uniquename: &str, reference_uniquename: &str,
                    maybe_genotype_uniquename: Option<&str>, evidence: &str,